@@ -0,0 +1,193 @@
+extern crate dash;
+extern crate structopt;
+extern crate structopt_derive;
+use dash::graph::rapper::copy_wrapper;
+use dash::graph::splice;
+use dash::util::Result;
+use std::fs::{remove_file, File, OpenOptions};
+use std::io::{copy, sink, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "Copy benchmark",
+    about = "Compares the throughput of copy_wrapper against splice-based copies between files, pipes and tcp streams."
+)]
+struct Opt {
+    #[structopt(
+        short = "s",
+        long = "size",
+        default_value = "512",
+        help = "MB to copy per run."
+    )]
+    size: usize,
+    #[structopt(short = "t", long = "trials", default_value = "3")]
+    trials: usize,
+    #[structopt(
+        short = "d",
+        long = "dir",
+        default_value = "/tmp",
+        help = "Folder for the input and output files."
+    )]
+    dir: String,
+    #[structopt(
+        short = "n",
+        long = "nonblocking",
+        help = "Make the tcp streams non-blocking, like the runtime hands them to nodes."
+    )]
+    nonblocking: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Method {
+    Rapper,
+    Splice,
+}
+
+impl Method {
+    fn copy(self, reader: &mut File, writer: &mut TcpStream) -> Result<u64> {
+        match self {
+            Method::Rapper => copy_wrapper(reader, writer),
+            Method::Splice => splice::copy(reader, writer),
+        }
+    }
+}
+
+fn connect(nonblocking: bool) -> Result<(TcpStream, TcpStream)> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let sender = TcpStream::connect(listener.local_addr()?)?;
+    let (receiver, _) = listener.accept()?;
+    sender.set_nonblocking(nonblocking)?;
+    receiver.set_nonblocking(nonblocking)?;
+    Ok((sender, receiver))
+}
+
+fn drain(mut receiver: TcpStream) -> JoinHandle<Result<u64>> {
+    spawn(move || {
+        receiver.set_nonblocking(false)?;
+        Ok(copy(&mut receiver, &mut sink())?)
+    })
+}
+
+fn join(handle: JoinHandle<Result<u64>>) -> Result<u64> {
+    match handle.join() {
+        Ok(res) => res,
+        Err(e) => failure::bail!("Benchmark thread panicked: {:?}", e),
+    }
+}
+
+fn file_to_tcp(method: Method, input: &Path, nonblocking: bool) -> Result<u64> {
+    let (mut sender, receiver) = connect(nonblocking)?;
+    let drain_thread = drain(receiver);
+    let mut file = File::open(input)?;
+    method.copy(&mut file, &mut sender)?;
+    drop(sender);
+    join(drain_thread)
+}
+
+fn tcp_to_file(method: Method, input: &Path, output: &Path, nonblocking: bool) -> Result<u64> {
+    let (sender, mut receiver) = connect(nonblocking)?;
+    let input = input.to_path_buf();
+    let send_thread: JoinHandle<Result<u64>> = spawn(move || {
+        let mut sender = sender;
+        sender.set_nonblocking(false)?;
+        Ok(copy(&mut File::open(input)?, &mut sender)?)
+    });
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(output)?;
+    match method {
+        Method::Rapper => copy_wrapper(&mut receiver, &mut file)?,
+        Method::Splice => splice::copy(&mut receiver, &mut file)?,
+    };
+    // copy_wrapper loses count of what it copied before a WouldBlock, so count what was sent
+    let bytes = join(send_thread)?;
+    let written = file.metadata()?.len();
+    if written != bytes {
+        failure::bail!("Sent {} bytes but wrote {} to {:?}", bytes, written, output);
+    }
+    Ok(bytes)
+}
+
+fn pipe_to_tcp(method: Method, input: &Path, nonblocking: bool) -> Result<u64> {
+    let (mut sender, receiver) = connect(nonblocking)?;
+    let drain_thread = drain(receiver);
+    let mut child = Command::new("cat")
+        .arg(input)
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdout = child.stdout.take().unwrap();
+    match method {
+        Method::Rapper => copy_wrapper(&mut stdout, &mut sender)?,
+        Method::Splice => splice::copy(&mut stdout, &mut sender)?,
+    };
+    drop(sender);
+    child.wait()?;
+    join(drain_thread)
+}
+
+fn create_input(path: &Path, size: usize) -> Result<()> {
+    let mut file = File::create(path)?;
+    let mut block = vec![0u8; 1 << 20];
+    for (i, byte) in block.iter_mut().enumerate() {
+        *byte = (i % 251) as u8;
+    }
+    for _ in 0..size {
+        file.write_all(&block)?;
+    }
+    Ok(())
+}
+
+fn report<F: FnMut() -> Result<u64>>(name: &str, trials: usize, mut run: F) -> Result<()> {
+    let mut elapsed = Duration::from_secs(0);
+    let mut bytes: u64 = 0;
+    for _ in 0..trials {
+        let start = Instant::now();
+        bytes += run()?;
+        elapsed += start.elapsed();
+    }
+    let mb = bytes as f64 / (1 << 20) as f64;
+    println!(
+        "{:<20} {:>10.1} MB/s ({:.1} MB in {:.3}s)",
+        name,
+        mb / elapsed.as_secs_f64(),
+        mb,
+        elapsed.as_secs_f64()
+    );
+    Ok(())
+}
+
+fn run(opt: &Opt) -> Result<()> {
+    let input: PathBuf = Path::new(&opt.dir).join("copy_bench_input");
+    let output: PathBuf = Path::new(&opt.dir).join("copy_bench_output");
+    create_input(&input, opt.size)?;
+    for &method in [Method::Rapper, Method::Splice].iter() {
+        report(&format!("file->tcp {:?}", method), opt.trials, || {
+            file_to_tcp(method, &input, opt.nonblocking)
+        })?;
+        report(&format!("tcp->file {:?}", method), opt.trials, || {
+            tcp_to_file(method, &input, &output, opt.nonblocking)
+        })?;
+        report(&format!("pipe->tcp {:?}", method), opt.trials, || {
+            pipe_to_tcp(method, &input, opt.nonblocking)
+        })?;
+    }
+    remove_file(&input)?;
+    let _ = remove_file(&output);
+    Ok(())
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    if let Err(e) = run(&opt) {
+        eprintln!("Copy benchmark failed: {:?}", e);
+        std::process::exit(exitcode::SOFTWARE);
+    }
+}
@@ -1,4 +1,7 @@
 extern crate dash;
+use dash::graph::eventloop;
+use dash::graph::pipe::BufferLimits;
+use dash::runtime::new_runtime;
 use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr};
 
@@ -10,5 +13,13 @@ fn main() {
         "/home/deeptir/research/fs_project/client_folders/remote".to_string(),
     );
     let runtime_port = "1234";
-    dash::start_runtime(runtime_port, client_map, true, "/tmp");
+    dash::start_runtime(
+        runtime_port,
+        client_map,
+        true,
+        "/tmp",
+        eventloop::DEFAULT_THREADS,
+        new_runtime::DEFAULT_REQUEST_THREADS,
+        BufferLimits::default(),
+    );
 }
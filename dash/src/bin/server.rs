@@ -1,12 +1,17 @@
 extern crate dash;
 extern crate structopt;
 extern crate structopt_derive;
+use dash::graph::pipe::BufferLimits;
+use dash::graph::tmpdir;
 use std::collections::HashMap;
+use std::fs::create_dir_all;
 use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
 use std::process;
 use std::str::FromStr;
+use std::time::Duration;
 use structopt::StructOpt;
-use tracing::{error, Level};
+use tracing::{error, info, Level};
 use tracing_subscriber::FmtSubscriber;
 
 #[derive(Debug, StructOpt)]
@@ -29,6 +34,35 @@ struct Opt {
     debug: bool,
     #[structopt(short = "tmp", long = "tmpfile")]
     tmp_file: String,
+    #[structopt(
+        long = "io_threads",
+        default_value = "2",
+        help = "Threads moving data between processes, files and connections."
+    )]
+    io_threads: usize,
+    #[structopt(
+        long = "request_threads",
+        default_value = "8",
+        help = "Threads reading requests from clients and other servers."
+    )]
+    request_threads: usize,
+    #[structopt(
+        long = "buffer_memory",
+        default_value = "8",
+        help = "MB each buffered stream holds in memory before spilling into the tmp folder."
+    )]
+    buffer_memory: usize,
+    #[structopt(
+        long = "buffer_disk",
+        help = "MB each program may spill into the tmp folder; writers wait once it's used up."
+    )]
+    buffer_disk: Option<u64>,
+    #[structopt(
+        long = "tmp_max_age",
+        default_value = "86400",
+        help = "Seconds after which tmp files left behind by programs that never finished are deleted at startup."
+    )]
+    tmp_max_age: u64,
 }
 
 fn main() {
@@ -50,6 +84,17 @@ fn main() {
         .finish();
     tracing::subscriber::set_global_default(subscriber).expect("setting defualt subscriber failed");
 
+    // clear out whatever programs that died with an earlier server left behind
+    let tmp_max_age = Duration::from_secs(opt.tmp_max_age);
+    let swept = match create_dir_all(&tmp_file) {
+        Ok(_) => tmpdir::sweep(Path::new(&tmp_file), tmp_max_age),
+        Err(e) => Err(e.into()),
+    };
+    match swept {
+        Ok(count) => info!("Removed {} stale program tmp dirs from {}", count, tmp_file),
+        Err(e) => error!("Could not sweep tmp folder {}: {:?}", tmp_file, e),
+    }
+
     // local loopback
     let addr = match Ipv4Addr::from_str(&ip_addr) {
         Ok(a) => a,
@@ -59,5 +104,16 @@ fn main() {
         }
     };
     client_map.insert(IpAddr::V4(addr), client_folder.clone());
-    dash::start_runtime(&runtime_port, client_map, debug, &tmp_file);
+    dash::start_runtime(
+        &runtime_port,
+        client_map,
+        debug,
+        &tmp_file,
+        opt.io_threads,
+        opt.request_threads,
+        BufferLimits {
+            memory: opt.buffer_memory << 20,
+            disk: opt.buffer_disk.map(|mb| mb << 20),
+        },
+    );
 }
@@ -1,12 +1,16 @@
+use super::compression::Compression;
+use super::eventloop::{Endpoint, Transfers};
 use super::execute::Execute;
 use super::filestream::FileStream;
 use super::info::{resolve_file_stream_option, resolve_file_streams, Info};
 use super::pipe::{
-    create_and_insert_channels, create_buffer_file, get_channel_name, BufferedPipe, PipeMode,
-    SharedChannelMap,
+    create_and_insert_channels, get_channel_name, BufferedPipe, PipeMode, SharedChannelMap, Tee,
 };
+use super::progress::Meter;
+use super::rapper::copy_metered;
 use super::rapper::copy_wrapper as copy;
 use super::rapper::stream_initiate_filter;
+use super::splice;
 use super::{program, stream, Location, Result};
 use failure::bail;
 use itertools::join;
@@ -21,7 +25,7 @@ use stream::{
     SharedStreamMap,
 };
 use thread::{spawn, JoinHandle};
-use tracing::{debug, error};
+use tracing::debug;
 use which::which;
 
 /// CommandNodes, which have args, are either file streams OR Strings.
@@ -97,8 +101,8 @@ pub struct CommandNode {
     resolved_args: Vec<String>,
     /// Input streams in serialized order.
     stdin: Vec<DashStream>,
-    /// Optional output stream for stdout.
-    stdout: Option<DashStream>,
+    /// Output streams for stdout, which each get a copy of the output.
+    stdout: Vec<DashStream>,
     /// Optional output stream for stderr.
     stderr: Option<DashStream>,
     /// Execution location for the node.
@@ -145,7 +149,7 @@ impl CommandNode {
     }
 
     pub fn clear_stdout(&mut self) {
-        self.stdout = None;
+        self.stdout.clear();
     }
 
     pub fn clear_stderr(&mut self) {
@@ -156,8 +160,8 @@ impl CommandNode {
         self.stdin.iter_mut()
     }
 
-    pub fn get_stdout_mut(&mut self) -> &mut Option<DashStream> {
-        &mut self.stdout
+    pub fn get_stdout_iter_mut(&mut self) -> IterMut<DashStream> {
+        self.stdout.iter_mut()
     }
 
     pub fn get_stderr_mut(&mut self) -> &mut Option<DashStream> {
@@ -297,8 +301,11 @@ impl CommandNode {
             if self.node_id == new_edge.get_left() {
                 // replace stream for one of outward edges
                 let mut replaced = false;
-                if let Some(ref mut stream) = self.stdout {
-                    replaced = stream_repl(stream, &edge, &new_edge, false);
+                for stream in self.stdout.iter_mut() {
+                    if stream_repl(stream, &edge, &new_edge, false) {
+                        replaced = true;
+                        break;
+                    }
                 }
                 if let Some(ref mut stream) = self.stderr {
                     if !replaced {
@@ -356,19 +363,16 @@ impl Info for CommandNode {
         self.stdin.clone()
     }
 
-    fn get_stdout(&self) -> Option<DashStream> {
+    fn get_stdout(&self) -> Vec<DashStream> {
         self.stdout.clone()
     }
 
     fn get_stdout_id(&self) -> Option<NodeId> {
-        match &self.stdout {
-            Some(dashstream) => match dashstream {
-                DashStream::Pipe(ps) => Some(ps.get_right()),
-                DashStream::Tcp(ts) => Some(ts.get_right()),
-                _ => None,
-            },
-            None => None,
-        }
+        self.stdout.iter().find_map(|dashstream| match dashstream {
+            DashStream::Pipe(ps) => Some(ps.get_right()),
+            DashStream::Tcp(ts) => Some(ts.get_right()),
+            _ => None,
+        })
     }
 
     fn get_stderr(&self) -> Option<DashStream> {
@@ -380,10 +384,7 @@ impl Info for CommandNode {
     }
 
     fn get_stdout_len(&self) -> usize {
-        match self.stdout {
-            Some(_) => 1,
-            None => 0,
-        }
+        self.stdout.len()
     }
 
     fn get_stderr_len(&self) -> usize {
@@ -422,13 +423,28 @@ impl Info for CommandNode {
                 );
             }
         }
-        if let Some(current) = &self.stdout {
+        if !self.stdout.is_empty() {
             debug!(
                 "Setting stdout on {:?} as {:?}, but stdout is already set to: {:?}",
-                self.node_id, stream, current
+                self.node_id, stream, self.stdout
             );
         }
-        self.stdout = Some(stream);
+        self.stdout = vec![stream];
+        Ok(())
+    }
+
+    fn add_stdout(&mut self, stream: DashStream) -> Result<()> {
+        match stream {
+            DashStream::Pipe(_) => {}
+            DashStream::Tcp(_) => {}
+            _ => {
+                bail!(
+                    "Cannot have stream of type {:?} as stdout to command node",
+                    stream
+                );
+            }
+        }
+        self.stdout.push(stream);
         Ok(())
     }
 
@@ -487,7 +503,7 @@ impl Info for CommandNode {
             }
         }
         resolve_file_streams(&mut self.stdin, parent_dir.as_path());
-        resolve_file_stream_option(&mut self.stdout, parent_dir.as_path());
+        resolve_file_streams(&mut self.stdout, parent_dir.as_path());
         resolve_file_stream_option(&mut self.stderr, parent_dir.as_path());
         Ok(())
     }
@@ -503,11 +519,7 @@ impl Info for CommandNode {
             let mut streams_to_add: Vec<(IOType, DashStream)> = Vec::new();
             // if edge to be replaced is an outward edge
             if self.get_id() == edge.get_left() {
-                let mut streams: Vec<DashStream> = Vec::new();
-                match &self.stdout {
-                    Some(stdout) => streams.push(stdout.clone()),
-                    None => {}
-                }
+                let mut streams: Vec<DashStream> = self.stdout.clone();
                 match &self.stderr {
                     Some(stderr) => streams.push(stderr.clone()),
                     None => {}
@@ -583,10 +595,11 @@ impl Info for CommandNode {
             }
             // add and remove the streams
             if self.get_id() == edge.get_left() {
+                self.stdout.retain(|x| !streams_to_remove.contains(&x));
                 for (iotype, stream) in streams_to_add.iter() {
                     match iotype {
                         IOType::Stdout => {
-                            self.stdout = Some(stream.clone());
+                            self.stdout.push(stream.clone());
                         }
                         IOType::Stderr => {
                             self.stderr = Some(stream.clone());
@@ -620,10 +633,12 @@ impl Info for CommandNode {
                 self.stdin.push(repl.clone());
             }
             IOType::Stdout => {
-                if let Some(_stream) = &self.stdout {
-                    self.stdout = Some(repl.clone());
-                } else {
-                    bail!("Trying to replace stdout pipestream with net but node has no stdout");
+                let old = DashStream::Pipe(pipe.clone());
+                match self.stdout.iter_mut().find(|stream| **stream == old) {
+                    Some(stream) => *stream = repl.clone(),
+                    None => {
+                        bail!("Trying to replace stdout pipestream {:?} with net but node has no such stdout", pipe);
+                    }
                 }
             }
             IOType::Stderr => {
@@ -645,16 +660,12 @@ impl Info for CommandNode {
                 .filter(|&s| stream_initiate_filter(s.clone(), self.node_id, is_server))
                 .cloned()
                 .collect(),
-            IOType::Stdout => match &self.stdout {
-                Some(stream) => {
-                    if stream_initiate_filter(stream.clone(), self.node_id, is_server) {
-                        vec![stream.clone()]
-                    } else {
-                        vec![]
-                    }
-                }
-                None => vec![],
-            },
+            IOType::Stdout => self
+                .stdout
+                .iter()
+                .filter(|&s| stream_initiate_filter(s.clone(), self.node_id, is_server))
+                .cloned()
+                .collect(),
             IOType::Stderr => match &self.stderr {
                 Some(stream) => {
                     if stream_initiate_filter(stream.clone(), self.node_id, is_server) {
@@ -695,31 +706,49 @@ impl Execute for CommandNode {
             cmd.stdin(Stdio::piped());
         }
 
-        if let Some(stdout) = &self.stdout {
+        if !self.stdout.is_empty() {
             // need to create the buffer file pipe for the reader and writer to access later
             debug!(
                 "Setting stdout for node {:?} to be Stdio::Piped",
                 self.node_id
             );
-            match stdout {
-                DashStream::Tcp(netstream) => {
-                    if netstream.get_bufferable() {
-                        create_buffer_file(tmp_folder.as_path(), self.node_id, IOType::Stdout)?;
-                        create_and_insert_channels(self.node_id, IOType::Stdout, &mut channels)?;
+            // output that fans out is copied into a buffer for each node it goes to
+            let fans_out = self.stdout.len() > 1;
+            for stdout in self.stdout.iter() {
+                match stdout {
+                    DashStream::Tcp(netstream) => {
+                        if fans_out || netstream.get_bufferable() {
+                            create_and_insert_channels(
+                                self.node_id,
+                                netstream.get_right(),
+                                IOType::Stdout,
+                                tmp_folder.as_path(),
+                                &mut channels,
+                            )?;
+                        }
                     }
-                    cmd.stdout(Stdio::piped());
-                }
-                DashStream::Pipe(pipestream) => {
-                    if pipestream.get_bufferable() {
-                        create_buffer_file(tmp_folder.as_path(), self.node_id, IOType::Stdout)?;
-                        create_and_insert_channels(self.node_id, IOType::Stdout, &mut channels)?;
+                    DashStream::Pipe(pipestream) => {
+                        if fans_out && !pipestream.get_bufferable() {
+                            bail!(
+                                "Node {:?} sends stdout to several nodes, so its pipe to {:?} must be bufferable",
+                                self.node_id,
+                                pipestream.get_right()
+                            );
+                        }
+                        if pipestream.get_bufferable() {
+                            create_and_insert_channels(
+                                self.node_id,
+                                pipestream.get_right(),
+                                IOType::Stdout,
+                                tmp_folder.as_path(),
+                                &mut channels,
+                            )?;
+                        }
                     }
-                    cmd.stdout(Stdio::piped());
-                }
-                _ => {
-                    cmd.stdout(Stdio::piped());
+                    _ => {}
                 }
             }
+            cmd.stdout(Stdio::piped());
         }
 
         if let Some(stream) = &self.stderr {
@@ -727,15 +756,25 @@ impl Execute for CommandNode {
             match stream {
                 DashStream::Tcp(netstream) => {
                     if netstream.get_bufferable() {
-                        create_buffer_file(tmp_folder.as_path(), self.node_id, IOType::Stderr)?;
-                        create_and_insert_channels(self.node_id, IOType::Stderr, &mut channels)?;
+                        create_and_insert_channels(
+                            self.node_id,
+                            netstream.get_right(),
+                            IOType::Stderr,
+                            tmp_folder.as_path(),
+                            &mut channels,
+                        )?;
                     }
                     cmd.stderr(Stdio::piped());
                 }
                 DashStream::Pipe(pipestream) => {
                     if pipestream.get_bufferable() {
-                        create_buffer_file(tmp_folder.as_path(), self.node_id, IOType::Stderr)?;
-                        create_and_insert_channels(self.node_id, IOType::Stderr, &mut channels)?;
+                        create_and_insert_channels(
+                            self.node_id,
+                            pipestream.get_right(),
+                            IOType::Stderr,
+                            tmp_folder.as_path(),
+                            &mut channels,
+                        )?;
                     }
                     cmd.stderr(Stdio::piped());
                 }
@@ -753,7 +792,7 @@ impl Execute for CommandNode {
             )?;
         }
 
-        if let Some(_) = &self.stdout {
+        if !self.stdout.is_empty() {
             let stdout_handle = child.stdout.expect("Could not get stdout handle for proc");
             pipes.insert(
                 self.get_handle_identifier(IOType::Stdout),
@@ -775,29 +814,62 @@ impl Execute for CommandNode {
     fn redirect(
         &mut self,
         mut pipes: SharedPipeMap,
-        network_connections: SharedStreamMap,
+        mut network_connections: SharedStreamMap,
         channels: SharedChannelMap,
-        tmp_folder: PathBuf,
+        _tmp_folder: PathBuf,
+        transfers: &Transfers,
     ) -> Result<()> {
-        let mut threads: Vec<(IOType, JoinHandle<Result<()>>)> = Vec::new();
-
-        // spawn a stdin thread to handle the input
         if self.stdin.len() > 0 {
-            let stdin_prog_id = self.prog_id;
             let stdin_handle = pipes.remove(&self.get_handle_identifier(IOType::Stdin))?;
-            let stdin_streams = self.stdin.clone();
-            let stdin_id = self.node_id;
-            let pipes_clone = pipes.clone();
-            let network_connections_clone = network_connections.clone();
-            let channels_clone = channels.clone();
-            let tmp_folder_clone = tmp_folder.clone();
-            debug!(
-                "Spawning thread for copying stdin into node {:?}",
-                self.node_id
-            );
-            threads.push((
-                IOType::Stdin,
-                spawn(move || {
+            let name = format!("node {:?} stdin", self.node_id);
+            if self.stdin.iter().all(|stream| stream.is_forwardable()) {
+                let mut inputs: Vec<(Box<dyn Endpoint>, Meter)> = Vec::new();
+                for input_stream in self.stdin.iter() {
+                    let meter = transfers.meter_stream(input_stream, self.node_id);
+                    match input_stream {
+                        DashStream::Tcp(netstream) => {
+                            inputs.push((Box::new(network_connections.remove(netstream)?), meter));
+                        }
+                        DashStream::Pipe(pipestream) => {
+                            let handle_identifier = HandleIdentifier::new(
+                                self.prog_id,
+                                pipestream.get_left(),
+                                pipestream.get_output_type(),
+                            );
+                            // if left side of the pipe is a command node, then there will be a
+                            // previous handle
+                            if pipes.contains_key(&handle_identifier)? {
+                                inputs.push((Box::new(pipes.remove(&handle_identifier)?), meter));
+                            } else {
+                                debug!(
+                                    "No pipe found for handle identifier: {:?}",
+                                    handle_identifier
+                                );
+                            }
+                        }
+                        DashStream::File(filestream) => {
+                            inputs.push((Box::new(filestream.open()?), meter));
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+                transfers.forward_metered(&name, inputs, Box::new(stdin_handle))?;
+            } else {
+                debug!(
+                    "Spawning thread for copying stdin into node {:?}",
+                    self.node_id
+                );
+                let stdin_id = self.node_id;
+                let stdin_prog_id = self.prog_id;
+                let stdin_streams: Vec<(DashStream, Meter)> = self
+                    .stdin
+                    .iter()
+                    .map(|stream| (stream.clone(), transfers.meter_stream(stream, stdin_id)))
+                    .collect();
+                let pipes_clone = pipes.clone();
+                let network_connections_clone = network_connections.clone();
+                let channels_clone = channels.clone();
+                transfers.spawn(&name, move || {
                     redirect_stdin(
                         stdin_id,
                         stdin_prog_id,
@@ -806,105 +878,86 @@ impl Execute for CommandNode {
                         pipes_clone,
                         network_connections_clone,
                         channels_clone,
-                        tmp_folder_clone,
                     )
-                }),
-            ));
+                })?;
+            }
         }
 
-        // spawn a stdout thread to handle sending the stdout
-        if let Some(stream) = &self.stdout {
-            let stdout_id = self.node_id;
-            let stdout_prog_id = self.prog_id;
-            let stream_clone = stream.clone();
-            let pipes_clone = pipes.clone();
-            let network_connections_clone = network_connections.clone();
-            let channels_clone = channels.clone();
-            let tmp_folder_clone = tmp_folder.clone();
+        let mut outputs = vec![(IOType::Stderr, self.stderr.clone())];
+        if self.stdout.len() == 1 {
+            outputs.push((IOType::Stdout, self.stdout.first().cloned()));
+        } else if self.stdout.len() > 1 {
             debug!(
-                "Spawning thread to copy stdout from a node {:?}",
+                "Spawning thread to fan out stdout from node {:?}",
                 self.node_id
             );
-            threads.push((
-                IOType::Stdout,
-                spawn(move || {
-                    redirect_output(
-                        stdout_id,
-                        stdout_prog_id,
-                        stream_clone,
-                        pipes_clone,
-                        network_connections_clone,
-                        channels_clone,
-                        tmp_folder_clone,
-                        IOType::Stdout,
-                    )
-                }),
-            ));
-        }
-
-        // spawn a stderr thread to handle sending the stderr
-        if let Some(stream) = &self.stderr {
+            let name = format!("node {:?} {:?}", self.node_id, IOType::Stdout);
             let id = self.node_id;
             let prog = self.prog_id;
-            let stream_clone = stream.clone();
+            let streams = self.stdout.clone();
             let pipes_clone = pipes.clone();
             let network_connections_clone = network_connections.clone();
             let channels_clone = channels.clone();
-            let tmp_folder_clone = tmp_folder.clone();
-            debug!(
-                "Spawning thread to copy stderr from a node {:?}",
-                self.node_id
-            );
-            threads.push((
-                IOType::Stderr,
-                spawn(move || {
-                    redirect_output(
-                        id,
-                        prog,
-                        stream_clone,
-                        pipes_clone,
-                        network_connections_clone,
-                        channels_clone,
-                        tmp_folder_clone,
-                        IOType::Stderr,
-                    )
-                }),
-            ));
+            transfers.spawn(&name, move || {
+                fan_out_stdout(
+                    id,
+                    prog,
+                    streams,
+                    pipes_clone,
+                    network_connections_clone,
+                    channels_clone,
+                )
+            })?;
         }
-
-        // join all the threads
-        for (iotype, thread) in threads {
-            match thread.join() {
-                Ok(res) => match res {
-                    Ok(_) => {}
-                    Err(e) => {
-                        error!(
-                            "Node {:?} {:?} thread joined with an error: {:?}",
-                            self.node_id, iotype, e
-                        );
-                        bail!(
-                            "Node {:?} {:?} thread joined with an error: {:?}",
-                            self.node_id,
-                            iotype,
-                            e
-                        );
-                    }
-                },
-                Err(e) => {
-                    error!(
-                        "Node {:?} {:?} thread could not join with an error: {:?}",
-                        self.node_id, iotype, e
-                    );
-                    bail!(
-                        "Node {:?} {:?} thread could not join with an error: {:?}",
-                        self.node_id,
-                        iotype,
-                        e
+        for (iotype, output) in outputs.into_iter() {
+            let name = format!("node {:?} {:?}", self.node_id, iotype);
+            match output {
+                Some(DashStream::Tcp(netstream))
+                    if !netstream.get_bufferable()
+                        && netstream.get_compression() == Compression::None =>
+                {
+                    let handle =
+                        pipes.remove(&HandleIdentifier::new(self.prog_id, self.node_id, iotype))?;
+                    let tcp_stream = match network_connections.remove(&netstream) {
+                        Ok(s) => s,
+                        Err(e) => {
+                            bail!(
+                                "Failed to find tcp stream with info {:?}: {:?}",
+                                netstream,
+                                e
+                            );
+                        }
+                    };
+                    transfers.forward(&name, vec![Box::new(handle)], Box::new(tcp_stream))?;
+                }
+                // the command on the other end of a plain pipe reads it directly
+                Some(DashStream::Pipe(pipestream)) if !pipestream.get_bufferable() => {}
+                // compressed and buffered outputs copy on a thread of their own
+                Some(stream @ DashStream::Tcp(_)) | Some(stream @ DashStream::Pipe(_)) => {
+                    debug!(
+                        "Spawning thread to copy {:?} from a node {:?}",
+                        iotype, self.node_id
                     );
+                    let id = self.node_id;
+                    let prog = self.prog_id;
+                    let pipes_clone = pipes.clone();
+                    let network_connections_clone = network_connections.clone();
+                    let channels_clone = channels.clone();
+                    transfers.spawn(&name, move || {
+                        redirect_output(
+                            id,
+                            prog,
+                            stream,
+                            pipes_clone,
+                            network_connections_clone,
+                            channels_clone,
+                            iotype,
+                        )
+                    })?;
                 }
+                _ => {}
             }
         }
-
         Ok(())
     }
 }
@@ -913,37 +966,33 @@ fn redirect_stdin(
     _node_id: NodeId,
     prog_id: ProgId,
     stdin_handle: OutputHandle,
-    stdin_streams: Vec<DashStream>,
+    stdin_streams: Vec<(DashStream, Meter)>,
     mut pipes: SharedPipeMap,
     mut network_connections: SharedStreamMap,
     mut channels: SharedChannelMap,
-    tmp_folder: PathBuf,
 ) -> Result<()> {
     let stdin_handle_option: Option<ChildStdin> = stdin_handle.into();
     let mut stdin = stdin_handle_option.unwrap();
-    for input_stream in stdin_streams.iter() {
+    for (input_stream, meter) in stdin_streams.iter() {
         match input_stream {
             DashStream::Tcp(netstream) => {
-                let mut tcpstream = network_connections.remove(&netstream)?;
-                copy(&mut tcpstream, &mut stdin)?;
+                let mut tcpstream = network_connections.remove_blocking(netstream)?;
+                netstream
+                    .get_compression()
+                    .metered_receive(&mut tcpstream, &mut stdin, meter)?;
             }
             DashStream::Pipe(pipestream) => {
                 if pipestream.get_bufferable() {
                     let channel_end = channels.remove(&get_channel_name(
                         pipestream.get_left(),
+                        pipestream.get_right(),
                         PipeMode::Read,
                         pipestream.get_output_type(),
                     ))?;
                     // copy from the buffer file, not the process
                     // buffered pipe is indexed by left end of the pipe
-                    let mut buffered_pipe = BufferedPipe::new(
-                        pipestream.get_left(),
-                        pipestream.get_output_type(),
-                        tmp_folder.as_path(),
-                        PipeMode::Read,
-                        channel_end,
-                    )?;
-                    copy(&mut buffered_pipe, &mut stdin)?;
+                    let mut buffered_pipe = BufferedPipe::new(PipeMode::Read, channel_end)?;
+                    copy_metered(&mut buffered_pipe, &mut stdin, meter)?;
                     buffered_pipe.drop_file()?;
                 } else {
                     // just copy from the process directly as normal
@@ -957,7 +1006,7 @@ fn redirect_stdin(
                     let contains = pipes.contains_key(&handle_identifier)?;
                     if contains {
                         let mut prev_handle = pipes.remove(&handle_identifier)?;
-                        copy(&mut prev_handle, &mut stdin)?;
+                        splice::copy(&mut prev_handle, &mut stdin)?;
                     } else {
                         tracing::debug!(
                             "No pipe found for handle identifier: {:?}",
@@ -969,7 +1018,7 @@ fn redirect_stdin(
             DashStream::File(filestream) => {
                 // Open a read version of the file, and copy it into the current process
                 let mut file_handle = filestream.open()?;
-                copy(&mut file_handle, &mut stdin)?;
+                splice::copy(&mut file_handle, &mut stdin)?;
             }
             _ => {
                 bail!("Command node should not see input from file, stdout, or stderr stream handle: {:?}", input_stream);
@@ -986,12 +1035,11 @@ fn redirect_output(
     mut pipes: SharedPipeMap,
     mut network_connections: SharedStreamMap,
     mut channels: SharedChannelMap,
-    tmp_folder: PathBuf,
     iotype: IOType,
 ) -> Result<()> {
     match stream.clone() {
         DashStream::Tcp(netstream) => {
-            let mut tcp_stream = match network_connections.remove(&netstream) {
+            let mut tcp_stream = match network_connections.remove_blocking(&netstream) {
                 Ok(s) => s,
                 Err(e) => {
                     bail!(
@@ -1003,15 +1051,15 @@ fn redirect_output(
             };
             if netstream.get_bufferable() {
                 // spawn the write thread
+                let right = netstream.get_right();
                 let left_channel =
-                    channels.remove(&get_channel_name(node_id, PipeMode::Write, iotype))?;
+                    channels.remove(&get_channel_name(node_id, right, PipeMode::Write, iotype))?;
                 let right_channel =
-                    channels.remove(&get_channel_name(node_id, PipeMode::Read, iotype))?;
-                let mut left_pipe =
-                    BufferedPipe::new(node_id, iotype, &tmp_folder, PipeMode::Write, left_channel)?;
-                let mut right_pipe =
-                    BufferedPipe::new(node_id, iotype, &tmp_folder, PipeMode::Read, right_channel)?;
+                    channels.remove(&get_channel_name(node_id, right, PipeMode::Read, iotype))?;
+                let mut left_pipe = BufferedPipe::new(PipeMode::Write, left_channel)?;
+                let mut right_pipe = BufferedPipe::new(PipeMode::Read, right_channel)?;
                 let mut handle = pipes.remove(&HandleIdentifier::new(prog_id, node_id, iotype))?;
+                let compression = netstream.get_compression();
                 let copy_thread: JoinHandle<Result<()>> = spawn(move || {
                     copy(&mut handle, &mut left_pipe)?;
                     left_pipe.set_write_done()?;
@@ -1020,7 +1068,7 @@ fn redirect_output(
 
                 // spawn the copy into tcp connection thread
                 let send_thread: JoinHandle<Result<()>> = spawn(move || {
-                    copy(&mut right_pipe, &mut tcp_stream)?;
+                    compression.send(&mut right_pipe, &mut tcp_stream)?;
                     right_pipe.drop_file()?;
                     Ok(())
                 });
@@ -1051,7 +1099,9 @@ fn redirect_output(
             } else {
                 // directly copy the stdout into the connection without any intermediate buffering
                 let mut handle = pipes.remove(&HandleIdentifier::new(prog_id, node_id, iotype))?;
-                copy(&mut handle, &mut tcp_stream)?;
+                netstream
+                    .get_compression()
+                    .splice_send(&mut handle, &mut tcp_stream)?;
             }
         }
         DashStream::Pipe(pipestream) => {
@@ -1059,15 +1109,13 @@ fn redirect_output(
                 // need to copy stdout of the command into the buffered pipe
                 let mut stdout_handle =
                     pipes.remove(&HandleIdentifier::new(prog_id, node_id, iotype))?;
-                let channel_end =
-                    channels.remove(&get_channel_name(node_id, PipeMode::Write, iotype))?;
-                let mut buffered_pipe = BufferedPipe::new(
+                let channel_end = channels.remove(&get_channel_name(
                     node_id,
-                    iotype,
-                    tmp_folder.as_path(),
+                    pipestream.get_right(),
                     PipeMode::Write,
-                    channel_end,
-                )?;
+                    iotype,
+                ))?;
+                let mut buffered_pipe = BufferedPipe::new(PipeMode::Write, channel_end)?;
                 copy(&mut stdout_handle, &mut buffered_pipe)?;
                 buffered_pipe.set_write_done()?;
             }
@@ -1076,3 +1124,76 @@ fn redirect_output(
     }
     Ok(())
 }
+
+/// Copies a command's stdout into a buffer for each node it fans out to, and sends the buffers
+/// for nodes on other machines over their connections. A slow reader only holds the others
+/// back once its buffer is full.
+fn fan_out_stdout(
+    node_id: NodeId,
+    prog_id: ProgId,
+    streams: Vec<DashStream>,
+    mut pipes: SharedPipeMap,
+    mut network_connections: SharedStreamMap,
+    mut channels: SharedChannelMap,
+) -> Result<()> {
+    let mut outputs: Vec<BufferedPipe> = Vec::new();
+    let mut send_threads: Vec<JoinHandle<Result<()>>> = Vec::new();
+    for stream in streams.into_iter() {
+        let right = match &stream {
+            DashStream::Pipe(pipestream) => pipestream.get_right(),
+            DashStream::Tcp(netstream) => netstream.get_right(),
+            _ => bail!("Cannot fan out stdout into stream {:?}", stream),
+        };
+        let channel_end = channels.remove(&get_channel_name(
+            node_id,
+            right,
+            PipeMode::Write,
+            IOType::Stdout,
+        ))?;
+        outputs.push(BufferedPipe::new(PipeMode::Write, channel_end)?);
+        if let DashStream::Tcp(netstream) = stream {
+            let mut tcp_stream = network_connections.remove_blocking(&netstream)?;
+            let channel_end = channels.remove(&get_channel_name(
+                node_id,
+                right,
+                PipeMode::Read,
+                IOType::Stdout,
+            ))?;
+            let mut buffered_pipe = BufferedPipe::new(PipeMode::Read, channel_end)?;
+            let compression = netstream.get_compression();
+            send_threads.push(spawn(move || {
+                compression.send(&mut buffered_pipe, &mut tcp_stream)?;
+                buffered_pipe.drop_file()?;
+                Ok(())
+            }));
+        }
+    }
+
+    let mut handle = pipes.remove(&HandleIdentifier::new(prog_id, node_id, IOType::Stdout))?;
+    let mut tee = Tee::new(outputs);
+    copy(&mut handle, &mut tee)?;
+    tee.set_write_done()?;
+    drop(tee);
+
+    for send_thread in send_threads.into_iter() {
+        match send_thread.join() {
+            Ok(res) => {
+                if let Err(e) = res {
+                    bail!(
+                        "Thread to send stdout of node {:?} into tcp stream failed: {:?}",
+                        node_id,
+                        e
+                    );
+                }
+            }
+            Err(e) => {
+                bail!(
+                    "Thread to send stdout of node {:?} into tcp stream failed to join: {:?}",
+                    node_id,
+                    e
+                );
+            }
+        }
+    }
+    Ok(())
+}
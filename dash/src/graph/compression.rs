@@ -0,0 +1,139 @@
+use super::progress::{Meter, Metered};
+use super::rapper::copy_wrapper as copy;
+use super::splice;
+use super::Result;
+use failure::bail;
+use lz4_flex::frame::{FrameDecoder, FrameEncoder};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::str::FromStr;
+
+/// zstd level for compressed streams: fast enough to keep up with the commands feeding them.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Codec the bytes of a network stream are compressed with while in flight.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Hash, Eq)]
+pub enum Compression {
+    /// Send the bytes as they are.
+    None,
+    /// LZ4 frames: cheap, for links that are only somewhat slow.
+    Lz4,
+    /// zstd: smaller output for more CPU, for the slowest links.
+    Zstd,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::None
+    }
+}
+
+impl FromStr for Compression {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Compression::None),
+            "lz4" => Ok(Compression::Lz4),
+            "zstd" => Ok(Compression::Zstd),
+            _ => bail!("Unknown compression {:?}; expected none, lz4 or zstd", s),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Compression::None => "none",
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd",
+        };
+        f.pad(name)
+    }
+}
+
+impl Compression {
+    /// Copies everything from reader into the sending end of a stream, compressing it.
+    pub fn send<R: Read + ?Sized, W: Write>(self, reader: &mut R, writer: &mut W) -> Result<u64> {
+        match self {
+            Compression::None => copy(reader, writer),
+            Compression::Lz4 => {
+                let mut encoder = FrameEncoder::new(writer);
+                let bytes = copy(reader, &mut encoder)?;
+                finish(encoder.finish().map_err(|e| e.into()))?;
+                Ok(bytes)
+            }
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(writer, ZSTD_LEVEL)?;
+                let bytes = copy(reader, &mut encoder)?;
+                finish(encoder.finish())?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Copies everything from the receiving end of a stream into writer, decompressing it.
+    pub fn receive<R: Read, W: Write + ?Sized>(
+        self,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<u64> {
+        match self {
+            Compression::None => copy(reader, writer),
+            Compression::Lz4 => copy(&mut FrameDecoder::new(reader), writer),
+            Compression::Zstd => copy(&mut zstd::Decoder::new(reader)?, writer),
+        }
+    }
+
+    /// Like `send`, but uncompressed streams are moved between the descriptors in the kernel.
+    pub fn splice_send<R, W>(self, reader: &mut R, writer: &mut W) -> Result<u64>
+    where
+        R: Read + AsRawFd + ?Sized,
+        W: Write + AsRawFd,
+    {
+        match self {
+            Compression::None => splice::copy(reader, writer),
+            _ => self.send(reader, writer),
+        }
+    }
+
+    /// Like `receive`, but uncompressed streams are moved between the descriptors in the kernel.
+    pub fn splice_receive<R, W>(self, reader: &mut R, writer: &mut W) -> Result<u64>
+    where
+        R: Read + AsRawFd,
+        W: Write + AsRawFd + ?Sized,
+    {
+        match self {
+            Compression::None => splice::copy(reader, writer),
+            _ => self.receive(reader, writer),
+        }
+    }
+
+    /// Like `splice_receive`, counting what comes out of the stream on meter. A metered stream
+    /// goes through memory, so its lines can be counted.
+    pub fn metered_receive<R, W>(self, reader: &mut R, writer: &mut W, meter: &Meter) -> Result<u64>
+    where
+        R: Read + AsRawFd,
+        W: Write + AsRawFd + ?Sized,
+    {
+        match meter.is_on() {
+            true => self.receive(reader, &mut Metered::new(writer, meter)),
+            false => self.splice_receive(reader, writer),
+        }
+    }
+}
+
+/// Writes out the end of the compressed stream. Like `copy_wrapper`, a reader that hung up
+/// isn't an error.
+fn finish<W: Write>(finished: std::io::Result<W>) -> Result<()> {
+    let result = finished.and_then(|mut writer| writer.flush());
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => match e.kind() {
+            ErrorKind::BrokenPipe | ErrorKind::ConnectionAborted => Ok(()),
+            _ => bail!("{:?}", e),
+        },
+    }
+}
@@ -0,0 +1,891 @@
+use super::program::NodeId;
+use super::progress::{Meter, Progress};
+use super::stream::DashStream;
+use super::Result;
+use failure::bail;
+use std::collections::VecDeque;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Threads moving data for programs run with `Program::execute`.
+pub const DEFAULT_THREADS: usize = 2;
+
+static GLOBAL: OnceLock<EventLoop> = OnceLock::new();
+
+/// Anything a transfer can move data out of or into: child pipes, tcp streams, files and fifos.
+pub trait Endpoint: AsRawFd + Send {}
+
+impl<T: AsRawFd + Send> Endpoint for T {}
+
+type Done = Box<dyn FnOnce(Result<u64>) + Send>;
+
+/// An input, along with the meter for the edge it comes in on.
+type Input = (Box<dyn Endpoint>, Meter);
+
+/// Copies each input into the output, one after the other, then closes all of them.
+struct Transfer {
+    name: String,
+    inputs: VecDeque<Input>,
+    output: Box<dyn Endpoint>,
+    done: Done,
+}
+
+type Timer = Box<dyn FnOnce() + Send>;
+
+/// What a worker thread is handed: a transfer to run, or a timer to fire at the given instant.
+enum Job {
+    Transfer(Transfer),
+    Timer(Instant, Timer),
+}
+
+/// Moves data between file descriptors for every node running on this machine from a fixed
+/// number of threads, each waiting on its transfers with epoll, instead of a thread per stream.
+#[derive(Clone)]
+pub struct EventLoop {
+    workers: Arc<Vec<Worker>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl EventLoop {
+    pub fn new(threads: usize) -> Result<Self> {
+        if threads == 0 {
+            bail!("Event loop needs at least one thread");
+        }
+        let mut workers: Vec<Worker> = Vec::new();
+        for i in 0..threads {
+            workers.push(Worker::spawn(i)?);
+        }
+        Ok(EventLoop {
+            workers: Arc::new(workers),
+            next: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// Event loop shared by everything on this machine that doesn't bring its own.
+    pub fn global() -> EventLoop {
+        GLOBAL
+            .get_or_init(|| {
+                EventLoop::new(DEFAULT_THREADS).expect("Failed to start the global event loop")
+            })
+            .clone()
+    }
+
+    pub fn get_threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Calls `callback` from one of the event loop's threads once `delay` has passed. The
+    /// thread's transfers wait on it, so it should only do bookkeeping.
+    pub fn after<F>(&self, delay: Duration, callback: F) -> Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.send(Job::Timer(Instant::now() + delay, Box::new(callback)))
+    }
+
+    fn submit(&self, transfer: Transfer) -> Result<()> {
+        self.send(Job::Transfer(transfer))
+    }
+
+    fn send(&self, job: Job) -> Result<()> {
+        let idx = self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        self.workers[idx].send(job)
+    }
+}
+
+/// Tracks the transfers and blocking redirections a program starts, and reports once all of
+/// them have finished.
+#[derive(Clone)]
+pub struct Transfers {
+    event_loop: EventLoop,
+    state: Arc<Mutex<GroupState>>,
+    progress: Progress,
+}
+
+#[derive(Default)]
+struct GroupState {
+    outstanding: usize,
+    error: Option<failure::Error>,
+    on_done: Option<Box<dyn FnOnce(Result<()>) + Send>>,
+}
+
+impl Transfers {
+    pub fn new(event_loop: &EventLoop) -> Self {
+        Transfers::with_progress(event_loop, &Progress::default())
+    }
+
+    /// Transfers whose redirections count what they move along each edge on progress.
+    pub fn with_progress(event_loop: &EventLoop, progress: &Progress) -> Self {
+        Transfers {
+            event_loop: event_loop.clone(),
+            state: Arc::new(Mutex::new(GroupState::default())),
+            progress: progress.clone(),
+        }
+    }
+
+    /// Meter for the edge from left into right; off unless the program reports progress.
+    pub fn meter(&self, left: NodeId, right: NodeId) -> Meter {
+        self.progress.meter(left, right)
+    }
+
+    /// Meter for the edge a node's input stream comes in on, if it comes from another node.
+    pub fn meter_stream(&self, stream: &DashStream, right: NodeId) -> Meter {
+        match stream {
+            DashStream::Pipe(pipestream) => self.meter(pipestream.get_left(), right),
+            DashStream::Tcp(netstream) => self.meter(netstream.get_left(), right),
+            _ => Meter::default(),
+        }
+    }
+
+    /// Copies the inputs, one after the other, into the output on the event loop.
+    pub fn forward(
+        &self,
+        name: &str,
+        inputs: Vec<Box<dyn Endpoint>>,
+        output: Box<dyn Endpoint>,
+    ) -> Result<()> {
+        self.forward_then(name, inputs, output, || Ok(()))
+    }
+
+    /// Like `forward`, counting what comes in from each input on its meter. Metered inputs
+    /// are copied through memory so their lines can be counted.
+    pub fn forward_metered(
+        &self,
+        name: &str,
+        inputs: Vec<Input>,
+        output: Box<dyn Endpoint>,
+    ) -> Result<()> {
+        self.submit(name, inputs, output, || Ok(()))
+    }
+
+    /// Like `forward`, then runs `then` once everything is copied and all the ends are closed.
+    pub fn forward_then<F>(
+        &self,
+        name: &str,
+        inputs: Vec<Box<dyn Endpoint>>,
+        output: Box<dyn Endpoint>,
+        then: F,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> Result<()> + Send + 'static,
+    {
+        let inputs = inputs
+            .into_iter()
+            .map(|input| (input, Meter::default()))
+            .collect();
+        self.submit(name, inputs, output, then)
+    }
+
+    fn submit<F>(
+        &self,
+        name: &str,
+        inputs: Vec<Input>,
+        output: Box<dyn Endpoint>,
+        then: F,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> Result<()> + Send + 'static,
+    {
+        self.begin()?;
+        let state = self.state.clone();
+        let label = name.to_string();
+        let transfer = Transfer {
+            name: name.to_string(),
+            inputs: inputs.into_iter().collect(),
+            output: output,
+            done: Box::new(move |result: Result<u64>| {
+                let result = result.and_then(|bytes| {
+                    debug!("{} finished after {} bytes", label, bytes);
+                    then()
+                });
+                finish(&state, result, &label);
+            }),
+        };
+        if let Err(e) = self.event_loop.submit(transfer) {
+            finish(&self.state, Err(failure::format_err!("{:?}", e)), name);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Runs a redirection that has to block, like one going through a codec or a buffer file,
+    /// on its own thread.
+    pub fn spawn<F>(&self, name: &str, work: F) -> Result<()>
+    where
+        F: FnOnce() -> Result<()> + Send + 'static,
+    {
+        self.begin()?;
+        let state = self.state.clone();
+        let label = name.to_string();
+        let spawned = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || finish(&state, work(), &label));
+        if let Err(e) = spawned {
+            finish(&self.state, Err(failure::format_err!("{:?}", e)), name);
+            bail!("Failed to spawn thread for {}: {:?}", name, e);
+        }
+        Ok(())
+    }
+
+    /// Calls `on_done` with the first error, if any, once everything started so far and
+    /// anything those start in turn has finished.
+    pub fn on_done<F>(&self, on_done: F)
+    where
+        F: FnOnce(Result<()>) + Send + 'static,
+    {
+        let mut state = self.state.lock().unwrap();
+        if state.outstanding > 0 {
+            state.on_done = Some(Box::new(on_done));
+            return;
+        }
+        let result = match state.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        };
+        drop(state);
+        on_done(result);
+    }
+
+    /// Blocks until everything started so far has finished.
+    pub fn wait(&self) -> Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        self.on_done(move |result| {
+            let _ = sender.send(result);
+        });
+        match receiver.recv() {
+            Ok(result) => result,
+            Err(e) => bail!("Transfers dropped before finishing: {:?}", e),
+        }
+    }
+
+    fn begin(&self) -> Result<()> {
+        match self.state.lock() {
+            Ok(mut state) => {
+                state.outstanding += 1;
+                Ok(())
+            }
+            Err(e) => bail!("Lock is poisoned: {:?}", e),
+        }
+    }
+}
+
+fn finish(state: &Arc<Mutex<GroupState>>, result: Result<()>, name: &str) {
+    let mut state = match state.lock() {
+        Ok(s) => s,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    if let Err(e) = result {
+        tracing::error!("{} failed: {:?}", name, e);
+        if state.error.is_none() {
+            state.error = Some(failure::format_err!("{} failed: {:?}", name, e));
+        }
+    }
+    state.outstanding -= 1;
+    // with nobody waiting yet, `on_done` picks the result up when it's registered
+    if state.outstanding > 0 || state.on_done.is_none() {
+        return;
+    }
+    let on_done = state.on_done.take();
+    let result = match state.error.take() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    };
+    drop(state);
+    if let Some(on_done) = on_done {
+        on_done(result);
+    }
+}
+
+#[cfg(target_os = "linux")]
+use reactor::Worker;
+
+#[cfg(not(target_os = "linux"))]
+use blocking::Worker;
+
+#[cfg(target_os = "linux")]
+mod reactor {
+    use super::{Done, Endpoint, Input, Job, Timer, Transfer};
+    use crate::graph::progress::Meter;
+    use crate::graph::splice::{appends, kind, Kind, Pipe};
+    use crate::graph::Result;
+    use failure::bail;
+    use nix::errno::Errno;
+    use nix::fcntl::{fcntl, splice, FcntlArg, OFlag, SpliceFFlags};
+    use nix::sys::epoll::{
+        epoll_create1, epoll_ctl, epoll_wait, EpollCreateFlags, EpollEvent, EpollFlags, EpollOp,
+    };
+    use nix::sys::sendfile::sendfile;
+    use nix::unistd::{close, pipe2, read, write};
+    use std::collections::{HashMap, VecDeque};
+    use std::os::unix::io::RawFd;
+    use std::sync::{mpsc, Mutex};
+    use std::thread;
+    use std::time::Instant;
+    use tracing::error;
+
+    /// Event data for the pipe that wakes a worker up to take new transfers.
+    const WAKE: u64 = u64::MAX;
+    const MAX_EVENTS: usize = 256;
+    /// Most bytes to move per call: the default capacity of a pipe.
+    const CHUNK_SIZE: usize = 1 << 16;
+    /// Bytes one transfer moves before the others on the same thread get a turn.
+    const BUDGET: usize = 1 << 20;
+
+    pub struct Worker {
+        queue: Mutex<mpsc::Sender<Job>>,
+        /// Write end of the wake-up pipe; closing it stops the thread once it's idle.
+        wake: RawFd,
+    }
+
+    impl Worker {
+        pub fn spawn(idx: usize) -> Result<Self> {
+            let (wake_read, wake_write) = pipe2(OFlag::O_NONBLOCK | OFlag::O_CLOEXEC)?;
+            let epoll = epoll_create1(EpollCreateFlags::EPOLL_CLOEXEC)?;
+            let mut event = EpollEvent::new(EpollFlags::EPOLLIN | EpollFlags::EPOLLET, WAKE);
+            epoll_ctl(epoll, EpollOp::EpollCtlAdd, wake_read, &mut event)?;
+            let (sender, receiver) = mpsc::channel();
+            thread::Builder::new()
+                .name(format!("event-loop-{}", idx))
+                .spawn(move || {
+                    let mut reactor = Reactor {
+                        epoll: epoll,
+                        wake: wake_read,
+                        queue: receiver,
+                        active: HashMap::default(),
+                        ready: VecDeque::new(),
+                        timers: Vec::new(),
+                        next_id: 0,
+                    };
+                    if let Err(e) = reactor.run() {
+                        error!("Event loop thread {} failed: {:?}", idx, e);
+                    }
+                    let _ = close(wake_read);
+                    let _ = close(epoll);
+                })?;
+            Ok(Worker {
+                queue: Mutex::new(sender),
+                wake: wake_write,
+            })
+        }
+
+        pub fn send(&self, job: Job) -> Result<()> {
+            match self.queue.lock() {
+                Ok(queue) => {
+                    if queue.send(job).is_err() {
+                        bail!("Event loop thread has exited");
+                    }
+                }
+                Err(e) => bail!("Lock is poisoned: {:?}", e),
+            }
+            // a full pipe means the thread has a wake-up pending already
+            match write(self.wake, &[1]) {
+                Ok(_) | Err(nix::Error::Sys(Errno::EAGAIN)) => Ok(()),
+                Err(e) => bail!("Failed to wake event loop thread: {:?}", e),
+            }
+        }
+    }
+
+    impl Drop for Worker {
+        fn drop(&mut self) {
+            let _ = close(self.wake);
+        }
+    }
+
+    struct Reactor {
+        epoll: RawFd,
+        wake: RawFd,
+        queue: mpsc::Receiver<Job>,
+        active: HashMap<u64, Active>,
+        /// Transfers to advance before waiting again: those that just arrived or used up their
+        /// budget.
+        ready: VecDeque<u64>,
+        /// Timers yet to fire, with when they're due.
+        timers: Vec<(Instant, Timer)>,
+        next_id: u64,
+    }
+
+    impl Reactor {
+        fn run(&mut self) -> Result<()> {
+            let mut events = vec![EpollEvent::empty(); MAX_EVENTS];
+            let mut closed = false;
+            loop {
+                if closed && self.active.is_empty() {
+                    return Ok(());
+                }
+                let timeout = match (self.ready.is_empty(), self.next_due()) {
+                    (false, _) => 0,
+                    (true, Some(due)) => {
+                        let wait = due.saturating_duration_since(Instant::now());
+                        // round up, so the timer is due once epoll gives up waiting
+                        ((wait.as_micros() + 999) / 1000) as isize
+                    }
+                    (true, None) => -1,
+                };
+                let n = match epoll_wait(self.epoll, &mut events, timeout) {
+                    Ok(n) => n,
+                    Err(nix::Error::Sys(Errno::EINTR)) => continue,
+                    Err(e) => bail!("epoll_wait failed: {:?}", e),
+                };
+                for event in events[..n].iter() {
+                    if event.data() == WAKE {
+                        if self.drain_wake()? {
+                            closed = true;
+                            let _ = epoll_ctl(self.epoll, EpollOp::EpollCtlDel, self.wake, None);
+                        }
+                        while let Ok(job) = self.queue.try_recv() {
+                            match job {
+                                Job::Transfer(transfer) => self.start(transfer),
+                                Job::Timer(due, timer) => self.timers.push((due, timer)),
+                            }
+                        }
+                    } else {
+                        self.ready.push_back(event.data());
+                    }
+                }
+                let ready: Vec<u64> = self.ready.drain(..).collect();
+                for id in ready {
+                    let progress = match self.active.get_mut(&id) {
+                        Some(active) => active.advance(self.epoll, id),
+                        None => continue,
+                    };
+                    match progress {
+                        Progress::Waiting => {}
+                        Progress::Yield => self.ready.push_back(id),
+                        Progress::Done(result) => {
+                            let active = self.active.remove(&id).unwrap();
+                            active.complete(self.epoll, result);
+                        }
+                    }
+                }
+                self.fire_timers();
+            }
+        }
+
+        fn next_due(&self) -> Option<Instant> {
+            self.timers.iter().map(|(due, _)| *due).min()
+        }
+
+        fn fire_timers(&mut self) {
+            let now = Instant::now();
+            let (due, waiting): (Vec<(Instant, Timer)>, Vec<(Instant, Timer)>) =
+                self.timers.drain(..).partition(|(due, _)| *due <= now);
+            self.timers = waiting;
+            for (_, timer) in due.into_iter() {
+                timer();
+            }
+        }
+
+        /// Empties the wake-up pipe; returns whether its write end was closed.
+        fn drain_wake(&self) -> Result<bool> {
+            let mut buf = [0u8; 64];
+            loop {
+                match read(self.wake, &mut buf) {
+                    Ok(0) => return Ok(true),
+                    Ok(_) => {}
+                    Err(nix::Error::Sys(Errno::EAGAIN)) => return Ok(false),
+                    Err(nix::Error::Sys(Errno::EINTR)) => {}
+                    Err(e) => bail!("Failed to read wake-up pipe: {:?}", e),
+                }
+            }
+        }
+
+        fn start(&mut self, transfer: Transfer) {
+            let id = self.next_id;
+            self.next_id += 1;
+            let mut active = Active::new(transfer);
+            match active.open(self.epoll, id) {
+                Ok(_) => {
+                    self.active.insert(id, active);
+                    self.ready.push_back(id);
+                }
+                Err(e) => active.complete(self.epoll, Err(e)),
+            }
+        }
+    }
+
+    enum Progress {
+        /// Blocked until epoll reports one of the ends ready.
+        Waiting,
+        /// Used up its budget with more to move.
+        Yield,
+        Done(Result<u64>),
+    }
+
+    /// How bytes get from the current input to the output.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    enum Mode {
+        /// sendfile out of a regular file.
+        Sendfile,
+        /// splice straight across, when one of the ends is a pipe.
+        Splice,
+        /// splice through a pipe of our own.
+        Piped,
+        /// read into a buffer and write it out, for ends the kernel can't splice between.
+        Buffered,
+    }
+
+    struct Active {
+        name: String,
+        inputs: VecDeque<Input>,
+        input: Option<Box<dyn Endpoint>>,
+        /// Meter for the edge the current input comes in on.
+        meter: Meter,
+        output: Box<dyn Endpoint>,
+        output_kind: Kind,
+        output_appends: bool,
+        mode: Mode,
+        pipe: Option<Pipe>,
+        /// Bytes sitting in our pipe that still have to go to the output.
+        piped: usize,
+        buf: Vec<u8>,
+        start: usize,
+        end: usize,
+        total: u64,
+        done: Option<Done>,
+    }
+
+    impl Active {
+        fn new(transfer: Transfer) -> Self {
+            Active {
+                name: transfer.name,
+                inputs: transfer.inputs,
+                input: None,
+                meter: Meter::default(),
+                output: transfer.output,
+                output_kind: Kind::Other,
+                output_appends: false,
+                mode: Mode::Buffered,
+                pipe: None,
+                piped: 0,
+                buf: Vec::new(),
+                start: 0,
+                end: 0,
+                total: 0,
+                done: Some(transfer.done),
+            }
+        }
+
+        fn open(&mut self, epoll: RawFd, id: u64) -> Result<()> {
+            let fd = self.output.as_raw_fd();
+            self.output_kind = kind(fd)?;
+            self.output_appends = self.output_kind == Kind::File && appends(fd)?;
+            watch(epoll, fd, self.output_kind, EpollFlags::EPOLLOUT, id)
+        }
+
+        fn advance(&mut self, epoll: RawFd, id: u64) -> Progress {
+            match self.step(epoll, id) {
+                Ok(progress) => progress,
+                Err(e) => Progress::Done(Err(e)),
+            }
+        }
+
+        fn step(&mut self, epoll: RawFd, id: u64) -> Result<Progress> {
+            let out_fd = self.output.as_raw_fd();
+            let mut moved: usize = 0;
+            loop {
+                if moved >= BUDGET {
+                    return Ok(Progress::Yield);
+                }
+                // hand over whatever was already pulled in before pulling more
+                if self.piped > 0 {
+                    let read_end = self.pipe.as_ref().unwrap().read_end;
+                    match splice_nonblocking(read_end, out_fd, self.piped) {
+                        Ok(n) => {
+                            self.piped -= n;
+                            self.total += n as u64;
+                            moved += n;
+                        }
+                        Err(nix::Error::Sys(Errno::EINTR)) => {}
+                        Err(nix::Error::Sys(Errno::EAGAIN)) => return Ok(Progress::Waiting),
+                        Err(e) if hung_up(e) => return Ok(Progress::Done(Ok(self.total))),
+                        Err(nix::Error::Sys(Errno::EINVAL)) => self.unpipe(read_end)?,
+                        Err(e) => bail!("{}: splice failed: {:?}", self.name, e),
+                    }
+                    continue;
+                }
+                if self.start < self.end {
+                    match write(out_fd, &self.buf[self.start..self.end]) {
+                        Ok(n) => {
+                            self.start += n;
+                            self.total += n as u64;
+                            moved += n;
+                        }
+                        Err(nix::Error::Sys(Errno::EINTR)) => {}
+                        Err(nix::Error::Sys(Errno::EAGAIN)) => return Ok(Progress::Waiting),
+                        Err(e) if hung_up(e) => return Ok(Progress::Done(Ok(self.total))),
+                        Err(e) => bail!("{}: write failed: {:?}", self.name, e),
+                    }
+                    continue;
+                }
+                let in_fd = match &self.input {
+                    Some(input) => input.as_raw_fd(),
+                    None => {
+                        if self.next_input(epoll, id)? {
+                            continue;
+                        }
+                        return Ok(Progress::Done(Ok(self.total)));
+                    }
+                };
+                let pulled = match self.mode {
+                    Mode::Sendfile => sendfile(out_fd, in_fd, None, CHUNK_SIZE),
+                    Mode::Splice => splice_nonblocking(in_fd, out_fd, CHUNK_SIZE),
+                    Mode::Piped => {
+                        let write_end = self.pipe.as_ref().unwrap().write_end;
+                        splice_nonblocking(in_fd, write_end, CHUNK_SIZE)
+                    }
+                    Mode::Buffered => {
+                        self.buf.resize(CHUNK_SIZE, 0);
+                        read(in_fd, &mut self.buf)
+                    }
+                };
+                match pulled {
+                    Ok(0) => self.end_input(epoll),
+                    Ok(n) => match self.mode {
+                        Mode::Sendfile | Mode::Splice => {
+                            self.total += n as u64;
+                            moved += n;
+                        }
+                        Mode::Piped => self.piped = n,
+                        Mode::Buffered => {
+                            self.meter.record(&self.buf[..n]);
+                            self.start = 0;
+                            self.end = n;
+                        }
+                    },
+                    Err(nix::Error::Sys(Errno::EINTR)) => {}
+                    Err(nix::Error::Sys(Errno::EAGAIN)) => return Ok(Progress::Waiting),
+                    Err(nix::Error::Sys(Errno::EPIPE)) => {
+                        return Ok(Progress::Done(Ok(self.total)));
+                    }
+                    // a connection that dropped on the reading side ends that input
+                    Err(e) if hung_up(e) => match self.mode {
+                        Mode::Piped | Mode::Buffered => self.end_input(epoll),
+                        _ => return Ok(Progress::Done(Ok(self.total))),
+                    },
+                    // nothing moved, so fall back to copying through a buffer
+                    Err(nix::Error::Sys(Errno::EINVAL)) | Err(nix::Error::Sys(Errno::ENOSYS))
+                        if self.mode != Mode::Buffered =>
+                    {
+                        self.mode = Mode::Buffered;
+                    }
+                    Err(e) => bail!("{}: {:?} failed: {:?}", self.name, self.mode, e),
+                }
+            }
+        }
+
+        /// Moves on to the next input; returns false once there are none left.
+        fn next_input(&mut self, epoll: RawFd, id: u64) -> Result<bool> {
+            let (input, meter) = match self.inputs.pop_front() {
+                Some(input) => input,
+                None => return Ok(false),
+            };
+            let fd = input.as_raw_fd();
+            let input_kind = kind(fd)?;
+            watch(
+                epoll,
+                fd,
+                input_kind,
+                EpollFlags::EPOLLIN | EpollFlags::EPOLLRDHUP,
+                id,
+            )?;
+            self.mode = if input_kind == Kind::Other
+                || self.output_kind == Kind::Other
+                || self.output_appends
+                || meter.is_on()
+            {
+                Mode::Buffered
+            } else if input_kind == Kind::File {
+                Mode::Sendfile
+            } else if input_kind == Kind::Pipe || self.output_kind == Kind::Pipe {
+                Mode::Splice
+            } else {
+                Mode::Piped
+            };
+            if self.mode == Mode::Piped && self.pipe.is_none() {
+                let (read_end, write_end) = pipe2(OFlag::O_NONBLOCK | OFlag::O_CLOEXEC)?;
+                self.pipe = Some(Pipe {
+                    read_end: read_end,
+                    write_end: write_end,
+                });
+            }
+            self.input = Some(input);
+            self.meter = meter;
+            Ok(true)
+        }
+
+        fn end_input(&mut self, epoll: RawFd) {
+            if let Some(input) = self.input.take() {
+                let _ = epoll_ctl(epoll, EpollOp::EpollCtlDel, input.as_raw_fd(), None);
+            }
+        }
+
+        /// The output won't take spliced data: move what's in our pipe into the buffer, and
+        /// copy through the buffer from here on.
+        fn unpipe(&mut self, read_end: RawFd) -> Result<()> {
+            self.buf.resize(CHUNK_SIZE.max(self.piped), 0);
+            let mut filled = 0;
+            while filled < self.piped {
+                match read(read_end, &mut self.buf[filled..self.piped]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(nix::Error::Sys(Errno::EINTR)) => {}
+                    Err(e) => bail!("{}: failed to empty pipe: {:?}", self.name, e),
+                }
+            }
+            self.start = 0;
+            self.end = filled;
+            self.piped = 0;
+            self.mode = Mode::Buffered;
+            Ok(())
+        }
+
+        /// Stops watching and closes both ends before reporting the result.
+        fn complete(mut self, epoll: RawFd, result: Result<u64>) {
+            self.end_input(epoll);
+            let _ = epoll_ctl(epoll, EpollOp::EpollCtlDel, self.output.as_raw_fd(), None);
+            let done = self.done.take().unwrap();
+            drop(self);
+            done(result);
+        }
+    }
+
+    /// Makes the descriptor non-blocking and has epoll report it (edge-triggered) as part of
+    /// transfer `id`. Regular files are always ready, so they're left alone.
+    fn watch(epoll: RawFd, fd: RawFd, fd_kind: Kind, events: EpollFlags, id: u64) -> Result<()> {
+        if fd_kind == Kind::File {
+            return Ok(());
+        }
+        let flags = OFlag::from_bits_truncate(fcntl(fd, FcntlArg::F_GETFL)?);
+        fcntl(fd, FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK))?;
+        let mut event = EpollEvent::new(events | EpollFlags::EPOLLET, id);
+        match epoll_ctl(epoll, EpollOp::EpollCtlAdd, fd, &mut event) {
+            Ok(_) | Err(nix::Error::Sys(Errno::EPERM)) => Ok(()),
+            Err(e) => bail!("Failed to watch {:?}: {:?}", fd, e),
+        }
+    }
+
+    fn splice_nonblocking(in_fd: RawFd, out_fd: RawFd, len: usize) -> nix::Result<usize> {
+        splice(
+            in_fd,
+            None,
+            out_fd,
+            None,
+            len,
+            SpliceFFlags::SPLICE_F_MOVE | SpliceFFlags::SPLICE_F_NONBLOCK,
+        )
+    }
+
+    fn hung_up(e: nix::Error) -> bool {
+        matches!(
+            e,
+            nix::Error::Sys(Errno::EPIPE)
+                | nix::Error::Sys(Errno::ECONNRESET)
+                | nix::Error::Sys(Errno::ECONNABORTED)
+        )
+    }
+
+    impl Drop for Active {
+        fn drop(&mut self) {
+            // the endpoints close themselves; make sure nothing is left unreported
+            if let Some(done) = self.done.take() {
+                done(Err(failure::format_err!("{} was dropped", self.name)));
+            }
+        }
+    }
+}
+
+/// Without epoll, each transfer blocks on a thread of its own.
+#[cfg(not(target_os = "linux"))]
+mod blocking {
+    use super::{Job, Transfer};
+    use crate::graph::progress::Meter;
+    use crate::graph::Result;
+    use failure::bail;
+    use nix::errno::Errno;
+    use nix::fcntl::{fcntl, FcntlArg, OFlag};
+    use nix::unistd::{read, write};
+    use std::os::unix::io::{AsRawFd, RawFd};
+    use std::thread;
+    use std::time::Instant;
+
+    pub struct Worker;
+
+    impl Worker {
+        pub fn spawn(_idx: usize) -> Result<Self> {
+            Ok(Worker)
+        }
+
+        pub fn send(&self, job: Job) -> Result<()> {
+            let transfer = match job {
+                Job::Transfer(transfer) => transfer,
+                Job::Timer(due, timer) => {
+                    thread::Builder::new()
+                        .name("timer".to_string())
+                        .spawn(move || {
+                            thread::sleep(due.saturating_duration_since(Instant::now()));
+                            timer();
+                        })?;
+                    return Ok(());
+                }
+            };
+            thread::Builder::new()
+                .name(transfer.name.clone())
+                .spawn(move || {
+                    let Transfer {
+                        name: _,
+                        inputs,
+                        output,
+                        done,
+                    } = transfer;
+                    let mut total: u64 = 0;
+                    let mut result: Result<()> = Ok(());
+                    for (input, meter) in inputs.iter() {
+                        match copy(input.as_raw_fd(), output.as_raw_fd(), meter) {
+                            Ok(n) => total += n,
+                            Err(e) => {
+                                result = Err(e);
+                                break;
+                            }
+                        }
+                    }
+                    drop(inputs);
+                    drop(output);
+                    done(result.map(|_| total));
+                })?;
+            Ok(())
+        }
+    }
+
+    fn copy(in_fd: RawFd, out_fd: RawFd, meter: &Meter) -> Result<u64> {
+        for fd in [in_fd, out_fd].iter() {
+            let flags = OFlag::from_bits_truncate(fcntl(*fd, FcntlArg::F_GETFL)?);
+            fcntl(*fd, FcntlArg::F_SETFL(flags & !OFlag::O_NONBLOCK))?;
+        }
+        let mut buf = [0u8; 1 << 16];
+        let mut total: u64 = 0;
+        loop {
+            let n = match read(in_fd, &mut buf) {
+                Ok(0) => return Ok(total),
+                Ok(n) => n,
+                Err(nix::Error::Sys(Errno::EINTR)) => continue,
+                Err(nix::Error::Sys(Errno::ECONNRESET)) => return Ok(total),
+                Err(e) => bail!("{:?}", e),
+            };
+            meter.record(&buf[..n]);
+            let mut written = 0;
+            while written < n {
+                match write(out_fd, &buf[written..n]) {
+                    Ok(w) => written += w,
+                    Err(nix::Error::Sys(Errno::EINTR)) => {}
+                    Err(nix::Error::Sys(Errno::EPIPE)) => return Ok(total),
+                    Err(e) => bail!("{:?}", e),
+                }
+            }
+            total += n as u64;
+        }
+    }
+}
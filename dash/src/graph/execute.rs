@@ -1,3 +1,4 @@
+use super::eventloop::Transfers;
 use super::pipe::SharedChannelMap;
 use super::stream::{SharedPipeMap, SharedStreamMap};
 use super::Result;
@@ -14,12 +15,14 @@ pub trait Execute {
     ) -> Result<()>;
 
     /// Redirects input and output of node to the correct places based on where the stdin, stdout
-    /// and stderr go to.
+    /// and stderr go to. Doesn't block: the copies are started in transfers, which reports once
+    /// they're done.
     fn redirect(
         &mut self,
         pipes: SharedPipeMap,
         network_connections: SharedStreamMap,
         channels: SharedChannelMap,
         tmp_folder: PathBuf,
+        transfers: &Transfers,
     ) -> Result<()>;
 }
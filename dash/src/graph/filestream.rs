@@ -69,6 +69,10 @@ impl FifoStream {
     pub fn get_location(&self) -> Location {
         self.dest_location.clone()
     }
+
+    pub fn get_path(&self) -> PathBuf {
+        self.path.clone()
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Hash, Eq, Copy)]
@@ -85,7 +89,7 @@ impl Default for FifoMode {
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Hash, Eq, Copy)]
 pub enum FileMode {
-    /// Create the file and write to it.
+    /// Create the file, replacing what was in it, and write to it.
     CREATE,
     /// Just read permissions.
     READ,
@@ -115,7 +119,11 @@ impl FileStream {
     pub fn open(&self) -> Result<File> {
         let mut open_options = OpenOptions::new();
         match self.mode {
-            FileMode::CREATE => open_options.write(true).create(true).read(true),
+            FileMode::CREATE => open_options
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .read(true),
             FileMode::READ => open_options.read(true),
             FileMode::APPEND => open_options.write(true).append(true).read(true),
             FileMode::REGULAR => open_options.read(true).write(true).create(true),
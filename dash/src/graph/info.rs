@@ -16,7 +16,8 @@ pub trait Info {
 
     fn get_stdin(&self) -> Vec<DashStream>;
 
-    fn get_stdout(&self) -> Option<DashStream>;
+    /// Streams stdout goes to; more than one when the output fans out to several nodes.
+    fn get_stdout(&self) -> Vec<DashStream>;
 
     fn get_stdout_id(&self) -> Option<NodeId>;
 
@@ -30,8 +31,12 @@ pub trait Info {
 
     fn add_stdin(&mut self, stream: DashStream) -> Result<()>;
 
+    /// Sends stdout to stream alone, replacing any streams it went to before.
     fn set_stdout(&mut self, stream: DashStream) -> Result<()>;
 
+    /// Sends stdout to stream as well as the streams it already goes to.
+    fn add_stdout(&mut self, stream: DashStream) -> Result<()>;
+
     fn set_stderr(&mut self, stream: DashStream) -> Result<()>;
 
     fn get_dot_label(&self) -> Result<String>;
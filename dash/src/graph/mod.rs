@@ -1,15 +1,20 @@
 use super::util::Result;
 pub mod cmd;
 pub mod command;
+pub mod compression;
+pub mod eventloop;
 pub mod execute;
 pub mod filestream;
 pub mod info;
 pub mod pipe;
 pub mod program;
+pub mod progress;
 pub mod rapper;
 pub mod read;
 pub mod read2;
+pub mod splice;
 pub mod stream;
+pub mod tmpdir;
 pub mod write;
 pub mod write2;
 use failure::bail;
@@ -19,9 +24,8 @@ use std::hash::Hash;
 use std::mem::drop;
 use std::sync::{Arc, Mutex};
 
-// TODO: the current design allows nodes to have multiple stdout or stderr handles,
-// but the way the code is written, it would panic if any node ever had more than 1 stderr or
-// stdout handle, because the first copy would only copy into the first output stream.
+// Command nodes can send stdout to several nodes, which each get a copy, but nodes have at most
+// one stderr handle.
 
 /// Represents where a computation should take place, or where a stream leads to.
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Hash, Eq)]
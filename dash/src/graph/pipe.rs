@@ -2,45 +2,121 @@ use super::program::NodeId;
 use super::stream::IOType;
 use super::Result;
 use super::SharedMap;
-use crossbeam::channel::{bounded, select, Receiver, Sender};
 use failure::bail;
+use std::cmp::min;
+use std::collections::VecDeque;
 use std::fs::{remove_file, File, OpenOptions};
-use std::io::{Read, Write};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::os::unix::fs::FileExt;
 use std::path::{Path, PathBuf};
-use std::process::Stdio;
-use tracing::error;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use tracing::debug;
+
+/// Bytes each buffered stream keeps in memory before spilling to disk.
+pub const DEFAULT_MEMORY_LIMIT: usize = 8 << 20;
+
+/// How much a program may hold for streams whose reader hasn't caught up yet, e.g. the later
+/// stdin streams of a node that reads its inputs in order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BufferLimits {
+    /// Bytes each stream holds in memory before spilling into the tmp folder.
+    pub memory: usize,
+    /// Bytes all of a program's streams may spill into the tmp folder together, or None for no
+    /// cap. Once it's used up, writers wait for readers to free some, which in turn stops the
+    /// processes and connections feeding them.
+    pub disk: Option<u64>,
+}
+
+impl Default for BufferLimits {
+    fn default() -> Self {
+        BufferLimits {
+            memory: DEFAULT_MEMORY_LIMIT,
+            disk: None,
+        }
+    }
+}
 
 pub enum ChannelEnd {
-    Sender(Sender<u32>),
-    Receiver(Receiver<u32>),
+    Sender(Buffer),
+    Receiver(Buffer),
 }
 
-pub type SharedChannelMap = SharedMap<String, ChannelEnd>;
+/// Ends of the buffered pipes in a program, which all spill into the same disk quota.
+pub struct SharedChannelMap {
+    ends: SharedMap<String, ChannelEnd>,
+    quota: Arc<DiskQuota>,
+    memory_limit: usize,
+}
+
+impl Default for SharedChannelMap {
+    fn default() -> Self {
+        SharedChannelMap::with_limits(BufferLimits::default())
+    }
+}
+
+/// Returns a new reference to the same map
+impl Clone for SharedChannelMap {
+    fn clone(&self) -> Self {
+        SharedChannelMap {
+            ends: self.ends.clone(),
+            quota: self.quota.clone(),
+            memory_limit: self.memory_limit,
+        }
+    }
+}
+
+impl SharedChannelMap {
+    pub fn new() -> Self {
+        SharedChannelMap::default()
+    }
+
+    pub fn with_limits(limits: BufferLimits) -> Self {
+        SharedChannelMap {
+            ends: SharedMap::new(),
+            quota: Arc::new(DiskQuota::new(limits.disk)),
+            memory_limit: limits.memory,
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<ChannelEnd> {
+        self.ends.remove(&name.to_string())
+    }
+}
 
+/// Creates the buffer between the write and read ends of a bufferable stream from left to
+/// right, and inserts both ends into the map.
 pub fn create_and_insert_channels(
-    node_id: NodeId,
+    left: NodeId,
+    right: NodeId,
     iotype: IOType,
+    tmp: &Path,
     shared_map: &mut SharedChannelMap,
 ) -> Result<()> {
-    let (s, r) = get_channel_ends();
-    shared_map.insert(get_channel_name(node_id, PipeMode::Write, iotype), s)?;
-    shared_map.insert(get_channel_name(node_id, PipeMode::Read, iotype), r)?;
+    let buffer = Buffer::new(
+        buffer_name(tmp, left, right, iotype),
+        shared_map.memory_limit,
+        shared_map.quota.clone(),
+    );
+    shared_map.ends.insert(
+        get_channel_name(left, right, PipeMode::Write, iotype),
+        ChannelEnd::Sender(buffer.clone()),
+    )?;
+    shared_map.ends.insert(
+        get_channel_name(left, right, PipeMode::Read, iotype),
+        ChannelEnd::Receiver(buffer),
+    )?;
     Ok(())
 }
 
-fn get_channel_ends() -> (ChannelEnd, ChannelEnd) {
-    let (s, r) = bounded(1);
-    (ChannelEnd::Sender(s), ChannelEnd::Receiver(r))
-}
-
-/// Unique naming scheme for pipe channels.
-pub fn get_channel_name(node_id: NodeId, mode: PipeMode, iotype: IOType) -> String {
-    format!("{}_{:?}_{:?}", node_id, iotype, mode)
+/// Unique naming scheme for pipe channels: one per stream, so a node whose output fans out has
+/// a channel for each node it goes to.
+pub fn get_channel_name(left: NodeId, right: NodeId, mode: PipeMode, iotype: IOType) -> String {
+    format!("{}_{}_{:?}_{:?}", left, right, iotype, mode)
 }
 
-fn buffer_name(tmp: &Path, id: NodeId, iotype: IOType) -> PathBuf {
+fn buffer_name(tmp: &Path, left: NodeId, right: NodeId, iotype: IOType) -> PathBuf {
     let mut ret = tmp.to_path_buf();
-    ret.push(&format!("{:?}_{:?}", id, iotype));
+    ret.push(&format!("{:?}_{:?}_{:?}", left, right, iotype));
     ret
 }
 
@@ -49,190 +125,409 @@ pub enum PipeMode {
     Write,
     Read,
 }
-pub struct BufferedPipe {
-    /// Filepath
-    filepath: PathBuf,
-    /// Is this the write or the read end of the pipe?
-    mode: PipeMode,
-    /// Handle to the filepath
-    handle: File,
-    /// Channel end
-    channel: ChannelEnd,
-    /// finished writing?
-    finished_writing: bool,
+
+/// Bytes a program's buffers have spilled to disk, shared by all of them.
+struct DiskQuota {
+    limit: Option<u64>,
+    state: Mutex<QuotaState>,
 }
 
-/// Creates a new FIFO with read, write permissions for the owner.
-pub fn create_buffer_file(tmp: &Path, id: NodeId, iotype: IOType) -> Result<()> {
-    let mut open_options = OpenOptions::new();
-    open_options.write(true).create(true).read(true);
-    open_options.open(buffer_name(tmp, id, iotype))?;
-    Ok(())
+#[derive(Default)]
+struct QuotaState {
+    used: u64,
+    /// Buffers whose writers may be waiting for room.
+    buffers: Vec<Weak<Shared>>,
 }
 
-impl BufferedPipe {
-    pub fn new(
-        id: NodeId,
-        iotype: IOType,
-        tmp: &Path,
-        mode: PipeMode,
-        channel: ChannelEnd,
-    ) -> Result<Self> {
-        let handle = match mode {
-            PipeMode::Write => {
-                match channel {
-                    ChannelEnd::Receiver(_) => {
-                        bail!("Cannot pass in Write Mode with a Receiver");
+impl DiskQuota {
+    fn new(limit: Option<u64>) -> Self {
+        DiskQuota {
+            limit: limit,
+            state: Mutex::new(QuotaState::default()),
+        }
+    }
+
+    fn register(&self, buffer: &Arc<Shared>) {
+        let mut state = self.state.lock().unwrap();
+        state.buffers.retain(|b| b.strong_count() > 0);
+        state.buffers.push(Arc::downgrade(buffer));
+    }
+
+    /// Takes up to wanted bytes of the quota and returns how many it got.
+    fn reserve(&self, wanted: u64) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let granted = match self.limit {
+            Some(limit) => min(wanted, limit.saturating_sub(state.used)),
+            None => wanted,
+        };
+        state.used += granted;
+        granted
+    }
+
+    /// Gives bytes back to the quota and wakes any writer waiting for room.
+    /// Must be called without holding any buffer's lock.
+    fn release(&self, len: u64) {
+        if len == 0 {
+            return;
+        }
+        let buffers: Vec<Arc<Shared>> = {
+            let mut state = self.state.lock().unwrap();
+            state.used -= len;
+            if self.limit.is_none() {
+                return;
+            }
+            state.buffers.iter().filter_map(|b| b.upgrade()).collect()
+        };
+        for buffer in buffers.iter() {
+            // taking the lock means a writer that just found no room is already waiting
+            let _state = buffer.state.lock();
+            buffer.changed.notify_all();
+        }
+    }
+}
+
+/// Data written into a buffered stream that its reader hasn't read yet: first whatever fits in
+/// memory, then everything after it in a spill file.
+struct Shared {
+    path: PathBuf,
+    memory_limit: usize,
+    quota: Arc<DiskQuota>,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    memory: VecDeque<Vec<u8>>,
+    in_memory: usize,
+    /// How much of the front chunk was already read.
+    front_offset: usize,
+    spill: Option<File>,
+    /// Bytes in the spill file, all taken from the quota.
+    spilled: u64,
+    /// How much of the spill file was already read.
+    read_offset: u64,
+    write_done: bool,
+    writer_gone: bool,
+    reader_gone: bool,
+}
+
+/// The buffer shared by the two ends of a bufferable stream.
+#[derive(Clone)]
+pub struct Buffer(Arc<Shared>);
+
+impl Buffer {
+    fn new(path: PathBuf, memory_limit: usize, quota: Arc<DiskQuota>) -> Self {
+        let shared = Arc::new(Shared {
+            path: path,
+            memory_limit: memory_limit,
+            quota: quota,
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+        });
+        shared.quota.register(&shared);
+        Buffer(shared)
+    }
+
+    fn lock(&self) -> std::io::Result<MutexGuard<'_, State>> {
+        match self.0.state.lock() {
+            Ok(state) => Ok(state),
+            Err(e) => Err(Error::other(format!("Lock is poisoned: {:?}", e))),
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let shared = &self.0;
+        let mut state = self.lock()?;
+        loop {
+            if state.reader_gone {
+                return Err(Error::new(
+                    ErrorKind::BrokenPipe,
+                    "Reader of the buffered pipe went away",
+                ));
+            }
+            // memory only takes data while the spill file is empty, so it's all read first;
+            // an empty buffer always takes one write so tiny limits still make progress
+            if state.read_offset == state.spilled {
+                let room = match state.in_memory {
+                    0 => buf.len(),
+                    _ => shared.memory_limit.saturating_sub(state.in_memory),
+                };
+                if room > 0 {
+                    let len = min(room, buf.len());
+                    state.memory.push_back(buf[..len].to_vec());
+                    state.in_memory += len;
+                    shared.changed.notify_all();
+                    return Ok(len);
+                }
+            }
+            let granted = shared.quota.reserve(buf.len() as u64);
+            if granted > 0 {
+                if let Err(e) = spill(&shared.path, &mut state, &buf[..granted as usize]) {
+                    drop(state);
+                    shared.quota.release(granted);
+                    return Err(e);
+                }
+                state.spilled += granted;
+                shared.changed.notify_all();
+                return Ok(granted as usize);
+            }
+            state = match shared.changed.wait(state) {
+                Ok(s) => s,
+                Err(e) => return Err(Error::other(format!("Lock is poisoned: {:?}", e))),
+            };
+        }
+    }
+
+    fn read(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let shared = &self.0;
+        let mut state = self.lock()?;
+        loop {
+            if !state.memory.is_empty() {
+                let offset = state.front_offset;
+                let front_len = state.memory[0].len();
+                let len = min(buf.len(), front_len - offset);
+                buf[..len].copy_from_slice(&state.memory[0][offset..offset + len]);
+                if offset + len == front_len {
+                    state.memory.pop_front();
+                    state.front_offset = 0;
+                } else {
+                    state.front_offset += len;
+                }
+                state.in_memory -= len;
+                shared.changed.notify_all();
+                return Ok(len);
+            }
+            if state.read_offset < state.spilled {
+                let len = min(buf.len() as u64, state.spilled - state.read_offset) as usize;
+                let read = match &state.spill {
+                    Some(file) => file.read_at(&mut buf[..len], state.read_offset)?,
+                    None => 0,
+                };
+                if read == 0 {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        format!("Spill file {:?} is shorter than expected", shared.path),
+                    ));
+                }
+                state.read_offset += read as u64;
+                if state.read_offset == state.spilled {
+                    // caught up: start the spill file over and give its space back
+                    let freed = state.spilled;
+                    if let Some(file) = &state.spill {
+                        file.set_len(0)?;
                     }
-                    _ => {}
+                    state.spilled = 0;
+                    state.read_offset = 0;
+                    shared.changed.notify_all();
+                    drop(state);
+                    shared.quota.release(freed);
                 }
-                OpenOptions::new()
-                    .write(true)
-                    .open(buffer_name(tmp, id, iotype))?
+                return Ok(read);
             }
-            PipeMode::Read => {
-                match channel {
-                    ChannelEnd::Sender(_) => {
-                        bail!("Cannot pass in Read Mode with a Sender");
+            if state.write_done {
+                return Ok(0);
+            }
+            if state.writer_gone {
+                return Err(Error::other(
+                    "Writer of the buffered pipe went away before finishing",
+                ));
+            }
+            state = match shared.changed.wait(state) {
+                Ok(s) => s,
+                Err(e) => return Err(Error::other(format!("Lock is poisoned: {:?}", e))),
+            };
+        }
+    }
+
+    fn close(&self, mode: PipeMode, write_done: bool) {
+        let shared = &self.0;
+        let freed = {
+            let mut state = match shared.state.lock() {
+                Ok(s) => s,
+                Err(_) => return,
+            };
+            shared.changed.notify_all();
+            match mode {
+                PipeMode::Write => {
+                    state.write_done |= write_done;
+                    state.writer_gone = true;
+                    return;
+                }
+                PipeMode::Read => {
+                    state.reader_gone = true;
+                    state.memory.clear();
+                    state.in_memory = 0;
+                    if state.spill.take().is_some() {
+                        let _ = remove_file(shared.path.as_path());
                     }
-                    _ => {}
+                    let freed = state.spilled;
+                    state.spilled = 0;
+                    state.read_offset = 0;
+                    freed
                 }
-                OpenOptions::new()
-                    .read(true)
-                    .open(buffer_name(tmp, id, iotype))?
             }
         };
-        Ok(BufferedPipe {
-            filepath: buffer_name(tmp, id, iotype),
-            mode: mode,
-            handle: handle,
-            channel: channel,
-            finished_writing: false,
-        })
+        shared.quota.release(freed);
     }
+}
 
-    pub fn get_handle(&self) -> Result<File> {
-        let handle = match self.mode {
-            PipeMode::Write => OpenOptions::new()
+/// Appends data to the spill file, creating it on the first spill.
+fn spill(path: &Path, state: &mut State, data: &[u8]) -> std::io::Result<()> {
+    if state.spill.is_none() {
+        debug!("Spilling buffered pipe into {:?}", path);
+        state.spill = Some(
+            OpenOptions::new()
+                .read(true)
                 .write(true)
                 .create(true)
-                .open(self.filepath.as_path()),
-            PipeMode::Read => OpenOptions::new().read(true).open(self.filepath.as_path()),
+                .truncate(true)
+                .open(path)?,
+        );
+    }
+    match &state.spill {
+        Some(file) => file.write_all_at(data, state.spilled),
+        None => unreachable!(),
+    }
+}
+
+pub struct BufferedPipe {
+    /// Is this the write or the read end of the pipe?
+    mode: PipeMode,
+    /// Buffer shared with the other end
+    buffer: Buffer,
+    /// finished writing?
+    finished_writing: bool,
+}
+
+impl BufferedPipe {
+    pub fn new(mode: PipeMode, channel: ChannelEnd) -> Result<Self> {
+        let buffer = match (mode, channel) {
+            (PipeMode::Write, ChannelEnd::Sender(buffer)) => buffer,
+            (PipeMode::Read, ChannelEnd::Receiver(buffer)) => buffer,
+            (PipeMode::Write, ChannelEnd::Receiver(_)) => {
+                bail!("Cannot pass in Write Mode with a Receiver");
+            }
+            (PipeMode::Read, ChannelEnd::Sender(_)) => {
+                bail!("Cannot pass in Read Mode with a Sender");
+            }
         };
-        match handle {
-            Ok(h) => Ok(h),
-            Err(e) => bail!("{:?}", e),
-        }
+        Ok(BufferedPipe {
+            mode: mode,
+            buffer: buffer,
+            finished_writing: false,
+        })
     }
 
-    /// On finishing the copy from process into the file, need to "set write done" on the buffered
-    /// pipe so the receiving thread will know the write is done.
+    /// On finishing the copy from process into the buffer, need to "set write done" on the
+    /// buffered pipe so the reader knows there's nothing more coming.
     pub fn set_write_done(&mut self) -> Result<()> {
-        match &mut self.channel {
-            ChannelEnd::Sender(ref mut sender) => {
-               sender.send(1)?;
-               Ok(())
+        match self.mode {
+            PipeMode::Write => {
+                self.finished_writing = true;
+                self.buffer.close(PipeMode::Write, true);
+                Ok(())
             }
-            ChannelEnd::Receiver(_) => {
+            PipeMode::Read => {
                 bail!("Cannot call set_write_done with a receiver channel; should only have a writer channel")
             }
         }
     }
 
+    /// Removes the spill file, if the buffer needed one.
     pub fn drop_file(&mut self) -> Result<()> {
-        remove_file(self.filepath.as_path())?;
-        Ok(())
+        match remove_file(self.buffer.0.path.as_path()) {
+            Ok(_) => Ok(()),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => bail!("Could not remove {:?}: {:?}", self.buffer.0.path, e),
+        }
     }
 }
 
 impl Write for BufferedPipe {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.handle.write(buf)
+        self.buffer.write(buf)
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
-        self.handle.flush()
+        Ok(())
     }
 }
 
 impl Read for BufferedPipe {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let normal_read = |handle: &mut File, buffer: &mut [u8]| -> std::io::Result<usize> {
-            match handle.read(buffer) {
-                Ok(size) => {
-                    return Ok(size);
-                }
-                Err(e) => {
-                    println!("Error reading: {:?}", e);
-                    return Err(e);
+        self.buffer.read(buf)
+    }
+}
+
+impl Drop for BufferedPipe {
+    /// Lets the other end know this one is gone: a reader fails if the writer never finished,
+    /// and a writer fails instead of waiting on a reader that went away.
+    fn drop(&mut self) {
+        match self.mode {
+            PipeMode::Write => {
+                if !self.finished_writing {
+                    self.buffer.close(PipeMode::Write, false);
                 }
             }
-        };
-        // if already finished writing, do read as normal
-        if self.finished_writing {
-            return normal_read(&mut self.handle, buf);
-        }
-        let receiver = match &mut self.channel {
-            ChannelEnd::Receiver(ref mut r) => r,
-            _ => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Channel must be a receiver",
-                ));
-            }
-        };
-        loop {
-            select! {
-                recv(receiver) -> msg => {
-                    match msg {
-                        Ok(num) => {
-                            assert_eq!(num, 1);
-                            self.finished_writing = true;
-                            // now do a normal read
-                            return normal_read(&mut self.handle, buf);
-                        }
-                        Err(e) => {
-                            return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Didn't receive msg on the channel: {:?}", e)));
-                        }
-                    }
+            PipeMode::Read => self.buffer.close(PipeMode::Read, false),
+        }
+    }
+}
+
+/// Write end of a stream that fans out: everything written goes into the buffered pipe of each
+/// of its readers. Readers that leave early are dropped, and writes only fail once all of them
+/// have left.
+pub struct Tee {
+    outputs: Vec<BufferedPipe>,
+}
+
+impl Tee {
+    pub fn new(outputs: Vec<BufferedPipe>) -> Self {
+        Tee { outputs: outputs }
+    }
+
+    /// Lets the readers that are still there know nothing more is coming.
+    pub fn set_write_done(&mut self) -> Result<()> {
+        for output in self.outputs.iter_mut() {
+            output.set_write_done()?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut error: Option<Error> = None;
+        self.outputs
+            .retain_mut(|output| match output.write_all(buf) {
+                Ok(_) => true,
+                Err(ref e) if e.kind() == ErrorKind::BrokenPipe => {
+                    debug!("Reader of a fanned out stream left early");
+                    false
                 }
-                default => {
-                    // try reading from the file
-                    match self.handle.read(buf) {
-                        Ok(size) => {
-                            if size == 0 {
-                                if self.finished_writing {
-                                    return Ok(0);
-                                } else {
-                                    continue;
-                                }
-                            } else {
-                                return Ok(size);
-                            }
-                        }
-                        Err(e) => {
-                            return Err(e);
-                        }
-                    }
+                Err(e) => {
+                    error = Some(e);
+                    true
                 }
-            }
+            });
+        match error {
+            Some(e) => Err(e),
+            None if self.outputs.is_empty() => Err(Error::new(
+                ErrorKind::BrokenPipe,
+                "every reader of the stream is gone",
+            )),
+            None => Ok(buf.len()),
         }
     }
-}
 
-/// Turns BufferedPipe object into an Stdio.
-/// Allows commands to *directly* write output into the BufferedPipe object without an extra copy.
-/// TODO: we might not be able to use this because we need some way of knowing that the thread is
-/// done.
-impl From<BufferedPipe> for Stdio {
-    fn from(buffered_pipe: BufferedPipe) -> Stdio {
-        let handle = match buffered_pipe.get_handle() {
-            Ok(h) => h,
-            Err(e) => {
-                error!("Could not get a file handled for buffered pipe: {:?}", e);
-                panic!("Could not get a file handled for buffered pipe: {:?}", e);
-            }
-        };
-        Stdio::from(handle)
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
     }
 }
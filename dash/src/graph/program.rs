@@ -1,13 +1,16 @@
 use super::command as cmd;
+use super::eventloop::{EventLoop, Transfers};
 use super::execute::Execute;
 use super::info::Info;
-use super::pipe::SharedChannelMap;
+use super::pipe::{BufferLimits, SharedChannelMap};
+use super::progress::Progress;
 use super::rapper::Rapper;
 use super::read2 as read;
+use super::tmpdir::{self, TmpDir};
 use super::write2 as write;
 use super::{filestream, stream, Location, Result};
 use failure::bail;
-use filestream::{FifoMode, FifoStream, FileStream};
+use filestream::{FifoMode, FifoStream, FileMode, FileStream};
 use serde::{Deserialize, Serialize};
 use std::collections::hash_map;
 use std::collections::HashMap;
@@ -17,9 +20,8 @@ use std::fs::File;
 use std::path::Path;
 use std::path::PathBuf;
 use std::slice;
-use std::thread;
+use std::sync::mpsc;
 use stream::{DashStream, IOType, NetStream, PipeStream, SharedPipeMap, SharedStreamMap};
-use thread::{spawn, JoinHandle};
 pub type NodeId = u32;
 pub type ProgId = u32;
 use std::io::Write;
@@ -71,16 +73,17 @@ impl Execute for Elem {
         network_connections: SharedStreamMap,
         channels: SharedChannelMap,
         tmp_folder: PathBuf,
+        transfers: &Transfers,
     ) -> Result<()> {
         match self {
             Elem::Write(write_node) => {
-                write_node.redirect(pipes, network_connections, channels, tmp_folder)
+                write_node.redirect(pipes, network_connections, channels, tmp_folder, transfers)
             }
             Elem::Cmd(command_node) => {
-                command_node.redirect(pipes, network_connections, channels, tmp_folder)
+                command_node.redirect(pipes, network_connections, channels, tmp_folder, transfers)
             }
             Elem::Read(read_node) => {
-                read_node.redirect(pipes, network_connections, channels, tmp_folder)
+                read_node.redirect(pipes, network_connections, channels, tmp_folder, transfers)
             }
         }
     }
@@ -187,19 +190,9 @@ impl Rapper for Elem {
 
     fn get_stdout(&self) -> Vec<DashStream> {
         match self {
-            Elem::Write(write_node) => match write_node.get_stdout() {
-                Some(stream) => vec![stream],
-                None => vec![],
-            },
-            Elem::Read(read_node) => match read_node.get_stdout() {
-                Some(stream) => vec![stream],
-                None => vec![],
-            },
-
-            Elem::Cmd(cmd_node) => match cmd_node.get_stdout() {
-                Some(stream) => vec![stream],
-                None => vec![],
-            },
+            Elem::Write(write_node) => write_node.get_stdout(),
+            Elem::Read(read_node) => read_node.get_stdout(),
+            Elem::Cmd(cmd_node) => cmd_node.get_stdout(),
         }
     }
 
@@ -231,9 +224,9 @@ impl Rapper for Elem {
 
     fn add_stdout(&mut self, stream: DashStream) -> Result<()> {
         match self {
-            Elem::Write(write_node) => write_node.set_stdout(stream),
-            Elem::Read(read_node) => read_node.set_stdout(stream),
-            Elem::Cmd(cmd_node) => cmd_node.set_stdout(stream),
+            Elem::Write(write_node) => write_node.add_stdout(stream),
+            Elem::Read(read_node) => read_node.add_stdout(stream),
+            Elem::Cmd(cmd_node) => cmd_node.add_stdout(stream),
         }
     }
 
@@ -417,9 +410,10 @@ impl Node {
         network_connections: SharedStreamMap,
         channels: SharedChannelMap,
         tmp_folder: PathBuf,
+        transfers: &Transfers,
     ) -> Result<()> {
         self.elem
-            .redirect(pipes, network_connections, channels, tmp_folder)
+            .redirect(pipes, network_connections, channels, tmp_folder, transfers)
     }
 
     pub fn spawn(
@@ -470,6 +464,18 @@ impl Node {
     pub fn get_stdout_id(&self) -> Option<NodeId> {
         self.elem.get_stdout_id()
     }
+
+    /// Ids of every node this node's stdout goes to.
+    pub fn get_stdout_ids(&self) -> Vec<NodeId> {
+        self.get_stdout()
+            .iter()
+            .filter_map(|stream| match stream {
+                DashStream::Pipe(ps) => Some(ps.get_right()),
+                DashStream::Tcp(ns) => Some(ns.get_right()),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 /// One sided edges in the program graph
@@ -513,6 +519,15 @@ pub struct Program {
     counter: u32,
     sink_nodes: Vec<NodeId>,
     source_nodes: Vec<NodeId>,
+    /// Files staged in before commands run: the left node writes a copy of a file that the
+    /// right node's command reads, so must finish before the command is spawned.
+    staging: Vec<Link>,
+    /// Client session the program belongs to, which namespaces its tmp files with its id.
+    session: String,
+    /// Tmp files the program creates, like fifos and staged copies, and where.
+    tmp_files: Vec<(Location, PathBuf)>,
+    /// Whether the machines running the program report how much moves along each edge.
+    progress: bool,
 }
 
 impl Default for Program {
@@ -525,6 +540,10 @@ impl Default for Program {
             counter: 0,
             sink_nodes: vec![],
             source_nodes: vec![],
+            staging: vec![],
+            session: String::from("local"),
+            tmp_files: vec![],
+            progress: false,
         }
     }
 }
@@ -546,6 +565,66 @@ impl Program {
         self.id = id;
     }
 
+    pub fn get_session(&self) -> String {
+        self.session.clone()
+    }
+
+    pub fn set_session(&mut self, session: &str) {
+        self.session = session.to_string();
+    }
+
+    /// Folder, relative to a tmp folder, that holds this program's tmp files.
+    pub fn get_tmp_namespace(&self) -> PathBuf {
+        tmpdir::namespace(&self.session, self.id)
+    }
+
+    pub fn get_tmp_files(&self) -> &Vec<(Location, PathBuf)> {
+        &self.tmp_files
+    }
+
+    pub fn get_progress(&self) -> bool {
+        self.progress
+    }
+
+    /// Has the program report how much moves along each edge while it runs. Every pipe goes
+    /// through a buffer, so nothing moves between two nodes without being counted.
+    pub fn set_progress(&mut self) -> Result<()> {
+        for (_id, node) in self.nodes.iter_mut() {
+            match node.get_mut_elem() {
+                Elem::Cmd(ref mut cmdnode) => {
+                    for stream in cmdnode.get_stdin_iter_mut() {
+                        if let DashStream::Pipe(pipestream) = stream {
+                            pipestream.set_bufferable();
+                        }
+                    }
+                    for stream in cmdnode.get_stdout_iter_mut() {
+                        if let DashStream::Pipe(pipestream) = stream {
+                            pipestream.set_bufferable();
+                        }
+                    }
+                    if let Some(DashStream::Pipe(mut pipestream)) = cmdnode.get_stderr() {
+                        pipestream.set_bufferable();
+                        cmdnode.set_stderr(DashStream::Pipe(pipestream))?;
+                    }
+                }
+                Elem::Write(ref mut writenode) => {
+                    for stream in writenode.get_stdin_iter_mut() {
+                        if let DashStream::Pipe(pipestream) = stream {
+                            pipestream.set_bufferable();
+                        }
+                    }
+                }
+                Elem::Read(ref mut readnode) => {
+                    if let DashStream::Pipe(pipestream) = readnode.get_stdout_mut() {
+                        pipestream.set_bufferable();
+                    }
+                }
+            }
+        }
+        self.progress = true;
+        Ok(())
+    }
+
     pub fn write_dot(&self, filename: &str) -> Result<()> {
         let mut file = File::create(filename)?;
         file.write_all(b"digraph {\n")?;
@@ -702,6 +781,10 @@ impl Program {
         self.edges.iter()
     }
 
+    pub fn get_staging_iter(&self) -> slice::Iter<Link> {
+        self.staging.iter()
+    }
+
     pub fn contains(&self, id: NodeId) -> bool {
         self.nodes.contains_key(&id)
     }
@@ -908,6 +991,75 @@ impl Program {
         Ok(new_node_ids)
     }
 
+    /// Inserts a command node on a node's stdout pipe, so the node's output passes through the
+    /// new node before reaching the node it used to go to. Returns the new node's id.
+    pub fn insert_after(&mut self, id: NodeId, elem: Elem) -> Result<NodeId> {
+        let node = match self.get_node(id) {
+            Some(n) => n,
+            None => bail!(
+                "Could not find node id: {:?}, where we are calling insert_after",
+                id
+            ),
+        };
+        match elem {
+            Elem::Cmd(_) => {}
+            _ => bail!("Can only insert command nodes after another node"),
+        }
+        let right = match node.get_stdout().iter().find_map(|stream| match stream {
+            DashStream::Pipe(pipestream) => Some(pipestream.get_right()),
+            _ => None,
+        }) {
+            Some(right) => right,
+            None => bail!("Node {:?} has no stdout pipe to insert a node on", id),
+        };
+        let new_id = self.add_elem(elem);
+        let old_edge = Link {
+            left: id,
+            right: right,
+        };
+        // the old destination now reads from the new node, which reads from the node
+        {
+            let right_node = match self.nodes.get_mut(&right) {
+                Some(n) => n,
+                None => bail!("Pipestream has right node that doesn't exist"),
+            };
+            right_node.replace_stream_edges(
+                old_edge.clone(),
+                vec![Link {
+                    left: new_id,
+                    right: right,
+                }],
+            )?;
+        }
+        {
+            let left_node = self.nodes.get_mut(&id).unwrap();
+            left_node.replace_stream_edges(
+                old_edge.clone(),
+                vec![Link {
+                    left: id,
+                    right: new_id,
+                }],
+            )?;
+        }
+        {
+            let new_node = self.nodes.get_mut(&new_id).unwrap();
+            new_node.add_stdin(DashStream::Pipe(PipeStream::new(
+                id,
+                new_id,
+                IOType::Stdout,
+            )?))?;
+            new_node.add_stdout(DashStream::Pipe(PipeStream::new(
+                new_id,
+                right,
+                IOType::Stdout,
+            )?))?;
+        }
+        self.edges.retain(|edge| *edge != old_edge);
+        self.add_unique_edge(id, new_id);
+        self.add_unique_edge(new_id, right);
+        Ok(new_id)
+    }
+
     pub fn remove_node(&mut self, id: NodeId) -> Result<()> {
         match self.nodes.remove(&id) {
             Some(_) => {}
@@ -917,6 +1069,8 @@ impl Program {
         }
         self.edges
             .retain(|x| x.get_left() != id && x.get_right() != id);
+        self.staging
+            .retain(|x| x.get_left() != id && x.get_right() != id);
         if self.sink_nodes.contains(&id) {
             self.sink_nodes.retain(|x| *x != id);
         }
@@ -1080,15 +1234,14 @@ impl Program {
                     // NOOP (no pipes coming out of writenodes)
                 }
                 Elem::Cmd(ref mut cmdnode) => {
-                    match cmdnode.get_stdout_mut() {
-                        Some(ref mut dashstream) => match dashstream {
+                    for dashstream in cmdnode.get_stdout_iter_mut() {
+                        match dashstream {
                             DashStream::Pipe(ref mut pipestream) => {
                                 pipestream.set_left(*left);
                                 pipestream.set_right(*right);
                             }
                             _ => {}
-                        },
-                        None => {}
+                        }
                     }
 
                     match cmdnode.get_stderr_mut() {
@@ -1214,7 +1367,8 @@ impl Program {
         Ok(())
     }
 
-    /// Inserts a remote read operation into the program.
+    /// Inserts a remote read operation into the program: the file is streamed from its origin
+    /// into a fifo at the access location, for a command there to read.
     pub fn add_remote_fifo_read(
         &mut self,
         origin_loc: &Location,
@@ -1222,42 +1376,121 @@ impl Program {
         origin_filestream: &FileStream,
         fifo_location: &FifoStream,
     ) -> Result<()> {
-        // add in a read node and write node
         let mut readnode = read::ReadNode::default();
         readnode.add_stdin(DashStream::File(origin_filestream.clone()))?;
-        let readnode_id = self.add_elem(Elem::Read(readnode));
+        readnode.set_loc(origin_loc.clone());
         let mut writenode = write::WriteNode::default();
         let mut new_fifo = fifo_location.clone();
         new_fifo.set_mode(FifoMode::WRITE);
-        writenode.set_stdout(DashStream::Fifo(fifo_location.clone()))?;
-        let writenode_id = self.add_elem(Elem::Write(writenode));
+        writenode.set_stdout(DashStream::Fifo(new_fifo))?;
+        writenode.set_loc(access_loc.clone());
+        self.add_remote_copy(readnode, writenode)?;
+        self.tmp_files
+            .push((access_loc.clone(), fifo_location.get_path()));
+        Ok(())
+    }
 
-        // add edge in between them
-        self.add_unique_edge(readnode_id, writenode_id);
-        let netstream = NetStream::new(
-            readnode_id,
-            writenode_id,
-            IOType::Stdout,
-            origin_loc.clone(),
+    /// Inserts a remote write operation into the program: a command at the access location
+    /// writes into a fifo there, which is streamed back to the file at its origin. The file is
+    /// appended to or replaced according to its mode.
+    pub fn add_remote_fifo_write(
+        &mut self,
+        origin_loc: &Location,
+        access_loc: &Location,
+        origin_filestream: &FileStream,
+        fifo_location: &FifoStream,
+    ) -> Result<()> {
+        let mut readnode = read::ReadNode::default();
+        readnode.add_stdin(DashStream::File(FileStream::new_with_mode(
+            fifo_location.get_path(),
+            FileMode::READ,
             access_loc.clone(),
-        )?;
+        )))?;
+        readnode.set_input_fifo(true);
+        readnode.set_loc(access_loc.clone());
+        let mut writenode = write::WriteNode::default();
+        writenode.set_stdout(DashStream::File(origin_filestream.clone()))?;
+        writenode.set_loc(origin_loc.clone());
+        self.add_remote_copy(readnode, writenode)?;
+        self.tmp_files
+            .push((access_loc.clone(), fifo_location.get_path()));
+        Ok(())
+    }
 
-        // set the connection between two nodes
-        let read = self.nodes.get_mut(&readnode_id).unwrap();
-        {
-            let read_elem = read.get_mut_elem();
-            read_elem.add_stdout(DashStream::Tcp(netstream.clone()))?;
+    /// Places the read node for an output process substitution next to the command writing
+    /// into its fifo, which becomes a tmp file there.
+    pub fn set_output_fifo(&mut self, id: NodeId, fifo: &FileStream) -> Result<()> {
+        let node = match self.nodes.get_mut(&id) {
+            Some(node) => node,
+            None => bail!("Node {:?} for output fifo {:?} not in program", id, fifo),
+        };
+        match node.get_mut_elem() {
+            Elem::Read(ref mut readnode) if readnode.is_input_fifo() => {
+                readnode.add_stdin(DashStream::File(FileStream::new_with_mode(
+                    fifo.get_path(),
+                    FileMode::READ,
+                    fifo.get_location(),
+                )))?;
+                readnode.set_loc(fifo.get_location());
+            }
+            _ => bail!("Node {:?} doesn't read from an output fifo", id),
         }
+        self.tmp_files.push((fifo.get_location(), fifo.get_path()));
+        Ok(())
+    }
 
-        let write = self.nodes.get_mut(&writenode_id).unwrap();
-        {
-            let write_elem = write.get_mut_elem();
-            write_elem.add_stdin(DashStream::Tcp(netstream.clone()))?;
+    /// Inserts a stage-in operation into the program: the file is copied from its origin into a
+    /// tmp file at the access location, which is finished before command `id` is spawned and
+    /// removed once the program is done. For commands that can't read their input from a fifo,
+    /// like ones that seek within it.
+    pub fn add_remote_stage_in(
+        &mut self,
+        origin_loc: &Location,
+        access_loc: &Location,
+        origin_filestream: &FileStream,
+        tmp_filestream: &FileStream,
+        id: NodeId,
+    ) -> Result<()> {
+        if !self.nodes.contains_key(&id) {
+            bail!("Staging a file for node {:?} not in program", id);
         }
-
+        let mut readnode = read::ReadNode::default();
+        readnode.add_stdin(DashStream::File(origin_filestream.clone()))?;
+        readnode.set_loc(origin_loc.clone());
+        let mut writenode = write::WriteNode::default();
+        let mut tmp = tmp_filestream.clone();
+        tmp.set_mode(FileMode::CREATE);
+        writenode.set_stdout(DashStream::File(tmp))?;
+        writenode.set_loc(access_loc.clone());
+        let writenode_id = self.add_remote_copy(readnode, writenode)?;
+        self.staging.push(Link::new(writenode_id, id));
+        self.tmp_files
+            .push((access_loc.clone(), tmp_filestream.get_path()));
         Ok(())
     }
 
+    /// Adds a read node that copies into a write node at another location, returning the write
+    /// node's id. The pipe between them becomes a network stream in `make_pipes_networked`.
+    fn add_remote_copy(
+        &mut self,
+        readnode: read::ReadNode,
+        writenode: write::WriteNode,
+    ) -> Result<NodeId> {
+        let readnode_id = self.add_elem(Elem::Read(readnode));
+        let writenode_id = self.add_elem(Elem::Write(writenode));
+        self.add_unique_edge(readnode_id, writenode_id);
+        let pipe = PipeStream::new(readnode_id, writenode_id, IOType::Stdout)?;
+        self.nodes
+            .get_mut(&readnode_id)
+            .unwrap()
+            .add_stdout(DashStream::Pipe(pipe.clone()))?;
+        self.nodes
+            .get_mut(&writenode_id)
+            .unwrap()
+            .add_stdin(DashStream::Pipe(pipe))?;
+        Ok(writenode_id)
+    }
+
     /// Iterates through all the edges in the program,
     /// and if any two nodes connected by an edge are not at the same location,
     /// makes the corresponding pipe a TCP stream.
@@ -1332,33 +1565,30 @@ impl Program {
     }
 
     // Finds source->sink paths, ignoring paths that involve output for stderr.
-    // RIGHT NOW, this works because we have the guarantee that each node has at most 2 outward
-    // edges, 1 for stdout and 1 for stderr.
-    // Need to redo this if we ever have nodes that broadcast output to multiple nodes.
+    // A node whose stdout fans out to several nodes is on a separate path for each of them.
     pub fn get_stdout_forward_paths(&self) -> Vec<Vec<NodeId>> {
         let mut ret: Vec<Vec<NodeId>> = Vec::new();
         for node_id in self.source_nodes.iter() {
-            let mut path: Vec<NodeId> = Vec::new();
-            let mut found_sink = false;
-            let mut current_node = *node_id;
-            path.push(current_node);
-            while !found_sink {
+            let mut unfinished: Vec<Vec<NodeId>> = vec![vec![*node_id]];
+            while let Some(mut path) = unfinished.pop() {
+                let current_node = *path.last().unwrap();
                 let node = self.nodes.get(&current_node).unwrap();
-                match node.get_stdout_id() {
-                    Some(n) => {
-                        current_node = n;
-                    }
-                    None => {
-                        found_sink = true;
-                    }
+                let next_nodes = node.get_stdout_ids();
+                if next_nodes.is_empty() {
+                    path.push(current_node);
+                    ret.push(path);
+                    continue;
                 }
-                path.push(current_node);
-
-                if self.sink_nodes.contains(&current_node) {
-                    found_sink = true;
+                for next_node in next_nodes.into_iter() {
+                    let mut next_path = path.clone();
+                    next_path.push(next_node);
+                    if self.sink_nodes.contains(&next_node) {
+                        ret.push(next_path);
+                    } else {
+                        unfinished.push(next_path);
+                    }
                 }
             }
-            ret.push(path);
         }
         ret
     }
@@ -1385,12 +1615,30 @@ impl Program {
         }
 
         // add all the relevant edges to each subprogram
-        for (_, prog) in map.iter_mut() {
+        for (location, prog) in map.iter_mut() {
+            prog.id = self.id;
+            prog.session = self.session.clone();
+            prog.progress = self.progress;
+            prog.tmp_files = self
+                .tmp_files
+                .iter()
+                .filter(|(loc, _)| loc == location)
+                .cloned()
+                .collect();
             let nodes = prog.get_nodes();
             let edges = self.find_contained_edges(nodes);
             for edge in edges.iter() {
                 prog.add_unique_edge(edge.get_left(), edge.get_right());
             }
+            prog.staging = self
+                .staging
+                .iter()
+                .filter(|link| {
+                    prog.nodes.contains_key(&link.get_left())
+                        && prog.nodes.contains_key(&link.get_right())
+                })
+                .cloned()
+                .collect();
         }
 
         Ok(map)
@@ -1533,7 +1781,7 @@ impl Program {
         let node = self.nodes.get(&node_id).unwrap();
         match node.get_elem() {
             Elem::Cmd(cmdnode) => {
-                if let Some(stdout) = cmdnode.get_stdout() {
+                for stdout in cmdnode.get_stdout() {
                     match stdout {
                         DashStream::Pipe(ps) => {
                             ret.push((IOType::Stdout, Link::new(ps.get_left(), ps.get_right())));
@@ -1561,7 +1809,7 @@ impl Program {
                 }
             }
             Elem::Read(readnode) => {
-                if let Some(stdout) = readnode.get_stdout() {
+                for stdout in readnode.get_stdout() {
                     match stdout {
                         DashStream::Pipe(ps) => {
                             ret.push((IOType::Stdout, Link::new(ps.get_left(), ps.get_right())));
@@ -1648,84 +1896,208 @@ impl Program {
     /// execute.
     /// when executing the node. Note that if it's a client, folder should be none; no filepaths
     /// need to be resolved.
+    /// Blocks until the program is done, moving its data on this machine's global event loop.
     pub fn execute(&mut self, stream_map: SharedStreamMap, tmp_folder: String) -> Result<()> {
+        self.execute_with_progress(stream_map, tmp_folder, &Progress::default())
+    }
+
+    /// Like `execute`, counting what moves along each edge on progress.
+    pub fn execute_with_progress(
+        &mut self,
+        stream_map: SharedStreamMap,
+        tmp_folder: String,
+        progress: &Progress,
+    ) -> Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        self.start(
+            stream_map,
+            tmp_folder,
+            &EventLoop::global(),
+            BufferLimits::default(),
+            progress,
+            move |result| {
+                let _ = sender.send(result);
+            },
+        );
+        match receiver.recv() {
+            Ok(result) => result,
+            Err(e) => bail!("Program finished without reporting a result: {:?}", e),
+        }
+    }
+
+    /// Starts executing the program without waiting for it: spawns the processes and hands all
+    /// the copying between them to the event loop. Calls on_done once every node has finished,
+    /// or as soon as starting one fails.
+    /// Streams that have to wait for their reader are buffered within buffer_limits.
+    /// What moves along each edge is counted on progress.
+    pub fn start<F>(
+        &self,
+        stream_map: SharedStreamMap,
+        tmp_folder: String,
+        event_loop: &EventLoop,
+        buffer_limits: BufferLimits,
+        progress: &Progress,
+        on_done: F,
+    ) where
+        F: FnOnce(Result<()>) + Send + 'static,
+    {
+        // everything the program leaves in the tmp folder goes once it's done, however it ends
+        let tmp_dir = match self.create_tmp_dir(Path::new(&tmp_folder)) {
+            Ok(dir) => dir,
+            Err(e) => return on_done(Err(e)),
+        };
+        let tmp = tmp_dir.get_path().to_path_buf();
+        let on_done = move |result: Result<()>| {
+            let removed = tmp_dir.remove();
+            on_done(result.and(removed))
+        };
         let pipe_map = SharedPipeMap::new();
-        let channel_map = SharedChannelMap::new();
-        let execution_order = self.execution_order();
-        let mut node_threads: Vec<JoinHandle<Result<()>>> = Vec::new();
-        let mut node_thread_ids: Vec<NodeId> = Vec::new();
+        let channel_map = SharedChannelMap::with_limits(buffer_limits);
 
         // First, set the current dir if this program requires it.
         // theoretically should not break anything else, as stuff is being executed with full paths
         match self.get_current_dir() {
             Some(pathbuf) => {
                 tracing::debug!("Trying to set current dir on server: {:?}", pathbuf);
-                env::set_current_dir(pathbuf.as_path())?;
+                if let Err(e) = env::set_current_dir(pathbuf.as_path()) {
+                    return on_done(Err(e.into()));
+                }
             }
             None => {}
         }
-        // First execute any commands, e.g. spawn the initial processes
-        for node_id in execution_order.iter() {
-            let node = match self.nodes.get_mut(node_id) {
+        // Copy in any staged files before anything else, as commands need them complete.
+        let staged: Vec<NodeId> = self
+            .staging
+            .iter()
+            .map(|link| link.get_left())
+            .filter(|id| self.nodes.contains_key(id))
+            .collect();
+        let staging = Transfers::with_progress(event_loop, progress);
+        for node_id in staged.iter() {
+            let mut node_clone = self.nodes.get(node_id).unwrap().clone();
+            tracing::debug!("staging in file with node {:?}", node_id);
+            let started = node_clone
+                .spawn(
+                    pipe_map.clone(),
+                    stream_map.clone(),
+                    channel_map.clone(),
+                    tmp.clone(),
+                )
+                .and_then(|_| {
+                    node_clone.run_redirection(
+                        pipe_map.clone(),
+                        stream_map.clone(),
+                        channel_map.clone(),
+                        tmp.clone(),
+                        &staging,
+                    )
+                });
+            if let Err(e) = started {
+                return on_done(Err(e));
+            }
+        }
+        let prog = self.clone();
+        let event_loop = event_loop.clone();
+        let progress = progress.clone();
+        staging.on_done(move |result| {
+            if let Err(e) = result {
+                return on_done(Err(e));
+            }
+            let transfers = Transfers::with_progress(&event_loop, &progress);
+            let launcher = transfers.clone();
+            let inline = staged.is_empty();
+            let launch = move || {
+                prog.spawn_nodes(&staged, pipe_map, stream_map, channel_map, tmp, &launcher)
+            };
+            // with nothing staged this is still the caller's thread; otherwise it's an event loop
+            // thread, which mustn't hold up its other transfers while the nodes start
+            let launched = match inline {
+                true => launch(),
+                false => transfers.spawn("spawn-nodes", launch),
+            };
+            if let Err(e) = launched {
+                return on_done(Err(e));
+            }
+            transfers.on_done(move |result| {
+                tracing::debug!("all transfers finished");
+                on_done(result)
+            });
+        });
+    }
+
+    /// Creates the program's tmp dir under tmp and lists the program's other tmp files in it.
+    fn create_tmp_dir(&self, tmp: &Path) -> Result<TmpDir> {
+        let part = self.nodes.keys().min().cloned().unwrap_or(0);
+        let tmp_dir = TmpDir::create(tmp, &self.session, self.id, part)?;
+        for (_, path) in self.tmp_files.iter() {
+            tmp_dir.register(path)?;
+        }
+        Ok(tmp_dir)
+    }
+
+    /// Spawns and redirects every node that isn't staging in a file.
+    fn spawn_nodes(
+        &self,
+        staged: &[NodeId],
+        pipe_map: SharedPipeMap,
+        stream_map: SharedStreamMap,
+        channel_map: SharedChannelMap,
+        tmp: PathBuf,
+        transfers: &Transfers,
+    ) -> Result<()> {
+        let execution_order: Vec<NodeId> = self
+            .execution_order()
+            .into_iter()
+            .filter(|id| !staged.contains(id))
+            .collect();
+
+        // First execute any commands, e.g. spawn the initial processes.
+        // Read and write nodes go first, so fifos they create exist before commands open them.
+        let mut spawn_order = execution_order.clone();
+        spawn_order.sort_by_key(|id| match self.nodes.get(id).map(|node| &node.elem) {
+            Some(Elem::Cmd(_)) => 1,
+            _ => 0,
+        });
+        for node_id in spawn_order.iter() {
+            let node = match self.nodes.get(node_id) {
                 Some(n) => n,
                 None => bail!(
                     "Execution order produced node_id {:?} not in node map",
                     node_id
                 ),
             };
-            let pipe_map_copy = pipe_map.clone();
-            let stream_map_copy = stream_map.clone();
             let mut node_clone = node.clone();
-            let tmp = Path::new(&tmp_folder).to_path_buf();
             // This call is non-blocking
-            node_clone.spawn(pipe_map_copy, stream_map_copy, channel_map.clone(), tmp)?;
+            node_clone.spawn(
+                pipe_map.clone(),
+                stream_map.clone(),
+                channel_map.clone(),
+                tmp.clone(),
+            )?;
             tracing::debug!("finished spawning: {:?}", node);
         }
 
-        // Next, loop over and run redirection commands
+        // Next, start the redirection for every node; the copies run on the event loop
         for node_id in execution_order.iter() {
-            let node = match self.nodes.get_mut(node_id) {
+            let node = match self.nodes.get(node_id) {
                 Some(n) => n,
                 None => bail!(
                     "Execution order produced node_id {:?} not in node_map",
                     node_id
                 ),
             };
-            let pipe_map_copy = pipe_map.clone();
-            let stream_map_copy = stream_map.clone();
-            let channels_clone = channel_map.clone();
             let mut node_clone = node.clone();
-            let tmp = Path::new(&tmp_folder).to_path_buf();
-            // This call is non-blocking
             tracing::debug!("about to run redirection for: {:?},", node_id);
-            node_threads.push(spawn(move || {
-                node_clone.run_redirection(pipe_map_copy, stream_map_copy, channels_clone, tmp)
-            }));
-            node_thread_ids.push(*node_id);
-        }
-
-        // Join all the threads to make sure it worked
-        let mut count: usize = 0;
-        for thread in node_threads {
-            match thread.join() {
-                Ok(res) => match res {
-                    Ok(_) => {}
-                    Err(e) => {
-                        tracing::error!("Error on thread {:?}", count);
-                        bail!(
-                            "Node failed to execute: {:?} id {:?}",
-                            e,
-                            node_thread_ids[count]
-                        );
-                    }
-                },
-                Err(e) => {
-                    bail!("Thread failed to join!: {:?}", e);
-                }
+            if let Err(e) = node_clone.run_redirection(
+                pipe_map.clone(),
+                stream_map.clone(),
+                channel_map.clone(),
+                tmp.clone(),
+                transfers,
+            ) {
+                bail!("Node failed to execute: {:?} id {:?}", e, node_id);
             }
-            count += 1;
         }
-        tracing::debug!("joined all the threads");
         Ok(())
     }
 
@@ -0,0 +1,242 @@
+use super::program::NodeId;
+use super::Result;
+use failure::bail;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often machines running a program that reports progress send what it has moved.
+pub const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+// Each edge is metered once, where its reading side takes the data in, so adding up the
+// reports from every machine running part of a program counts nothing twice.
+
+/// Bytes and lines moved along one edge, from node left into node right.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub struct EdgeProgress {
+    pub left: NodeId,
+    pub right: NodeId,
+    pub bytes: u64,
+    pub lines: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    bytes: AtomicU64,
+    lines: AtomicU64,
+}
+
+/// Counts what moves along one edge. The default meter is off and counts nothing.
+#[derive(Debug, Clone, Default)]
+pub struct Meter(Option<Arc<Counts>>);
+
+impl PartialEq for Meter {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl Meter {
+    pub fn is_on(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Counts data that went by.
+    pub fn record(&self, buf: &[u8]) {
+        if let Some(counts) = &self.0 {
+            let lines = buf.iter().filter(|b| **b == b'\n').count();
+            counts.bytes.fetch_add(buf.len() as u64, Ordering::Relaxed);
+            counts.lines.fetch_add(lines as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts bytes that went by without being seen, so their lines are unknown.
+    pub fn record_bytes(&self, bytes: u64) {
+        if let Some(counts) = &self.0 {
+            counts.bytes.fetch_add(bytes, Ordering::Relaxed);
+        }
+    }
+
+    /// Bytes and lines counted so far.
+    pub fn get(&self) -> (u64, u64) {
+        match &self.0 {
+            Some(counts) => (
+                counts.bytes.load(Ordering::Relaxed),
+                counts.lines.load(Ordering::Relaxed),
+            ),
+            None => (0, 0),
+        }
+    }
+
+    fn set(&self, bytes: u64, lines: u64) {
+        if let Some(counts) = &self.0 {
+            counts.bytes.store(bytes, Ordering::Relaxed);
+            counts.lines.store(lines, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Writer that counts everything written through it on a meter.
+pub struct Metered<'a, W: ?Sized> {
+    inner: &'a mut W,
+    meter: Meter,
+}
+
+impl<'a, W: Write + ?Sized> Metered<'a, W> {
+    pub fn new(inner: &'a mut W, meter: &Meter) -> Self {
+        Metered {
+            inner: inner,
+            meter: meter.clone(),
+        }
+    }
+}
+
+impl<'a, W: Write + ?Sized> Write for Metered<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.meter.record(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+type EdgeMeters = Arc<Mutex<HashMap<(NodeId, NodeId), Meter>>>;
+
+/// Meters for every edge of a running program. Programs that don't report progress get the
+/// default, which hands out meters that are off.
+#[derive(Clone, Default)]
+pub struct Progress(Option<EdgeMeters>);
+
+impl Progress {
+    pub fn new() -> Self {
+        Progress(Some(Arc::new(Mutex::new(HashMap::default()))))
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Meter for the edge from left into right, shared by everything that moves data along it.
+    pub fn meter(&self, left: NodeId, right: NodeId) -> Meter {
+        let edges = match &self.0 {
+            Some(edges) => edges,
+            None => return Meter::default(),
+        };
+        let mut edges = match edges.lock() {
+            Ok(e) => e,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        edges
+            .entry((left, right))
+            .or_insert_with(|| Meter(Some(Arc::new(Counts::default()))))
+            .clone()
+    }
+
+    /// Takes in the counts another machine reported for the edges it meters.
+    pub fn update(&self, reported: &[EdgeProgress]) {
+        for edge in reported.iter() {
+            self.meter(edge.left, edge.right)
+                .set(edge.bytes, edge.lines);
+        }
+    }
+
+    /// Counts for every edge so far, in edge order.
+    pub fn snapshot(&self) -> Result<Vec<EdgeProgress>> {
+        let edges = match &self.0 {
+            Some(edges) => edges,
+            None => return Ok(vec![]),
+        };
+        let edges = match edges.lock() {
+            Ok(e) => e,
+            Err(e) => bail!("Lock is poisoned: {:?}", e),
+        };
+        let mut ret: Vec<EdgeProgress> = edges
+            .iter()
+            .map(|((left, right), meter)| {
+                let (bytes, lines) = meter.get();
+                EdgeProgress {
+                    left: *left,
+                    right: *right,
+                    bytes: bytes,
+                    lines: lines,
+                }
+            })
+            .collect();
+        ret.sort_by_key(|edge| (edge.left, edge.right));
+        Ok(ret)
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.snapshot() {
+            Ok(edges) => f.debug_tuple("Progress").field(&edges).finish(),
+            Err(_) => f.write_str("Progress(poisoned)"),
+        }
+    }
+}
+
+/// How fast one edge moved data since the previous sample.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct EdgeRate {
+    pub edge: EdgeProgress,
+    pub bytes_per_sec: f64,
+}
+
+/// Rates of every edge at one point in a program's run.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Sample {
+    pub edges: Vec<EdgeRate>,
+    /// Node reading the slowest edge still moving data, if any is.
+    pub bottleneck: Option<NodeId>,
+}
+
+/// Turns successive snapshots of a program's progress into rates.
+#[derive(Default)]
+pub struct Throughput {
+    last: HashMap<(NodeId, NodeId), u64>,
+    last_at: Option<Instant>,
+}
+
+impl Throughput {
+    /// Rate of each edge since the previous sample. The bottleneck is the node reading the
+    /// slowest edge that moved anything since then: the nodes before it keep up with it, and
+    /// the nodes after it can go no faster. Ties go to the earlier edge.
+    pub fn sample(&mut self, edges: Vec<EdgeProgress>, at: Instant) -> Sample {
+        let secs = match self.last_at {
+            Some(last_at) => at.saturating_duration_since(last_at).as_secs_f64(),
+            None => 0.0,
+        };
+        let mut rates: Vec<EdgeRate> = Vec::new();
+        for edge in edges.into_iter() {
+            let before = self.last.insert((edge.left, edge.right), edge.bytes);
+            let moved = edge.bytes.saturating_sub(before.unwrap_or(0));
+            let bytes_per_sec = match secs > 0.0 {
+                true => moved as f64 / secs,
+                false => 0.0,
+            };
+            rates.push(EdgeRate {
+                edge: edge,
+                bytes_per_sec: bytes_per_sec,
+            });
+        }
+        self.last_at = Some(at);
+        let mut bottleneck: Option<&EdgeRate> = None;
+        for rate in rates.iter().filter(|rate| rate.bytes_per_sec > 0.0) {
+            if bottleneck.map_or(true, |slowest| rate.bytes_per_sec < slowest.bytes_per_sec) {
+                bottleneck = Some(rate);
+            }
+        }
+        Sample {
+            bottleneck: bottleneck.map(|rate| rate.edge.right),
+            edges: rates,
+        }
+    }
+}
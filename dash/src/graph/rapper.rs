@@ -1,4 +1,5 @@
 use super::program::{Link, NodeId};
+use super::progress::{Meter, Metered};
 use super::stream;
 use super::Location;
 use super::Result;
@@ -244,6 +245,9 @@ pub struct InputStreamMetadata {
     bytes_copied: HashMap<usize, u64>,
     /// finished_tmp
     finished_tmp: HashMap<usize, bool>,
+    /// meters for the edges the input streams come in on, to report their progress
+    #[serde(skip)]
+    meters: HashMap<usize, Meter>,
 }
 
 impl InputStreamMetadata {
@@ -270,6 +274,7 @@ impl InputStreamMetadata {
             filenames: filenames,
             bytes_copied: bytes_copied,
             finished_tmp: map,
+            meters: HashMap::default(),
         }
     }
 
@@ -313,6 +318,14 @@ impl InputStreamMetadata {
     pub fn increment_bytes(&mut self, idx: usize, bytes: u64) {
         let counter = self.bytes_copied.get_mut(&idx).unwrap();
         *counter += bytes;
+        if let Some(meter) = self.meters.get(&idx) {
+            meter.record_bytes(bytes);
+        }
+    }
+
+    /// Reports the bytes copied from input stream idx on meter.
+    pub fn set_meter(&mut self, idx: usize, meter: Meter) {
+        self.meters.insert(idx, meter);
     }
 
     pub fn finished(&self, id: usize) -> bool {
@@ -519,3 +532,16 @@ pub trait Rapper {
     // Assumes nodes don't broadcast to multiple nodes.
     fn get_stdout_id(&self) -> Option<NodeId>;
 }
+
+/// Like `copy_wrapper`, counting everything copied on meter.
+pub fn copy_metered<R: ?Sized, W: ?Sized>(
+    reader: &mut R,
+    writer: &mut W,
+    meter: &Meter,
+) -> Result<u64>
+where
+    R: Read,
+    W: Write,
+{
+    copy_wrapper(reader, &mut Metered::new(writer, meter))
+}
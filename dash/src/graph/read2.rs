@@ -1,12 +1,17 @@
+use super::compression::Compression;
+use super::eventloop::Transfers;
 use super::execute::Execute;
-use super::filestream::FileStream;
+use super::filestream::{FifoMode, FifoStream, FileStream};
 use super::info::Info;
-use super::pipe::SharedChannelMap;
+use super::pipe::{
+    create_and_insert_channels, get_channel_name, BufferedPipe, PipeMode, SharedChannelMap,
+};
 use super::rapper::copy_wrapper as copy;
 use super::rapper::stream_initiate_filter;
 use super::{program, stream, Location, Result};
 use failure::bail;
 use program::{Link, NodeId, ProgId};
+use std::fs::remove_file;
 use std::path::PathBuf;
 use stream::{DashStream, IOType, NetStream, PipeStream, SharedPipeMap, SharedStreamMap};
 use tracing::error;
@@ -24,6 +29,8 @@ pub struct ReadNode {
     stdout: DashStream,
     /// Execution location of read node.
     location: Location,
+    /// Is the input a fifo this node creates, for a command at its location to write into?
+    input_fifo: bool,
 }
 
 impl ReadNode {
@@ -42,6 +49,14 @@ impl ReadNode {
     pub fn get_input_location(&self) -> Result<Location> {
         Ok(self.input.get_location())
     }
+
+    pub fn set_input_fifo(&mut self, input_fifo: bool) {
+        self.input_fifo = input_fifo;
+    }
+
+    pub fn is_input_fifo(&self) -> bool {
+        self.input_fifo
+    }
 }
 
 impl Info for ReadNode {
@@ -65,8 +80,8 @@ impl Info for ReadNode {
         vec![DashStream::File(self.input.clone())]
     }
 
-    fn get_stdout(&self) -> Option<DashStream> {
-        Some(self.stdout.clone())
+    fn get_stdout(&self) -> Vec<DashStream> {
+        vec![self.stdout.clone()]
     }
 
     fn get_stdout_id(&self) -> Option<NodeId> {
@@ -119,6 +134,11 @@ impl Info for ReadNode {
         Ok(())
     }
 
+    /// Read nodes send their input to a single node.
+    fn add_stdout(&mut self, stream: DashStream) -> Result<()> {
+        self.set_stdout(stream)
+    }
+
     fn set_stderr(&mut self, _stream: DashStream) -> Result<()> {
         unimplemented!()
     }
@@ -231,24 +251,94 @@ impl Execute for ReadNode {
         &mut self,
         _pipes: SharedPipeMap,
         _network_connections: SharedStreamMap,
-        _channels: SharedChannelMap,
-        _tmp_folder: PathBuf,
+        mut channels: SharedChannelMap,
+        tmp_folder: PathBuf,
     ) -> Result<()> {
+        // the fifo must exist before the command writing into it starts
+        if self.input_fifo {
+            FifoStream::new(
+                self.input.get_path().as_path(),
+                self.location.clone(),
+                FifoMode::READ,
+            )
+            .create()?;
+        }
+        // a fifo read by a command on the same machine is copied into a buffer the command
+        // reads from
+        if let DashStream::Pipe(pipestream) = &self.stdout {
+            if pipestream.get_bufferable() {
+                create_and_insert_channels(
+                    self.node_id,
+                    pipestream.get_right(),
+                    IOType::Stdout,
+                    tmp_folder.as_path(),
+                    &mut channels,
+                )?;
+            }
+        }
         Ok(())
     }
     fn redirect(
         &mut self,
         _pipes: SharedPipeMap,
         mut network_connections: SharedStreamMap,
-        _channels: SharedChannelMap,
+        channels: SharedChannelMap,
         _tmp_folder: PathBuf,
+        transfers: &Transfers,
+    ) -> Result<()> {
+        let name = format!("read node {:?}", self.node_id);
+        match &self.stdout {
+            // a fifo input blocks on open until its writer shows up, so it copies on a thread
+            DashStream::Tcp(netstream)
+                if !self.input_fifo && netstream.get_compression() == Compression::None =>
+            {
+                let file_handle = self.input.open()?;
+                let tcpstream = network_connections.remove(netstream)?;
+                transfers.forward(&name, vec![Box::new(file_handle)], Box::new(tcpstream))
+            }
+            _ => {
+                let mut node = self.clone();
+                transfers.spawn(&name, move || {
+                    node.copy_input(network_connections, channels)
+                })
+            }
+        }
+    }
+}
+
+impl ReadNode {
+    fn copy_input(
+        &mut self,
+        mut network_connections: SharedStreamMap,
+        mut channels: SharedChannelMap,
     ) -> Result<()> {
         let mut file_handle = self.input.open()?;
         match &self.stdout {
             DashStream::Tcp(netstream) => {
-                let mut tcpstream = network_connections.remove(&netstream)?;
+                let mut tcpstream = network_connections.remove_blocking(netstream)?;
                 // hopefully this will immediately block until the next process is ready
-                copy(&mut file_handle, &mut tcpstream)?;
+                netstream
+                    .get_compression()
+                    .splice_send(&mut file_handle, &mut tcpstream)?;
+                if self.input_fifo {
+                    drop(file_handle);
+                    remove_file(self.input.get_path())?;
+                }
+            }
+            DashStream::Pipe(pipe) if pipe.get_bufferable() => {
+                let channel_end = channels.remove(&get_channel_name(
+                    self.node_id,
+                    pipe.get_right(),
+                    PipeMode::Write,
+                    IOType::Stdout,
+                ))?;
+                let mut buffered_pipe = BufferedPipe::new(PipeMode::Write, channel_end)?;
+                copy(&mut file_handle, &mut buffered_pipe)?;
+                buffered_pipe.set_write_done()?;
+                if self.input_fifo {
+                    drop(file_handle);
+                    remove_file(self.input.get_path())?;
+                }
             }
             DashStream::Pipe(pipe) => {
                 error!("Read node should not send output to a pipe: {:?}", pipe);
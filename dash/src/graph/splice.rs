@@ -0,0 +1,226 @@
+use super::rapper::copy_wrapper;
+use super::Result;
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+
+/// Copies everything from reader to writer like `copy_wrapper`, but on Linux moves the bytes
+/// between the file descriptors inside the kernel: with `sendfile` out of regular files, and
+/// with `splice` otherwise (through an intermediate pipe when neither end is one). Waits for
+/// non-blocking descriptors to be ready with `poll` instead of sleeping. Descriptors the kernel
+/// can't move data between, like files opened for appending, are copied with `copy_wrapper`.
+pub fn copy<R, W>(reader: &mut R, writer: &mut W) -> Result<u64>
+where
+    R: Read + AsRawFd + ?Sized,
+    W: Write + AsRawFd + ?Sized,
+{
+    // anything the writer buffered has to go out before bytes that bypass it
+    writer.flush()?;
+    #[cfg(target_os = "linux")]
+    {
+        linux::copy(reader, writer)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        copy_wrapper(reader, writer)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) use linux::{appends, kind, Kind, Pipe};
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::copy_wrapper;
+    use super::Result;
+    use failure::bail;
+    use nix::errno::Errno;
+    use nix::fcntl::{fcntl, splice, FcntlArg, OFlag, SpliceFFlags};
+    use nix::poll::{poll, PollFd, PollFlags};
+    use nix::sys::sendfile::sendfile;
+    use nix::sys::stat::{fstat, SFlag};
+    use nix::unistd::{close, pipe, read};
+    use std::io::{Read, Write};
+    use std::os::unix::io::{AsRawFd, RawFd};
+
+    /// Most bytes to move per call: the default capacity of a pipe.
+    const CHUNK_SIZE: usize = 1 << 16;
+
+    /// Outcome of a single transfer between two descriptors.
+    enum Moved {
+        Bytes(usize),
+        /// The kernel can't move data between these descriptors; nothing was moved.
+        Unsupported,
+        /// The receiving end hung up, like a broken pipe in `copy_wrapper`.
+        Hangup,
+    }
+
+    /// Kinds of descriptors data can be moved between.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub(crate) enum Kind {
+        File,
+        Pipe,
+        Socket,
+        Other,
+    }
+
+    pub(crate) fn kind(fd: RawFd) -> Result<Kind> {
+        let mode = SFlag::from_bits_truncate(fstat(fd)?.st_mode) & SFlag::S_IFMT;
+        Ok(match mode {
+            SFlag::S_IFREG => Kind::File,
+            SFlag::S_IFIFO => Kind::Pipe,
+            SFlag::S_IFSOCK => Kind::Socket,
+            _ => Kind::Other,
+        })
+    }
+
+    pub(crate) fn appends(fd: RawFd) -> Result<bool> {
+        let flags = OFlag::from_bits_truncate(fcntl(fd, FcntlArg::F_GETFL)?);
+        Ok(flags.contains(OFlag::O_APPEND))
+    }
+
+    /// Closes both ends of an intermediate pipe once the copy is done.
+    pub(crate) struct Pipe {
+        pub(crate) read_end: RawFd,
+        pub(crate) write_end: RawFd,
+    }
+
+    impl Drop for Pipe {
+        fn drop(&mut self) {
+            let _ = close(self.read_end);
+            let _ = close(self.write_end);
+        }
+    }
+
+    pub fn copy<R, W>(reader: &mut R, writer: &mut W) -> Result<u64>
+    where
+        R: Read + AsRawFd + ?Sized,
+        W: Write + AsRawFd + ?Sized,
+    {
+        let in_fd = reader.as_raw_fd();
+        let out_fd = writer.as_raw_fd();
+        let (in_kind, out_kind) = (kind(in_fd)?, kind(out_fd)?);
+        if in_kind == Kind::Other
+            || out_kind == Kind::Other
+            || (out_kind == Kind::File && appends(out_fd)?)
+        {
+            return copy_wrapper(reader, writer);
+        }
+        let mut total: u64 = 0;
+        if in_kind == Kind::File {
+            loop {
+                match transfer(in_fd, out_fd, || sendfile(out_fd, in_fd, None, CHUNK_SIZE))? {
+                    Moved::Bytes(0) | Moved::Hangup => return Ok(total),
+                    Moved::Bytes(n) => total += n as u64,
+                    Moved::Unsupported if total == 0 => return copy_wrapper(reader, writer),
+                    Moved::Unsupported => bail!("sendfile stopped working after {} bytes", total),
+                }
+            }
+        }
+        if in_kind == Kind::Pipe || out_kind == Kind::Pipe {
+            loop {
+                match transfer(in_fd, out_fd, || splice_chunk(in_fd, out_fd, CHUNK_SIZE))? {
+                    Moved::Bytes(0) | Moved::Hangup => return Ok(total),
+                    Moved::Bytes(n) => total += n as u64,
+                    Moved::Unsupported if total == 0 => return copy_wrapper(reader, writer),
+                    Moved::Unsupported => bail!("splice stopped working after {} bytes", total),
+                }
+            }
+        }
+
+        // splice needs a pipe on one end, so go through one of our own
+        let (read_end, write_end) = pipe()?;
+        let pipe = Pipe {
+            read_end: read_end,
+            write_end: write_end,
+        };
+        loop {
+            let mut buffered = match transfer(in_fd, pipe.write_end, || {
+                splice_chunk(in_fd, pipe.write_end, CHUNK_SIZE)
+            })? {
+                Moved::Bytes(0) | Moved::Hangup => return Ok(total),
+                Moved::Bytes(n) => n,
+                Moved::Unsupported if total == 0 => return copy_wrapper(reader, writer),
+                Moved::Unsupported => bail!("splice stopped working after {} bytes", total),
+            };
+            while buffered > 0 {
+                match transfer(pipe.read_end, out_fd, || {
+                    splice_chunk(pipe.read_end, out_fd, buffered)
+                })? {
+                    Moved::Bytes(0) | Moved::Hangup => return Ok(total),
+                    Moved::Bytes(n) => {
+                        buffered -= n;
+                        total += n as u64;
+                    }
+                    Moved::Unsupported => {
+                        // hand what's already in the pipe over by hand, then copy the rest
+                        total += drain(pipe.read_end, writer, buffered)?;
+                        return Ok(total + copy_wrapper(reader, writer)?);
+                    }
+                }
+            }
+        }
+    }
+
+    fn splice_chunk(in_fd: RawFd, out_fd: RawFd, len: usize) -> nix::Result<usize> {
+        splice(
+            in_fd,
+            None,
+            out_fd,
+            None,
+            len,
+            SpliceFFlags::SPLICE_F_MOVE | SpliceFFlags::SPLICE_F_MORE,
+        )
+    }
+
+    /// Runs one transfer, retrying when interrupted and waiting for non-blocking descriptors
+    /// to be ready.
+    fn transfer<F>(in_fd: RawFd, out_fd: RawFd, mut op: F) -> Result<Moved>
+    where
+        F: FnMut() -> nix::Result<usize>,
+    {
+        loop {
+            match op() {
+                Ok(n) => return Ok(Moved::Bytes(n)),
+                Err(nix::Error::Sys(Errno::EINTR)) => {}
+                Err(nix::Error::Sys(Errno::EAGAIN)) => {
+                    wait_for(in_fd, PollFlags::POLLIN)?;
+                    wait_for(out_fd, PollFlags::POLLOUT)?;
+                }
+                Err(nix::Error::Sys(Errno::EPIPE))
+                | Err(nix::Error::Sys(Errno::ECONNRESET))
+                | Err(nix::Error::Sys(Errno::ECONNABORTED)) => return Ok(Moved::Hangup),
+                Err(nix::Error::Sys(Errno::EINVAL)) | Err(nix::Error::Sys(Errno::ENOSYS)) => {
+                    return Ok(Moved::Unsupported)
+                }
+                Err(e) => bail!("{:?}", e),
+            }
+        }
+    }
+
+    /// Blocks until the descriptor is ready, or has hung up.
+    fn wait_for(fd: RawFd, events: PollFlags) -> Result<()> {
+        let mut fds = [PollFd::new(fd, events)];
+        loop {
+            match poll(&mut fds, -1) {
+                Ok(_) => return Ok(()),
+                Err(nix::Error::Sys(Errno::EINTR)) => {}
+                Err(e) => bail!("Failed to poll {:?}: {:?}", fd, e),
+            }
+        }
+    }
+
+    /// Reads `len` bytes out of the pipe and writes them.
+    fn drain<W: Write + ?Sized>(read_end: RawFd, writer: &mut W, len: usize) -> Result<u64> {
+        let mut buf = [0u8; CHUNK_SIZE];
+        let mut left = len;
+        while left > 0 {
+            let n = read(read_end, &mut buf[..left.min(CHUNK_SIZE)])?;
+            if n == 0 {
+                break;
+            }
+            writer.write_all(&buf[..n])?;
+            left -= n;
+        }
+        Ok((len - left) as u64)
+    }
+}
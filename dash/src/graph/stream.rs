@@ -1,3 +1,4 @@
+use super::compression::Compression;
 use super::filestream::{FifoStream, FileStream};
 use super::program::{NodeId, ProgId};
 use super::{Location, Result, SharedMap};
@@ -6,6 +7,7 @@ use serde::{Deserialize, Serialize};
 use std::convert::Into;
 use std::io::{Read, Write};
 use std::net::TcpStream;
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::process::{ChildStderr, ChildStdin, ChildStdout};
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Hash, Eq)]
@@ -85,6 +87,8 @@ pub struct NetStream {
     right_location: Location,
     /// Should we buffer into a file
     buffer_into_file: bool,
+    /// Codec both sides agree to compress the stream with
+    compression: Compression,
 }
 
 impl Default for NetStream {
@@ -96,6 +100,7 @@ impl Default for NetStream {
             left_location: Location::Client,
             right_location: Location::Client,
             buffer_into_file: false,
+            compression: Compression::None,
         }
     }
 }
@@ -119,6 +124,7 @@ impl NetStream {
             left_location: left_location,
             right_location: right_location,
             buffer_into_file: false,
+            compression: Compression::None,
         })
     }
 
@@ -197,6 +203,14 @@ impl NetStream {
     pub fn set_bufferable(&mut self) {
         self.buffer_into_file = true;
     }
+
+    pub fn get_compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
 }
 
 /// Kinds of inputs and outputs for node
@@ -237,6 +251,30 @@ impl DashStream {
         }
         Ok(())
     }
+
+    pub fn set_compression(&mut self, compression: Compression) -> Result<()> {
+        match self {
+            DashStream::Tcp(ref mut ns) => {
+                ns.set_compression(compression);
+            }
+            _ => {
+                bail!("Setting compression on non netstream");
+            }
+        }
+        Ok(())
+    }
+
+    /// Can the event loop read this input's bytes as they are? Compressed streams and buffered
+    /// pipes go through userspace code that blocks, and fifos block on open.
+    pub fn is_forwardable(&self) -> bool {
+        match self {
+            DashStream::Tcp(ns) => ns.get_compression() == Compression::None,
+            DashStream::Pipe(ps) => !ps.get_bufferable(),
+            DashStream::File(_) => true,
+            _ => false,
+        }
+    }
+
     pub fn get_dot_label(&self) -> Result<String> {
         match self {
             DashStream::File(fs) => Ok(fs.get_dot_label()),
@@ -362,6 +400,16 @@ impl Write for OutputHandle {
         }
     }
 }
+impl AsRawFd for OutputHandle {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            OutputHandle::Stdin(handle) => handle.as_raw_fd(),
+            OutputHandle::Stdout(handle) => handle.as_raw_fd(),
+            OutputHandle::Stderr(handle) => handle.as_raw_fd(),
+        }
+    }
+}
+
 impl Into<Option<ChildStdin>> for OutputHandle {
     fn into(self) -> Option<ChildStdin> {
         match self {
@@ -393,3 +441,14 @@ pub type SharedPipeMap = SharedMap<HandleIdentifier, OutputHandle>;
 
 /// Used to manage tcp connections when executing nodes.
 pub type SharedStreamMap = SharedMap<NetStream, TcpStream>;
+
+impl SharedStreamMap {
+    /// Removes the connection for this stream so a node can copy through it.
+    /// The runtime keeps connections non-blocking while setting them up; once a node owns one,
+    /// copies should wait on the socket rather than spin on WouldBlock.
+    pub fn remove_blocking(&mut self, netstream: &NetStream) -> Result<TcpStream> {
+        let stream = self.remove(netstream)?;
+        stream.set_nonblocking(false)?;
+        Ok(stream)
+    }
+}
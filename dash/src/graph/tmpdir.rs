@@ -0,0 +1,157 @@
+use super::program::{NodeId, ProgId};
+use super::Result;
+use failure::bail;
+use std::fs::{
+    create_dir_all, read_dir, read_to_string, remove_dir, remove_dir_all, remove_file, OpenOptions,
+};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+/// File in each program's tmp dir listing the tmp files it owns outside of it.
+const MANIFEST: &str = "MANIFEST";
+
+static SESSION: OnceLock<String> = OnceLock::new();
+
+/// Names this process's programs in tmp folders, so programs from different clients, or from
+/// a client that restarted, never share tmp files.
+pub fn session_id() -> String {
+    SESSION
+        .get_or_init(|| {
+            let started = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            format!("{}-{}", std::process::id(), started)
+        })
+        .clone()
+}
+
+/// Where a program keeps its tmp files under a tmp folder.
+pub fn namespace(session: &str, prog_id: ProgId) -> PathBuf {
+    [session, &prog_id.to_string()].iter().collect()
+}
+
+/// The tmp dir of the part of a program running on this machine:
+/// `<tmp>/<session>/<prog_id>/<part>`, where part is the lowest node id in it, so parts of one
+/// program sharing a tmp folder keep out of each other's way. Buffers and anything else the
+/// part's nodes create go inside it, and tmp files the client placed elsewhere are listed in
+/// its manifest, so `remove` or a later `sweep` can delete all of them.
+pub struct TmpDir {
+    path: PathBuf,
+}
+
+impl TmpDir {
+    pub fn create(tmp: &Path, session: &str, prog_id: ProgId, part: NodeId) -> Result<Self> {
+        let path = tmp.join(namespace(session, prog_id)).join(part.to_string());
+        create_dir_all(&path)?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.join(MANIFEST))?;
+        Ok(TmpDir { path: path })
+    }
+
+    pub fn get_path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// Records a tmp file this program owns, making sure its folder exists.
+    pub fn register(&self, file: &Path) -> Result<()> {
+        if let Some(parent) = file.parent() {
+            create_dir_all(parent)?;
+        }
+        let mut manifest = OpenOptions::new()
+            .append(true)
+            .open(self.path.join(MANIFEST))?;
+        match file.to_str() {
+            Some(name) if !name.contains('\n') => writeln!(manifest, "{}", name)?,
+            _ => bail!("Can't list tmp file {:?} in a manifest", file),
+        }
+        Ok(())
+    }
+
+    /// Deletes the registered files and the tmp dir, then the program's and session's folders
+    /// if this was the last part running under them.
+    pub fn remove(&self) -> Result<()> {
+        remove_listed(&self.path)?;
+        remove_dir_all(&self.path)?;
+        // fails when other parts or programs from the session are still running
+        for folder in self.path.ancestors().skip(1).take(2) {
+            let _ = remove_dir(folder);
+        }
+        Ok(())
+    }
+}
+
+fn remove_listed(dir: &Path) -> Result<()> {
+    let manifest = match read_to_string(dir.join(MANIFEST)) {
+        Ok(m) => m,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => bail!("Could not read manifest in {:?}: {:?}", dir, e),
+    };
+    for line in manifest.lines().filter(|l| !l.is_empty()) {
+        match remove_file(line) {
+            Ok(_) => {}
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => bail!("Could not remove tmp file {:?}: {:?}", line, e),
+        }
+        // the client nests tmp files in the program's namespace too
+        if let Some(parent) = Path::new(line).parent() {
+            let _ = remove_dir(parent);
+            if let Some(session) = parent.parent() {
+                let _ = remove_dir(session);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Deletes tmp dirs under tmp, and the files their manifests list, that haven't been touched
+/// in max_age: those of programs that were running when a server or client died.
+/// Returns how many it removed.
+pub fn sweep(tmp: &Path, max_age: Duration) -> Result<usize> {
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for session in subdirs(tmp)? {
+        for program in subdirs(&session)? {
+            for part in subdirs(&program)? {
+                let modified = match part.join(MANIFEST).metadata() {
+                    Ok(metadata) => metadata.modified()?,
+                    // not a program's tmp dir
+                    Err(_) => continue,
+                };
+                match now.duration_since(modified) {
+                    Ok(age) if age >= max_age => {}
+                    _ => continue,
+                }
+                debug!("Sweeping stale tmp dir {:?}", part);
+                if let Err(e) = remove_listed(&part) {
+                    warn!("Could not sweep {:?}: {:?}", part, e);
+                    continue;
+                }
+                if let Err(e) = remove_dir_all(&part) {
+                    warn!("Could not sweep {:?}: {:?}", part, e);
+                    continue;
+                }
+                removed += 1;
+            }
+            let _ = remove_dir(&program);
+        }
+        let _ = remove_dir(&session);
+    }
+    Ok(removed)
+}
+
+fn subdirs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut ret: Vec<PathBuf> = Vec::new();
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            ret.push(path);
+        }
+    }
+    Ok(ret)
+}
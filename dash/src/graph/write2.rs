@@ -1,8 +1,11 @@
+use super::eventloop::{Endpoint, Transfers};
 use super::execute::Execute;
 use super::info::{resolve_file_streams, Info};
 use super::pipe::{get_channel_name, BufferedPipe, PipeMode, SharedChannelMap};
-use super::rapper::copy_wrapper as copy;
+use super::progress::Meter;
+use super::rapper::copy_metered;
 use super::rapper::stream_initiate_filter;
+use super::splice;
 use super::{program, stream, Location, Result};
 use failure::bail;
 use program::{Link, NodeId, ProgId};
@@ -82,8 +85,8 @@ impl Info for WriteNode {
         self.stdin.clone()
     }
 
-    fn get_stdout(&self) -> Option<DashStream> {
-        Some(self.output.clone())
+    fn get_stdout(&self) -> Vec<DashStream> {
+        vec![self.output.clone()]
     }
 
     fn get_stdout_id(&self) -> Option<NodeId> {
@@ -136,6 +139,11 @@ impl Info for WriteNode {
         Ok(())
     }
 
+    /// Write nodes write to a single output.
+    fn add_stdout(&mut self, stream: DashStream) -> Result<()> {
+        self.set_stdout(stream)
+    }
+
     fn set_stderr(&mut self, _stream: DashStream) -> Result<()> {
         unimplemented!()
     }
@@ -290,11 +298,64 @@ impl Execute for WriteNode {
     }
 
     fn redirect(
+        &mut self,
+        mut pipes: SharedPipeMap,
+        mut network_connections: SharedStreamMap,
+        channels: SharedChannelMap,
+        _tmp_folder: PathBuf,
+        transfers: &Transfers,
+    ) -> Result<()> {
+        let name = format!("write node {:?}", self.node_id);
+        match &self.output {
+            DashStream::File(filestream) if self.stdin.iter().all(|s| s.is_forwardable()) => {
+                let mut inputs: Vec<(Box<dyn Endpoint>, Meter)> = Vec::new();
+                for input_stream in self.stdin.iter() {
+                    let meter = transfers.meter_stream(input_stream, self.node_id);
+                    match input_stream {
+                        DashStream::Tcp(netstream) => {
+                            inputs.push((Box::new(network_connections.remove(netstream)?), meter));
+                        }
+                        DashStream::Pipe(pipestream) => {
+                            let identifier = HandleIdentifier::new(
+                                self.prog_id,
+                                pipestream.get_left(),
+                                pipestream.get_output_type(),
+                            );
+                            inputs.push((Box::new(pipes.remove(&identifier)?), meter));
+                        }
+                        _ => bail!(
+                            "Cannot have stream of type {:?} as output of write node",
+                            input_stream
+                        ),
+                    }
+                }
+                let output = filestream.open_with_append()?;
+                transfers.forward_metered(&name, inputs, Box::new(output))
+            }
+            // fifos block on open until the reader shows up, and stdout and stderr are shared
+            // with the rest of the process, so those copy on a thread
+            _ => {
+                let mut node = self.clone();
+                let meters: Vec<Meter> = self
+                    .stdin
+                    .iter()
+                    .map(|stream| transfers.meter_stream(stream, self.node_id))
+                    .collect();
+                transfers.spawn(&name, move || {
+                    node.copy_inputs(pipes, network_connections, channels, meters)
+                })
+            }
+        }
+    }
+}
+
+impl WriteNode {
+    fn copy_inputs(
         &mut self,
         mut pipes: SharedPipeMap,
         mut network_connections: SharedStreamMap,
         mut channels: SharedChannelMap,
-        tmp_folder: PathBuf,
+        meters: Vec<Meter>,
     ) -> Result<()> {
         debug!(
             "Spawning thread for copying stdin into node {:?}",
@@ -302,24 +363,33 @@ impl Execute for WriteNode {
         );
 
         // open the file for appending
-        for input_stream in self.stdin.iter() {
+        for (input_stream, meter) in self.stdin.iter().zip(meters.iter()) {
             match &input_stream {
                 DashStream::Tcp(netstream) => {
-                    let mut tcpstream = network_connections.remove(&netstream)?;
+                    let mut tcpstream = network_connections.remove_blocking(netstream)?;
+                    let compression = netstream.get_compression();
                     match &self.output {
                         DashStream::File(filestream) => {
                             let mut f = filestream.open_with_append()?;
-                            copy(&mut tcpstream, &mut f)?;
+                            compression.metered_receive(&mut tcpstream, &mut f, meter)?;
                         }
                         DashStream::Fifo(fifostream) => {
                             let mut f = fifostream.open()?;
-                            copy(&mut tcpstream, &mut f)?;
+                            compression.metered_receive(&mut tcpstream, &mut f, meter)?;
                         }
                         DashStream::Stdout => {
-                            copy(&mut tcpstream, &mut std::io::stdout())?;
+                            compression.metered_receive(
+                                &mut tcpstream,
+                                &mut std::io::stdout(),
+                                meter,
+                            )?;
                         }
                         DashStream::Stderr => {
-                            copy(&mut tcpstream, &mut std::io::stderr())?;
+                            compression.metered_receive(
+                                &mut tcpstream,
+                                &mut std::io::stderr(),
+                                meter,
+                            )?;
                         }
                         _ => {
                             error!(
@@ -338,26 +408,21 @@ impl Execute for WriteNode {
                         true => {
                             let channel_end = channels.remove(&get_channel_name(
                                 pipestream.get_left(),
+                                pipestream.get_right(),
                                 PipeMode::Read,
                                 pipestream.get_output_type(),
                             ))?;
-                            let mut handle = BufferedPipe::new(
-                                pipestream.get_left(),
-                                pipestream.get_output_type(),
-                                tmp_folder.as_path(),
-                                PipeMode::Read,
-                                channel_end,
-                            )?;
+                            let mut handle = BufferedPipe::new(PipeMode::Read, channel_end)?;
                             match &self.output {
                                 DashStream::File(filestream) => {
                                     let mut f = filestream.open_with_append()?;
-                                    copy(&mut handle, &mut f)?;
+                                    copy_metered(&mut handle, &mut f, meter)?;
                                 }
                                 DashStream::Stdout => {
-                                    copy(&mut handle, &mut std::io::stdout())?;
+                                    copy_metered(&mut handle, &mut std::io::stdout(), meter)?;
                                 }
                                 DashStream::Stderr => {
-                                    copy(&mut handle, &mut std::io::stderr())?;
+                                    copy_metered(&mut handle, &mut std::io::stderr(), meter)?;
                                 }
                                 _ => {
                                     error!(
@@ -382,13 +447,13 @@ impl Execute for WriteNode {
                             match &self.output {
                                 DashStream::File(filestream) => {
                                     let mut f = filestream.open_with_append()?;
-                                    copy(&mut handle, &mut f)?;
+                                    splice::copy(&mut handle, &mut f)?;
                                 }
                                 DashStream::Stdout => {
-                                    copy(&mut handle, &mut std::io::stdout())?;
+                                    splice::copy(&mut handle, &mut std::io::stdout())?;
                                 }
                                 DashStream::Stderr => {
-                                    copy(&mut handle, &mut std::io::stderr())?;
+                                    splice::copy(&mut handle, &mut std::io::stderr())?;
                                 }
                                 _ => {
                                     error!(
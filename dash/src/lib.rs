@@ -9,6 +9,7 @@ pub mod graph;
 pub mod runtime;
 pub mod serialize;
 pub mod util;
+use crate::graph::pipe::BufferLimits;
 use crate::runtime::new_runtime::{ClientMap, ServerRuntime};
 use crate::runtime::runtime::ShellServer;
 use crate::runtime::runtime_util::Server;
@@ -27,10 +28,27 @@ pub fn start_shell(runtime_port: &str, client_folder: &str, debug: bool) {
     let _ = child.join();
 }
 
-pub fn start_runtime(runtime_port: &str, client_map: ClientMap, debug: bool, tmp_file: &str) {
+pub fn start_runtime(
+    runtime_port: &str,
+    client_map: ClientMap,
+    debug: bool,
+    tmp_file: &str,
+    io_threads: usize,
+    request_threads: usize,
+    buffer_limits: BufferLimits,
+) {
     let localhost = "0.0.0.0";
-    let mut runtime =
-        ServerRuntime::new(localhost, runtime_port, client_map, debug, tmp_file).unwrap();
+    let mut runtime = ServerRuntime::new(
+        localhost,
+        runtime_port,
+        client_map,
+        debug,
+        tmp_file,
+        io_threads,
+        request_threads,
+    )
+    .unwrap();
+    runtime.set_buffer_limits(buffer_limits);
     let child = thread::spawn(move || match runtime.handle_incoming() {
         Ok(_) => unreachable!(),
         Err(e) => {
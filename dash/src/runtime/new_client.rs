@@ -1,4 +1,4 @@
-use super::graph::{program, stream, Location};
+use super::graph::{program, progress::Progress, stream, Location};
 use super::runtime_util::Addr;
 use super::serialize::{read_msg_and_type, rpc, write_msg_and_type};
 use super::Result;
@@ -87,6 +87,7 @@ impl ShellClient {
         &self,
         program_map: &mut HashMap<Location, program::Program>,
         shared_map: &mut SharedStreamMap,
+        progress: &Progress,
     ) -> Result<()> {
         let mut execution_threads: Vec<JoinHandle<Result<()>>> = Vec::new();
         for (loc, prog) in program_map.iter_mut() {
@@ -95,6 +96,7 @@ impl ShellClient {
             let shared_map_copy = shared_map.clone();
             let port = self.port.clone();
             let tmp_folder = self.tmp.clone();
+            let progress = progress.clone();
             execution_threads.push(thread::spawn(move || {
                 let ret = execute_subprogram(
                     location.clone(),
@@ -102,6 +104,7 @@ impl ShellClient {
                     shared_map_copy,
                     port,
                     tmp_folder,
+                    progress,
                 );
                 debug!("One of threads joined: {:?}", location);
                 ret
@@ -126,6 +129,16 @@ impl ShellClient {
 
     /// Executes the given program by offloading the relevant nodes to the correct machines.
     pub fn run_command(&self, program: program::Program) -> Result<()> {
+        self.run_command_with_progress(program, &Progress::default())
+    }
+
+    /// Like `run_command`, collecting what moves along each edge of a program that reports
+    /// progress on progress, from every machine running part of it, while it runs.
+    pub fn run_command_with_progress(
+        &self,
+        program: program::Program,
+        progress: &Progress,
+    ) -> Result<()> {
         // split the program into portions that each node needs execute
         let mut program_map = match program.split_by_machine() {
             Ok(m) => m,
@@ -139,7 +152,7 @@ impl ShellClient {
         let mut shared_map = SharedStreamMap::new();
         self.run_setup(&mut program_map, &mut shared_map)?;
         // now try to execute each portion of the program:
-        self.send_program(&mut program_map, &mut shared_map)?;
+        self.send_program(&mut program_map, &mut shared_map, progress)?;
         Ok(())
     }
 
@@ -293,12 +306,14 @@ fn run_stream_setup(
 /// program: Program -> subprogram to be executed.
 /// shared_map: SharedStreamMap: handle for map with client's subprogram TCP streams.
 /// port: String -> port that server is listening to
+/// progress: Progress -> where the counts for each edge go, as they come in.
 pub fn execute_subprogram(
     loc: Location,
     mut prog: program::Program,
     shared_stream_map: SharedStreamMap,
     port: String,
     tmp_folder: String,
+    progress: Progress,
 ) -> Result<()> {
     tracing::warn!("Sending program {:?} to loc {:?} for execution", prog, loc);
     match loc {
@@ -306,7 +321,7 @@ pub fn execute_subprogram(
             // execute the subprogram
             debug!("executing following subprogram locally: {:?}", prog);
             prog.resolve_args("")?; // noop for client
-            match prog.execute(shared_stream_map, tmp_folder) {
+            match prog.execute_with_progress(shared_stream_map, tmp_folder, &progress) {
                 Ok(_) => {
                     info!("Client executed successfully!");
                     return Ok(());
@@ -328,8 +343,14 @@ pub fn execute_subprogram(
                 &mut stream,
             )?;
             stream.set_nonblocking(false)?;
-            let (_, next_msg) = read_msg_and_type(&mut stream)?;
-            let msg = deserialize(&next_msg[..])?;
+            // programs reporting progress send it until the control message with the result
+            let mut next = read_msg_and_type(&mut stream)?;
+            while next.0 == rpc::MessageType::Progress {
+                let report: rpc::ProgressReport = deserialize(&next.1[..])?;
+                progress.update(&report.edges);
+                next = read_msg_and_type(&mut stream)?;
+            }
+            let msg = deserialize(&next.1[..])?;
             match msg {
                 rpc::ClientReturnCode::Success => {
                     info!("Server returned success for program execution: {:?}", ip);
@@ -1,13 +1,22 @@
 extern crate walkdir;
-use super::graph::{filestream::FileStream, program, stream, Location};
+use super::graph::{
+    eventloop::EventLoop,
+    filestream::FileStream,
+    pipe::BufferLimits,
+    program,
+    progress::{Progress, REPORT_INTERVAL},
+    stream, Location,
+};
 use super::runtime_util::{new_server, Addr, Server};
 use super::serialize::{read_msg_and_type, rpc, write_msg_and_type};
 use super::Result;
 use bincode::{deserialize, serialize};
+use crossbeam::channel::{unbounded, Receiver, Sender};
 use failure::bail;
 use std::collections::HashMap;
 use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::{fs, thread};
 use stream::SharedStreamMap;
 use tracing::{debug, error, info};
@@ -15,6 +24,9 @@ use walkdir::WalkDir;
 /// matches client IP to folder name
 pub type ClientMap = HashMap<IpAddr, String>;
 
+/// Threads reading the request off each new connection.
+pub const DEFAULT_REQUEST_THREADS: usize = 8;
+
 /// Map from client Ip to data structure that facilitates sharing streams across threads.
 type ClientStreamMap = HashMap<IpAddr, SharedStreamMap>;
 /// Runtime on server that services client requests.
@@ -25,17 +37,65 @@ pub struct ServerRuntime {
     client_stream_map: ClientStreamMap,
     debug: bool,
     tmp: String,
+    event_loop: EventLoop,
+    request_threads: usize,
+    buffer_limits: BufferLimits,
+}
+
+/// A connection waiting for a request thread to read its message.
+struct Request {
+    stream: TcpStream,
+    peer_addr: SocketAddr,
+    folder_result: Result<String>,
+    stream_map: SharedStreamMap,
+}
+
+/// What every request thread needs besides the request itself.
+#[derive(Clone)]
+struct Context {
+    addr: Addr,
+    server_name: String,
+    tmp: String,
+    event_loop: EventLoop,
+    buffer_limits: BufferLimits,
+    setups: Sender<(Request, Vec<u8>)>,
+}
+
+impl Context {
+    fn report(&self, peer_addr: SocketAddr, result: Result<()>) {
+        match result {
+            Ok(_) => {
+                info!(
+                    "{}: Successfully handled request from {}",
+                    self.server_name, peer_addr
+                );
+            }
+            Err(e) => {
+                error!(
+                    "{}: Error handling request from {}: {:?}",
+                    self.server_name, peer_addr, e
+                );
+            }
+        }
+    }
 }
 
 impl ServerRuntime {
     /// Constructs a new server runtime with the given public IP, port, and ClientMap.
+    /// Programs move their data on io_threads event loop threads, and requests are read by
+    /// request_threads threads, however many clients connect.
     pub fn new(
         ip: &str,
         port: &str,
         client_map: ClientMap,
         debug: bool,
         tmp: &str,
+        io_threads: usize,
+        request_threads: usize,
     ) -> Result<Self> {
+        if request_threads == 0 {
+            bail!("Server runtime needs at least one request thread");
+        }
         for (_, folder) in client_map.iter() {
             fs::create_dir_all(folder)?;
         }
@@ -47,9 +107,17 @@ impl ServerRuntime {
             client_stream_map: new_client_stream_map,
             debug: debug,
             tmp: tmp.to_string(),
+            event_loop: EventLoop::new(io_threads)?,
+            request_threads: request_threads,
+            buffer_limits: BufferLimits::default(),
         })
     }
 
+    /// Sets how much each program may buffer for streams whose reader hasn't caught up yet.
+    pub fn set_buffer_limits(&mut self, buffer_limits: BufferLimits) {
+        self.buffer_limits = buffer_limits;
+    }
+
     fn find_client_folder(&self, addr: SocketAddr) -> Result<String> {
         match self.client_map.get(&addr.ip()) {
             Some(v) => Ok(v.clone()),
@@ -88,6 +156,35 @@ impl Server for ServerRuntime {
 
     fn handle_incoming(&mut self) -> Result<()> {
         let clone = self.get_clone()?;
+        let (requests, request_receiver) = unbounded::<Request>();
+        let (setups, setup_receiver) = unbounded::<(Request, Vec<u8>)>();
+        let context = Context {
+            addr: self.addr.clone(),
+            server_name: self.server_name(),
+            tmp: self.tmp.clone(),
+            event_loop: self.event_loop.clone(),
+            buffer_limits: self.buffer_limits,
+            setups: setups,
+        };
+        // Setting up a stream waits on the other server's request threads, so it gets its own
+        // threads: two servers setting up streams to each other can't starve each other.
+        spawn_pool("setup", self.request_threads, setup_receiver, {
+            let context = context.clone();
+            move |(request, buf)| {
+                let peer_addr = request.peer_addr;
+                let result = setup_stream(request, buf, &context.addr);
+                context.report(peer_addr, result);
+            }
+        })?;
+        spawn_pool("request", self.request_threads, request_receiver, {
+            let context = context.clone();
+            move |request| {
+                let peer_addr = request.peer_addr;
+                if let Err(e) = handle_request(request, &context) {
+                    context.report(peer_addr, Err(e));
+                }
+            }
+        })?;
         for stream in clone.incoming() {
             match stream {
                 Ok(s) => {
@@ -96,33 +193,15 @@ impl Server for ServerRuntime {
                     let folder_result = self.find_client_folder(peer_addr.clone());
                     // find, or create a new stream map for this client
                     let stream_map = self.get_stream_map(peer_addr.clone());
-                    let server_name = self.server_name();
-                    let debug = self.debug.clone();
-                    let tmp = self.tmp.clone();
-                    let addr = self.addr.clone();
-                    thread::spawn(move || {
-                        match handle_spawned_client(
-                            s,
-                            folder_result,
-                            stream_map,
-                            addr,
-                            debug.clone(),
-                            tmp,
-                        ) {
-                            Ok(_) => {
-                                info!(
-                                    "{}: Successfully handled request from {}",
-                                    server_name, peer_addr
-                                );
-                            }
-                            Err(e) => {
-                                error!(
-                                    "{}: Error handling request from {}: {:?}",
-                                    server_name, peer_addr, e
-                                );
-                            }
-                        }
-                    });
+                    let request = Request {
+                        stream: s,
+                        peer_addr: peer_addr,
+                        folder_result: folder_result,
+                        stream_map: stream_map,
+                    };
+                    if requests.send(request).is_err() {
+                        bail!("{}: Request threads exited", self.server_name());
+                    }
                 }
                 Err(e) => {
                     if self.debug {
@@ -139,14 +218,35 @@ impl Server for ServerRuntime {
     }
 }
 
-fn handle_spawned_client(
-    mut stream: TcpStream,
-    folder_result: Result<String>,
-    mut stream_map: SharedStreamMap,
-    addr: Addr,
-    _debug: bool,
-    tmp_folder: String,
-) -> Result<()> {
+/// Starts threads that handle whatever is sent on receiver until every sender is gone.
+fn spawn_pool<T, F>(name: &str, threads: usize, receiver: Receiver<T>, handle: F) -> Result<()>
+where
+    T: Send + 'static,
+    F: Fn(T) + Clone + Send + 'static,
+{
+    for i in 0..threads {
+        let receiver = receiver.clone();
+        let handle = handle.clone();
+        thread::Builder::new()
+            .name(format!("{}-{}", name, i))
+            .spawn(move || {
+                for job in receiver.iter() {
+                    handle(job);
+                }
+            })?;
+    }
+    Ok(())
+}
+
+/// Reads the request off a new connection and answers it, except that stream setups are handed
+/// to the setup threads and programs answer once the event loop finishes them.
+fn handle_request(request: Request, context: &Context) -> Result<()> {
+    let Request {
+        mut stream,
+        peer_addr,
+        folder_result,
+        mut stream_map,
+    } = request;
     let folder = match folder_result {
         Ok(f) => f,
         Err(e) => {
@@ -159,59 +259,16 @@ fn handle_spawned_client(
     match msg_type {
         // handle setting up the stream to another machine
         rpc::MessageType::SetupStreams => {
-            let msg: rpc::NetworkStreamInfo = match deserialize(&buf[..]) {
-                Ok(info) => info,
-                Err(e) => {
-                    let response = serialize(&rpc::ClientReturnCode::Failure)?;
-                    write_msg_and_type(response.to_vec(), rpc::MessageType::Control, &mut stream)?;
-                    bail!("Error deserializing setup stream msg: {:?}", e);
-                }
-            };
-            let connection_addr = match msg.netstream.get_receiving_side() {
-                Location::Server(ip) => Addr::new(&ip, &msg.port).get_addr(),
-                Location::Client => {
-                    bail!("Server {:?} cannot initiate connection to the client", addr);
-                }
-            };
-
-            // start a connection to another server
-            let mut connection = TcpStream::connect(connection_addr)?;
-            // send a pipe message to another server to setup a stream
-            let netstream_info: rpc::NetworkStreamInfo = rpc::NetworkStreamInfo {
-                loc: Location::Server(addr.get_ip()),
-                port: msg.port.clone(),
-                prog_id: msg.prog_id,
-                netstream: msg.netstream.clone(),
+            let request = Request {
+                stream: stream,
+                peer_addr: peer_addr,
+                folder_result: Ok(folder),
+                stream_map: stream_map,
             };
-            let outermsg = serialize(&netstream_info)?;
-            write_msg_and_type(outermsg.to_vec(), rpc::MessageType::Pipe, &mut connection)?;
-
-            // wait for success:
-            let (_, response_buf) = read_msg_and_type(&mut connection)?;
-            let response: rpc::ClientReturnCode = deserialize(&response_buf[..])?;
-            match response {
-                rpc::ClientReturnCode::Success => {
-                    // Send the client an ACK that it made all the pipe requests
-                    let ack = serialize(&rpc::ClientReturnCode::Success)?;
-                    write_msg_and_type(ack.to_vec(), rpc::MessageType::Control, &mut stream)?;
-
-                    // save the connection in the shared map
-                    connection.set_nonblocking(true)?;
-                    stream_map.insert(msg.netstream, connection)?;
-                    Ok(())
-                }
-                rpc::ClientReturnCode::Failure => {
-                    // Send the client a message saying that it failed
-                    let nack = serialize(&rpc::ClientReturnCode::Failure)?;
-                    write_msg_and_type(nack.to_vec(), rpc::MessageType::Control, &mut stream)?;
-                    bail!(
-                        "Failed to setup stream to another server: {:?} on server {:?}",
-                        netstream_info,
-                        addr
-                    );
-                }
+            if context.setups.send((request, buf)).is_err() {
+                bail!("Setup threads exited");
             }
-            // Just setup any TCP streams
+            Ok(())
         }
         rpc::MessageType::ProgramExecution => {
             let mut program: program::Program = match deserialize(&buf[..]) {
@@ -229,14 +286,43 @@ fn handle_spawned_client(
             // all the streams must be setup for this part of the program,
             // so execute the program!
             program.resolve_args(&folder)?;
-            let response = match program.execute(stream_map, tmp_folder) {
-                Ok(_) => serialize(&rpc::ClientReturnCode::Success)?,
-                Err(e) => {
-                    error!("Could not execute program because {:?}", e);
-                    serialize(&rpc::ClientReturnCode::Failure)?
-                }
-            };
-            write_msg_and_type(response.to_vec(), rpc::MessageType::Control, &mut stream)?;
+            let done_context = context.clone();
+            if !program.get_progress() {
+                program.start(
+                    stream_map,
+                    context.tmp.clone(),
+                    &context.event_loop,
+                    context.buffer_limits,
+                    &Progress::default(),
+                    move |result| {
+                        done_context.report(peer_addr, respond(&mut stream, result));
+                    },
+                );
+                return Ok(());
+            }
+            // a timer on the event loop sends the client the program's progress until it's done
+            let progress = Progress::new();
+            let reporter = Arc::new(Mutex::new(ProgressReporter {
+                stream: stream,
+                prog_id: program.get_id(),
+                progress: progress.clone(),
+                finished: false,
+            }));
+            schedule_report(&context.event_loop, reporter.clone());
+            program.start(
+                stream_map,
+                context.tmp.clone(),
+                &context.event_loop,
+                context.buffer_limits,
+                &progress,
+                move |result| {
+                    let mut reporter = match reporter.lock() {
+                        Ok(r) => r,
+                        Err(poisoned) => poisoned.into_inner(),
+                    };
+                    done_context.report(peer_addr, reporter.finish(result));
+                },
+            );
             Ok(())
         }
         rpc::MessageType::Pipe => {
@@ -258,6 +344,7 @@ fn handle_spawned_client(
             // send a success message back to the sender saying this stream was inserted
             let response = serialize(&rpc::ClientReturnCode::Success)?;
             write_msg_and_type(response.to_vec(), rpc::MessageType::Control, &mut stream)?;
+            context.report(peer_addr, Ok(()));
             Ok(())
         }
         rpc::MessageType::SizeRequest => {
@@ -315,8 +402,139 @@ fn handle_spawned_client(
                 &mut stream,
             )?;
 
+            context.report(peer_addr, Ok(()));
             Ok(())
         }
         _ => Ok(()),
     }
 }
+
+/// Tells the client whether its part of the program ran.
+fn respond(stream: &mut TcpStream, result: Result<()>) -> Result<()> {
+    let response = match result {
+        Ok(_) => serialize(&rpc::ClientReturnCode::Success)?,
+        Err(e) => {
+            error!("Could not execute program because {:?}", e);
+            serialize(&rpc::ClientReturnCode::Failure)?
+        }
+    };
+    write_msg_and_type(response.to_vec(), rpc::MessageType::Control, stream)?;
+    Ok(())
+}
+
+/// Sends the client what has moved along each edge of a program that reports progress.
+struct ProgressReporter {
+    stream: TcpStream,
+    prog_id: program::ProgId,
+    progress: Progress,
+    /// Set once the final counts and the result went out; no more reports follow.
+    finished: bool,
+}
+
+impl ProgressReporter {
+    fn send_report(&mut self) -> Result<()> {
+        let report = rpc::ProgressReport {
+            prog_id: self.prog_id,
+            edges: self.progress.snapshot()?,
+        };
+        let message = serialize(&report)?;
+        write_msg_and_type(
+            message.to_vec(),
+            rpc::MessageType::Progress,
+            &mut self.stream,
+        )
+    }
+
+    /// Sends the final counts and whether the program ran.
+    fn finish(&mut self, result: Result<()>) -> Result<()> {
+        self.finished = true;
+        self.send_report()?;
+        respond(&mut self.stream, result)
+    }
+}
+
+/// Has the event loop send a report every `REPORT_INTERVAL` until the program finishes.
+fn schedule_report(event_loop: &EventLoop, reporter: Arc<Mutex<ProgressReporter>>) {
+    let next_loop = event_loop.clone();
+    let scheduled = event_loop.after(REPORT_INTERVAL, move || {
+        let mut locked = match reporter.lock() {
+            Ok(r) => r,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if locked.finished {
+            return;
+        }
+        if let Err(e) = locked.send_report() {
+            // the final result goes out, or fails, on its own
+            error!("Could not report progress: {:?}", e);
+            return;
+        }
+        drop(locked);
+        schedule_report(&next_loop, reporter);
+    });
+    if let Err(e) = scheduled {
+        error!("Could not schedule progress report: {:?}", e);
+    }
+}
+
+/// Connects to the server on the receiving side of the stream and saves the connection once that
+/// server has it too.
+fn setup_stream(request: Request, buf: Vec<u8>, addr: &Addr) -> Result<()> {
+    let Request {
+        mut stream,
+        mut stream_map,
+        ..
+    } = request;
+    let msg: rpc::NetworkStreamInfo = match deserialize(&buf[..]) {
+        Ok(info) => info,
+        Err(e) => {
+            let response = serialize(&rpc::ClientReturnCode::Failure)?;
+            write_msg_and_type(response.to_vec(), rpc::MessageType::Control, &mut stream)?;
+            bail!("Error deserializing setup stream msg: {:?}", e);
+        }
+    };
+    let connection_addr = match msg.netstream.get_receiving_side() {
+        Location::Server(ip) => Addr::new(&ip, &msg.port).get_addr(),
+        Location::Client => {
+            bail!("Server {:?} cannot initiate connection to the client", addr);
+        }
+    };
+
+    // start a connection to another server
+    let mut connection = TcpStream::connect(connection_addr)?;
+    // send a pipe message to another server to setup a stream
+    let netstream_info: rpc::NetworkStreamInfo = rpc::NetworkStreamInfo {
+        loc: Location::Server(addr.get_ip()),
+        port: msg.port.clone(),
+        prog_id: msg.prog_id,
+        netstream: msg.netstream.clone(),
+    };
+    let outermsg = serialize(&netstream_info)?;
+    write_msg_and_type(outermsg.to_vec(), rpc::MessageType::Pipe, &mut connection)?;
+
+    // wait for success:
+    let (_, response_buf) = read_msg_and_type(&mut connection)?;
+    let response: rpc::ClientReturnCode = deserialize(&response_buf[..])?;
+    match response {
+        rpc::ClientReturnCode::Success => {
+            // Send the client an ACK that it made all the pipe requests
+            let ack = serialize(&rpc::ClientReturnCode::Success)?;
+            write_msg_and_type(ack.to_vec(), rpc::MessageType::Control, &mut stream)?;
+
+            // save the connection in the shared map
+            connection.set_nonblocking(true)?;
+            stream_map.insert(msg.netstream, connection)?;
+            Ok(())
+        }
+        rpc::ClientReturnCode::Failure => {
+            // Send the client a message saying that it failed
+            let nack = serialize(&rpc::ClientReturnCode::Failure)?;
+            write_msg_and_type(nack.to_vec(), rpc::MessageType::Control, &mut stream)?;
+            bail!(
+                "Failed to setup stream to another server: {:?} on server {:?}",
+                netstream_info,
+                addr
+            );
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use super::graph::{program, stream, Location};
+use super::graph::{program, progress, stream, Location};
 use super::util::Result;
 use std;
 use std::io::prelude::*;
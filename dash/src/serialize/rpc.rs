@@ -1,4 +1,4 @@
-use super::{program, stream, Location};
+use super::{program, progress, stream, Location};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 #[derive(Serialize, Deserialize, PartialEq, Debug, Copy, Clone)]
@@ -60,6 +60,13 @@ pub struct SizeRequest {
     pub failed: bool,
 }
 
+/// What has moved along each edge a server meters, sent while its part of a program runs.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ProgressReport {
+    pub prog_id: program::ProgId,
+    pub edges: Vec<progress::EdgeProgress>,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub enum MessageType {
     /// Request to execute a set of nodes
@@ -72,6 +79,8 @@ pub enum MessageType {
     SetupStreams,
     /// Request size for files
     SizeRequest,
+    /// Progress of a running program, ahead of the control message with its result.
+    Progress,
 }
 impl MessageType {
     pub fn from_u32(value: u32) -> MessageType {
@@ -81,6 +90,7 @@ impl MessageType {
             3 => MessageType::Control,
             4 => MessageType::SetupStreams,
             5 => MessageType::SizeRequest,
+            6 => MessageType::Progress,
             _ => panic!("Passing in unknown message type to constructor: {}", value),
         }
     }
@@ -92,6 +102,7 @@ impl MessageType {
             MessageType::Control => 3,
             MessageType::SetupStreams => 4,
             MessageType::SizeRequest => 5,
+            MessageType::Progress => 6,
         }
     }
 }
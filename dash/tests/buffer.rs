@@ -0,0 +1,160 @@
+mod common;
+use crate::common::TestInfo;
+use dash::graph::pipe::{
+    create_and_insert_channels, get_channel_name, BufferLimits, BufferedPipe, PipeMode,
+    SharedChannelMap, Tee,
+};
+use dash::graph::stream::IOType;
+use std::fs::read_dir;
+use std::io::{copy, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const CHUNK: usize = 1024;
+
+/// Both ends of a buffered stdout for node 1, spilling into the test's execution folder.
+fn buffered_pipe(test_info: &TestInfo, limits: BufferLimits) -> (BufferedPipe, BufferedPipe) {
+    let mut channels = SharedChannelMap::with_limits(limits);
+    buffered_pipe_to(test_info, &mut channels, 2)
+}
+
+/// Both ends of a buffered stdout from node 1 to node right.
+fn buffered_pipe_to(
+    test_info: &TestInfo,
+    channels: &mut SharedChannelMap,
+    right: u32,
+) -> (BufferedPipe, BufferedPipe) {
+    let tmp = test_info.get_execution_folder();
+    create_and_insert_channels(1, right, IOType::Stdout, tmp.as_path(), channels).unwrap();
+    let write_end = channels
+        .remove(&get_channel_name(1, right, PipeMode::Write, IOType::Stdout))
+        .unwrap();
+    let read_end = channels
+        .remove(&get_channel_name(1, right, PipeMode::Read, IOType::Stdout))
+        .unwrap();
+    (
+        BufferedPipe::new(PipeMode::Write, write_end).unwrap(),
+        BufferedPipe::new(PipeMode::Read, read_end).unwrap(),
+    )
+}
+
+/// Writes chunks numbered 0..chunks, counting the bytes the buffer has taken.
+fn write_chunks(
+    mut writer: BufferedPipe,
+    chunks: usize,
+    written: Arc<AtomicUsize>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for i in 0..chunks {
+            writer.write_all(&[(i % 251) as u8; CHUNK]).unwrap();
+            written.fetch_add(CHUNK, Ordering::SeqCst);
+        }
+        writer.set_write_done().unwrap();
+    })
+}
+
+fn check_chunks(data: &[u8], chunks: usize) {
+    assert_eq!(data.len(), chunks * CHUNK);
+    for (i, chunk) in data.chunks(CHUNK).enumerate() {
+        assert!(chunk.iter().all(|b| *b == (i % 251) as u8));
+    }
+}
+
+#[test]
+fn spills_in_order() {
+    let test_info = TestInfo::new(String::from("buffer_spill"), 1, 0, 0);
+    test_info.setup_tmp_folder();
+    let limits = BufferLimits {
+        memory: 4 * CHUNK,
+        disk: None,
+    };
+    let (writer, mut reader) = buffered_pipe(&test_info, limits);
+    let written = Arc::new(AtomicUsize::new(0));
+    // nothing reads until the writer is done, so most of this goes to disk
+    write_chunks(writer, 64, written).join().unwrap();
+    let mut data: Vec<u8> = Vec::new();
+    copy(&mut reader, &mut data).unwrap();
+    check_chunks(&data, 64);
+    reader.drop_file().unwrap();
+    let left = read_dir(test_info.get_execution_folder()).unwrap().count();
+    assert_eq!(left, 0);
+}
+
+#[test]
+fn disk_cap_holds_back_writer() {
+    let test_info = TestInfo::new(String::from("buffer_cap"), 1, 0, 0);
+    test_info.setup_tmp_folder();
+    let limits = BufferLimits {
+        memory: 2 * CHUNK,
+        disk: Some(8 * CHUNK as u64),
+    };
+    let (writer, mut reader) = buffered_pipe(&test_info, limits);
+    let written = Arc::new(AtomicUsize::new(0));
+    let write_thread = write_chunks(writer, 256, written.clone());
+    thread::sleep(Duration::from_millis(200));
+    assert!(written.load(Ordering::SeqCst) <= 10 * CHUNK);
+
+    let mut data: Vec<u8> = Vec::new();
+    copy(&mut reader, &mut data).unwrap();
+    write_thread.join().unwrap();
+    check_chunks(&data, 256);
+}
+
+#[test]
+fn reader_sees_writer_fail() {
+    let test_info = TestInfo::new(String::from("buffer_writer_fails"), 1, 0, 0);
+    test_info.setup_tmp_folder();
+    let (mut writer, mut reader) = buffered_pipe(&test_info, BufferLimits::default());
+    writer.write_all(&[0u8; CHUNK]).unwrap();
+    // dropped without set_write_done
+    drop(writer);
+    let mut data: Vec<u8> = Vec::new();
+    assert!(reader.read_to_end(&mut data).is_err());
+}
+
+#[test]
+fn writer_sees_reader_leave() {
+    let test_info = TestInfo::new(String::from("buffer_reader_leaves"), 1, 0, 0);
+    test_info.setup_tmp_folder();
+    let limits = BufferLimits {
+        memory: CHUNK,
+        disk: Some(0),
+    };
+    let (mut writer, reader) = buffered_pipe(&test_info, limits);
+    writer.write_all(&[0u8; CHUNK]).unwrap();
+    let write_thread = thread::spawn(move || writer.write_all(&[0u8; CHUNK]));
+    thread::sleep(Duration::from_millis(50));
+    drop(reader);
+    assert!(write_thread.join().unwrap().is_err());
+}
+
+#[test]
+fn tee_outlives_reader_leaving() {
+    let test_info = TestInfo::new(String::from("buffer_tee"), 1, 0, 0);
+    test_info.setup_tmp_folder();
+    let mut channels = SharedChannelMap::with_limits(BufferLimits {
+        memory: CHUNK,
+        disk: None,
+    });
+    let (first_writer, mut first_reader) = buffered_pipe_to(&test_info, &mut channels, 2);
+    let (second_writer, second_reader) = buffered_pipe_to(&test_info, &mut channels, 3);
+    let mut tee = Tee::new(vec![first_writer, second_writer]);
+    tee.write_all(&[0u8; CHUNK]).unwrap();
+    // like `head`, the second reader leaves before the stream is done
+    drop(second_reader);
+    for i in 1..64 {
+        tee.write_all(&[(i % 251) as u8; CHUNK]).unwrap();
+    }
+    tee.set_write_done().unwrap();
+    let mut data: Vec<u8> = Vec::new();
+    copy(&mut first_reader, &mut data).unwrap();
+    check_chunks(&data, 64);
+
+    // writes fail once every reader is gone
+    let (writer, reader) = buffered_pipe_to(&test_info, &mut channels, 4);
+    let mut tee = Tee::new(vec![writer]);
+    drop(reader);
+    assert!(tee.write_all(&[0u8; CHUNK]).is_err());
+}
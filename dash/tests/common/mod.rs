@@ -1,4 +1,5 @@
 use dash::graph::command::CommandNode;
+use dash::graph::compression::Compression;
 use dash::graph::filestream::{FifoMode, FifoStream, FileStream};
 use dash::graph::info::Info;
 use dash::graph::program::{Elem, Program};
@@ -267,13 +268,20 @@ pub fn generate_program(
         assert_eq!(id, i as u32);
     }
 
-    // add all the edges
+    // add all the edges; tcp edges can name a codec, like "tcp:zstd"
     for (edge, edge_info) in edges.iter() {
         let edge_type = &edge_info.0;
         let bufferable = &edge_info.1;
         let left = edge.0;
         let right = edge.1;
-        match edge_type.as_ref() {
+        let (edge_type, compression) = match edge_type.find(':') {
+            Some(pos) => (
+                &edge_type[..pos],
+                edge_type[pos + 1..].parse::<Compression>()?,
+            ),
+            None => (edge_type.as_str(), Compression::None),
+        };
+        match edge_type {
             "pipe" => {
                 let mut pipe = PipeStream::new(left as u32, right as u32, IOType::Stdout)?;
                 if *bufferable {
@@ -301,6 +309,7 @@ pub fn generate_program(
                 if *bufferable {
                     pipe.set_bufferable();
                 }
+                pipe.set_compression(compression);
                 let left_node = prog.get_mut_node(left as u32).unwrap().get_mut_elem();
                 {
                     left_node.add_stdout(DashStream::Tcp(pipe.clone())).unwrap()
@@ -0,0 +1,99 @@
+mod common;
+use crate::common::TestInfo;
+use dash::graph::eventloop::{Endpoint, EventLoop, Transfers};
+use std::fs::File;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Connected pair of non-blocking streams, like the runtime hands to nodes.
+fn nonblocking_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (receiver, _) = listener.accept().unwrap();
+    sender.set_nonblocking(true).unwrap();
+    receiver.set_nonblocking(true).unwrap();
+    (sender, receiver)
+}
+
+/// Sends the test's inputs, one after the other, over tcp into its output file.
+fn forward_through_tcp(transfers: &Transfers, test_info: &TestInfo, num_inputs: u32) {
+    let (sender, receiver) = nonblocking_pair();
+    let inputs: Vec<Box<dyn Endpoint>> = (0..num_inputs)
+        .map(|i| Box::new(File::open(test_info.input_file_name(i)).unwrap()) as Box<dyn Endpoint>)
+        .collect();
+    let output = File::create(test_info.output_file_name()).unwrap();
+    transfers
+        .forward("file_to_tcp", inputs, Box::new(sender))
+        .unwrap();
+    transfers
+        .forward("tcp_to_file", vec![Box::new(receiver)], Box::new(output))
+        .unwrap();
+}
+
+#[test]
+fn concatenate_inputs() {
+    let test_info = TestInfo::new(String::from("eventloop_concatenate"), 1, 3, 5000);
+    test_info.setup_tmp_folder();
+    test_info.generate_input();
+    let event_loop = EventLoop::new(1).unwrap();
+    let transfers = Transfers::new(&event_loop);
+    forward_through_tcp(&transfers, &test_info, 3);
+    transfers.wait().unwrap();
+    assert!(test_info.check_original_output());
+}
+
+#[test]
+fn many_transfers_one_thread() {
+    let tests: Vec<TestInfo> = (0..16)
+        .map(|i| TestInfo::new(format!("eventloop_many_{}", i), i, 1, 5000))
+        .collect();
+    let event_loop = EventLoop::new(1).unwrap();
+    let transfers = Transfers::new(&event_loop);
+    for test_info in tests.iter() {
+        test_info.setup_tmp_folder();
+        test_info.generate_input();
+        forward_through_tcp(&transfers, test_info, 1);
+    }
+    transfers.wait().unwrap();
+    for test_info in tests.iter() {
+        assert!(test_info.check_original_output());
+    }
+}
+
+#[test]
+fn on_done_after_finishing() {
+    let event_loop = EventLoop::new(1).unwrap();
+    let transfers = Transfers::new(&event_loop);
+    transfers
+        .spawn("failing", || failure::bail!("redirection failed"))
+        .unwrap();
+    transfers.spawn("succeeding", || Ok(())).unwrap();
+    assert!(transfers.wait().is_err());
+}
+
+#[test]
+fn timers_fire_in_order_while_transfers_run() {
+    let test_info = TestInfo::new(String::from("eventloop_timers"), 1, 1, 5000);
+    test_info.setup_tmp_folder();
+    test_info.generate_input();
+    let event_loop = EventLoop::new(1).unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let start = Instant::now();
+    for delay in [60, 20].iter() {
+        let sender = sender.clone();
+        event_loop
+            .after(Duration::from_millis(*delay), move || {
+                sender.send((*delay, start.elapsed())).unwrap();
+            })
+            .unwrap();
+    }
+    let transfers = Transfers::new(&event_loop);
+    forward_through_tcp(&transfers, &test_info, 1);
+    transfers.wait().unwrap();
+    assert!(test_info.check_original_output());
+    let fired: Vec<(u64, Duration)> = receiver.iter().take(2).collect();
+    assert_eq!(fired[0].0, 20);
+    assert_eq!(fired[1].0, 60);
+    assert!(fired[1].1 >= Duration::from_millis(60));
+}
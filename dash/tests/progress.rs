@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+mod common;
+use crate::common::{generate_program, NodeInfo, TestInfo, WriteType};
+use dash::graph::progress::{EdgeProgress, Progress, Throughput};
+use dash::graph::stream::SharedStreamMap;
+use std::fs::metadata;
+use std::time::{Duration, Instant};
+
+#[test]
+fn counts_each_edge() {
+    let test_info = TestInfo::new(String::from("progress_edges"), 1, 1, 1000);
+    test_info.setup_tmp_folder();
+    test_info.generate_input();
+    let nodes = vec!["cat", "grep", "write"];
+    let mut edges: HashMap<(usize, usize), (String, bool)> = HashMap::default();
+    edges.insert((1, 2), (String::from("pipe"), false));
+    edges.insert((2, 3), (String::from("pipe"), false));
+    let mut node_data: HashMap<usize, NodeInfo> = HashMap::default();
+    node_data.insert(
+        1,
+        NodeInfo {
+            input_file: Some(test_info.input_file_name(0)),
+            ..Default::default()
+        },
+    );
+    node_data.insert(
+        2,
+        NodeInfo {
+            input_keyword: Some(String::from("d")),
+            ..Default::default()
+        },
+    );
+    node_data.insert(
+        3,
+        NodeInfo {
+            output_file: Some((test_info.output_file_name(), WriteType::File)),
+            ..Default::default()
+        },
+    );
+    let mut test_prog = generate_program(&nodes, &edges, &node_data).unwrap();
+    test_prog.set_progress().unwrap();
+
+    let progress = Progress::new();
+    test_prog
+        .execute_with_progress(
+            SharedStreamMap::new(),
+            test_info
+                .get_execution_folder()
+                .as_path()
+                .to_str()
+                .unwrap()
+                .to_string(),
+            &progress,
+        )
+        .unwrap();
+
+    let keywords = vec!["d"];
+    assert!(test_info.check_grepped_output(&keywords));
+    let input_bytes = metadata(test_info.input_file_name(0)).unwrap().len();
+    let output_bytes = metadata(test_info.output_file_name()).unwrap().len();
+    let counted = progress.snapshot().unwrap();
+    assert_eq!(
+        counted,
+        vec![
+            EdgeProgress {
+                left: 1,
+                right: 2,
+                bytes: input_bytes,
+                lines: test_info.content().len() as u64,
+            },
+            EdgeProgress {
+                left: 2,
+                right: 3,
+                bytes: output_bytes,
+                lines: test_info.grepped_content("d").len() as u64,
+            },
+        ]
+    );
+    test_info.delete_folder();
+}
+
+#[test]
+fn finds_bottleneck() {
+    let edge = |left, right, bytes| EdgeProgress {
+        left: left,
+        right: right,
+        bytes: bytes,
+        lines: 0,
+    };
+    let mut throughput = Throughput::default();
+    let start = Instant::now();
+    let first = throughput.sample(vec![edge(1, 2, 0), edge(2, 3, 0), edge(3, 4, 0)], start);
+    assert_eq!(first.bottleneck, None);
+
+    // node 4 takes its input in more slowly than node 3 does, so it holds the rest back; the
+    // edge into node 2 is done
+    let second = throughput.sample(
+        vec![edge(1, 2, 0), edge(2, 3, 4000), edge(3, 4, 1000)],
+        start + Duration::from_secs(2),
+    );
+    let rates: Vec<f64> = second.edges.iter().map(|rate| rate.bytes_per_sec).collect();
+    assert_eq!(rates, vec![0.0, 2000.0, 500.0]);
+    assert_eq!(second.bottleneck, Some(4));
+}
@@ -282,6 +282,122 @@ fn cmd_tcp_cmd_write() {
     test_info.delete_folder();
 }
 
+#[test]
+// compressed on the way out of the read node, decompressed by the write node
+fn read_tcp_write_zstd() {
+    let test_info = TestInfo::new(String::from("read_tcp_zstd"), 1, 1, 1000);
+    test_info.setup_tmp_folder();
+    test_info.generate_input();
+    let nodes = vec!["read", "write"];
+    let mut edges: HashMap<(usize, usize), (String, bool)> = HashMap::default();
+    edges.insert((1, 2), (String::from("tcp:zstd"), false));
+    let mut node_data: HashMap<usize, NodeInfo> = HashMap::default();
+    node_data.insert(
+        1,
+        NodeInfo {
+            input_file: Some(test_info.input_file_name(0)),
+            location: server(),
+            ..Default::default()
+        },
+    );
+    node_data.insert(
+        2,
+        NodeInfo {
+            output_file: Some((test_info.output_file_name(), WriteType::File)),
+            ..Default::default()
+        },
+    );
+    let mut prog = match generate_program(&nodes, &edges, &node_data) {
+        Ok(prog) => prog,
+        Err(e) => {
+            println!(
+                "Failed to generate prog with nodes {:?}, edges {:?}, node data {:?}",
+                nodes, edges, node_data
+            );
+            panic!("Error: {:?}", e);
+        }
+    };
+
+    let execution_tmp = test_info
+        .get_execution_folder()
+        .as_path()
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    match execute_test_program(&execution_tmp.as_str(), &mut prog) {
+        Ok(_) => {}
+        Err(e) => {
+            panic!("Issue executing program: {:?}", e);
+        }
+    }
+
+    assert!(test_info.check_original_output());
+    test_info.delete_folder();
+}
+
+#[test]
+// buffered command output, compressed and decompressed into the next command's stdin
+fn cmd_tcp_cmd_write_lz4() {
+    let test_info = TestInfo::new(String::from("cmd_tcp_lz4"), 1, 1, 1000);
+    test_info.setup_tmp_folder();
+    test_info.generate_input();
+    let nodes = vec!["cat", "grep", "write"];
+    let mut edges: HashMap<(usize, usize), (String, bool)> = HashMap::default();
+    edges.insert((1, 2), (String::from("tcp:lz4"), true));
+    edges.insert((2, 3), (String::from("pipe"), false));
+    let mut node_data: HashMap<usize, NodeInfo> = HashMap::default();
+    node_data.insert(
+        1,
+        NodeInfo {
+            input_file: Some(test_info.input_file_name(0)),
+            location: server(),
+            ..Default::default()
+        },
+    );
+    node_data.insert(
+        2,
+        NodeInfo {
+            input_keyword: Some(String::from("f")),
+            ..Default::default()
+        },
+    );
+    node_data.insert(
+        3,
+        NodeInfo {
+            output_file: Some((test_info.output_file_name(), WriteType::File)),
+            ..Default::default()
+        },
+    );
+    let mut prog = match generate_program(&nodes, &edges, &node_data) {
+        Ok(prog) => prog,
+        Err(e) => {
+            println!(
+                "Failed to generate prog with nodes {:?}, edges {:?}, node data {:?}",
+                nodes, edges, node_data
+            );
+            panic!("Error: {:?}", e);
+        }
+    };
+
+    let execution_tmp = test_info
+        .get_execution_folder()
+        .as_path()
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    match execute_test_program(&execution_tmp.as_str(), &mut prog) {
+        Ok(_) => {}
+        Err(e) => {
+            panic!("Issue executing program: {:?}", e);
+        }
+    }
+
+    assert!(test_info.check_grepped_output(&vec!["f"]));
+    test_info.delete_folder();
+}
+
 #[test]
 fn double_tcp_buffering() {
     // two input files
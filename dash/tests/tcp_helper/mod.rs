@@ -1,6 +1,7 @@
 extern crate rand;
 use bincode::{deserialize, serialize};
 use dash::graph::program::Program;
+use dash::graph::progress::Progress;
 use dash::graph::stream::SharedStreamMap;
 use dash::graph::Location;
 use dash::runtime::new_client::execute_subprogram;
@@ -167,8 +168,14 @@ fn send_program(
         let port = port.to_string();
         let tmp_folder = tmp_folder.to_string();
         execution_threads.push(thread::spawn(move || {
-            let ret =
-                execute_subprogram(location.clone(), program, shared_map_copy, port, tmp_folder);
+            let ret = execute_subprogram(
+                location.clone(),
+                program,
+                shared_map_copy,
+                port,
+                tmp_folder,
+                Progress::default(),
+            );
             ret
         }));
     }
@@ -0,0 +1,50 @@
+mod common;
+use crate::common::TestInfo;
+use dash::graph::tmpdir::{self, TmpDir};
+use std::fs::{read_dir, File};
+use std::time::Duration;
+
+#[test]
+fn remove_deletes_registered_files() {
+    let test_info = TestInfo::new(String::from("tmpdir_remove"), 1, 0, 0);
+    test_info.setup_tmp_folder();
+    let tmp = test_info.get_execution_folder();
+    let tmp_dir = TmpDir::create(&tmp, "session", 1, 1).unwrap();
+    let staged = tmp.join(tmpdir::namespace("session", 1)).join("staged.txt");
+    tmp_dir.register(&staged).unwrap();
+    File::create(&staged).unwrap();
+    File::create(tmp_dir.get_path().join("buffer")).unwrap();
+
+    tmp_dir.remove().unwrap();
+    assert_eq!(read_dir(&tmp).unwrap().count(), 0);
+}
+
+#[test]
+fn remove_keeps_other_parts() {
+    let test_info = TestInfo::new(String::from("tmpdir_parts"), 1, 0, 0);
+    test_info.setup_tmp_folder();
+    let tmp = test_info.get_execution_folder();
+    let client = TmpDir::create(&tmp, "session", 1, 1).unwrap();
+    let server = TmpDir::create(&tmp, "session", 1, 3).unwrap();
+    client.remove().unwrap();
+    assert!(server.get_path().exists());
+    server.remove().unwrap();
+    assert_eq!(read_dir(&tmp).unwrap().count(), 0);
+}
+
+#[test]
+fn sweep_removes_stale_dirs() {
+    let test_info = TestInfo::new(String::from("tmpdir_sweep"), 1, 0, 0);
+    test_info.setup_tmp_folder();
+    let tmp = test_info.get_execution_folder();
+    let tmp_dir = TmpDir::create(&tmp, "session", 1, 1).unwrap();
+    let staged = tmp.join(tmpdir::namespace("session", 1)).join("staged.txt");
+    tmp_dir.register(&staged).unwrap();
+    File::create(&staged).unwrap();
+    TmpDir::create(&tmp, "session", 2, 1).unwrap();
+
+    assert_eq!(tmpdir::sweep(&tmp, Duration::from_secs(3600)).unwrap(), 0);
+    assert!(staged.exists());
+    assert_eq!(tmpdir::sweep(&tmp, Duration::from_secs(0)).unwrap(), 2);
+    assert_eq!(read_dir(&tmp).unwrap().count(), 0);
+}
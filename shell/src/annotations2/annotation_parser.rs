@@ -170,7 +170,9 @@ named_complete!(
 named_complete!(
     parse_command_name<String>,
     map!(
-        many1!(alt!(alpha1 | tag!(" ") | tag!("/") | tag!("-"))),
+        many1!(alt!(
+            alphanumeric1 | tag!(" ") | tag!("/") | tag!("-") | tag!("_") | tag!(".")
+        )),
         |elts: Vec<CompleteByteSlice>| {
             let mut name = "".to_string();
             for elt in elts.iter() {
@@ -214,6 +216,10 @@ named_complete!(
     parse_attached<Info>,
     map!(tag!("attached"), |_| { Info::Attached })
 );
+named_complete!(
+    parse_appends<Info>,
+    map!(tag!("appends"), |_| { Info::Appends })
+);
 named_complete!(
     parse_individual_info<Info>,
     alt!(
@@ -226,13 +232,25 @@ named_complete!(
             | parse_multiple
             | parse_splittable
             | parse_attached
+            | parse_appends
+    )
+);
+// fields each kind of argument takes; options take all of them
+named_complete!(
+    parse_flag_info<Info>,
+    alt!(parse_short | parse_long | parse_desc | parse_multiple)
+);
+named_complete!(
+    parse_param_info<Info>,
+    alt!(
+        parse_type | parse_size | parse_default | parse_multiple | parse_splittable | parse_appends
     )
 );
 named_complete!(
     parse_param<Result<Param>>,
     map!(
         many1!(do_parse!(
-            info: parse_individual_info >> opt!(tag!(",")) >> (info)
+            info: parse_param_info >> opt!(tag!(",")) >> (info)
         )),
         |vec_options: Vec<Info>| {
             let mut param: Param = Default::default();
@@ -253,6 +271,9 @@ named_complete!(
                     Info::Splittable => {
                         param.splittable = true;
                     }
+                    Info::Appends => {
+                        param.appends = true;
+                    }
                     _ => {
                         bail!("Could not parse individual param: provide only type, size, default value");
                     }
@@ -302,6 +323,9 @@ named_complete!(
                     Info::Attached => {
                         param.attached_to_short = true;
                     }
+                    Info::Appends => {
+                        param.appends = true;
+                    }
                     _ => {
                         bail!("Could not parse individual param: provide only type, size, default value");
                     }
@@ -316,7 +340,7 @@ named_complete!(
     parse_flag<Result<Opt>>,
     map!(
         many1!(do_parse!(
-            info: delimited!(opt!(tag!(",")), parse_individual_info, opt!(tag!(","))) >> (info)
+            info: delimited!(opt!(tag!(",")), parse_flag_info, opt!(tag!(","))) >> (info)
         )),
         |vec_options: Vec<Info>| {
             let mut opt: Opt = Default::default();
@@ -344,107 +368,136 @@ named_complete!(
     )
 );
 
+/// Errors past the start of each part of an annotation are failures, so the parsers around them
+/// don't backtrack and the error keeps the input left where it happened. Each code names what
+/// was expected there.
+const COMMAND_OPTIONS: u32 = 1;
+const COLON: u32 = 2;
+const SECTION: u32 = 3;
+const OPEN_BRACKET: u32 = 4;
+const OPEN_ARGUMENT: u32 = 5;
+const CLOSE_ARGUMENTS: u32 = 6;
+const FLAG_FIELD: u32 = 7;
+const OPT_PARAM_FIELD: u32 = 8;
+const PARAM_FIELD: u32 = 9;
+
+/// How much input is left, to find where each argument starts.
+fn remaining_len(i: CompleteByteSlice) -> IResult<CompleteByteSlice, usize, u32> {
+    Ok((i, i.0.len()))
+}
+
 named_complete!(
-    parse_flag_set<Vec<Result<Opt>>>,
-    many1!(do_parse!(
-        tag!("(") >> flag: parse_flag >> tag!(")") >> opt!(tag!(",")) >> (flag)
+    parse_flag_set<Vec<(usize, Result<Opt>)>>,
+    many0!(do_parse!(
+        left: remaining_len
+            >> tag!("(")
+            >> flag: return_error!(ErrorKind::Custom(FLAG_FIELD), parse_flag)
+            >> return_error!(ErrorKind::Custom(FLAG_FIELD), tag!(")"))
+            >> opt!(tag!(","))
+            >> ((left, flag))
     ))
 );
 named_complete!(
-    parse_opt_with_param_set<Vec<Result<(Opt, Param)>>>,
-    many1!(do_parse!(
-        tag!("(") >> opt_param: parse_opt_with_param >> tag!(")") >> opt!(tag!(",")) >> (opt_param)
+    parse_opt_with_param_set<Vec<(usize, Result<(Opt, Param)>)>>,
+    many0!(do_parse!(
+        left: remaining_len
+            >> tag!("(")
+            >> opt_param: return_error!(ErrorKind::Custom(OPT_PARAM_FIELD), parse_opt_with_param)
+            >> return_error!(ErrorKind::Custom(OPT_PARAM_FIELD), tag!(")"))
+            >> opt!(tag!(","))
+            >> ((left, opt_param))
     ))
 );
 named_complete!(
-    parse_param_set<Vec<Result<Param>>>,
-    many1!(do_parse!(
-        tag!("(") >> param: parse_param >> tag!(")") >> opt!(tag!(",")) >> (param)
+    parse_param_set<Vec<(usize, Result<Param>)>>,
+    many0!(do_parse!(
+        left: remaining_len
+            >> tag!("(")
+            >> param: return_error!(ErrorKind::Custom(PARAM_FIELD), parse_param)
+            >> return_error!(ErrorKind::Custom(PARAM_FIELD), tag!(")"))
+            >> opt!(tag!(","))
+            >> ((left, param))
     ))
 );
 
+/// Collects the arguments of a section, each with how much input was left where it starts.
+fn section_arguments<T>(
+    set: Vec<(usize, Result<T>)>,
+    argument: fn(T) -> Argument,
+) -> Result<Vec<(usize, Argument)>> {
+    let mut ret: Vec<(usize, Argument)> = Vec::new();
+    for (left, arg) in set {
+        ret.push((left, argument(arg?)));
+    }
+    Ok(ret)
+}
+
 named_complete!(
-    parse_params<Result<Vec<Argument>>>,
+    parse_params<Result<Vec<(usize, Argument)>>>,
     map!(
         do_parse!(
-            tag!("PARAMS:") >> tag!("[") >> param_set: parse_param_set >> tag!("]") >> (param_set)
+            tag!("PARAMS:")
+                >> return_error!(ErrorKind::Custom(OPEN_BRACKET), tag!("["))
+                >> return_error!(ErrorKind::Custom(OPEN_ARGUMENT), peek!(tag!("(")))
+                >> param_set: parse_param_set
+                >> return_error!(ErrorKind::Custom(CLOSE_ARGUMENTS), tag!("]"))
+                >> (param_set)
         ),
-        |option_set: Vec<Result<Param>>| {
-            let mut ret: Vec<Argument> = Vec::new();
-            for opt in option_set {
-                match opt {
-                    Ok(o) => {
-                        ret.push(Argument::LoneParam(o));
-                    }
-                    Err(e) => {
-                        bail!("Error in LoneParam: {:?}", e);
-                    }
-                }
-            }
-            Ok(ret)
-        }
+        |param_set: Vec<(usize, Result<Param>)>| section_arguments(param_set, Argument::LoneParam)
     )
 );
 
 named_complete!(
-    parse_opt_with_params<Result<Vec<Argument>>>,
+    parse_opt_with_params<Result<Vec<(usize, Argument)>>>,
     map!(
         do_parse!(
             tag!("OPTPARAMS:")
-                >> tag!("[")
+                >> return_error!(ErrorKind::Custom(OPEN_BRACKET), tag!("["))
+                >> return_error!(ErrorKind::Custom(OPEN_ARGUMENT), peek!(tag!("(")))
                 >> param_set: parse_opt_with_param_set
-                >> tag!("]")
+                >> return_error!(ErrorKind::Custom(CLOSE_ARGUMENTS), tag!("]"))
                 >> (param_set)
         ),
-        |option_set: Vec<Result<(Opt, Param)>>| {
-            let mut ret: Vec<Argument> = vec![];
-            for opt in option_set {
-                match opt {
-                    Ok(o) => {
-                        ret.push(Argument::OptWithParam(o.0, o.1));
-                    }
-                    Err(e) => {
-                        bail!("Error in LoneParam: {:?}", e);
-                    }
-                }
-            }
-            Ok(ret)
+        |param_set: Vec<(usize, Result<(Opt, Param)>)>| {
+            section_arguments(param_set, |(opt, param)| Argument::OptWithParam(opt, param))
         }
     )
 );
 
 named_complete!(
-    parse_flags<Result<Vec<Argument>>>,
+    parse_flags<Result<Vec<(usize, Argument)>>>,
     map!(
         do_parse!(
-            tag!("FLAGS:") >> tag!("[") >> param_set: parse_flag_set >> tag!("]") >> (param_set)
+            tag!("FLAGS:")
+                >> return_error!(ErrorKind::Custom(OPEN_BRACKET), tag!("["))
+                >> return_error!(ErrorKind::Custom(OPEN_ARGUMENT), peek!(tag!("(")))
+                >> param_set: parse_flag_set
+                >> return_error!(ErrorKind::Custom(CLOSE_ARGUMENTS), tag!("]"))
+                >> (param_set)
         ),
-        |option_set: Vec<Result<Opt>>| {
-            let mut ret: Vec<Argument> = vec![];
-            for opt in option_set {
-                match opt {
-                    Ok(o) => {
-                        ret.push(Argument::LoneOption(o));
-                    }
-                    Err(e) => {
-                        bail!("Error in LoneParam: {:?}", e);
-                    }
-                }
-            }
-            Ok(ret)
-        }
+        |param_set: Vec<(usize, Result<Opt>)>| section_arguments(param_set, Argument::LoneOption)
     )
 );
 named_complete!(
-    parse_arguments<Result<Vec<Argument>>>,
+    parse_section_name<CompleteByteSlice>,
+    alt!(tag!("FLAGS:") | tag!("OPTPARAMS:") | tag!("PARAMS:"))
+);
+named_complete!(
+    parse_arguments<Result<Vec<(usize, Argument)>>>,
     map!(
-        many1!(do_parse!(
+        do_parse!(
             opt!(tag!(" "))
-                >> ret: alt!(parse_flags | parse_opt_with_params | parse_params)
-                >> (ret)
-        )),
-        |vec: Vec<Result<Vec<Argument>>>| {
-            let mut ret: Vec<Argument> = Vec::new();
+                >> return_error!(ErrorKind::Custom(SECTION), peek!(parse_section_name))
+                >> sections:
+                    many0!(do_parse!(
+                        opt!(tag!(" "))
+                            >> ret: alt!(parse_flags | parse_opt_with_params | parse_params)
+                            >> (ret)
+                    ))
+                >> (sections)
+        ),
+        |vec: Vec<Result<Vec<(usize, Argument)>>>| {
+            let mut ret: Vec<(usize, Argument)> = Vec::new();
 
             for v in vec {
                 match v {
@@ -492,6 +545,89 @@ named_complete!(
     })
 );
 
+named_complete!(
+    parse_params_first<IndividualParseOption>,
+    map!(tag!("params_first"), {
+        |_| IndividualParseOption::ParamsFirst
+    })
+);
+
+named_complete!(
+    parse_random_access<IndividualParseOption>,
+    map!(tag!("random_access"), {
+        |_| IndividualParseOption::RandomAccess
+    })
+);
+
+named_complete!(
+    parse_implicit_input<IndividualParseOption>,
+    map!(
+        do_parse!(
+            tag!("implicit_input:") >> tag!("(") >> path: take_until!(")") >> tag!(")") >> (path)
+        ),
+        |s: CompleteByteSlice| {
+            IndividualParseOption::ImplicitInput(String::from(str::from_utf8(s.0).unwrap()))
+        }
+    )
+);
+
+named_complete!(
+    parse_implicit_output<IndividualParseOption>,
+    map!(
+        do_parse!(
+            tag!("implicit_output:") >> tag!("(") >> path: take_until!(")") >> tag!(")") >> (path)
+        ),
+        |s: CompleteByteSlice| {
+            IndividualParseOption::ImplicitOutput(String::from(str::from_utf8(s.0).unwrap()))
+        }
+    )
+);
+
+named_complete!(
+    parse_selectivity<IndividualParseOption>,
+    map!(
+        do_parse!(
+            tag!("selectivity:") >> tag!("(") >> ratio: take_until!(")") >> tag!(")") >> (ratio)
+        ),
+        |s: CompleteByteSlice| {
+            IndividualParseOption::Selectivity(String::from(str::from_utf8(s.0).unwrap()))
+        }
+    )
+);
+
+named_complete!(
+    parse_expands_input<IndividualParseOption>,
+    map!(tag!("expands_input"), {
+        |_| IndividualParseOption::ExpandsInput
+    })
+);
+
+named_complete!(
+    parse_constant_output<IndividualParseOption>,
+    map!(tag!("constant_output"), {
+        |_| IndividualParseOption::ConstantOutput
+    })
+);
+
+named_complete!(
+    parse_output_per_input_file<IndividualParseOption>,
+    map!(tag!("output_per_input_file"), {
+        |_| IndividualParseOption::OutputPerInputFile
+    })
+);
+
+named_complete!(
+    parse_combiner<IndividualParseOption>,
+    map!(
+        do_parse!(
+            tag!("combiner:") >> tag!("(") >> combiner: take_until!(")") >> tag!(")") >> (combiner)
+        ),
+        |s: CompleteByteSlice| {
+            IndividualParseOption::Combiner(String::from(str::from_utf8(s.0).unwrap()))
+        }
+    )
+);
+
 named_complete!(
     parse_individual_parsing_option<IndividualParseOption>,
     alt!(
@@ -499,6 +635,15 @@ named_complete!(
             | parse_splittable_across_input
             | parse_reduces_input
             | parse_needs_current_dir
+            | parse_params_first
+            | parse_random_access
+            | parse_implicit_input
+            | parse_implicit_output
+            | parse_selectivity
+            | parse_expands_input
+            | parse_constant_output
+            | parse_output_per_input_file
+            | parse_combiner
     )
 );
 named_complete!(
@@ -527,6 +672,44 @@ named_complete!(
                     IndividualParseOption::NeedsCurrentDir => {
                         parsing_opt.needs_current_dir = true;
                     }
+                    IndividualParseOption::ParamsFirst => {
+                        parsing_opt.params_first = true;
+                    }
+                    IndividualParseOption::RandomAccess => {
+                        parsing_opt.access_type = AccessType::Random;
+                    }
+                    IndividualParseOption::ImplicitInput(path) => {
+                        parsing_opt.implicit_files.push(ImplicitFile {
+                            file_type: ArgType::InputFile,
+                            path: path.clone(),
+                        });
+                    }
+                    IndividualParseOption::Selectivity(ratio) => {
+                        match ratio.trim().parse::<f64>() {
+                            Ok(ratio) if ratio > 0.0 && ratio.is_finite() => {
+                                parsing_opt.output_size = Some(OutputSize::Ratio(ratio));
+                            }
+                            _ => bail!("selectivity should be a positive number, not {:?}", ratio),
+                        }
+                    }
+                    IndividualParseOption::ExpandsInput => {
+                        parsing_opt.output_size = Some(OutputSize::ExpandsInput);
+                    }
+                    IndividualParseOption::ConstantOutput => {
+                        parsing_opt.output_size = Some(OutputSize::Constant);
+                    }
+                    IndividualParseOption::OutputPerInputFile => {
+                        parsing_opt.output_size = Some(OutputSize::PerInputFile);
+                    }
+                    IndividualParseOption::Combiner(combiner) => {
+                        parsing_opt.combiner = Combiner::new(combiner);
+                    }
+                    IndividualParseOption::ImplicitOutput(path) => {
+                        parsing_opt.implicit_files.push(ImplicitFile {
+                            file_type: ArgType::OutputFile,
+                            path: path.clone(),
+                        });
+                    }
                 }
             }
             Ok(parsing_opt)
@@ -538,27 +721,32 @@ named_complete!(
 // syntax so it works?
 // Then, need to define the syntax for splitting commands across inputs
 named_complete!(
-    pub parse_annotation<Result<Command>>,
+    pub parse_annotation<Result<(Command, Vec<usize>)>>,
     map!(
         do_parse!(
             name: parse_command_name
-                >> opt!(tag!("["))
-                >> parsing_options: parse_parsing_options
-                >> opt!(tag!("]"))
-                >> tag!(":")
+                >> parsing_options:
+                    opt!(do_parse!(
+                        tag!("[")
+                            >> opts: parse_parsing_options
+                            >> return_error!(ErrorKind::Custom(COMMAND_OPTIONS), tag!("]"))
+                            >> (opts)
+                    ))
+                >> return_error!(ErrorKind::Custom(COLON), tag!(":"))
                 >> arg_list: parse_arguments
                 >> (name, parsing_options, arg_list)
         ),
         |(name, parsing_options, arg_list): (
             String,
-            Result<ParsingOptions>,
-            Result<Vec<Argument>>
+            Option<Result<ParsingOptions>>,
+            Result<Vec<(usize, Argument)>>
         )| {
             let opts = match parsing_options {
-                Ok(o) => o,
-                Err(e) => {
+                Some(Ok(o)) => o,
+                Some(Err(e)) => {
                     bail!("Could not parse parsing options: {:?}", e);
                 }
+                None => ParsingOptions::default(),
             };
             let args = match arg_list {
                 Ok(a) => a,
@@ -566,15 +754,191 @@ named_complete!(
                     bail!("Could not parse args: {:?}", e);
                 }
             };
-            Ok(Command {
-                command_name: name,
-                args: args,
-                parsing_options: opts,
-            })
+            let (lefts, args): (Vec<usize>, Vec<Argument>) = args.into_iter().unzip();
+            Ok((
+                Command {
+                    command_name: name,
+                    args: args,
+                    parsing_options: opts,
+                },
+                lefts,
+            ))
         }
     )
 );
 
+/// Parses one annotation in the one-line syntax, along with the column (starting at 1) where each
+/// of its arguments starts. Syntax errors point at the column where the parser stopped.
+pub fn parse_annotation_line(ann: &str) -> Result<(Command, Vec<usize>)> {
+    let (column, message) = match parse_annotation(CompleteByteSlice(ann.as_bytes())) {
+        Ok((rest, parsed)) => {
+            let trailing = rest
+                .0
+                .iter()
+                .take_while(|b| b.is_ascii_whitespace())
+                .count();
+            if trailing == rest.0.len() {
+                let (cmd, lefts) = parsed?;
+                let columns = lefts.iter().map(|left| ann.len() - left + 1).collect();
+                return Ok((cmd, columns));
+            }
+            // anything left over would otherwise be silently dropped
+            describe_syntax_error(ann, ann.len() - rest.0.len() + trailing, SECTION)
+        }
+        Err(Err::Failure(Context::Code(rest, ErrorKind::Custom(code)))) => {
+            describe_syntax_error(ann, ann.len() - rest.0.len(), code)
+        }
+        Err(_) => (
+            1,
+            "expected a command name made of letters, digits, spaces, `/`, `-`, `_` and `.`"
+                .to_string(),
+        ),
+    };
+    bail!("{}", point_at(ann, column, &message))
+}
+
+/// The column (starting at 1) of a syntax error found at `pos` and what was expected there.
+fn describe_syntax_error(ann: &str, pos: usize, code: u32) -> (usize, String) {
+    let message = match code {
+        COMMAND_OPTIONS if ann[pos..].starts_with(|c: char| c.is_alphanumeric()) => format!(
+            "unknown command option {}; expected long_arg_single_dash, splittable_across_input, reduces_input, needs_current_dir, params_first, random_access, implicit_input:(path), implicit_output:(path), selectivity:(ratio), expands_input, constant_output, output_per_input_file or combiner:(combiner)",
+            token_at(ann, pos)
+        ),
+        COMMAND_OPTIONS => format!(
+            "expected `]` after the command options, found {}",
+            token_at(ann, pos)
+        ),
+        COLON => format!(
+            "expected `:` after the command name, found {}",
+            token_at(ann, pos)
+        ),
+        SECTION => {
+            // a missing colon makes the first section part of the name, e.g. `tar FLAGS:[`
+            if let Some(name) = ann[..pos]
+                .trim_end()
+                .strip_suffix(':')
+                .filter(|name| !name.contains(':'))
+            {
+                let last_word = name.rsplit(' ').next().unwrap_or("");
+                if name.contains(' ') && ["FLAGS", "OPTPARAMS", "PARAMS"].contains(&last_word) {
+                    return (
+                        name.len() - last_word.len(),
+                        "expected `:` after the command name".to_string(),
+                    );
+                }
+            }
+            format!(
+                "expected FLAGS:, OPTPARAMS: or PARAMS:, found {}",
+                token_at(ann, pos)
+            )
+        }
+        OPEN_BRACKET => format!(
+            "expected `[` after the section name, found {}",
+            token_at(ann, pos)
+        ),
+        OPEN_ARGUMENT => format!(
+            "expected `(` to start an argument, found {}",
+            token_at(ann, pos)
+        ),
+        CLOSE_ARGUMENTS => format!(
+            "expected `(` or `]` after an argument, found {}",
+            token_at(ann, pos)
+        ),
+        FLAG_FIELD => describe_field_error(ann, pos, "FLAGS", "short, long, desc and multiple"),
+        OPT_PARAM_FIELD => describe_field_error(
+            ann,
+            pos,
+            "OPTPARAMS",
+            "short, long, desc, type, size, default_value, multiple, splittable, attached and appends",
+        ),
+        _ => describe_field_error(
+            ann,
+            pos,
+            "PARAMS",
+            "type, size, default_value, multiple, splittable and appends",
+        ),
+    };
+    (pos + 1, message)
+}
+
+/// Describes why the fields of an argument stopped parsing at `pos`: a field that doesn't parse,
+/// one the section doesn't allow, or something other than `,` or `)` after a field.
+fn describe_field_error(ann: &str, pos: usize, section_name: &str, allowed: &str) -> String {
+    if pos > 0 && !ann[..pos].ends_with(|c| c == '(' || c == ',') {
+        return format!(
+            "expected `,` or `)` after a field, found {}",
+            token_at(ann, pos)
+        );
+    }
+    match parse_individual_info(CompleteByteSlice(ann[pos..].as_bytes())) {
+        Ok((_, info)) => {
+            let field = match info {
+                Info::ParamType(_) => "type",
+                Info::Size(_) => "size",
+                Info::DefaultValue(_) => "default_value",
+                Info::Delim(_) => "list_separator",
+                Info::Short(_) => "short",
+                Info::Long(_) => "long",
+                Info::Desc(_) => "desc",
+                Info::Multiple => "multiple",
+                Info::Splittable => "splittable",
+                Info::Attached => "attached",
+                Info::Appends => "appends",
+            };
+            format!(
+                "{} is not allowed in {}; arguments there take {}",
+                field, section_name, allowed
+            )
+        }
+        Err(_) => describe_bad_field(&field_at(ann, pos), section_name, allowed),
+    }
+}
+
+fn describe_bad_field(field: &str, section_name: &str, allowed: &str) -> String {
+    let key = field.split(':').next().unwrap_or("");
+    let expected = match key {
+        "type" => "type:input_file, type:output_file or type:str",
+        "size" => "size:0, size:1, size:list(list_separator:( )) or size:specific_size(size:2,list_separator:( ))",
+        "default_value" => "default_value:\"value\"",
+        "short" => "short: followed by letters or digits",
+        "long" => "long: followed by letters, `-` and `_`",
+        "desc" => "desc:(description)",
+        _ if field.is_empty() => {
+            return format!("expected a field; arguments in {} take {}", section_name, allowed)
+        }
+        _ => {
+            return format!(
+                "unknown field `{}`; arguments in {} take {}",
+                field, section_name, allowed
+            )
+        }
+    };
+    format!("bad field `{}`; expected {}", field, expected)
+}
+
+/// The text of the field at `pos`, up to the next comma or parenthesis.
+fn field_at(ann: &str, pos: usize) -> String {
+    ann[pos..]
+        .chars()
+        .take_while(|c| !",()[]".contains(*c))
+        .collect()
+}
+
+/// Describes the text at `pos` for error messages.
+fn token_at(ann: &str, pos: usize) -> String {
+    match ann[pos..].chars().next() {
+        None => "the end of the line".to_string(),
+        Some(c) if !c.is_alphanumeric() => format!("`{}`", c),
+        Some(_) => format!(
+            "`{}`",
+            ann[pos..]
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+                .collect::<String>()
+        ),
+    }
+}
+
 /// Constructor that parses the annotation string into a struct.
 /// Assumes annotations are of the form:
 /// command_name[PARSING_OPTIONS:long_arg_single_dash]: FLAGS:[(short:o,long:option,desc:desc),()...
@@ -673,7 +1037,7 @@ mod tests {
 
     #[test]
     fn test_parse_param_set() {
-        let (_, n): (CompleteByteSlice, Vec<Result<Param>>) = parse_param_set(CompleteByteSlice(
+        let (_, n): (CompleteByteSlice, Vec<(usize, Result<Param>)>) = parse_param_set(CompleteByteSlice(
             b"(type:input_file,size:1,default_value:\".\"),(type:str,size:1,default_value:\"..\"),",
         ))
         .unwrap();
@@ -693,16 +1057,17 @@ mod tests {
             ..Default::default()
         };
 
-        assert_eq!(first_param, *n[0].as_ref().unwrap());
-        assert_eq!(second_param, *n[1].as_ref().unwrap());
+        assert_eq!(first_param, *n[0].1.as_ref().unwrap());
+        assert_eq!(second_param, *n[1].1.as_ref().unwrap());
     }
 
     #[test]
     fn test_parse_params() {
-        let (_, n): (CompleteByteSlice, Result<Vec<Argument>>) = parse_params(CompleteByteSlice(
-            b"PARAMS:[(type:input_file,size:1,default_value:\".\"),(type:str,size:1),]",
-        ))
-        .unwrap();
+        let (_, n): (CompleteByteSlice, Result<Vec<(usize, Argument)>>) =
+            parse_params(CompleteByteSlice(
+                b"PARAMS:[(type:input_file,size:1,default_value:\".\"),(type:str,size:1),]",
+            ))
+            .unwrap();
         let second_param: Param = Param {
             param_type: ArgType::Str,
             size: ParamSize::One,
@@ -724,8 +1089,8 @@ mod tests {
             }
         };
         assert_eq!(arg_list.len(), 2);
-        assert_eq!(arg_list[0], Argument::LoneParam(first_param));
-        assert_eq!(arg_list[1], Argument::LoneParam(second_param));
+        assert_eq!(arg_list[0].1, Argument::LoneParam(first_param));
+        assert_eq!(arg_list[1].1, Argument::LoneParam(second_param));
     }
 
     #[test]
@@ -746,10 +1111,11 @@ mod tests {
     }
     #[test]
     fn test_parse_flag_set() {
-        let (_, n): (CompleteByteSlice, Vec<Result<Opt>>) = parse_flag_set(CompleteByteSlice(
-            b"(short:o,long:option,desc:(foo foo)),(short:d,long:debug,desc:(debug mode)))",
-        ))
-        .unwrap();
+        let (_, n): (CompleteByteSlice, Vec<(usize, Result<Opt>)>) =
+            parse_flag_set(CompleteByteSlice(
+                b"(short:o,long:option,desc:(foo foo)),(short:d,long:debug,desc:(debug mode)))",
+            ))
+            .unwrap();
 
         let option1: Opt = Opt {
             short: "o".to_string(),
@@ -765,13 +1131,13 @@ mod tests {
             multiple: false,
         };
 
-        assert_eq!(option1, *n[0].as_ref().unwrap());
-        assert_eq!(option2, *n[1].as_ref().unwrap());
+        assert_eq!(option1, *n[0].1.as_ref().unwrap());
+        assert_eq!(option2, *n[1].1.as_ref().unwrap());
     }
 
     #[test]
     fn test_parse_flags() {
-        let (_, n): (CompleteByteSlice, Result<Vec<Argument>>) = parse_flags(CompleteByteSlice(
+        let (_, n): (CompleteByteSlice, Result<Vec<(usize, Argument)>>) = parse_flags(CompleteByteSlice(
             b"FLAGS:[(short:o,long:option,desc:(foo foo)),(short:d,long:debug,desc:(debug mode))]",
         ))
         .unwrap();
@@ -791,8 +1157,8 @@ mod tests {
         };
 
         let flags = n.unwrap();
-        assert_eq!(Argument::LoneOption(option1), flags[0]);
-        assert_eq!(Argument::LoneOption(option2), flags[1]);
+        assert_eq!(Argument::LoneOption(option1), flags[0].1);
+        assert_eq!(Argument::LoneOption(option2), flags[1].1);
     }
 
     #[test]
@@ -829,7 +1195,7 @@ mod tests {
 
     #[test]
     fn test_parse_opt_param_set() {
-        let (_, n): (CompleteByteSlice, Vec<Result<(Opt,Param)>>) = parse_opt_with_param_set(CompleteByteSlice(
+        let (_, n): (CompleteByteSlice, Vec<(usize, Result<(Opt,Param)>)>) = parse_opt_with_param_set(CompleteByteSlice(
             b"(short:d,long:directory,type:input_file,size:1,default_value:\".\"),(short:p,long:parent,desc:(parent dir),type:str,size:1,default_value:\"..\"),",
         ))
         .unwrap();
@@ -863,15 +1229,15 @@ mod tests {
             ..Default::default()
         };
 
-        assert_eq!(first_param, (*n[0].as_ref().unwrap()).1);
-        assert_eq!(second_param, (*n[1].as_ref().unwrap()).1);
-        assert_eq!(first_opt, (*n[0].as_ref().unwrap()).0);
-        assert_eq!(second_opt, (*n[1].as_ref().unwrap()).0);
+        assert_eq!(first_param, (*n[0].1.as_ref().unwrap()).1);
+        assert_eq!(second_param, (*n[1].1.as_ref().unwrap()).1);
+        assert_eq!(first_opt, (*n[0].1.as_ref().unwrap()).0);
+        assert_eq!(second_opt, (*n[1].1.as_ref().unwrap()).0);
     }
 
     #[test]
     fn test_parse_opt_params() {
-        let (_, n): (CompleteByteSlice, Result<Vec<Argument>>) = parse_opt_with_params(CompleteByteSlice(
+        let (_, n): (CompleteByteSlice, Result<Vec<(usize, Argument)>>) = parse_opt_with_params(CompleteByteSlice(
             b"OPTPARAMS:[(short:d,long:directory,type:input_file,size:1,default_value:\".\"),(short:p,long:parent,desc:(parent dir),type:str,size:1,default_value:\"..\"),]",
         )).unwrap();
 
@@ -909,19 +1275,22 @@ mod tests {
             }
         };
         assert_eq!(arg_list.len(), 2);
-        assert_eq!(arg_list[0], Argument::OptWithParam(first_opt, first_param));
         assert_eq!(
-            arg_list[1],
+            arg_list[0].1,
+            Argument::OptWithParam(first_opt, first_param)
+        );
+        assert_eq!(
+            arg_list[1].1,
             Argument::OptWithParam(second_opt, second_param)
         );
     }
 
     #[test]
     fn test_parse_command() {
-        let (_, command): (CompleteByteSlice, Result<Command>) = parse_annotation(CompleteByteSlice(
+        let (_, command): (CompleteByteSlice, Result<(Command, Vec<usize>)>) = parse_annotation(CompleteByteSlice(
             b"tar: FLAGS:[(short:o,long:option,desc:(foo foo)),(short:d,long:debug,desc:(debug mode))] OPTPARAMS:[(short:d,long:directory,type:input_file,size:1,default_value:\".\"),(short:p,long:parent,desc:(parent dir),type:str,size:1,default_value:\"..\"),]",
         )).unwrap();
-        let cmd = command.unwrap();
+        let (cmd, _) = command.unwrap();
         let arg_list = cmd.args;
         assert_eq!(cmd.command_name, "tar".to_string());
         let option1: Opt = Opt {
@@ -976,4 +1345,62 @@ mod tests {
         assert_eq!(Argument::LoneOption(option1), arg_list[0]);
         assert_eq!(Argument::LoneOption(option2), arg_list[1]);
     }
+
+    #[test]
+    fn test_parse_random_access() {
+        let (_, command): (CompleteByteSlice, Result<(Command, Vec<usize>)>) = parse_annotation(
+            CompleteByteSlice(b"sqlite3[random_access]: PARAMS:[(type:input_file,size:1)]"),
+        )
+        .unwrap();
+        let (cmd, _) = command.unwrap();
+        assert_eq!(cmd.parsing_options.access_type, AccessType::Random);
+        assert_eq!(
+            cmd.to_string(),
+            "sqlite3[random_access]: PARAMS:[(type:input_file,size:1)]"
+        );
+    }
+
+    #[test]
+    fn test_parse_output_size() {
+        let cmd = Command::new("grep[selectivity:(0.05)]: PARAMS:[(type:str,size:1)]").unwrap();
+        assert_eq!(
+            cmd.parsing_options.output_size,
+            Some(OutputSize::Ratio(0.05))
+        );
+        assert_eq!(
+            cmd.to_string(),
+            "grep[selectivity:(0.05)]: PARAMS:[(type:str,size:1)]"
+        );
+        let cmd =
+            Command::new("md5sum[constant_output]: PARAMS:[(type:input_file,size:1)]").unwrap();
+        assert_eq!(cmd.parsing_options.output_size, Some(OutputSize::Constant));
+        assert!(Command::new("grep[selectivity:(-1)]: PARAMS:[(type:str,size:1)]").is_err());
+    }
+
+    #[test]
+    fn test_parse_combiner() {
+        let cmd = Command::new("wc[combiner:(sum)]: FLAGS:[(short:l)]").unwrap();
+        assert_eq!(cmd.parsing_options.combiner, Combiner::Sum);
+        assert_eq!(cmd.to_string(), "wc[combiner:(sum)]: FLAGS:[(short:l)]");
+        let cmd = Command::new("sort[combiner:(sort -m)]: FLAGS:[(short:r)]").unwrap();
+        assert_eq!(
+            cmd.parsing_options.combiner,
+            Combiner::Custom("sort -m".to_string())
+        );
+        let cmd = Command::new("cat: PARAMS:[(type:input_file,size:1)]").unwrap();
+        assert_eq!(cmd.parsing_options.combiner, Combiner::Concat);
+        assert!(Command::new_validated("uniq[combiner:(awk 'x)]: FLAGS:[(short:c)]").is_err());
+    }
+
+    #[test]
+    fn test_parse_appends() {
+        let ann = "tee: FLAGS:[(short:a)] PARAMS:[(type:output_file,size:1,appends)]";
+        let cmd = Command::new_validated(ann).unwrap();
+        match &cmd.args[1] {
+            Argument::LoneParam(param) => assert!(param.appends),
+            arg => panic!("expected a param, got {:?}", arg),
+        }
+        assert_eq!(cmd.to_string(), ann);
+        assert!(Command::new_validated("cat: PARAMS:[(type:input_file,size:1,appends)]").is_err());
+    }
 }
@@ -1,3 +1,4 @@
+use super::cmd_parser::MatchedOption;
 use super::config::filecache::FileCache;
 use super::config::network::FileNetwork;
 use super::grammar::*;
@@ -10,7 +11,7 @@ use failure::bail;
 use glob::glob;
 use std::collections::HashMap;
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 /// Attempts to run glob on the input FileStream and returns a vector of NodeArgs.
 /// TODO: some of the Errors in glob might result from certain directories being unreadable.
 fn glob_wrapper(input: &FileStream) -> Option<Vec<NodeArg>> {
@@ -38,9 +39,41 @@ fn glob_wrapper(input: &FileStream) -> Option<Vec<NodeArg>> {
         Err(_) => None,
     }
 }
+/// Expands a leading `~` or `$VAR` in the path of an implicit file; None if the variable isn't
+/// set, in which case the command doesn't access the file. Other users' homes (`~bob/x`) aren't
+/// expanded, and give None too.
+fn expand_implicit_path(path: &str) -> Option<PathBuf> {
+    let (var_name, rest) = if path == "~" || path.starts_with("~/") {
+        ("HOME", &path[1..])
+    } else if path.starts_with('~') {
+        return None;
+    } else if let Some(var) = path.strip_prefix('$') {
+        match var.find('/') {
+            Some(idx) => (&var[..idx], &var[idx..]),
+            None => (var, ""),
+        }
+    } else {
+        return Some(PathBuf::from(path));
+    };
+    let value = env::var(var_name).ok()?;
+    Some(PathBuf::from(format!("{}{}", value, rest)))
+}
+
+/// Turns a value given for a parameter into a string or a file, based on the parameter's type.
+fn param_value(param: &Param, value: &str) -> NodeArg {
+    match param.param_type {
+        ArgType::Str => NodeArg::Str(value.to_string()),
+        ArgType::InputFile
+        | ArgType::OutputFile
+        | ArgType::InputFileList
+        | ArgType::OutputFileList => {
+            NodeArg::Stream(FileStream::new(Path::new(value), Location::default()))
+        }
+    }
+}
+
 /// Specifies a parsed mapping between arguments that appear at runtime.
 /// TODO: would be eaiser if clap just exposed a argmatch method itself.
-/// TODO: with this, you can handle subcommands properly
 #[derive(Debug, PartialEq, Clone)]
 pub struct ArgMatch {
     /// What is the command name
@@ -53,6 +86,13 @@ pub struct ArgMatch {
     parsing_options: ParsingOptions,
     /// Is there an arg that is splittable?
     splittable_arg: Option<usize>,
+    /// How many options of parent commands come before each word of a subcommand's name, like
+    /// `-C dir` in `git -C dir log`. These are the first arguments in the arg list.
+    global_args: Vec<usize>,
+    /// Files from the annotation's implicit inputs and outputs, with `~` and environment
+    /// variables expanded, and whether the command finds the file itself from an absolute, home or
+    /// environment path, which only names the same file on the client.
+    implicit_files: Vec<(ArgType, FileStream, bool)>,
 }
 
 /// Helper struct to handle when nodes are assigned to locations where a certain argument doesn't
@@ -73,6 +113,8 @@ pub struct RemoteAccessInfo {
     pub access_location: Location,
     /// Argument type (can only be "input" or "output")
     pub argtype: ArgType,
+    /// Whether the command appends to the file, if it's an output
+    pub appends: bool,
 }
 
 impl RemoteAccessInfo {
@@ -133,18 +175,7 @@ impl ArgMatch {
                 match argument {
                     Argument::LoneOption(_) => {}
                     Argument::OptWithParam(_, param) | Argument::LoneParam(param) => {
-                        match param.param_type {
-                            ArgType::Str => val_list.push(NodeArg::Str(value.to_string())),
-                            ArgType::InputFile
-                            | ArgType::OutputFile
-                            | ArgType::InputFileList
-                            | ArgType::OutputFileList => {
-                                val_list.push(NodeArg::Stream(FileStream::new(
-                                    Path::new(value),
-                                    Location::default(),
-                                )));
-                            }
-                        }
+                        val_list.push(param_value(param, value));
                     }
                 }
             }
@@ -215,6 +246,20 @@ impl ArgMatch {
             map: map,
             parsing_options: annotation.parsing_options.clone(),
             splittable_arg: splittable_arg,
+            global_args: Vec::new(),
+            implicit_files: annotation
+                .parsing_options
+                .implicit_files
+                .iter()
+                .filter_map(|file| {
+                    let path = expand_implicit_path(&file.path)?;
+                    Some((
+                        file.file_type,
+                        FileStream::new(&path, Location::default()),
+                        !file.is_relative(),
+                    ))
+                })
+                .collect(),
         })
     }
 
@@ -229,6 +274,8 @@ impl ArgMatch {
             map: HashMap::default(),
             parsing_options: ParsingOptions::default(),
             splittable_arg: None,
+            global_args: Vec::new(),
+            implicit_files: Vec::new(),
         }
     }
 
@@ -244,83 +291,168 @@ impl ArgMatch {
             map: self.map.clone(),
             parsing_options: self.parsing_options.clone(),
             splittable_arg: None,
+            global_args: self.global_args.clone(),
+            implicit_files: self.implicit_files.clone(),
         })
     }
 
+    /// The match for running the command again on the concatenated outputs of its parallel
+    /// parts, for the `rerun` combiner: the same options, without positional arguments or files.
+    pub fn rerun_match(&self) -> ArgMatch {
+        let keep = |ind: usize| match self.map.get(&ind) {
+            Some(Argument::LoneOption(_)) => true,
+            Some(Argument::OptWithParam(_, param)) => !param.is_file_type(),
+            _ => false,
+        };
+        let mut global_args: Vec<usize> = Vec::new();
+        let mut start = 0;
+        for count in self.global_args.iter() {
+            global_args.push((start..start + count).filter(|ind| keep(*ind)).count());
+            start += count;
+        }
+        let mut arg_list: Vec<Vec<NodeArg>> = Vec::new();
+        let mut map: HashMap<usize, Argument> = HashMap::default();
+        for (ind, args) in self.arg_list.iter().enumerate() {
+            if keep(ind) {
+                map.insert(arg_list.len(), self.map[&ind].clone());
+                arg_list.push(args.clone());
+            }
+        }
+        let mut parsing_options = self.parsing_options.clone();
+        parsing_options.splittable_across_input = false;
+        parsing_options.combiner = Combiner::Concat;
+        ArgMatch {
+            cmd_name: self.cmd_name.clone(),
+            arg_list: arg_list,
+            map: map,
+            parsing_options: parsing_options,
+            splittable_arg: None,
+            global_args: global_args,
+            implicit_files: self.implicit_files.clone(),
+        }
+    }
+
+    /// Adds options of parent commands given before each word of the subcommand's name, as
+    /// matched by `CmdParser::match_option`. They are placed first in the arg list.
+    pub fn add_global_args(&mut self, levels: Vec<Vec<MatchedOption>>) {
+        let count: usize = levels.iter().map(|level| level.len()).sum();
+        let mut map: HashMap<usize, Argument> = self
+            .map
+            .drain()
+            .map(|(ind, arg)| (ind + count, arg))
+            .collect();
+        self.splittable_arg = self.splittable_arg.map(|ind| ind + count);
+        let mut arg_list: Vec<Vec<NodeArg>> = Vec::new();
+        for (arg, value) in levels.iter().flat_map(|level| level.iter()) {
+            map.insert(arg_list.len(), arg.clone());
+            match (arg, value) {
+                (Argument::OptWithParam(_, param), Some(value)) => {
+                    arg_list.push(vec![param_value(param, value)])
+                }
+                _ => arg_list.push(vec![]),
+            }
+        }
+        arg_list.append(&mut self.arg_list);
+        self.arg_list = arg_list;
+        self.map = map;
+        self.global_args = levels.iter().map(|level| level.len()).collect();
+    }
+
     /// Reconstructs the arguments into a string that can be used at runtime.
     pub fn reconstruct(&self) -> Result<Vec<NodeArg>> {
-        // TODO: handle subcommands in a more robust way
-
         let mut ret: Vec<NodeArg> = Vec::new();
+        let mut num_global = 0;
         if self.cmd_name.len() > 1 {
-            for cmpt in self.cmd_name[1..].iter() {
+            for (level, cmpt) in self.cmd_name[1..].iter().enumerate() {
+                // parent command options given before this word
+                let count = self.global_args.get(level).cloned().unwrap_or(0);
+                for ind in num_global..num_global + count {
+                    self.push_option(self.map.get(&ind).unwrap(), &self.arg_list[ind], &mut ret)?;
+                }
+                num_global += count;
                 ret.push(NodeArg::Str(format!("{}", cmpt)));
             }
         }
-        // iterate through the options first, then lone parameters last
+        // iterate through the options first, then lone parameters last, unless the command
+        // takes its parameters first
+        let mut params: Vec<NodeArg> = Vec::new();
         for (ind, args) in self.arg_list.iter().enumerate() {
-            let arg_info = self.map.get(&ind).unwrap();
-            match arg_info {
-                Argument::LoneOption(opt) => {
-                    assert!(args.len() == 0);
-                    if opt.short != "" {
-                        ret.push(NodeArg::Str(format!("-{}", &opt.short)));
+            match self.map.get(&ind) {
+                Some(Argument::LoneOption(_)) => {}
+                Some(Argument::OptWithParam(_, _)) => {}
+                // unparsed invocations have no argument info, and keep their arguments as they
+                // are
+                Some(Argument::LoneParam(_)) | None => {
+                    params.append(&mut args.clone());
+                }
+            }
+        }
+        if self.parsing_options.params_first {
+            ret.append(&mut params);
+        }
+        for (ind, args) in self.arg_list.iter().enumerate().skip(num_global) {
+            if let Some(arg_info) = self.map.get(&ind) {
+                self.push_option(arg_info, args, &mut ret)?;
+            }
+        }
+        ret.append(&mut params);
+
+        Ok(ret)
+    }
+
+    /// Adds an option and its values to the reconstructed arguments; ignores lone parameters.
+    fn push_option(
+        &self,
+        arg_info: &Argument,
+        args: &[NodeArg],
+        ret: &mut Vec<NodeArg>,
+    ) -> Result<()> {
+        match arg_info {
+            Argument::LoneOption(opt) => {
+                assert!(args.is_empty());
+                if !opt.short.is_empty() {
+                    ret.push(NodeArg::Str(format!("-{}", &opt.short)));
+                } else {
+                    if self.parsing_options.long_arg_single_dash {
+                        ret.push(NodeArg::Str(format!("-{}", &opt.long)));
                     } else {
-                        if self.parsing_options.long_arg_single_dash {
-                            ret.push(NodeArg::Str(format!("-{}", &opt.long)));
-                        } else {
-                            ret.push(NodeArg::Str(format!("--{}", &opt.long)));
-                        }
+                        ret.push(NodeArg::Str(format!("--{}", &opt.long)));
                     }
                 }
-                Argument::OptWithParam(opt, param) => {
-                    if param.attached_to_short && opt.short != "" {
-                        if param.param_type != ArgType::Str {
-                            bail!("Dash doesn't handle attached_to_short for non-string arg types");
+            }
+            Argument::OptWithParam(opt, param) => {
+                if param.attached_to_short && !opt.short.is_empty() {
+                    if param.param_type != ArgType::Str {
+                        bail!("Dash doesn't handle attached_to_short for non-string arg types");
+                    }
+                    assert!(param.size == ParamSize::One);
+                    assert!(args.len() == 1);
+                    match &args[0] {
+                        NodeArg::Str(s) => {
+                            ret.push(NodeArg::Str(format!("-{}{}", &opt.short, s)));
                         }
-                        assert!(param.size == ParamSize::One);
-                        assert!(args.len() == 1);
-                        match &args[0] {
-                            NodeArg::Str(s) => {
-                                ret.push(NodeArg::Str(format!("-{}{}", &opt.short, s)));
-                            }
-                            NodeArg::Stream(_) => {
-                                unreachable!();
-                            }
+                        NodeArg::Stream(_) => {
+                            unreachable!();
                         }
-                        continue;
                     }
+                    return Ok(());
+                }
 
-                    if opt.short != "" {
-                        ret.push(NodeArg::Str(format!("-{}", &opt.short)));
+                if !opt.short.is_empty() {
+                    ret.push(NodeArg::Str(format!("-{}", &opt.short)));
+                } else {
+                    if self.parsing_options.long_arg_single_dash {
+                        ret.push(NodeArg::Str(format!("-{}", &opt.long)));
                     } else {
-                        if self.parsing_options.long_arg_single_dash {
-                            ret.push(NodeArg::Str(format!("-{}", &opt.long)));
-                        } else {
-                            ret.push(NodeArg::Str(format!("--{}", &opt.long)));
-                        }
+                        ret.push(NodeArg::Str(format!("--{}", &opt.long)));
                     }
-                    // add the values
-                    ret.append(&mut args.clone());
-                }
-                Argument::LoneParam(_) => {}
-            }
-        }
-
-        // now add the lone parameters in to the return list
-        for (ind, args) in self.arg_list.iter().enumerate() {
-            let argument = self.map.get(&ind).unwrap();
-            match argument {
-                Argument::LoneOption(_) => {}
-                Argument::OptWithParam(_, _) => {}
-                Argument::LoneParam(_param) => {
-                    // push all the values
-                    ret.append(&mut args.clone());
                 }
+                // add the values
+                ret.extend_from_slice(args);
             }
+            Argument::LoneParam(_) => {}
         }
-
-        Ok(ret)
+        Ok(())
     }
 
     /// Splits into multiple matches by the given argument.
@@ -457,6 +589,14 @@ impl ArgMatch {
         self.parsing_options.reduces_input
     }
 
+    pub fn get_output_size(&self) -> Option<OutputSize> {
+        self.parsing_options.output_size
+    }
+
+    pub fn get_combiner(&self) -> Combiner {
+        self.parsing_options.combiner.clone()
+    }
+
     pub fn get_splittable_across_input(&self) -> bool {
         self.parsing_options.splittable_across_input
     }
@@ -465,13 +605,49 @@ impl ArgMatch {
         self.parsing_options.needs_current_dir
     }
 
-    /// Returns vector of all the file related dependencies this node sees.
+    /// If the command runs in the current directory: it needs the directory itself, or reads or
+    /// writes implicit files relative to it.
+    pub fn uses_current_dir(&self) -> bool {
+        self.get_needs_current_dir()
+            || self
+                .parsing_options
+                .implicit_files
+                .iter()
+                .any(|file| file.is_relative())
+    }
+
+    /// Files the command accesses that aren't named in its arguments, so must be accessed in
+    /// place wherever it runs.
+    pub fn implicit_dependencies(&self) -> Vec<(ArgType, FileStream)> {
+        self.implicit_files
+            .iter()
+            .map(|(argtype, fs, _)| (*argtype, fs.clone()))
+            .collect()
+    }
+
+    /// If the command accesses implicit files through paths it resolves itself with the client's
+    /// home and environment, so must run on the client.
+    pub fn needs_client(&self) -> bool {
+        self.implicit_files
+            .iter()
+            .any(|(_, _, on_client)| *on_client)
+    }
+
+    /// Returns vector of all the file related dependencies this node sees, including implicit
+    /// ones.
     pub fn file_dependencies(&self) -> Vec<(ArgType, FileStream)> {
+        let mut ret = self.argument_files();
+        ret.extend(self.implicit_dependencies());
+        ret
+    }
+
+    /// The files named in the command's arguments, in order.
+    pub fn argument_files(&self) -> Vec<(ArgType, FileStream)> {
         let mut ret: Vec<(ArgType, FileStream)> = Vec::new();
         for (i, args) in self.arg_list.iter().enumerate() {
-            match self.map.get(&i).unwrap() {
-                Argument::LoneOption(_) => {}
-                Argument::OptWithParam(_, param) | Argument::LoneParam(param) => {
+            match self.map.get(&i) {
+                Some(Argument::LoneOption(_)) | None => {}
+                Some(Argument::OptWithParam(_, param)) | Some(Argument::LoneParam(param)) => {
                     if param.is_file_type() {
                         for arg in args.iter() {
                             match arg {
@@ -490,13 +666,21 @@ impl ArgMatch {
         ret
     }
 
+    /// The values of the argument the command can be split across, if any.
+    pub fn get_splittable_values(&self) -> Vec<NodeArg> {
+        match self.splittable_arg {
+            Some(ind) => self.arg_list[ind].clone(),
+            None => Vec::new(),
+        }
+    }
+
     /// Resolve any glob related things within any of the arguments.
     pub fn resolve_glob(&mut self) -> Result<()> {
         let mut repl_list: Vec<(usize, Vec<NodeArg>)> = Vec::new();
         for (i, args) in self.arg_list.iter().enumerate() {
-            match self.map.get(&i).unwrap() {
-                Argument::LoneOption(_) => {}
-                Argument::OptWithParam(_, param) | Argument::LoneParam(param) => {
+            match self.map.get(&i) {
+                Some(Argument::LoneOption(_)) | None => {}
+                Some(Argument::OptWithParam(_, param)) | Some(Argument::LoneParam(param)) => {
                     if param.is_file_type() {
                         let mut repl_args: Vec<NodeArg> = Vec::new();
                         let mut changed = false;
@@ -562,9 +746,9 @@ impl ArgMatch {
     /// For any file related arguments, resolve to full path.
     pub fn resolve_file_paths(&mut self, filecache: &mut FileCache, pwd: &Path) -> Result<()> {
         for (i, args) in self.arg_list.iter_mut().enumerate() {
-            match self.map.get(&i).unwrap() {
-                Argument::LoneOption(_) => {}
-                Argument::OptWithParam(_, param) | Argument::LoneParam(param) => {
+            match self.map.get(&i) {
+                Some(Argument::LoneOption(_)) | None => {}
+                Some(Argument::OptWithParam(_, param)) | Some(Argument::LoneParam(param)) => {
                     if param.is_file_type() {
                         for arg in args.iter_mut() {
                             match arg {
@@ -578,6 +762,12 @@ impl ArgMatch {
                 }
             }
         }
+        for (_, fs, _) in self.implicit_files.iter_mut() {
+            filecache.resolve_path(fs, pwd)?;
+        }
+        // commands only read implicit inputs that exist
+        self.implicit_files
+            .retain(|(argtype, fs, _)| *argtype != ArgType::InputFile || fs.get_path().exists());
         Ok(())
     }
 
@@ -592,26 +782,41 @@ impl ArgMatch {
     ) -> Result<Vec<RemoteAccessInfo>> {
         let mut remote_access: Vec<RemoteAccessInfo> = Vec::new();
         for (arg_idx, args) in self.arg_list.iter_mut().enumerate() {
-            match self.map.get(&arg_idx).unwrap() {
-                Argument::LoneOption(_) => {}
-                Argument::OptWithParam(_, param) | Argument::LoneParam(param) => {
+            match self.map.get(&arg_idx) {
+                Some(Argument::LoneOption(_)) | None => {}
+                Some(Argument::OptWithParam(_, param)) | Some(Argument::LoneParam(param)) => {
                     if param.is_file_type() {
                         for (val_idx, arg) in args.iter_mut().enumerate() {
                             match arg {
                                 NodeArg::Stream(ref mut fs) => {
-                                    let file_location = config.get_location(fs);
-                                    if file_location == location {
+                                    // outputs must be written at the primary location, but
+                                    // inputs can be read from any replica
+                                    let is_output = matches!(
+                                        param.param_type,
+                                        ArgType::OutputFile | ArgType::OutputFileList
+                                    );
+                                    let available = match is_output {
+                                        true => config.get_location(fs) == location,
+                                        false => config.is_available_at(fs, &location),
+                                    };
+                                    let file_location = match is_output {
+                                        true => config.get_location(fs),
+                                        false => config.closest_location(fs, &location),
+                                    };
+                                    if available {
                                         // modify path
                                         config.strip_file_path(fs, &origin_location, &location)?;
                                         fs.set_location(location.clone());
                                     } else {
-                                        // need to modify argument for remote access
+                                        // need to modify argument for remote access; outputs
+                                        // are streamed back, even to the client
                                         if file_location == Location::Client
                                             && location != Location::Client
+                                            && !is_output
                                         {
                                             bail!("File {:?} cannot be accessed in loc {:?} from outside the client", fs, location);
                                         }
-                                        let remote_access_info = RemoteAccessInfo::new(
+                                        let mut remote_access_info = RemoteAccessInfo::new(
                                             fs.clone(),
                                             arg_idx,
                                             val_idx,
@@ -619,6 +824,7 @@ impl ArgMatch {
                                             location.clone(),
                                             param.param_type,
                                         );
+                                        remote_access_info.appends = param.appends;
                                         remote_access.push(remote_access_info);
                                     }
                                 }
@@ -634,9 +840,51 @@ impl ArgMatch {
         Ok(remote_access)
     }
 
+    /// Whether any argument names the path, as a file or a plain string.
+    pub fn names_path(&self, path: &Path) -> bool {
+        self.arg_list
+            .iter()
+            .flat_map(|args| args.iter())
+            .any(|arg| match arg {
+                NodeArg::Str(s) => Path::new(s) == path,
+                NodeArg::Stream(fs) => fs.get_path() == path,
+            })
+    }
+
+    /// Replaces every argument naming the path with the file.
+    pub fn replace_path_arg(&mut self, path: &Path, repl: &FileStream) {
+        for arg in self.arg_list.iter_mut().flat_map(|args| args.iter_mut()) {
+            let names_path = match arg {
+                NodeArg::Str(s) => Path::new(s) == path,
+                NodeArg::Stream(fs) => fs.get_path() == path,
+            };
+            if names_path {
+                *arg = NodeArg::Stream(repl.clone());
+            }
+        }
+    }
+
     pub fn change_arg(&mut self, info: &RemoteAccessInfo) -> Result<()> {
         let args = &mut self.arg_list[info.arg_idx];
         std::mem::replace(&mut args[info.val_idx], NodeArg::Stream(info.tmp.clone()));
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_implicit_path() {
+        // other tests change HOME, so only check what's kept of the path
+        assert!(expand_implicit_path("~/.config/tool.conf")
+            .unwrap()
+            .ends_with(".config/tool.conf"));
+        assert_eq!(expand_implicit_path("~bob/x"), None);
+        assert_eq!(expand_implicit_path("a~b"), Some(PathBuf::from("a~b")));
+        assert!(
+            Command::new_validated("tool[implicit_input:(~bob/x)]: FLAGS:[(short:a)]").is_err()
+        );
+    }
+}
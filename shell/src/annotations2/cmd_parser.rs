@@ -12,11 +12,13 @@ use std::collections::HashMap;
 use std::fmt;
 use tracing::debug;
 
+/// An option given before a subcommand, and its value if it takes one.
+pub type MatchedOption = (grammar::Argument, Option<String>);
+
 /// A parser represents a list of annotations associated with a certain command.
 /// These annotations are a "whitelist".
 /// Dash will only assign types if the invocation fits within one of the annotations.
-/// TODO: how do we properly deal with subcommands? Ideally those are also specified in the
-/// annotation with their own syntax.
+/// Subcommands have their own parsers, named by their full name (`git log`).
 pub struct CmdParser {
     /// Command this is parsing.
     name: String,
@@ -63,62 +65,10 @@ impl CmdParser {
         if annotation.command_name != self.name {
             bail!("Annotation does not refer to the same command as the parser");
         }
-        let mut lone_args_with_multiple = false;
-        let mut found_splittable = false;
-        for arg in annotation.args.iter() {
+        if let Some((arg, message)) = annotation.problems().into_iter().next() {
             match arg {
-                grammar::Argument::LoneOption(opt) => {
-                    if opt.short == "" && opt.long == "" {
-                        bail!("Atleast one of short or long should be specified for option.");
-                    }
-                }
-                grammar::Argument::OptWithParam(opt, param) => {
-                    if opt.short == "" && opt.long == "" {
-                        bail!("Atleast one of short or long should be specified for option.");
-                    }
-                    if param.splittable {
-                        found_splittable = true;
-                    }
-                }
-                grammar::Argument::LoneParam(param) => {
-                    // can only have multiple args if it's the last one
-                    if param.splittable {
-                        if found_splittable {
-                            bail!("Cannot have more than 1 arg with splittable turned on");
-                        }
-                        found_splittable = true;
-                    }
-                    match param.size {
-                        grammar::ParamSize::Zero => {
-                            bail!("Cannot have param with size 0");
-                        }
-                        grammar::ParamSize::One => {
-                            if param.splittable {
-                                bail!("Cannot have splittable command with size 1");
-                            }
-                        }
-                        grammar::ParamSize::SpecificSize(size, sep) => {
-                            if size == 1 && param.splittable {
-                                bail!("Cannot have splittable command with size 1");
-                            }
-                            // update this when we allow more delims
-                            if size > 1 && sep != grammar::ListSeparator::Comma {
-                                if lone_args_with_multiple {
-                                    bail!("Cannot have multiple args with size > 1");
-                                }
-                                lone_args_with_multiple = true;
-                            }
-                        }
-                        grammar::ParamSize::List(sep) => {
-                            if sep != grammar::ListSeparator::Comma {
-                                if lone_args_with_multiple {
-                                    bail!("Cannot have multiple args with size > 1");
-                                }
-                                lone_args_with_multiple = true;
-                            }
-                        }
-                    }
-                }
+                Some(idx) => bail!("argument {}: {}", idx + 1, message),
+                None => bail!("{}", message),
             }
         }
         Ok(())
@@ -131,7 +81,7 @@ impl CmdParser {
                 Ok(())
             }
             Err(e) => {
-                bail!("Error validating annotation: {:?}", e);
+                bail!("Error validating annotation: {}", e);
             }
         }
     }
@@ -191,6 +141,11 @@ impl CmdParser {
                         }
                         grammar::ParamSize::One => {
                             arg = arg.takes_value(true);
+                            // a repeated option takes one value each time, rather than
+                            // swallowing the positional arguments after it
+                            if opt.multiple || param.multiple {
+                                arg = arg.number_of_values(1);
+                            }
                         }
                         grammar::ParamSize::SpecificSize(amt, separator) => {
                             // default delimiter should be a comma
@@ -315,10 +270,24 @@ impl CmdParser {
     /// If no parser matches this invocation, returns a parsed command where all arguments are of
     /// type "str" (the default).
     pub fn parse_command(&self, invocation: &Vec<String>) -> Result<ArgMatch> {
+        match self.try_parse_command(invocation) {
+            Some(ret) => Ok(ret),
+            None => {
+                debug!(
+                    "Warning: invocation {:?} was not parsed by any parsers",
+                    invocation
+                );
+                Ok(ArgMatch::new_default(&self.name, invocation))
+            }
+        }
+    }
+
+    /// Parses the invocation with the first annotation that matches it, if any.
+    pub fn try_parse_command(&self, invocation: &Vec<String>) -> Option<ArgMatch> {
         for i in 0..self.annotations.len() {
-            match self.parse_invocation(&invocation, i) {
+            match self.parse_invocation(invocation, i) {
                 Ok(ret) => {
-                    return Ok(ret);
+                    return Some(ret);
                 }
                 Err(e) => {
                     if self.debug {
@@ -327,11 +296,79 @@ impl CmdParser {
                 }
             }
         }
-        debug!(
-            "Warning: invocation {:?} was not parsed by any parsers",
-            invocation
-        );
-        return Ok(ArgMatch::new_default(&self.name, &invocation));
+        None
+    }
+
+    /// Matches an option of this command at the start of `words`, as it would be given before a
+    /// subcommand (`git -C dir log`). Returns how many words the option used, and each option
+    /// with its value; grouped short flags (`-vf`) give several options.
+    /// Only options taking a single value can be matched.
+    pub fn match_option(&self, words: &[String]) -> Option<(usize, Vec<MatchedOption>)> {
+        let word = words.first()?;
+        let find = |short: &str, long: &str| {
+            self.annotations
+                .iter()
+                .flat_map(|annotation| annotation.args.iter())
+                .find(|arg| match arg {
+                    grammar::Argument::LoneOption(opt)
+                    | grammar::Argument::OptWithParam(opt, _) => {
+                        (!short.is_empty() && opt.short == short)
+                            || (!long.is_empty() && opt.long == long)
+                    }
+                    grammar::Argument::LoneParam(_) => false,
+                })
+                .cloned()
+        };
+        // returns how many words the option uses, given its attached value (`--key=val`)
+        let with_value = |arg: grammar::Argument, value: Option<&str>| match &arg {
+            grammar::Argument::LoneOption(_) if value.is_none() => Some((1, vec![(arg, None)])),
+            grammar::Argument::OptWithParam(_, param) if param.size == grammar::ParamSize::One => {
+                match value {
+                    Some(value) => Some((1, vec![(arg, Some(value.to_string()))])),
+                    None => Some((2, vec![(arg, Some(words.get(1)?.clone()))])),
+                }
+            }
+            _ => None,
+        };
+
+        if let Some(long) = word.strip_prefix("--") {
+            let mut parts = long.splitn(2, '=');
+            let name = parts.next()?;
+            return with_value(find("", name)?, parts.next());
+        }
+        let rest = word.strip_prefix('-')?;
+        if rest.is_empty() {
+            return None;
+        }
+        if self
+            .annotations
+            .iter()
+            .any(|annotation| annotation.long_arg_single_dash())
+        {
+            if let Some(arg) = find("", rest) {
+                return with_value(arg, None);
+            }
+        }
+        let mut ret: Vec<MatchedOption> = Vec::new();
+        for (i, c) in rest.char_indices() {
+            let arg = find(&c.to_string(), "")?;
+            let attached = &rest[i + c.len_utf8()..];
+            match &arg {
+                grammar::Argument::LoneOption(_) => ret.push((arg, None)),
+                grammar::Argument::OptWithParam(_, _) => {
+                    let value = if attached.is_empty() {
+                        None
+                    } else {
+                        Some(attached)
+                    };
+                    let (used, mut option) = with_value(arg, value)?;
+                    ret.append(&mut option);
+                    return Some((used, ret));
+                }
+                grammar::Argument::LoneParam(_) => return None,
+            }
+        }
+        Some((1, ret))
     }
 }
 #[cfg(test)]
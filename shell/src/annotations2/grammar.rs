@@ -1,10 +1,9 @@
 ///! Grammar Abstraction that defines the syntax of command line arguments.
-use super::annotation_parser::parse_annotation;
+use super::annotation_parser::parse_annotation_line;
+use super::yaml::load_annotation_yaml;
 use dash::util::Result;
 use failure::bail;
-use nom::types::CompleteByteSlice;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::collections::HashMap;
 use std::*;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -100,6 +99,8 @@ pub struct Param {
     pub splittable: bool,
     /// Sometimes short args are attached to the opt, e.g. pr -s,
     pub attached_to_short: bool,
+    /// Does the command append to this output file, like `tee -a`, instead of replacing it?
+    pub appends: bool,
 }
 
 impl Param {
@@ -129,6 +130,7 @@ pub enum Info {
     Multiple,     // allow multiple occurrences or not
     Splittable,   // If this argument is splittable
     Attached,     // If argument is attached to the option name
+    Appends,      // If the command appends to this output file
 }
 
 pub enum SizeInfo {
@@ -136,7 +138,81 @@ pub enum SizeInfo {
     Delimiter(ListSeparator),
 }
 
+/// How large a command's output is compared to its input, for the scheduler's cost estimates.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OutputSize {
+    /// Output is this fraction of the input size; above 1, the command expands its input.
+    Ratio(f64),
+    /// Output is larger than the input (e.g., `zcat`), by the scheduler's reduction factor.
+    ExpandsInput,
+    /// Output is small and doesn't depend on the input (e.g., `wc`, `md5sum`).
+    Constant,
+    /// Output is about a line per input file (e.g., `ls`).
+    PerInputFile,
+}
+
+/// How to merge the outputs of a command that was split into parallel parts.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Combiner {
+    /// Concatenate the parts' outputs in order.
+    Concat,
+    /// Add up each column of numbers across the parts, like the counts from `wc -l`.
+    Sum,
+    /// Run the command again, with the same options, on the concatenated parts; e.g., sorting
+    /// them again with the same keys, or keeping the first lines for `head -n`.
+    Rerun,
+    /// Run this command line on the concatenated parts.
+    Custom(String),
+}
+
+impl Default for Combiner {
+    fn default() -> Self {
+        Combiner::Concat
+    }
+}
+
+impl fmt::Display for Combiner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Combiner::Concat => write!(f, "concat"),
+            Combiner::Sum => write!(f, "sum"),
+            Combiner::Rerun => write!(f, "rerun"),
+            Combiner::Custom(command) => write!(f, "{}", command),
+        }
+    }
+}
+
+impl Combiner {
+    /// Parses `concat`, `sum` or `rerun`; anything else is a custom command line.
+    pub fn new(combiner: &str) -> Self {
+        match combiner.trim() {
+            "concat" => Combiner::Concat,
+            "sum" => Combiner::Sum,
+            "rerun" => Combiner::Rerun,
+            command => Combiner::Custom(command.to_string()),
+        }
+    }
+}
+
+/// A file the command reads or writes without it appearing in its arguments, like the
+/// `Makefile` read by `make`.
 #[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ImplicitFile {
+    /// InputFile or OutputFile.
+    pub file_type: ArgType,
+    /// Relative to the current directory, unless it starts with `/`, `~/` (the home directory) or
+    /// `$VAR` (an environment variable).
+    pub path: String,
+}
+
+impl ImplicitFile {
+    /// If the path is resolved against the current directory.
+    pub fn is_relative(&self) -> bool {
+        !(self.path.starts_with('/') || self.path.starts_with('~') || self.path.starts_with('$'))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct ParsingOptions {
     /// Option to configure that long options can be parsed with a single dash.
     pub long_arg_single_dash: bool,
@@ -146,8 +222,16 @@ pub struct ParsingOptions {
     pub reduces_input: bool,
     /// If the command implicitly relies on the current directory (e.g., many git commands)
     pub needs_current_dir: bool,
+    /// If positional arguments go before the options, like find's paths before its expression
+    pub params_first: bool,
     /// Does command read input sequentially
     pub access_type: AccessType,
+    /// Files the command accesses that aren't named in its arguments
+    pub implicit_files: Vec<ImplicitFile>,
+    /// How large the output is compared to the input; overrides `reduces_input`
+    pub output_size: Option<OutputSize>,
+    /// How to merge the outputs of parallel parts, if the command is split
+    pub combiner: Combiner,
 }
 
 impl Default for ParsingOptions {
@@ -157,7 +241,11 @@ impl Default for ParsingOptions {
             splittable_across_input: false,
             reduces_input: false,
             needs_current_dir: false,
+            params_first: false,
             access_type: AccessType::default(),
+            implicit_files: Vec::new(),
+            output_size: None,
+            combiner: Combiner::default(),
         }
     }
 }
@@ -171,10 +259,28 @@ pub enum IndividualParseOption {
     ReducesInput,
     /// Relies on the current dir (implicitly)
     NeedsCurrentDir,
+    /// Takes its positional arguments before its options
+    ParamsFirst,
+    /// Seeks within its input files, so they can't be streamed
+    RandomAccess,
+    /// Reads a file not named in its arguments
+    ImplicitInput(String),
+    /// Writes a file not named in its arguments
+    ImplicitOutput(String),
+    /// Output is this fraction of the input
+    Selectivity(String),
+    /// Output is larger than the input
+    ExpandsInput,
+    /// Output size doesn't depend on the input
+    ConstantOutput,
+    /// Output is a line per input file
+    OutputPerInputFile,
+    /// How to merge the outputs of parallel parts
+    Combiner(String),
 }
 
 /// An annotation is a command name and a vector of args
-#[derive(Debug, PartialEq, Eq, Default)]
+#[derive(Debug, PartialEq, Default)]
 pub struct Command {
     /// Name of command to be parsed.
     pub command_name: String,
@@ -230,14 +336,146 @@ impl Clone for Command {
 }
 
 impl Command {
+    /// Parses one annotation in the one-line syntax. Errors point at the column where the
+    /// annotation stops matching the syntax.
     pub fn new(ann: &str) -> Result<Self> {
-        match parse_annotation(CompleteByteSlice(ann.as_bytes())) {
-            Ok(a) => match a.1 {
-                Ok(annotation) => Ok(annotation),
-                Err(e) => bail!("Failed parsing annotaton: {:?}", e),
-            },
-            Err(e) => bail!("Failed parsing annotaton for {:?}: {:?}", ann, e),
+        let (cmd, _) = parse_annotation_line(ann)?;
+        Ok(cmd)
+    }
+
+    /// Parses and validates one annotation in the one-line syntax, pointing validation errors at
+    /// the argument that breaks the rule.
+    pub fn new_validated(ann: &str) -> Result<Self> {
+        let (cmd, columns) = parse_annotation_line(ann)?;
+        if let Some((arg, message)) = cmd.problems().into_iter().next() {
+            let column = match arg {
+                Some(idx) => columns.get(idx).cloned().unwrap_or(1),
+                None => 1,
+            };
+            bail!("{}", point_at(ann, column, &message));
         }
+        Ok(cmd)
+    }
+
+    /// Rules the annotation breaks that keep it from being used to parse commands, each with the
+    /// index of the argument that breaks it.
+    pub fn problems(&self) -> Vec<(Option<usize>, String)> {
+        let mut problems: Vec<(Option<usize>, String)> = Vec::new();
+        let mut shorts: HashMap<&str, usize> = HashMap::default();
+        let mut longs: HashMap<&str, usize> = HashMap::default();
+        let mut splittable: Option<usize> = None;
+        let mut positional_list: Option<usize> = None;
+        for (idx, arg) in self.args.iter().enumerate() {
+            let mut problem = |message: String| problems.push((Some(idx), message));
+            let param = match arg {
+                Argument::LoneOption(opt) | Argument::OptWithParam(opt, _) => {
+                    if opt.short.is_empty() && opt.long.is_empty() {
+                        problem("options need a short or long name".to_string());
+                    }
+                    if !opt.short.is_empty() {
+                        if let Some(other) = shorts.insert(&opt.short, idx) {
+                            problem(format!(
+                                "duplicate short option -{}, also in argument {}",
+                                opt.short,
+                                other + 1
+                            ));
+                        }
+                    }
+                    if !opt.long.is_empty() {
+                        if let Some(other) = longs.insert(&opt.long, idx) {
+                            problem(format!(
+                                "duplicate long option --{}, also in argument {}",
+                                opt.long,
+                                other + 1
+                            ));
+                        }
+                    }
+                    match arg {
+                        Argument::OptWithParam(_, param) => param,
+                        _ => continue,
+                    }
+                }
+                Argument::LoneParam(param) => {
+                    if let Some(list) = positional_list {
+                        problem(format!(
+                            "argument {} takes a space-separated list, so it must be the last positional argument",
+                            list + 1
+                        ));
+                    }
+                    match param.size {
+                        ParamSize::Zero => {
+                            problem("positional arguments can't have size 0".to_string())
+                        }
+                        ParamSize::One => {
+                            if param.splittable {
+                                problem(
+                                    "splittable arguments must take more than one value"
+                                        .to_string(),
+                                );
+                            }
+                        }
+                        ParamSize::SpecificSize(size, sep) => {
+                            if size == 1 && param.splittable {
+                                problem(
+                                    "splittable arguments must take more than one value"
+                                        .to_string(),
+                                );
+                            }
+                            if size > 1 && sep != ListSeparator::Comma {
+                                positional_list = Some(idx);
+                            }
+                        }
+                        ParamSize::List(sep) => {
+                            if sep != ListSeparator::Comma {
+                                positional_list = Some(idx);
+                            }
+                        }
+                    }
+                    param
+                }
+            };
+            if param.appends
+                && !matches!(
+                    param.param_type,
+                    ArgType::OutputFile | ArgType::OutputFileList
+                )
+            {
+                problem("only output files can be appended to".to_string());
+            }
+            if param.splittable {
+                match splittable {
+                    Some(other) => problems.push((
+                        Some(idx),
+                        format!(
+                            "only one argument can be splittable, and argument {} already is",
+                            other + 1
+                        ),
+                    )),
+                    None => splittable = Some(idx),
+                }
+            }
+        }
+        if let Combiner::Custom(command) = &self.parsing_options.combiner {
+            match shellwords::split(command) {
+                Ok(words) if !words.is_empty() => {}
+                _ => problems.push((None, format!("combiner {:?} isn't a command line", command))),
+            }
+        }
+        for file in self.parsing_options.implicit_files.iter() {
+            if file.path.trim().is_empty() || file.path == "$" {
+                problems.push((None, "implicit files need a path".to_string()));
+            } else if file.path.starts_with('~') && file.path != "~" && !file.path.starts_with("~/")
+            {
+                problems.push((
+                    None,
+                    format!(
+                        "implicit file {}: only the current user's home can be named, as ~/path",
+                        file.path
+                    ),
+                ));
+            }
+        }
+        problems
     }
 
     pub fn long_arg_single_dash(&self) -> bool {
@@ -287,15 +525,281 @@ impl Command {
     }
 }
 
+/// Writes the annotation back out in the syntax `Command::new` parses, keeping the argument order.
+/// Descriptions are left out.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.command_name)?;
+        let mut options: Vec<String> = Vec::new();
+        if self.parsing_options.long_arg_single_dash {
+            options.push("long_arg_single_dash".to_string());
+        }
+        if self.parsing_options.splittable_across_input {
+            options.push("splittable_across_input".to_string());
+        }
+        if self.parsing_options.reduces_input {
+            options.push("reduces_input".to_string());
+        }
+        if self.parsing_options.needs_current_dir {
+            options.push("needs_current_dir".to_string());
+        }
+        if self.parsing_options.params_first {
+            options.push("params_first".to_string());
+        }
+        if self.parsing_options.access_type == AccessType::Random {
+            options.push("random_access".to_string());
+        }
+        match self.parsing_options.output_size {
+            Some(OutputSize::Ratio(ratio)) => options.push(format!("selectivity:({})", ratio)),
+            Some(OutputSize::ExpandsInput) => options.push("expands_input".to_string()),
+            Some(OutputSize::Constant) => options.push("constant_output".to_string()),
+            Some(OutputSize::PerInputFile) => options.push("output_per_input_file".to_string()),
+            None => {}
+        }
+        if self.parsing_options.combiner != Combiner::Concat {
+            options.push(format!("combiner:({})", self.parsing_options.combiner));
+        }
+        for file in self.parsing_options.implicit_files.iter() {
+            match file.file_type {
+                ArgType::OutputFile => options.push(format!("implicit_output:({})", file.path)),
+                _ => options.push(format!("implicit_input:({})", file.path)),
+            }
+        }
+        if !options.is_empty() {
+            write!(f, "[{}]", options.join(","))?;
+        }
+        write!(f, ":")?;
+        // consecutive arguments of the same kind share a section
+        let mut section: Option<&str> = None;
+        for arg in self.args.iter() {
+            let (name, fields) = match arg {
+                Argument::LoneOption(opt) => {
+                    let mut fields = opt_fields(opt);
+                    if opt.multiple {
+                        fields.push("multiple".to_string());
+                    }
+                    ("FLAGS", fields)
+                }
+                Argument::OptWithParam(opt, param) => {
+                    let mut fields = opt_fields(opt);
+                    fields.append(&mut param_fields(param));
+                    ("OPTPARAMS", fields)
+                }
+                Argument::LoneParam(param) => ("PARAMS", param_fields(param)),
+            };
+            if section != Some(name) {
+                if section.is_some() {
+                    write!(f, "]")?;
+                }
+                write!(f, " {}:[", name)?;
+                section = Some(name);
+            } else {
+                write!(f, ",")?;
+            }
+            write!(f, "({})", fields.join(","))?;
+        }
+        if section.is_some() {
+            write!(f, "]")?;
+        }
+        Ok(())
+    }
+}
+
+fn opt_fields(opt: &Opt) -> Vec<String> {
+    let mut fields: Vec<String> = Vec::new();
+    if !opt.short.is_empty() {
+        fields.push(format!("short:{}", opt.short));
+    }
+    if !opt.long.is_empty() {
+        fields.push(format!("long:{}", opt.long));
+    }
+    fields
+}
+
+fn param_fields(param: &Param) -> Vec<String> {
+    let mut fields: Vec<String> = Vec::new();
+    if param.splittable {
+        fields.push("splittable".to_string());
+    }
+    let param_type = match param.param_type {
+        ArgType::InputFile | ArgType::InputFileList => "input_file",
+        ArgType::OutputFile | ArgType::OutputFileList => "output_file",
+        ArgType::Str => "str",
+    };
+    fields.push(format!("type:{}", param_type));
+    let separator = |sep: &ListSeparator| match sep {
+        ListSeparator::Space => "( )",
+        ListSeparator::Comma => "(,)",
+    };
+    let size = match &param.size {
+        ParamSize::Zero => "0".to_string(),
+        ParamSize::One => "1".to_string(),
+        ParamSize::SpecificSize(num, sep) => format!(
+            "specific_size(size:{},list_separator:{})",
+            num,
+            separator(sep)
+        ),
+        ParamSize::List(sep) => format!("list(list_separator:{})", separator(sep)),
+    };
+    fields.push(format!("size:{}", size));
+    if !param.default_value.is_empty() {
+        fields.push(format!("default_value:\"{}\"", param.default_value));
+    }
+    if param.multiple {
+        fields.push("multiple".to_string());
+    }
+    if param.attached_to_short {
+        fields.push("attached".to_string());
+    }
+    if param.appends {
+        fields.push("appends".to_string());
+    }
+    fields
+}
+
+/// Reads annotations in the one-line syntax, or YAML for files ending in .yaml or .yml.
+/// Fails listing every annotation that doesn't parse or validate.
 pub fn parse_annotation_file(file: &str) -> Result<Vec<Command>> {
+    let (cmds, errors) = load_annotation_file(file)?;
+    if !errors.is_empty() {
+        bail!("{}:\n{}", file, errors.join("\n"));
+    }
+    Ok(cmds)
+}
+
+/// Reads the annotations in a file that parse and validate, and an error for each that doesn't,
+/// so a bad annotation doesn't keep the rest from loading.
+pub fn load_annotation_file(file: &str) -> Result<(Vec<Command>, Vec<String>)> {
+    let contents = fs::read_to_string(file)?;
+    if file.ends_with(".yaml") || file.ends_with(".yml") {
+        return load_annotation_yaml(&contents);
+    }
+    Ok(load_annotation_str(&contents))
+}
+
+/// Parses annotations from a string with one annotation per line.
+pub fn parse_annotation_str(annotations: &str) -> Result<Vec<Command>> {
+    let (cmds, errors) = load_annotation_str(annotations);
+    if !errors.is_empty() {
+        bail!("{}", errors.join("\n"));
+    }
+    Ok(cmds)
+}
+
+/// Like `parse_annotation_str`, but skips bad lines, returning an error for each of the form
+/// `line 4, column 23: ...`.
+pub fn load_annotation_str(annotations: &str) -> (Vec<Command>, Vec<String>) {
     let mut ret: Vec<Command> = Vec::new();
-    let file = File::open(file)?;
-    let reader = BufReader::new(file);
+    let mut errors: Vec<String> = Vec::new();
+    for (idx, line) in annotations.lines().enumerate() {
+        if is_annotation_comment(line) {
+            continue;
+        }
+        match Command::new_validated(line) {
+            Ok(cmd) => ret.push(cmd),
+            Err(e) => errors.push(format!("line {}, {}", idx + 1, e)),
+        }
+    }
+    (ret, errors)
+}
+
+/// Blank lines and lines starting with `#` are skipped in annotation files.
+pub fn is_annotation_comment(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+/// Formats an error at a column of an annotation, followed by the annotation with a caret under
+/// the column.
+pub fn point_at(ann: &str, column: usize, message: &str) -> String {
+    format!(
+        "column {}: {}\n    {}\n    {}^",
+        column,
+        message,
+        ann,
+        " ".repeat(column - 1)
+    )
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_columns() {
+        let err = Command::new(
+            "tar: FLAGS:[(short:x),(short:z))] OPTPARAMS:[(short:f,type:input_file,size:1)]",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.starts_with("column 32: expected `(` or `]` after an argument, found `)`"));
+        assert!(err.ends_with(&format!("\n    {}^", " ".repeat(31))));
+
+        // a typo used to silently drop the rest of the annotation
+        let err =
+            Command::new("tar: FLAGS:[(short:x)] OPTPARAMS:[(short:f,type:inptu_file,size:1)]")
+                .unwrap_err()
+                .to_string();
+        assert!(err.starts_with("column 44: bad field `type:inptu_file`; expected type:input_file"));
+
+        let err = Command::new("tar: FLAGS:[(short:x,type:str)]")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("column 22: type is not allowed in FLAGS"));
+        let err = Command::new("tar[reduce]: FLAGS:[(short:x)]")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("column 5: unknown command option `reduce`"));
+        let err = Command::new("tar[reduces_input: FLAGS:[(short:x)]")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("column 18: expected `]` after the command options, found `:`"));
+        let err = Command::new("tar FLAGS:[(short:x)]")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("column 4: expected `:` after the command name"));
+        let err = Command::new("tar: FLAGS:[(short:x)] junk")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("column 24: expected FLAGS:, OPTPARAMS: or PARAMS:, found `junk`"));
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let err = Command::new_validated(
+            "tar: FLAGS:[(short:x),(short:z)] OPTPARAMS:[(short:x,type:str,size:1)]",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.starts_with("column 45: duplicate short option -x, also in argument 1"));
+        let cmd = Command::new(
+            "cp: PARAMS:[(type:input_file,size:list(list_separator:( ))),(type:output_file,size:1)]",
+        )
+        .unwrap();
+        assert_eq!(
+            cmd.problems(),
+            vec![(
+                Some(1),
+                "argument 1 takes a space-separated list, so it must be the last positional argument"
+                    .to_string()
+            )]
+        );
+        let cmd = Command::new("cat: PARAMS:[(splittable,type:input_file,size:list(list_separator:( )))] OPTPARAMS:[(short:f,splittable,type:input_file,size:list(list_separator:(,)))]").unwrap();
+        assert_eq!(cmd.problems().len(), 1);
+    }
 
-    for line in reader.lines() {
-        let line_src = line?;
-        let cmd = Command::new(line_src.as_ref())?;
-        ret.push(cmd);
+    #[test]
+    fn test_load_reports_all_lines() {
+        let (cmds, errors) = load_annotation_str(
+            "cat: PARAMS:[(type:input_file,size:list(list_separator:( ))),]
+# comment
+grep: OPTPARAMS:[(short:e,long:regexp,type:str,size:1)
+wc: FLAGS:[(short:l,long:lines)] PARAMS:[(type:input_file,size:list(list_separator:( )))]
+sort: FLAGS:[(short:r),(long:reverse),(short:r)]",
+        );
+        assert_eq!(cmds.len(), 2);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("line 3, column 55: expected `(` or `]`"));
+        assert!(errors[1].starts_with("line 5, column 39: duplicate short option -r"));
+        assert!(parse_annotation_str("wc: FLAGS:[(short:l)]\nwc: FLAGS:[(shrt:l)]").is_err());
     }
-    Ok(ret)
 }
@@ -0,0 +1,246 @@
+use super::grammar::ArgType;
+use super::parser::Parser;
+use dash::graph::command::NodeArg;
+use dash::util::Result;
+use failure::bail;
+use std::fs::read_to_string;
+
+/// An example invocation and what should come out of matching it against the annotations.
+/// Expectations that aren't given aren't checked.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Example {
+    /// Line of the examples file the invocation is on, counting from 1.
+    pub line: usize,
+    /// Command name followed by its arguments.
+    pub invocation: Vec<String>,
+    /// Whether no annotation should match, so the command would run unannotated.
+    pub default: bool,
+    /// Arguments that should be input files, in order.
+    pub inputs: Option<Vec<String>>,
+    /// Arguments that should be output files, in order.
+    pub outputs: Option<Vec<String>>,
+    /// Values of the argument the command should be splittable across.
+    pub splittable: Option<Vec<String>>,
+}
+
+/// Reads examples from a file; see `parse_examples`.
+pub fn parse_examples_file(examples_file: &str) -> Result<Vec<Example>> {
+    parse_examples(&read_to_string(examples_file)?)
+}
+
+/// Parses examples: each invocation on its own line, followed by indented lines with what is
+/// expected of it.
+/// - `inputs: <args>` and `outputs: <args>`: the arguments that are input or output files,
+/// - `splittable: <args>`: the values of the splittable argument,
+/// - `default`: no annotation matches the invocation.
+///
+/// Invocations and argument lists are split like shell words; empty lists expect none. Blank
+/// lines and lines starting with `#` are ignored.
+pub fn parse_examples(examples: &str) -> Result<Vec<Example>> {
+    let mut ret: Vec<Example> = Vec::new();
+    for (idx, line) in examples.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let line_num = idx + 1;
+        if !line.starts_with(char::is_whitespace) {
+            let invocation = split_words(trimmed, line_num)?;
+            ret.push(Example {
+                line: line_num,
+                invocation: invocation,
+                ..Default::default()
+            });
+            continue;
+        }
+        let example = match ret.last_mut() {
+            Some(example) => example,
+            None => bail!("line {}: expectation before any invocation", line_num),
+        };
+        let (key, value) = match trimmed.find(':') {
+            Some(pos) => (trimmed[..pos].trim(), Some(&trimmed[pos + 1..])),
+            None => (trimmed, None),
+        };
+        match (key, value) {
+            ("default", None) => example.default = true,
+            ("inputs", Some(value)) => example.inputs = Some(split_words(value, line_num)?),
+            ("outputs", Some(value)) => example.outputs = Some(split_words(value, line_num)?),
+            ("splittable", Some(value)) => {
+                example.splittable = Some(split_words(value, line_num)?)
+            }
+            _ => bail!(
+                "line {}: unknown expectation {:?}; expected inputs:, outputs:, splittable: or default",
+                line_num,
+                trimmed
+            ),
+        }
+    }
+    for example in ret.iter() {
+        if example.default
+            && (example.inputs.is_some()
+                || example.outputs.is_some()
+                || example.splittable.is_some())
+        {
+            bail!(
+                "line {}: an invocation expected to match no annotation can't expect files",
+                example.line
+            );
+        }
+    }
+    Ok(ret)
+}
+
+fn split_words(words: &str, line_num: usize) -> Result<Vec<String>> {
+    match shellwords::split(words) {
+        Ok(words) => Ok(words),
+        Err(_) => bail!("line {}: mismatched quotes in {:?}", line_num, words.trim()),
+    }
+}
+
+fn arg_string(arg: &NodeArg) -> String {
+    match arg {
+        NodeArg::Str(s) => s.clone(),
+        NodeArg::Stream(fs) => fs.get_path().to_string_lossy().to_string(),
+    }
+}
+
+/// Matches the example's invocation with the parser, returning a message for each expectation
+/// it doesn't meet.
+pub fn check_example(parser: &Parser, example: &Example) -> Vec<String> {
+    let mut mismatches: Vec<String> = Vec::new();
+    let (cmd, args) = match example.invocation.split_first() {
+        Some(split) => split,
+        None => return vec!["empty invocation".to_string()],
+    };
+    let arg_match = match (parser.annotated_match(cmd, args), example.default) {
+        (Some(_), true) => {
+            return vec!["expected no annotation to match, but one did".to_string()];
+        }
+        (None, false) => {
+            return vec!["no annotation matched, so it would run unannotated".to_string()];
+        }
+        (None, true) => return mismatches,
+        (Some(arg_match), false) => arg_match,
+    };
+    let files = arg_match.argument_files();
+    let files_of = |is_input: bool| -> Vec<String> {
+        files
+            .iter()
+            .filter(|(argtype, _)| match argtype {
+                ArgType::InputFile | ArgType::InputFileList => is_input,
+                ArgType::OutputFile | ArgType::OutputFileList => !is_input,
+                ArgType::Str => false,
+            })
+            .map(|(_, fs)| fs.get_path().to_string_lossy().to_string())
+            .collect()
+    };
+    let splittable: Vec<String> = arg_match
+        .get_splittable_values()
+        .iter()
+        .map(arg_string)
+        .collect();
+    let checks = vec![
+        ("inputs", &example.inputs, files_of(true)),
+        ("outputs", &example.outputs, files_of(false)),
+        ("splittable", &example.splittable, splittable),
+    ];
+    for (name, expected, actual) in checks {
+        if let Some(expected) = expected {
+            if *expected != actual {
+                mismatches.push(format!(
+                    "{}: expected {:?}, got {:?}",
+                    name, expected, actual
+                ));
+            }
+        }
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::grammar::parse_annotation_str;
+    use super::*;
+
+    fn parser() -> Parser {
+        Parser::from_annotations(
+            parse_annotation_str(
+                "sort: FLAGS:[(short:r)] OPTPARAMS:[(short:o,type:output_file,size:1)] PARAMS:[(splittable,type:input_file,size:list(list_separator:( )))]",
+            )
+            .unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_examples() {
+        let examples = parse_examples(
+            "# sorting
+sort -r -o out.txt a.txt 'b c.txt'
+    inputs: a.txt 'b c.txt'
+    outputs: out.txt
+
+sort --unknown
+    default
+",
+        )
+        .unwrap();
+        assert_eq!(
+            examples,
+            vec![
+                Example {
+                    line: 2,
+                    invocation: vec!["sort", "-r", "-o", "out.txt", "a.txt", "b c.txt"]
+                        .into_iter()
+                        .map(String::from)
+                        .collect(),
+                    inputs: Some(vec!["a.txt".to_string(), "b c.txt".to_string()]),
+                    outputs: Some(vec!["out.txt".to_string()]),
+                    ..Default::default()
+                },
+                Example {
+                    line: 6,
+                    invocation: vec!["sort".to_string(), "--unknown".to_string()],
+                    default: true,
+                    ..Default::default()
+                },
+            ]
+        );
+        assert!(parse_examples("    inputs: a.txt").is_err());
+        assert!(parse_examples("sort a.txt\n    input: a.txt").is_err());
+        assert!(parse_examples("sort --unknown\n    default\n    inputs:").is_err());
+    }
+
+    #[test]
+    fn test_check_example() {
+        let parser = parser();
+        let examples = parse_examples(
+            "sort -o out.txt a.txt b.txt
+    inputs: a.txt b.txt
+    outputs: out.txt
+    splittable: a.txt b.txt
+sort -o out.txt a.txt
+    inputs: b.txt
+    outputs:
+sort --unknown
+    default
+sort -r a.txt
+    default
+",
+        )
+        .unwrap();
+        assert!(check_example(&parser, &examples[0]).is_empty());
+        assert_eq!(
+            check_example(&parser, &examples[1]),
+            vec![
+                "inputs: expected [\"b.txt\"], got [\"a.txt\"]".to_string(),
+                "outputs: expected [], got [\"out.txt\"]".to_string(),
+            ]
+        );
+        assert!(check_example(&parser, &examples[2]).is_empty());
+        assert_eq!(
+            check_example(&parser, &examples[3]),
+            vec!["expected no annotation to match, but one did".to_string()]
+        );
+    }
+}
@@ -0,0 +1,377 @@
+use super::grammar::{Argument, Command, ListSeparator, Opt, Param, ParamSize};
+
+/// A draft annotation generated from a command's help text, with notes on what a human still
+/// needs to decide.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Draft {
+    pub annotation: Command,
+    /// Arguments that look like files, whose type should be set to input_file or output_file.
+    pub file_hints: Vec<String>,
+    /// Options found in the help text that annotations can't express.
+    pub skipped: Vec<String>,
+}
+
+/// Drafts an annotation from `--help` output or a man page (rendered or roff source).
+/// Options become flags or options taking one `str` value; positional arguments come from the
+/// usage line. File types, splittability and command-level metadata are left for a human.
+pub fn draft_annotation(command_name: &str, help: &str) -> Draft {
+    let text = strip_roff(help);
+    let mut draft = Draft {
+        annotation: Command {
+            command_name: command_name.to_string(),
+            ..Default::default()
+        },
+        file_hints: Vec::new(),
+        skipped: Vec::new(),
+    };
+    // long names given after a single dash, e.g. find's -maxdepth
+    let mut single_dash_longs: Vec<String> = Vec::new();
+    let mut double_dash_long = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if !trimmed.starts_with('-') || trimmed.starts_with("--") && trimmed.len() == 2 {
+            continue;
+        }
+        let spec = option_spec(trimmed);
+        let mut opt = Opt::default();
+        let mut value: Option<String> = None;
+        for part in spec.split(',').map(|part| part.trim()) {
+            let (name, part_value) = split_value(part);
+            if let Some(v) = part_value {
+                value = Some(v);
+            }
+            if let Some(long) = name.strip_prefix("--") {
+                if opt.long.is_empty() && is_long_name(long) {
+                    opt.long = long.to_string();
+                    double_dash_long |= long != "help" && long != "version";
+                } else if !is_long_name(long) {
+                    draft.skipped.push(name.to_string());
+                }
+            } else if let Some(short) = name.strip_prefix('-') {
+                if short.is_empty() {
+                    continue;
+                }
+                if short.chars().count() == 1 && short.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    if opt.short.is_empty() {
+                        opt.short = short.to_string();
+                    }
+                } else if is_long_name(short) && short.chars().any(|c| c.is_ascii_lowercase()) {
+                    if opt.long.is_empty() {
+                        opt.long = short.to_string();
+                        single_dash_longs.push(short.to_string());
+                    }
+                } else {
+                    // placeholders like grep's -NUM aren't options of their own
+                    draft.skipped.push(name.to_string());
+                }
+            }
+        }
+        if (opt.short.is_empty() && opt.long.is_empty())
+            || opt.long == "help"
+            || opt.long == "version"
+            || already_annotated(&draft.annotation, &opt)
+        {
+            continue;
+        }
+        match value {
+            Some(value) => {
+                if looks_like_file(&value) {
+                    draft
+                        .file_hints
+                        .push(format!("{} {}", option_name(&opt), value));
+                }
+                draft
+                    .annotation
+                    .args
+                    .push(Argument::OptWithParam(opt, Param::default()));
+            }
+            None => draft.annotation.args.push(Argument::LoneOption(opt)),
+        }
+    }
+    // annotations take long options after either one dash or two, not both
+    if double_dash_long && !single_dash_longs.is_empty() {
+        for arg in draft.annotation.args.iter_mut() {
+            if let Argument::LoneOption(opt) | Argument::OptWithParam(opt, _) = arg {
+                if single_dash_longs.contains(&opt.long) {
+                    opt.long = String::new();
+                }
+            }
+        }
+        draft.annotation.args.retain(|arg| match arg {
+            Argument::LoneOption(opt) | Argument::OptWithParam(opt, _) => {
+                !opt.short.is_empty() || !opt.long.is_empty()
+            }
+            Argument::LoneParam(_) => true,
+        });
+        for name in single_dash_longs.drain(..) {
+            draft.skipped.push(format!("-{}", name));
+        }
+    }
+    draft.annotation.parsing_options.long_arg_single_dash = !single_dash_longs.is_empty();
+    // option order doesn't matter, so list all flags before all options with values
+    draft
+        .annotation
+        .args
+        .sort_by_key(|arg| matches!(arg, Argument::OptWithParam(_, _)));
+
+    if let Some(usage) = usage_line(&text, command_name) {
+        let mut found_list = false;
+        for word in usage.split_whitespace() {
+            let stripped: String = word.chars().filter(|c| !"[]<>{}".contains(*c)).collect();
+            let name = stripped.trim_end_matches('.');
+            if name.is_empty()
+                || name.starts_with('-')
+                || name.to_uppercase().starts_with("OPTION")
+                || !name.chars().any(|c| c.is_ascii_alphabetic())
+            {
+                continue;
+            }
+            // annotations allow only one list of positional arguments
+            let size = if word.ends_with("...") && !found_list {
+                found_list = true;
+                ParamSize::List(ListSeparator::Space)
+            } else {
+                ParamSize::One
+            };
+            if looks_like_file(name) {
+                draft.file_hints.push(format!("positional {}", name));
+            }
+            draft.annotation.args.push(Argument::LoneParam(Param {
+                size: size,
+                ..Default::default()
+            }));
+        }
+    }
+    draft
+}
+
+/// Man page sources use roff; drop requests and font escapes so the help parser sees plain text.
+fn strip_roff(text: &str) -> String {
+    if !text
+        .lines()
+        .any(|line| line.starts_with(".TH") || line.starts_with(".SH"))
+    {
+        return text.to_string();
+    }
+    let mut ret = String::new();
+    for line in text.lines() {
+        if line.starts_with(".TP") || line.starts_with(".PP") || line.starts_with(".IP") {
+            continue;
+        }
+        let line = match line
+            .strip_prefix(".B ")
+            .or_else(|| line.strip_prefix(".BR "))
+        {
+            Some(rest) => rest,
+            None if line.starts_with('.') => {
+                ret.push('\n');
+                continue;
+            }
+            None => line,
+        };
+        let plain = line
+            .replace("\\fB", "")
+            .replace("\\fI", "")
+            .replace("\\fR", "")
+            .replace("\\fP", "")
+            .replace("\\-", "-")
+            .replace("\\(em", "-")
+            .replace("\\ ", " ")
+            .replace('"', "");
+        ret.push_str(&plain);
+        ret.push('\n');
+    }
+    ret
+}
+
+/// The option part of a help line: everything before the description, which is separated by
+/// at least two spaces or a tab.
+fn option_spec(line: &str) -> &str {
+    let end = [line.find("  "), line.find('\t')]
+        .iter()
+        .filter_map(|idx| *idx)
+        .min()
+        .unwrap_or(line.len());
+    &line[..end]
+}
+
+/// Splits `--key=KEYDEF`, `-t SEP` or `-n<num>` into the option name and its value placeholder.
+/// Optional values (`--color[=WHEN]`) are dropped, since the option is also valid alone.
+fn split_value(part: &str) -> (&str, Option<String>) {
+    if let Some(idx) = part.find("[=") {
+        return (&part[..idx], None);
+    }
+    if let Some(idx) = part.find('=') {
+        return (&part[..idx], Some(part[idx + 1..].to_string()));
+    }
+    if let Some(idx) = part.find(' ') {
+        let value = part[idx + 1..].trim();
+        if value.starts_with('[') {
+            return (&part[..idx], None);
+        }
+        return (&part[..idx], Some(value.to_string()));
+    }
+    if let Some(idx) = part.find('<') {
+        return (&part[..idx], Some(part[idx..].to_string()));
+    }
+    (part, None)
+}
+
+/// Long option names in annotations can only contain letters, dashes and underscores.
+fn is_long_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphabetic() || c == '-' || c == '_')
+        && !name.starts_with('-')
+}
+
+fn already_annotated(annotation: &Command, opt: &Opt) -> bool {
+    annotation.args.iter().any(|arg| match arg {
+        Argument::LoneOption(other) | Argument::OptWithParam(other, _) => {
+            (!opt.short.is_empty() && other.short == opt.short)
+                || (!opt.long.is_empty() && other.long == opt.long)
+        }
+        Argument::LoneParam(_) => false,
+    })
+}
+
+fn option_name(opt: &Opt) -> String {
+    if !opt.long.is_empty() {
+        format!("--{}", opt.long)
+    } else {
+        format!("-{}", opt.short)
+    }
+}
+
+fn looks_like_file(placeholder: &str) -> bool {
+    let upper = placeholder.to_uppercase();
+    ["FILE", "PATH", "DIR", "OUTPUT", "INPUT"]
+        .iter()
+        .any(|word| upper.contains(word))
+}
+
+/// The arguments after the command name on the first usage line, e.g. `[OPTION]... [FILE]...`.
+fn usage_line<'a>(text: &'a str, command_name: &str) -> Option<&'a str> {
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        let rest = if trimmed.to_lowercase().starts_with("usage:") {
+            let rest = trimmed["usage:".len()..].trim();
+            if rest.is_empty() {
+                lines.next()?.trim()
+            } else {
+                rest
+            }
+        } else if trimmed == "SYNOPSIS" {
+            lines.next()?.trim()
+        } else {
+            continue;
+        };
+        let base = command_name.rsplit('/').next().unwrap_or(command_name);
+        let idx = rest.find(base)?;
+        return Some(&rest[idx + base.len()..]);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::grammar::ArgType;
+    use super::*;
+
+    const SORT_HELP: &str = "Usage: sort [OPTION]... [FILE]...
+Write sorted concatenation of all FILE(s) to standard output.
+
+  -b, --ignore-leading-blanks  ignore leading blanks
+  -k, --key=KEYDEF          sort via a key; KEYDEF gives location and type
+  -o, --output=FILE         write result to FILE instead of standard output
+      --color[=WHEN]        colorize the output
+      --files0-from=F       read input from the files specified by
+  -t SEP                    use SEP instead of non-blank to blank transition
+  -r, --reverse             reverse the result of comparisons
+      --help     display this help and exit
+";
+
+    #[test]
+    fn test_draft_from_help() {
+        let draft = draft_annotation("sort", SORT_HELP);
+        let args = &draft.annotation.args;
+        assert_eq!(
+            args[0],
+            Argument::LoneOption(Opt {
+                short: "b".to_string(),
+                long: "ignore-leading-blanks".to_string(),
+                ..Default::default()
+            })
+        );
+        let key = args.iter().find_map(|arg| match arg {
+            Argument::OptWithParam(opt, param) if opt.long == "key" => Some(param),
+            _ => None,
+        });
+        assert_eq!(key, Some(&Param::default()));
+        assert_eq!(key.unwrap().param_type, ArgType::Str);
+        assert_eq!(draft.file_hints, vec!["--output FILE", "positional FILE"]);
+        assert_eq!(draft.skipped, vec!["--files0-from"]);
+        assert_eq!(
+            args.last(),
+            Some(&Argument::LoneParam(Param {
+                size: ParamSize::List(ListSeparator::Space),
+                ..Default::default()
+            }))
+        );
+        // the draft is itself a valid annotation
+        let printed = format!("{}", draft.annotation);
+        assert_eq!(Command::new(&printed).unwrap(), draft.annotation);
+    }
+
+    #[test]
+    fn test_draft_from_man_page() {
+        let man = ".TH HEAD 1
+.SH SYNOPSIS
+.B head
+[\\fI\\,OPTION\\/\\fR]... [\\fI\\,FILE\\/\\fR]...
+.SH DESCRIPTION
+.TP
+\\fB\\-n\\fR, \\fB\\-\\-lines\\fR=\\fI\\,[\\-]NUM\\/\\fR
+print the first NUM lines instead of the first 10
+.TP
+\\fB\\-q\\fR, \\fB\\-\\-quiet\\fR
+never print headers
+";
+        let draft = draft_annotation("head", man);
+        let printed = format!("{}", draft.annotation);
+        assert!(printed.contains("(short:n,long:lines,type:str,size:1)"));
+        assert!(printed.contains("(short:q,long:quiet)"));
+    }
+
+    #[test]
+    fn test_draft_skips_placeholders() {
+        let help = "Usage: grep [OPTION]... PATTERNS [FILE]...
+Search for PATTERNS in each FILE.
+
+  -E, --extended-regexp     PATTERNS are extended regular expressions
+  -i, --ignore-case         ignore case distinctions in patterns and data
+  -A, --after-context=NUM   print NUM lines of trailing context
+  -NUM                      same as --context=NUM
+      --help                display this help text and exit
+";
+        let draft = draft_annotation("grep", help);
+        assert_eq!(draft.skipped, vec!["-NUM"]);
+        assert!(!draft.annotation.parsing_options.long_arg_single_dash);
+        let printed = format!("{}", draft.annotation);
+        assert!(!printed.contains("NUM"));
+        assert!(printed.contains("(short:A,long:after-context,type:str,size:1)"));
+        assert_eq!(Command::new(&printed).unwrap(), draft.annotation);
+
+        // only a command whose long options all take a single dash gets long_arg_single_dash
+        let help = "Usage: find [-H] [-L] [starting-point...] [expression]
+
+  -maxdepth LEVELS   descend at most LEVELS of directories
+  -name PATTERN      base of file name matches PATTERN
+";
+        let draft = draft_annotation("find", help);
+        assert!(draft.annotation.parsing_options.long_arg_single_dash);
+        assert!(draft.skipped.is_empty());
+    }
+}
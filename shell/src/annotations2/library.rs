@@ -0,0 +1,197 @@
+use super::grammar::{parse_annotation_str, Command};
+use dash::util::Result;
+
+/// Annotations for common coreutils and data tools, bundled with the shell.
+pub const STANDARD_ANNOTATIONS: &str = include_str!("../../annotations/standard.txt");
+
+/// Parses the bundled annotations.
+pub fn standard_annotations() -> Result<Vec<Command>> {
+    parse_annotation_str(STANDARD_ANNOTATIONS)
+}
+
+/// Merges the standard annotations with the user's: any command the user annotates loses all of
+/// its standard annotations.
+pub fn with_user_annotations(user: Vec<Command>) -> Result<Vec<Command>> {
+    let mut ret: Vec<Command> = standard_annotations()?
+        .into_iter()
+        .filter(|cmd| !user.iter().any(|u| u.command_name == cmd.command_name))
+        .collect();
+    ret.extend(user);
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::grammar::ArgType;
+    use super::super::parser::Parser;
+    use super::*;
+    use dash::graph::command::NodeArg;
+    use std::path::PathBuf;
+
+    fn parser() -> Parser {
+        Parser::from_annotations(standard_annotations().unwrap()).unwrap()
+    }
+
+    fn invocation(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// Matches the invocation and returns its file dependencies.
+    fn files(parser: &Parser, cmd: &str, args: &[&str]) -> Vec<(ArgType, PathBuf)> {
+        parser
+            .match_invocation(cmd, invocation(args))
+            .unwrap()
+            .file_dependencies()
+            .into_iter()
+            .map(|(argtype, fs)| (argtype, fs.get_path()))
+            .collect()
+    }
+
+    #[test]
+    fn test_standard_annotations_parse() {
+        let cmds = standard_annotations().unwrap();
+        for name in [
+            "sort",
+            "uniq",
+            "cut",
+            "head",
+            "tail",
+            "tr",
+            "comm",
+            "join",
+            "paste",
+            "split",
+            "xargs",
+            "sed",
+            "awk",
+            "gzip",
+            "zcat",
+            "tar",
+            "jq",
+            "find",
+            "git status",
+            "git commit",
+        ]
+        .iter()
+        {
+            assert!(
+                cmds.iter().any(|cmd| cmd.command_name == *name),
+                "no annotation for {}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_standard_annotations_display() {
+        for cmd in standard_annotations().unwrap().into_iter() {
+            let printed = format!("{}", cmd);
+            assert_eq!(Command::new(&printed).unwrap(), cmd, "{}", printed);
+        }
+    }
+
+    #[test]
+    fn test_standard_invocations() {
+        let parser = parser();
+        assert_eq!(
+            files(&parser, "sort", &["-k", "2", "-o", "out.txt", "a.txt"]),
+            vec![
+                (ArgType::OutputFile, PathBuf::from("out.txt")),
+                (ArgType::InputFile, PathBuf::from("a.txt"))
+            ]
+        );
+        assert_eq!(
+            files(&parser, "comm", &["-12", "a.txt", "b.txt"]),
+            vec![
+                (ArgType::InputFile, PathBuf::from("a.txt")),
+                (ArgType::InputFile, PathBuf::from("b.txt"))
+            ]
+        );
+        assert_eq!(
+            files(&parser, "sed", &["-e", "s/a/b/", "a.txt"]),
+            vec![(ArgType::InputFile, PathBuf::from("a.txt"))]
+        );
+        assert_eq!(
+            files(&parser, "tar", &["-xzf", "a.tgz", "-C", "out"]).len(),
+            2
+        );
+        assert_eq!(
+            files(&parser, "jq", &["--arg", "k", "v", ".[$k]", "a.json"]),
+            vec![(ArgType::InputFile, PathBuf::from("a.json"))]
+        );
+        assert_eq!(
+            files(&parser, "git", &["add", "a.txt"]),
+            vec![(ArgType::InputFile, PathBuf::from("a.txt"))]
+        );
+    }
+
+    #[test]
+    fn test_find_paths_before_expression() {
+        let parser = parser();
+        let args = invocation(&["src", "docs", "-maxdepth", "2", "-name", "*.rs"]);
+        let arg_match = parser.match_invocation("find", args).unwrap();
+        // the paths are printed as given, so they aren't rewritten for a proxy
+        assert!(arg_match.get_needs_current_dir());
+        assert!(arg_match.file_dependencies().is_empty());
+        let rebuilt: Vec<String> = arg_match
+            .reconstruct()
+            .unwrap()
+            .iter()
+            .map(|arg| match arg {
+                NodeArg::Str(s) => s.clone(),
+                NodeArg::Stream(fs) => fs.get_path().to_string_lossy().to_string(),
+            })
+            .collect();
+        assert_eq!(rebuilt[..2], ["src", "docs"]);
+        assert!(rebuilt[2..].windows(2).any(|w| w == ["-maxdepth", "2"]));
+        assert!(rebuilt[2..].windows(2).any(|w| w == ["-name", "*.rs"]));
+    }
+
+    #[test]
+    fn test_grep_filenames_not_annotated() {
+        let parser = parser();
+        assert!(parser
+            .annotated_match("grep", &invocation(&["foo", "a.txt"]))
+            .is_some());
+        for args in [
+            &["foo", "a.txt", "b.txt"][..],
+            &["-H", "foo", "a.txt"],
+            &["-l", "foo", "a.txt"],
+            &["-e", "foo", "a.txt", "b.txt"],
+        ]
+        .iter()
+        {
+            assert!(
+                parser.annotated_match("grep", &invocation(args)).is_none(),
+                "{:?}",
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn test_splitting_depends_on_flags() {
+        let parser = parser();
+        let plain = parser
+            .match_invocation("grep", invocation(&["-i", "foo"]))
+            .unwrap();
+        assert!(plain.get_splittable_across_input());
+        let counted = parser
+            .match_invocation("grep", invocation(&["-c", "foo"]))
+            .unwrap();
+        assert!(!counted.get_splittable_across_input());
+        assert!(counted.get_reduces_input());
+    }
+
+    #[test]
+    fn test_user_annotations_override() {
+        let user = parse_annotation_str("sort: FLAGS:[(short:r)]").unwrap();
+        let cmds = with_user_annotations(user).unwrap();
+        let sorts: Vec<&Command> = cmds
+            .iter()
+            .filter(|cmd| cmd.command_name == "sort")
+            .collect();
+        assert_eq!(sorts.len(), 1);
+        assert!(cmds.iter().any(|cmd| cmd.command_name == "uniq"));
+    }
+}
@@ -20,5 +20,13 @@ pub mod argument_matcher;
 pub mod cmd_parser;
 /// Defines command line syntax.
 pub mod grammar;
+/// Checking how example invocations match annotations.
+pub mod harness;
+/// Drafting annotations from a command's help text.
+pub mod help;
+/// Standard annotations bundled with the shell.
+pub mod library;
 /// Parser to match command line with any of the annotations.
 pub mod parser;
+/// Annotations written in YAML, and conversion from the one-line syntax.
+pub mod yaml;
@@ -1,19 +1,43 @@
 use super::argument_matcher::ArgMatch;
-use super::cmd_parser::CmdParser;
-use super::grammar::parse_annotation_file;
+use super::cmd_parser::{CmdParser, MatchedOption};
+use super::grammar::{load_annotation_file, parse_annotation_file, Command};
+use super::library::with_user_annotations;
 use dash::util::Result;
 use std::collections::HashMap;
+/// The deepest annotated subcommand in an invocation.
+struct Subcommand {
+    /// Full name, e.g. `git log`.
+    name: String,
+    /// Invocation for the subcommand's parser: the subcommand's words, then its arguments.
+    invocation: Vec<String>,
+    /// Global options given before each word of the subcommand's name.
+    global_args: Vec<Vec<MatchedOption>>,
+}
+
 /// Keeps track of all the annotations and matches command line syntax with a particular annotation
 pub struct Parser {
     cmd_parsers: HashMap<String, CmdParser>,
 }
 
 impl Parser {
-    /// Constructs a new parser from a file containing various annotations.
+    /// Constructs a new parser from the standard annotations and a file containing various
+    /// annotations, which override the standard ones for the commands it annotates.
     pub fn new(annotations_file: &str) -> Result<Self> {
-        let mut parser_map: HashMap<String, CmdParser> = Default::default();
-        let cmds = parse_annotation_file(annotations_file)?;
+        let cmds = with_user_annotations(parse_annotation_file(annotations_file)?)?;
+        Parser::from_annotations(cmds)
+    }
 
+    /// Like `new`, but skips annotations in the file that don't parse or validate, returning an
+    /// error for each alongside the parser.
+    pub fn new_skipping_errors(annotations_file: &str) -> Result<(Self, Vec<String>)> {
+        let (cmds, errors) = load_annotation_file(annotations_file)?;
+        let parser = Parser::from_annotations(with_user_annotations(cmds)?)?;
+        Ok((parser, errors))
+    }
+
+    /// Constructs a new parser from a list of annotations.
+    pub fn from_annotations(cmds: Vec<Command>) -> Result<Self> {
+        let mut parser_map: HashMap<String, CmdParser> = Default::default();
         for cmd in cmds {
             if parser_map.contains_key(&cmd.command_name) {
                 let parser: &mut CmdParser = parser_map.get_mut(&cmd.command_name).unwrap(); // Should be safe to unwrap here
@@ -36,50 +60,97 @@ impl Parser {
     }
 
     /// Takes the specified invocation and returns a possible argument matcher.
+    /// Commands with subcommands (`git log`, `aws s3 cp`) match the deepest annotated subcommand
+    /// in the invocation. Options annotated for a command are global to its subcommands: they
+    /// can be given before any later word of a subcommand's name, as in `git -C dir log`.
     pub fn match_invocation(&self, cmd: &str, invocation: Vec<String>) -> Result<ArgMatch> {
-        match self.find_parser_key(cmd, &invocation) {
-            Some(cmd_parser_name) => {
-                let parser: &CmdParser = self.cmd_parsers.get(&cmd_parser_name).unwrap();
-                parser.parse_command(&invocation)
-            }
+        match self.annotated_match(cmd, &invocation) {
+            Some(arg_match) => Ok(arg_match),
             None => Ok(self.default_parse(cmd, &invocation)),
         }
     }
 
-    /// Searches through available parsers and returns key for parser, if one exists.
-    fn find_parser_key(&self, cmd: &str, invocation: &Vec<String>) -> Option<String> {
-        for (parser_name, _) in self.cmd_parsers.iter() {
-            let name_list: Vec<String> = parser_name
-                .clone()
-                .split(" ")
-                .map(|x| x.to_string())
-                .collect();
-            if name_list.len() == 1 {
-                if cmd.to_string() == name_list[0] {
-                    return Some(name_list[0].clone());
-                } else {
-                    continue;
-                }
-            } else {
-                let num_args = name_list.len() - 1;
-                // now join a string with the command name and the first n string args
-                let mut command_name = cmd.to_string();
-                if invocation.len() < num_args {
-                    // this parser isn't going to work
-                    continue;
-                }
-                let args = invocation.clone();
-                for i in 0..num_args {
-                    command_name.push_str(" ");
-                    command_name.push_str(args[i].as_str());
-                }
-                if command_name == *parser_name {
-                    return Some(parser_name.clone());
-                }
+    /// Like `match_invocation`, but returns None instead of falling back to the default parse
+    /// when no annotation matches the invocation.
+    pub fn annotated_match(&self, cmd: &str, invocation: &[String]) -> Option<ArgMatch> {
+        let subcommand = self.find_subcommand(cmd, invocation)?;
+        let parser: &CmdParser = self.cmd_parsers.get(&subcommand.name).unwrap();
+        let mut arg_match = parser.try_parse_command(&subcommand.invocation)?;
+        arg_match.add_global_args(subcommand.global_args);
+        Some(arg_match)
+    }
+
+    /// Walks down the subcommand tree formed by the annotated command names, skipping global
+    /// options, and returns the deepest annotated subcommand found.
+    fn find_subcommand(&self, cmd: &str, invocation: &[String]) -> Option<Subcommand> {
+        let mut name = cmd.to_string();
+        let mut words: Vec<String> = Vec::new();
+        let mut global_args: Vec<Vec<MatchedOption>> = Vec::new();
+        let mut pos = 0;
+        // name, position after its last word, and number of levels of the deepest match
+        let mut deepest: Option<(String, usize, usize)> = None;
+        if self.cmd_parsers.contains_key(&name) {
+            deepest = Some((name.clone(), 0, 0));
+        }
+        loop {
+            let mut level: Vec<MatchedOption> = Vec::new();
+            let mut next = pos;
+            while let Some((used, mut args)) = self.match_global_option(&name, &invocation[next..])
+            {
+                next += used;
+                level.append(&mut args);
+            }
+            let word = match invocation.get(next) {
+                Some(word) => word,
+                None => break,
+            };
+            let child = format!("{} {}", name, word);
+            if !self.has_subcommand(&child) {
+                break;
+            }
+            name = child;
+            words.push(word.clone());
+            global_args.push(level);
+            pos = next + 1;
+            if self.cmd_parsers.contains_key(&name) {
+                deepest = Some((name.clone(), pos, words.len()));
             }
         }
+        let (name, pos, levels) = deepest?;
+        global_args.truncate(levels);
+        // the command parser expects the subcommand's words at the start of the invocation
+        let mut subcommand_invocation: Vec<String> = words[..levels].to_vec();
+        subcommand_invocation.extend_from_slice(&invocation[pos..]);
+        Some(Subcommand {
+            name: name,
+            invocation: subcommand_invocation,
+            global_args: global_args,
+        })
+    }
 
-        None
+    /// Matches an option annotated for the command or one of its parents at the start of
+    /// `words`; the command's own options take precedence.
+    fn match_global_option(
+        &self,
+        name: &str,
+        words: &[String],
+    ) -> Option<(usize, Vec<MatchedOption>)> {
+        if !words.first()?.starts_with('-') {
+            return None;
+        }
+        let parts: Vec<&str> = name.split(' ').collect();
+        (1..=parts.len())
+            .rev()
+            .filter_map(|len| self.cmd_parsers.get(&parts[..len].join(" ")))
+            .find_map(|parser| parser.match_option(words))
+    }
+
+    /// Whether the name is annotated, or is a prefix of an annotated subcommand.
+    fn has_subcommand(&self, name: &str) -> bool {
+        let prefix = format!("{} ", name);
+        self.cmd_parsers
+            .keys()
+            .any(|key| key == name || key.starts_with(&prefix))
     }
 
     /// Default parse when no other parser is available.
@@ -87,3 +158,137 @@ impl Parser {
         ArgMatch::new_default(cmd, invocation)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::grammar::{parse_annotation_str, ArgType};
+    use super::*;
+    use dash::graph::command::NodeArg;
+    use std::path::PathBuf;
+
+    fn parser() -> Parser {
+        Parser::from_annotations(
+            parse_annotation_str(
+                "git: OPTPARAMS:[(short:C,type:input_file,size:1),(short:c,type:str,size:1)] FLAGS:[(long:no-pager)]
+git log: FLAGS:[(short:p),(long:oneline)] OPTPARAMS:[(short:n,type:str,size:1)]
+git remote: FLAGS:[(short:v)]
+git remote add: PARAMS:[(type:str,size:1),(type:str,size:1)]
+aws: OPTPARAMS:[(long:region,type:str,size:1)]
+aws s3 cp: PARAMS:[(type:input_file,size:1),(type:output_file,size:1)]",
+            )
+            .unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn reconstruct(parser: &Parser, cmd: &str, args: &[&str]) -> Vec<String> {
+        let invocation = args.iter().map(|arg| arg.to_string()).collect();
+        parser
+            .match_invocation(cmd, invocation)
+            .unwrap()
+            .reconstruct()
+            .unwrap()
+            .iter()
+            .map(|arg| match arg {
+                NodeArg::Str(s) => s.clone(),
+                NodeArg::Stream(fs) => fs.get_path().to_string_lossy().to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_global_options_before_subcommand() {
+        let parser = parser();
+        let arg_match = parser
+            .match_invocation(
+                "git",
+                vec!["-C", "repo", "--no-pager", "log", "-n", "3"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+            )
+            .unwrap();
+        let files: Vec<(ArgType, PathBuf)> = arg_match
+            .file_dependencies()
+            .into_iter()
+            .map(|(argtype, fs)| (argtype, fs.get_path()))
+            .collect();
+        assert_eq!(files, vec![(ArgType::InputFile, PathBuf::from("repo"))]);
+        assert_eq!(
+            reconstruct(
+                &parser,
+                "git",
+                &["-C", "repo", "--no-pager", "log", "-n", "3"]
+            ),
+            vec!["-C", "repo", "--no-pager", "log", "-n", "3"]
+        );
+        assert_eq!(
+            reconstruct(&parser, "git", &["-crepo=x", "log", "-p"]),
+            vec!["-c", "repo=x", "log", "-p"]
+        );
+    }
+
+    #[test]
+    fn test_deepest_subcommand() {
+        let parser = parser();
+        assert_eq!(
+            reconstruct(&parser, "git", &["remote", "add", "origin", "url"]),
+            vec!["remote", "add", "origin", "url"]
+        );
+        assert_eq!(
+            reconstruct(&parser, "git", &["remote", "-v"]),
+            vec!["remote", "-v"]
+        );
+        // `aws s3` isn't annotated, but `aws s3 cp` is, and inherits aws's options
+        assert_eq!(
+            reconstruct(&parser, "aws", &["s3", "--region", "us", "cp", "a", "b"]),
+            vec!["s3", "--region", "us", "cp", "a", "b"]
+        );
+        let arg_match = parser
+            .match_invocation(
+                "aws",
+                vec!["--region=us", "s3", "cp", "a", "b"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+            )
+            .unwrap();
+        assert_eq!(arg_match.file_dependencies().len(), 2);
+        // unknown subcommands and options fall back to the unparsed invocation
+        assert_eq!(
+            reconstruct(&parser, "git", &["-C", "repo", "push", "origin"]),
+            vec!["-C", "repo", "push", "origin"]
+        );
+        assert_eq!(
+            reconstruct(&parser, "git", &["--paginate", "log"]),
+            vec!["--paginate", "log"]
+        );
+    }
+
+    #[test]
+    fn test_implicit_files() {
+        let annotation = "make[implicit_input:(Makefile),implicit_input:($POSH_TEST_UNSET),implicit_output:($POSH_TEST_LOG_DIR/make.log)]: PARAMS:[(type:str,size:list(list_separator:( )))]";
+        let cmds = parse_annotation_str(annotation).unwrap();
+        assert_eq!(cmds[0].to_string(), annotation);
+        std::env::set_var("POSH_TEST_LOG_DIR", "/var/log");
+        let parser = Parser::from_annotations(cmds).unwrap();
+        let arg_match = parser
+            .match_invocation("make", vec!["all".to_string()])
+            .unwrap();
+        let files: Vec<(ArgType, PathBuf)> = arg_match
+            .file_dependencies()
+            .into_iter()
+            .map(|(argtype, fs)| (argtype, fs.get_path()))
+            .collect();
+        // unset variables mean the command doesn't access the file
+        assert_eq!(
+            files,
+            vec![
+                (ArgType::InputFile, PathBuf::from("Makefile")),
+                (ArgType::OutputFile, PathBuf::from("/var/log/make.log"))
+            ]
+        );
+        assert!(arg_match.uses_current_dir());
+        assert!(!arg_match.get_needs_current_dir());
+    }
+}
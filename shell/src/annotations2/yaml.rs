@@ -0,0 +1,748 @@
+use super::grammar::{
+    point_at, AccessType, ArgType, Argument, Combiner, Command, ImplicitFile, ListSeparator, Opt,
+    OutputSize, Param, ParamSize,
+};
+use dash::util::Result;
+use failure::bail;
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser as YamlParser};
+use yaml_rust::scanner::Marker;
+use yaml_rust::yaml::Hash;
+use yaml_rust::{Yaml, YamlEmitter, YamlLoader};
+
+/// Parses annotations written in YAML, an alternative to the one-line syntax.
+/// Each command maps to a list of annotations, tried in order:
+///
+/// sort:
+///   - reduces_input: true
+///     args:
+///       - flag: {short: r, long: reverse}
+///       - option: {short: o, long: output, type: output_file}
+///       - param: {type: input_file, size: list}
+///
+/// Command-level keys are `long_arg_single_dash`, `splittable_across_input`, `reduces_input`,
+/// `needs_current_dir`, `params_first`, `random_access`, `selectivity` (a number), `expands_input`,
+/// `constant_output`, `output_per_input_file`, `combiner` (concat, sum, rerun or a command
+/// line), and `implicit_inputs` and `implicit_outputs` (lists of paths). Flags take `short`,
+/// `long`, `desc` and `multiple`; options take those plus the param keys `type` (input_file,
+/// output_file or str; default str), `size` (a number or list; default 1), `separator` (space or
+/// comma; default space), `default`, `multiple`, `splittable`, `attached` and `appends`.
+pub fn parse_annotation_yaml(annotations: &str) -> Result<Vec<Command>> {
+    let (cmds, errors) = load_annotation_yaml(annotations)?;
+    if !errors.is_empty() {
+        bail!("{}", errors.join("\n"));
+    }
+    Ok(cmds)
+}
+
+/// Like `parse_annotation_yaml`, but skips annotations that don't parse or validate, returning
+/// an error for each, of the form `line 4, column 23: ...`. Fails only if the file as a whole
+/// isn't in the expected shape.
+pub fn load_annotation_yaml(annotations: &str) -> Result<(Vec<Command>, Vec<String>)> {
+    let docs = match YamlLoader::load_from_str(annotations) {
+        Ok(docs) => docs,
+        Err(e) => bail!("Could not parse yaml annotations: {}", e),
+    };
+    let mut ret: Vec<Command> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+    let doc = match docs.first() {
+        Some(doc) => doc,
+        None => return Ok((ret, errors)),
+    };
+    let mut receiver = PositionReceiver::default();
+    if let Err(e) = YamlParser::new(annotations.chars()).load(&mut receiver, false) {
+        bail!("Could not parse yaml annotations: {}", e);
+    }
+    let positions = match receiver.root {
+        Some(positions) => positions,
+        None => return Ok((ret, errors)),
+    };
+    let commands = match doc.as_hash() {
+        Some(commands) => commands,
+        None => bail!(
+            "{}",
+            locate(
+                annotations,
+                positions.at,
+                "Yaml annotations should map command names to annotations"
+            )
+        ),
+    };
+    for (i, (name, value)) in commands.iter().enumerate() {
+        let (name_pos, value_pos) = positions.entry(i);
+        let name = match name.as_str() {
+            Some(name) => name,
+            None => bail!(
+                "{}",
+                locate(
+                    annotations,
+                    name_pos.at,
+                    &format!("Command name {:?} should be a string", name)
+                )
+            ),
+        };
+        let listed: Vec<(&Yaml, &Positions)> = match value {
+            Yaml::Array(list) => list
+                .iter()
+                .enumerate()
+                .map(|(idx, annotation)| (annotation, value_pos.child(idx)))
+                .collect(),
+            Yaml::Hash(_) => vec![(value, value_pos)],
+            other => {
+                errors.push(locate(
+                    annotations,
+                    value_pos.at,
+                    &format!("{}: annotations should be a list, not {:?}", name, other),
+                ));
+                continue;
+            }
+        };
+        for (idx, (annotation, pos)) in listed.into_iter().enumerate() {
+            let context = format!("{}, annotation {}", name, idx + 1);
+            match parse_command(name, annotation, pos) {
+                Ok(cmd) => match cmd.problems().into_iter().next() {
+                    Some((Some(arg), message)) => errors.push(locate(
+                        annotations,
+                        arg_position(annotation, pos, arg).at,
+                        &format!("{}: argument {}: {}", context, arg + 1, message),
+                    )),
+                    Some((None, message)) => errors.push(locate(
+                        annotations,
+                        pos.at,
+                        &format!("{}: {}", context, message),
+                    )),
+                    None => ret.push(cmd),
+                },
+                Err((at, message)) => errors.push(locate(
+                    annotations,
+                    at,
+                    &format!("{}: {}", context, message),
+                )),
+            }
+        }
+    }
+    Ok((ret, errors))
+}
+
+/// An error, and where in the file the node it's about starts.
+type Located<T> = std::result::Result<T, (Marker, String)>;
+
+/// Where each node of a YAML document starts, shaped like the document: the children of a map
+/// are its keys and values in turn, those of a list are its items.
+#[derive(Debug)]
+struct Positions {
+    at: Marker,
+    children: Vec<Positions>,
+}
+
+impl Positions {
+    /// The child's position, or this node's if the document has no such child, as for aliases.
+    fn child(&self, idx: usize) -> &Positions {
+        self.children.get(idx).unwrap_or(self)
+    }
+
+    /// Positions of the key and the value of a map's entry.
+    fn entry(&self, idx: usize) -> (&Positions, &Positions) {
+        (self.child(2 * idx), self.child(2 * idx + 1))
+    }
+}
+
+/// Builds the positions of the first document from the parser's events.
+#[derive(Default)]
+struct PositionReceiver {
+    open: Vec<Positions>,
+    root: Option<Positions>,
+}
+
+impl PositionReceiver {
+    fn close(&mut self, node: Positions) {
+        match self.open.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => {
+                if self.root.is_none() {
+                    self.root = Some(node);
+                }
+            }
+        }
+    }
+}
+
+impl MarkedEventReceiver for PositionReceiver {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        match ev {
+            Event::MappingStart(_) | Event::SequenceStart(_) => self.open.push(Positions {
+                at: mark,
+                children: Vec::new(),
+            }),
+            Event::MappingEnd => {
+                // a block map is reported from its first value; point at its first key instead
+                if let Some(mut node) = self.open.pop() {
+                    if let Some(first) = node.children.first() {
+                        node.at = first.at;
+                    }
+                    self.close(node);
+                }
+            }
+            Event::SequenceEnd => {
+                if let Some(node) = self.open.pop() {
+                    self.close(node);
+                }
+            }
+            Event::Scalar(..) | Event::Alias(_) => self.close(Positions {
+                at: mark,
+                children: Vec::new(),
+            }),
+            _ => {}
+        }
+    }
+}
+
+/// Formats an error at a position in the file like those in the one-line syntax: the line and
+/// column, then the line with a caret under the column.
+fn locate(text: &str, at: Marker, message: &str) -> String {
+    let line = text.lines().nth(at.line().saturating_sub(1)).unwrap_or("");
+    format!(
+        "line {}, {}",
+        at.line(),
+        point_at(line, at.col() + 1, message)
+    )
+}
+
+/// Position of an annotation's argument, or of the annotation if it has no such argument.
+fn arg_position<'a>(annotation: &Yaml, pos: &'a Positions, arg: usize) -> &'a Positions {
+    let args = annotation
+        .as_hash()
+        .and_then(|map| map.keys().position(|key| key.as_str() == Some("args")));
+    match args {
+        Some(idx) => pos.entry(idx).1.child(arg),
+        None => pos,
+    }
+}
+
+/// Places an error at the node it's about.
+fn at<T>(pos: &Positions, result: Result<T>) -> Located<T> {
+    result.map_err(|e| (pos.at, e.to_string()))
+}
+
+fn parse_command(name: &str, yaml: &Yaml, pos: &Positions) -> Located<Command> {
+    let map = match yaml.as_hash() {
+        Some(map) => map,
+        None => {
+            return Err((
+                pos.at,
+                format!("annotation should be a map, not {:?}", yaml),
+            ))
+        }
+    };
+    let mut cmd = Command {
+        command_name: name.to_string(),
+        ..Default::default()
+    };
+    for (i, (key, value)) in map.iter().enumerate() {
+        let (key_pos, value_pos) = pos.entry(i);
+        match key.as_str() {
+            Some("long_arg_single_dash") => {
+                cmd.parsing_options.long_arg_single_dash = at(value_pos, get_bool(key, value))?
+            }
+            Some("splittable_across_input") => {
+                cmd.parsing_options.splittable_across_input = at(value_pos, get_bool(key, value))?
+            }
+            Some("reduces_input") => {
+                cmd.parsing_options.reduces_input = at(value_pos, get_bool(key, value))?
+            }
+            Some("needs_current_dir") => {
+                cmd.parsing_options.needs_current_dir = at(value_pos, get_bool(key, value))?
+            }
+            Some("params_first") => {
+                cmd.parsing_options.params_first = at(value_pos, get_bool(key, value))?
+            }
+            Some("random_access") => {
+                if at(value_pos, get_bool(key, value))? {
+                    cmd.parsing_options.access_type = AccessType::Random;
+                }
+            }
+            Some("selectivity") => {
+                let ratio = match value.as_f64().or_else(|| value.as_i64().map(|n| n as f64)) {
+                    Some(ratio) if ratio > 0.0 => ratio,
+                    _ => {
+                        return Err((
+                            value_pos.at,
+                            format!("selectivity should be a positive number, not {:?}", value),
+                        ))
+                    }
+                };
+                cmd.parsing_options.output_size = Some(OutputSize::Ratio(ratio));
+            }
+            Some("expands_input") => {
+                if at(value_pos, get_bool(key, value))? {
+                    cmd.parsing_options.output_size = Some(OutputSize::ExpandsInput);
+                }
+            }
+            Some("constant_output") => {
+                if at(value_pos, get_bool(key, value))? {
+                    cmd.parsing_options.output_size = Some(OutputSize::Constant);
+                }
+            }
+            Some("output_per_input_file") => {
+                if at(value_pos, get_bool(key, value))? {
+                    cmd.parsing_options.output_size = Some(OutputSize::PerInputFile);
+                }
+            }
+            Some("combiner") => {
+                cmd.parsing_options.combiner = Combiner::new(&at(value_pos, get_str(key, value))?)
+            }
+            Some("implicit_inputs") => {
+                for path in at(value_pos, get_str_list(key, value))? {
+                    cmd.parsing_options.implicit_files.push(ImplicitFile {
+                        file_type: ArgType::InputFile,
+                        path: path,
+                    });
+                }
+            }
+            Some("implicit_outputs") => {
+                for path in at(value_pos, get_str_list(key, value))? {
+                    cmd.parsing_options.implicit_files.push(ImplicitFile {
+                        file_type: ArgType::OutputFile,
+                        path: path,
+                    });
+                }
+            }
+            Some("args") => {
+                let args = match value.as_vec() {
+                    Some(args) => args,
+                    None => {
+                        return Err((
+                            value_pos.at,
+                            format!("args should be a list, not {:?}", value),
+                        ))
+                    }
+                };
+                for (idx, arg) in args.iter().enumerate() {
+                    match parse_arg(arg, value_pos.child(idx)) {
+                        Ok(arg) => cmd.args.push(arg),
+                        Err((at, e)) => return Err((at, format!("argument {}: {}", idx + 1, e))),
+                    }
+                }
+            }
+            _ => return Err((key_pos.at, format!("unknown key {:?}", key))),
+        }
+    }
+    Ok(cmd)
+}
+
+fn parse_arg(yaml: &Yaml, pos: &Positions) -> Located<Argument> {
+    let map = match yaml.as_hash() {
+        Some(map) if map.len() == 1 => map,
+        _ => {
+            return Err((
+                pos.at,
+                format!(
+                    "should be a map with one of flag, option or param, not {:?}",
+                    yaml
+                ),
+            ))
+        }
+    };
+    let (kind, fields) = map.iter().next().unwrap();
+    let (kind_pos, fields_pos) = pos.entry(0);
+    let fields = match fields {
+        Yaml::Hash(fields) => fields.clone(),
+        Yaml::Null => Hash::new(),
+        other => {
+            return Err((
+                fields_pos.at,
+                format!("fields should be a map, not {:?}", other),
+            ))
+        }
+    };
+    match kind.as_str() {
+        Some("flag") => {
+            let mut opt = Opt::default();
+            for (i, (key, value)) in fields.iter().enumerate() {
+                let (key_pos, value_pos) = fields_pos.entry(i);
+                if !at(value_pos, parse_opt_field(&mut opt, key, value))? {
+                    return Err((key_pos.at, format!("unknown flag key {:?}", key)));
+                }
+            }
+            Ok(Argument::LoneOption(opt))
+        }
+        Some("option") => {
+            let mut opt = Opt::default();
+            let mut param = Param::default();
+            let mut separator = ListSeparator::Space;
+            for (i, (key, value)) in fields.iter().enumerate() {
+                let (key_pos, value_pos) = fields_pos.entry(i);
+                if key.as_str() == Some("multiple") {
+                    param.multiple = at(value_pos, get_bool(key, value))?;
+                } else if !at(value_pos, parse_opt_field(&mut opt, key, value))?
+                    && !at(
+                        value_pos,
+                        parse_param_field(&mut param, &mut separator, key, value),
+                    )?
+                {
+                    return Err((key_pos.at, format!("unknown option key {:?}", key)));
+                }
+            }
+            set_separator(&mut param, separator);
+            Ok(Argument::OptWithParam(opt, param))
+        }
+        Some("param") => {
+            let mut param = Param::default();
+            let mut separator = ListSeparator::Space;
+            for (i, (key, value)) in fields.iter().enumerate() {
+                let (key_pos, value_pos) = fields_pos.entry(i);
+                if !at(
+                    value_pos,
+                    parse_param_field(&mut param, &mut separator, key, value),
+                )? {
+                    return Err((key_pos.at, format!("unknown param key {:?}", key)));
+                }
+            }
+            set_separator(&mut param, separator);
+            Ok(Argument::LoneParam(param))
+        }
+        _ => Err((kind_pos.at, format!("unknown argument kind {:?}", kind))),
+    }
+}
+
+/// Returns whether the key belongs to an option.
+fn parse_opt_field(opt: &mut Opt, key: &Yaml, value: &Yaml) -> Result<bool> {
+    match key.as_str() {
+        Some("short") => opt.short = get_str(key, value)?,
+        Some("long") => opt.long = get_str(key, value)?,
+        Some("desc") => opt.desc = get_str(key, value)?,
+        Some("multiple") => opt.multiple = get_bool(key, value)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Returns whether the key belongs to a param.
+fn parse_param_field(
+    param: &mut Param,
+    separator: &mut ListSeparator,
+    key: &Yaml,
+    value: &Yaml,
+) -> Result<bool> {
+    match key.as_str() {
+        Some("type") => {
+            param.param_type = match get_str(key, value)?.as_str() {
+                "input_file" => ArgType::InputFile,
+                "output_file" => ArgType::OutputFile,
+                "str" => ArgType::Str,
+                other => bail!(
+                    "unknown type {:?}, options are input_file, output_file, str",
+                    other
+                ),
+            }
+        }
+        Some("size") => {
+            param.size = match value {
+                Yaml::Integer(0) => ParamSize::Zero,
+                Yaml::Integer(1) => ParamSize::One,
+                Yaml::Integer(n) if *n > 1 => ParamSize::SpecificSize(*n as u64, *separator),
+                Yaml::String(s) if s == "list" => ParamSize::List(*separator),
+                other => bail!("size should be a number or list, not {:?}", other),
+            }
+        }
+        Some("separator") => {
+            *separator = match get_str(key, value)?.as_str() {
+                "space" => ListSeparator::Space,
+                "comma" => ListSeparator::Comma,
+                other => bail!("unknown separator {:?}, options are space, comma", other),
+            }
+        }
+        Some("default") => param.default_value = get_str(key, value)?,
+        Some("multiple") => param.multiple = get_bool(key, value)?,
+        Some("splittable") => param.splittable = get_bool(key, value)?,
+        Some("attached") => param.attached_to_short = get_bool(key, value)?,
+        Some("appends") => param.appends = get_bool(key, value)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// The separator may be listed before or after the size.
+fn set_separator(param: &mut Param, separator: ListSeparator) {
+    param.size = match param.size {
+        ParamSize::SpecificSize(n, _) => ParamSize::SpecificSize(n, separator),
+        ParamSize::List(_) => ParamSize::List(separator),
+        size => size,
+    };
+}
+
+fn get_str(key: &Yaml, value: &Yaml) -> Result<String> {
+    match value {
+        Yaml::String(s) => Ok(s.clone()),
+        // short options like -1 are read as numbers
+        Yaml::Integer(i) => Ok(i.to_string()),
+        other => bail!("{:?} should be a string, not {:?}", key, other),
+    }
+}
+
+/// A list of strings, or a single string.
+fn get_str_list(key: &Yaml, value: &Yaml) -> Result<Vec<String>> {
+    match value.as_vec() {
+        Some(values) => values.iter().map(|value| get_str(key, value)).collect(),
+        None => Ok(vec![get_str(key, value)?]),
+    }
+}
+
+fn get_bool(key: &Yaml, value: &Yaml) -> Result<bool> {
+    match value.as_bool() {
+        Some(b) => Ok(b),
+        None => bail!("{:?} should be true or false, not {:?}", key, value),
+    }
+}
+
+/// Writes annotations in the YAML format, grouping annotations for the same command in order.
+pub fn annotations_to_yaml(cmds: &[Command]) -> Result<String> {
+    let mut commands = Hash::new();
+    for cmd in cmds.iter() {
+        let key = Yaml::String(cmd.command_name.clone());
+        let entry = commands
+            .entry(key)
+            .or_insert_with(|| Yaml::Array(Vec::new()));
+        if let Yaml::Array(list) = entry {
+            list.push(command_to_yaml(cmd));
+        }
+    }
+    let mut out = String::new();
+    let mut emitter = YamlEmitter::new(&mut out);
+    if let Err(e) = emitter.dump(&Yaml::Hash(commands)) {
+        bail!("Could not write yaml annotations: {:?}", e);
+    }
+    out.push('\n');
+    Ok(out)
+}
+
+fn command_to_yaml(cmd: &Command) -> Yaml {
+    let mut map = Hash::new();
+    let options = &cmd.parsing_options;
+    for (name, set) in [
+        ("long_arg_single_dash", options.long_arg_single_dash),
+        ("splittable_across_input", options.splittable_across_input),
+        ("reduces_input", options.reduces_input),
+        ("needs_current_dir", options.needs_current_dir),
+        ("params_first", options.params_first),
+        ("random_access", options.access_type == AccessType::Random),
+        (
+            "expands_input",
+            options.output_size == Some(OutputSize::ExpandsInput),
+        ),
+        (
+            "constant_output",
+            options.output_size == Some(OutputSize::Constant),
+        ),
+        (
+            "output_per_input_file",
+            options.output_size == Some(OutputSize::PerInputFile),
+        ),
+    ]
+    .iter()
+    {
+        if *set {
+            map.insert(yaml_str(name), Yaml::Boolean(true));
+        }
+    }
+    if options.combiner != Combiner::Concat {
+        map.insert(
+            yaml_str("combiner"),
+            yaml_str(&options.combiner.to_string()),
+        );
+    }
+    if let Some(OutputSize::Ratio(ratio)) = options.output_size {
+        map.insert(yaml_str("selectivity"), Yaml::Real(ratio.to_string()));
+    }
+    for (name, file_type) in [
+        ("implicit_inputs", ArgType::InputFile),
+        ("implicit_outputs", ArgType::OutputFile),
+    ]
+    .iter()
+    {
+        let paths: Vec<Yaml> = options
+            .implicit_files
+            .iter()
+            .filter(|file| file.file_type == *file_type)
+            .map(|file| yaml_str(&file.path))
+            .collect();
+        if !paths.is_empty() {
+            map.insert(yaml_str(name), Yaml::Array(paths));
+        }
+    }
+    let args: Vec<Yaml> = cmd
+        .args
+        .iter()
+        .map(|arg| {
+            let mut fields = Hash::new();
+            let kind = match arg {
+                Argument::LoneOption(opt) => {
+                    opt_to_yaml(opt, &mut fields);
+                    "flag"
+                }
+                Argument::OptWithParam(opt, param) => {
+                    opt_to_yaml(opt, &mut fields);
+                    param_to_yaml(param, &mut fields);
+                    "option"
+                }
+                Argument::LoneParam(param) => {
+                    param_to_yaml(param, &mut fields);
+                    "param"
+                }
+            };
+            let mut arg_map = Hash::new();
+            arg_map.insert(yaml_str(kind), Yaml::Hash(fields));
+            Yaml::Hash(arg_map)
+        })
+        .collect();
+    map.insert(yaml_str("args"), Yaml::Array(args));
+    Yaml::Hash(map)
+}
+
+fn opt_to_yaml(opt: &Opt, fields: &mut Hash) {
+    if !opt.short.is_empty() {
+        fields.insert(yaml_str("short"), yaml_str(&opt.short));
+    }
+    if !opt.long.is_empty() {
+        fields.insert(yaml_str("long"), yaml_str(&opt.long));
+    }
+    if !opt.desc.is_empty() {
+        fields.insert(yaml_str("desc"), yaml_str(&opt.desc));
+    }
+    if opt.multiple {
+        fields.insert(yaml_str("multiple"), Yaml::Boolean(true));
+    }
+}
+
+fn param_to_yaml(param: &Param, fields: &mut Hash) {
+    let param_type = match param.param_type {
+        ArgType::InputFile | ArgType::InputFileList => "input_file",
+        ArgType::OutputFile | ArgType::OutputFileList => "output_file",
+        ArgType::Str => "str",
+    };
+    fields.insert(yaml_str("type"), yaml_str(param_type));
+    let separator = match param.size {
+        ParamSize::Zero => {
+            fields.insert(yaml_str("size"), Yaml::Integer(0));
+            None
+        }
+        ParamSize::One => None,
+        ParamSize::SpecificSize(n, sep) => {
+            fields.insert(yaml_str("size"), Yaml::Integer(n as i64));
+            Some(sep)
+        }
+        ParamSize::List(sep) => {
+            fields.insert(yaml_str("size"), yaml_str("list"));
+            Some(sep)
+        }
+    };
+    if separator == Some(ListSeparator::Comma) {
+        fields.insert(yaml_str("separator"), yaml_str("comma"));
+    }
+    if !param.default_value.is_empty() {
+        fields.insert(yaml_str("default"), yaml_str(&param.default_value));
+    }
+    for (name, set) in [
+        ("multiple", param.multiple),
+        ("splittable", param.splittable),
+        ("attached", param.attached_to_short),
+        ("appends", param.appends),
+    ]
+    .iter()
+    {
+        if *set {
+            fields.insert(yaml_str(name), Yaml::Boolean(true));
+        }
+    }
+}
+
+fn yaml_str(s: &str) -> Yaml {
+    Yaml::String(s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::library::standard_annotations;
+    use super::*;
+
+    #[test]
+    fn test_parse_yaml() {
+        let cmds = parse_annotation_yaml(
+            "sort:
+  - reduces_input: true
+    args:
+      - flag: {short: r, long: reverse}
+      - option: {short: o, long: output, type: output_file}
+      - param: {type: input_file, size: list, splittable: true}
+comm:
+  args:
+    - flag: {short: 1}
+    - param: {type: input_file}
+    - param: {type: input_file}
+",
+        )
+        .unwrap();
+        assert_eq!(cmds.len(), 2);
+        assert_eq!(
+            cmds[0],
+            Command::new(
+                "sort[reduces_input]: FLAGS:[(short:r,long:reverse)] OPTPARAMS:[(short:o,long:output,type:output_file,size:1)] PARAMS:[(splittable,type:input_file,size:list(list_separator:( )))]"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            cmds[1].args[0],
+            Argument::LoneOption(Opt {
+                short: "1".to_string(),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_yaml_errors() {
+        let err = parse_annotation_yaml(
+            "sort:
+  - args:
+      - flag: {short: r}
+      - param: {type: inptu_file}
+",
+        )
+        .unwrap_err();
+        assert!(format!("{}", err)
+            .starts_with("line 4, column 23: sort, annotation 1: argument 2: unknown type"));
+        assert!(format!("{}", err).ends_with(&format!(
+            "\n          - param: {{type: inptu_file}}\n    {}^",
+            " ".repeat(22)
+        )));
+        let err = parse_annotation_yaml("sort:\n  - sizes: 1\n").unwrap_err();
+        assert!(format!("{}", err).starts_with("line 2, column 5: sort, annotation 1: unknown key"));
+
+        // validation problems point at the argument
+        let (cmds, errors) = load_annotation_yaml(
+            "head:
+  - args:
+      - param: {type: input_file, size: list}
+      - param: {type: input_file, size: list}
+tail:
+  - args:
+      - flag: {long: quiet}
+",
+        )
+        .unwrap();
+        assert_eq!(cmds.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("line 4, column 9: head, annotation 1: argument 2: "));
+    }
+
+    #[test]
+    fn test_convert_round_trip() {
+        let mut cmds = standard_annotations().unwrap();
+        cmds.push(
+            Command::new("make[implicit_input:(Makefile),implicit_output:(~/make.log)]: PARAMS:[(type:str,size:1)]")
+                .unwrap(),
+        );
+        let yaml = annotations_to_yaml(&cmds).unwrap();
+        assert!(yaml.contains("implicit_inputs:\n      - Makefile"));
+        assert_eq!(parse_annotation_yaml(&yaml).unwrap(), cmds);
+    }
+}
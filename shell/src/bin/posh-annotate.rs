@@ -0,0 +1,171 @@
+extern crate dash;
+extern crate exitcode;
+extern crate shell;
+use dash::util::Result;
+use failure::bail;
+use shell::annotations2::cmd_parser::CmdParser;
+use shell::annotations2::grammar::parse_annotation_file;
+use shell::annotations2::harness::{check_example, parse_examples_file};
+use shell::annotations2::help::draft_annotation;
+use shell::annotations2::parser::Parser;
+use shell::annotations2::yaml::annotations_to_yaml;
+use std::fs::{read_to_string, write};
+use std::process::{exit, Command};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "posh_annotate", help = "Tools for writing Posh annotations")]
+enum Opt {
+    #[structopt(
+        name = "generate",
+        help = "Draft an annotation from a command's --help output or man page."
+    )]
+    Generate {
+        #[structopt(help = "Command to annotate, e.g. sort or \"git log\".")]
+        command: String,
+        #[structopt(
+            short = "m",
+            long = "man",
+            help = "Man page (roff source or rendered text) to read instead of running --help."
+        )]
+        man_file: Option<String>,
+    },
+    #[structopt(
+        name = "convert",
+        help = "Convert annotations between the one-line syntax and YAML. Files ending in .yaml or .yml are converted to the one-line syntax; others to YAML."
+    )]
+    Convert {
+        #[structopt(help = "Annotations file to convert.")]
+        annotation_file: String,
+        #[structopt(
+            short = "o",
+            long = "output",
+            help = "Where to write the converted annotations (default stdout)."
+        )]
+        output: Option<String>,
+    },
+    #[structopt(
+        name = "test",
+        help = "Check that example invocations match the annotations as expected. Each invocation in the examples file is followed by indented lines of expectations: `inputs: <args>`, `outputs: <args>`, `splittable: <args>` or `default` (no annotation matches)."
+    )]
+    Test {
+        #[structopt(
+            help = "Annotations file to test, used along with the standard annotations like the shell does."
+        )]
+        annotation_file: String,
+        #[structopt(help = "File of example invocations and what they should match.")]
+        examples_file: String,
+    },
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let result = match opt {
+        Opt::Generate { command, man_file } => generate(&command, man_file),
+        Opt::Convert {
+            annotation_file,
+            output,
+        } => convert(&annotation_file, output),
+        Opt::Test {
+            annotation_file,
+            examples_file,
+        } => match test(&annotation_file, &examples_file) {
+            Ok(true) => Ok(()),
+            Ok(false) => exit(exitcode::DATAERR),
+            Err(e) => Err(e),
+        },
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        exit(exitcode::USAGE);
+    }
+}
+
+fn generate(command: &str, man_file: Option<String>) -> Result<()> {
+    let (help, source) = match man_file {
+        Some(man_file) => (read_to_string(&man_file)?, man_file),
+        None => (run_help(command)?, format!("{} --help", command)),
+    };
+    let draft = draft_annotation(command, &help);
+    println!("# draft annotation generated from {}", source);
+    if !draft.file_hints.is_empty() {
+        println!(
+            "# set the type of these to input_file or output_file if they are files: {}",
+            draft.file_hints.join(", ")
+        );
+    }
+    if !draft.skipped.is_empty() {
+        println!(
+            "# skipped options annotations can't express: {}",
+            draft.skipped.join(", ")
+        );
+    }
+    println!("# also consider splittable, reduces_input, needs_current_dir and output size hints");
+    let mut parser = CmdParser::new(command);
+    if let Err(e) = parser.add_annotation(draft.annotation.clone()) {
+        println!("# the draft needs fixing before it can be used: {}", e);
+    }
+    println!("{}", draft.annotation);
+    Ok(())
+}
+
+fn convert(annotation_file: &str, output: Option<String>) -> Result<()> {
+    let cmds = parse_annotation_file(annotation_file)?;
+    let converted = if annotation_file.ends_with(".yaml") || annotation_file.ends_with(".yml") {
+        let lines: Vec<String> = cmds.iter().map(|cmd| cmd.to_string()).collect();
+        format!("{}\n", lines.join("\n"))
+    } else {
+        annotations_to_yaml(&cmds)?
+    };
+    match output {
+        Some(output) => write(&output, converted)?,
+        None => print!("{}", converted),
+    }
+    Ok(())
+}
+
+/// Matches each example against the annotations, printing the ones that don't match as
+/// expected. Returns whether all of them did.
+fn test(annotation_file: &str, examples_file: &str) -> Result<bool> {
+    let parser = Parser::new(annotation_file)?;
+    let examples = parse_examples_file(examples_file)?;
+    let mut failed = 0;
+    for example in examples.iter() {
+        let mismatches = check_example(&parser, example);
+        if mismatches.is_empty() {
+            continue;
+        }
+        failed += 1;
+        println!("line {}: {}", example.line, example.invocation.join(" "));
+        for mismatch in mismatches.iter() {
+            println!("    {}", mismatch);
+        }
+    }
+    println!(
+        "{} of {} examples matched as expected",
+        examples.len() - failed,
+        examples.len()
+    );
+    Ok(failed == 0)
+}
+
+/// Runs `<command> --help`; some commands print their help to stderr.
+fn run_help(command: &str) -> Result<String> {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    if words.is_empty() {
+        bail!("No command given");
+    }
+    let output = Command::new(words[0])
+        .args(&words[1..])
+        .arg("--help")
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    if !stdout.trim().is_empty() {
+        return Ok(stdout);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    if stderr.trim().is_empty() {
+        bail!("{} --help printed nothing", command);
+    }
+    Ok(stderr)
+}
@@ -0,0 +1,214 @@
+extern crate dash;
+extern crate exitcode;
+extern crate shell;
+use dash::util::Result;
+use shell::config::check;
+use shell::config::discovery;
+use shell::config::network::FileNetwork;
+use std::fs::write;
+use std::path::Path;
+use std::process::exit;
+use std::time::Duration;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "posh_config", help = "Tools for writing Posh mount configs")]
+enum Opt {
+    #[structopt(
+        name = "discover",
+        help = "Find remote mounts at the client and generate or validate a mount config."
+    )]
+    Discover {
+        #[structopt(
+            long = "mounts",
+            default_value = "/proc/mounts",
+            help = "Mounts file to read, in the /proc/mounts format."
+        )]
+        mounts: String,
+        #[structopt(
+            short = "f",
+            long = "mount_file",
+            help = "Existing mount config to validate instead of generating one."
+        )]
+        mount_file: Option<String>,
+        #[structopt(
+            short = "o",
+            long = "output",
+            help = "Where to write the generated config (default stdout)."
+        )]
+        output: Option<String>,
+        #[structopt(
+            long = "link_speed",
+            default_value = "500",
+            help = "Link speed (Mbps) between the client and each server in a generated config."
+        )]
+        link_speed: u32,
+        #[structopt(
+            long = "tmp_directory",
+            default_value = "/tmp/posh",
+            help = "Tmp directory for each server in a generated config."
+        )]
+        tmp_directory: String,
+        #[structopt(
+            short = "run",
+            long = "runtime_port",
+            default_value = "1235",
+            help = "Port the Posh proxies listen on."
+        )]
+        runtime_port: String,
+        #[structopt(long = "no_probe", help = "Don't check whether each proxy is running.")]
+        no_probe: bool,
+    },
+    #[structopt(
+        name = "check",
+        help = "Check a mount config and annotations file for problems."
+    )]
+    Check {
+        #[structopt(short = "f", long = "mount_file", help = "Mount config to check.")]
+        mount_file: String,
+        #[structopt(
+            short = "a",
+            long = "annotations_file",
+            help = "Annotations file to check."
+        )]
+        annotation_file: Option<String>,
+        #[structopt(
+            short = "run",
+            long = "runtime_port",
+            default_value = "1235",
+            help = "Port the Posh proxies listen on."
+        )]
+        runtime_port: String,
+        #[structopt(long = "no_probe", help = "Don't check whether each proxy is running.")]
+        no_probe: bool,
+    },
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let result = match opt {
+        Opt::Discover {
+            mounts,
+            mount_file,
+            output,
+            link_speed,
+            tmp_directory,
+            runtime_port,
+            no_probe,
+        } => discover(
+            &mounts,
+            mount_file,
+            output,
+            link_speed,
+            &tmp_directory,
+            &runtime_port,
+            no_probe,
+        ),
+        Opt::Check {
+            mount_file,
+            annotation_file,
+            runtime_port,
+            no_probe,
+        } => check(&mount_file, annotation_file, &runtime_port, no_probe),
+    };
+    match result {
+        Ok(true) => exit(exitcode::OK),
+        Ok(false) => exit(exitcode::CONFIG),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(exitcode::USAGE);
+        }
+    }
+}
+
+/// Returns whether the discovered mounts had no problems.
+fn discover(
+    mounts_file: &str,
+    mount_file: Option<String>,
+    output: Option<String>,
+    link_speed: u32,
+    tmp_directory: &str,
+    runtime_port: &str,
+    no_probe: bool,
+) -> Result<bool> {
+    let mounts = discovery::read_mounts(Path::new(mounts_file))?;
+    if mounts.is_empty() {
+        eprintln!(
+            "warning: no remote (NFS, CIFS or SSHFS) mounts in {}, so there is no config to generate or validate",
+            mounts_file
+        );
+        return Ok(false);
+    }
+    let mut problems: Vec<String> = Vec::new();
+    match mount_file {
+        Some(mount_file) => {
+            let config = FileNetwork::new(&mount_file)?;
+            problems.append(&mut discovery::validate_config(&mounts, &config));
+        }
+        None => {
+            let (config, mut left_out) =
+                discovery::generate_config(&mounts, link_speed, tmp_directory)?;
+            problems.append(&mut left_out);
+            match (config, output) {
+                (Some(config), Some(output)) => write(&output, config)?,
+                (Some(config), None) => print!("{}", config),
+                (None, _) => problems
+                    .push("every mount was left out, so no config was generated".to_string()),
+            }
+        }
+    }
+    if !no_probe {
+        let mut probed: Vec<&String> = Vec::new();
+        for server in mounts.iter().filter_map(|mount| mount.server.as_ref()) {
+            if probed.contains(&server) {
+                continue;
+            }
+            probed.push(server);
+            if !discovery::proxy_is_running(server, runtime_port, Duration::from_secs(2)) {
+                problems.push(format!(
+                    "no Posh proxy running at {}:{}",
+                    server, runtime_port
+                ));
+            }
+        }
+    }
+    for problem in problems.iter() {
+        eprintln!("warning: {}", problem);
+    }
+    Ok(problems.is_empty())
+}
+
+/// Returns whether the config and annotations had no problems.
+fn check(
+    mount_file: &str,
+    annotation_file: Option<String>,
+    runtime_port: &str,
+    no_probe: bool,
+) -> Result<bool> {
+    let mut problems: Vec<String> = Vec::new();
+    match FileNetwork::new(mount_file) {
+        Ok(config) => {
+            problems.append(&mut check::check_network(&config));
+            if !no_probe {
+                problems.append(&mut check::check_proxies(
+                    &config,
+                    runtime_port,
+                    Duration::from_secs(2),
+                ));
+            }
+        }
+        Err(e) => problems.push(format!("{}: {}", mount_file, e)),
+    }
+    if let Some(annotation_file) = annotation_file {
+        for problem in check::check_annotations(Path::new(&annotation_file))?.into_iter() {
+            problems.push(format!("{}: {}", annotation_file, problem));
+        }
+    }
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!("no problems found");
+    }
+    Ok(problems.is_empty())
+}
@@ -1,16 +1,23 @@
 extern crate dash;
 extern crate shell;
-use dash::graph::program;
+use dash::graph::program::{self, Elem, NodeId};
+use dash::graph::progress::{Progress, Sample, Throughput, REPORT_INTERVAL};
 use dash::runtime::new_client as client;
 use dash::util::Result;
 use failure::bail;
+use shell::annotations2::parser::Parser;
+use shell::config::scheduler::SchedulerConfig;
 use shell::interpreter::interpreter;
-use shell::scheduler::heuristic::HeuristicScheduler;
+use shell::scheduler::{new_scheduler, SchedulerType};
+use std::collections::HashMap;
 use std::env::current_dir;
-use std::io::{stdin, stdout, Write};
+use std::io::{stderr, stdin, stdout, Write};
 use std::path::{Path, PathBuf};
 use std::process;
 use std::process::exit;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Instant;
 use structopt::StructOpt;
 use tracing::{error, Level};
 use tracing_subscriber::{filter::LevelFilter, FmtSubscriber};
@@ -88,6 +95,16 @@ struct Opt {
         default_value = "off"
     )]
     trace_level: TraceLevel,
+    #[structopt(
+        long = "scheduler",
+        help = "Scheduler to use (heuristic, dp, all-local, all-remote); overrides the mount file."
+    )]
+    scheduler: Option<SchedulerType>,
+    #[structopt(
+        long = "skip_bad_annotations",
+        help = "Print errors for annotations that don't parse and load the rest, instead of exiting."
+    )]
+    skip_bad_annotations: bool,
 }
 fn main() {
     let opt = Opt::from_args();
@@ -98,6 +115,8 @@ fn main() {
     let tmp_file = opt.tmp_file;
     let splitting_factor: u32 = opt.splitting_factor;
     let trace_level = opt.trace_level;
+    let scheduler_type = opt.scheduler;
+    let skip_bad_annotations = opt.skip_bad_annotations;
     let subscriber = match trace_level {
         TraceLevel::Debug => FmtSubscriber::builder()
             .with_max_level(Level::DEBUG)
@@ -138,15 +157,42 @@ fn main() {
         }
     };
 
-    let mut interpreter = match interpreter::Interpreter::new(
+    let scheduler_config = match SchedulerConfig::new(&mount_info) {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Failed to read scheduler config from mount file: {:?}", e);
+            exit(exitcode::USAGE);
+        }
+    };
+    let scheduler_type = scheduler_type
+        .or(scheduler_config.scheduler_type)
+        .unwrap_or_default();
+    let parser = if skip_bad_annotations {
+        Parser::new_skipping_errors(&annotation_file).map(|(parser, errors)| {
+            for e in errors.iter() {
+                eprintln!("{}: skipping annotation at {}", annotation_file, e);
+            }
+            parser
+        })
+    } else {
+        Parser::new(&annotation_file)
+    };
+    let parser = match parser {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(exitcode::USAGE);
+        }
+    };
+    let mut interpreter = match interpreter::Interpreter::with_parser(
         &mount_info,
-        &annotation_file,
-        Box::new(HeuristicScheduler {}),
+        parser,
+        new_scheduler(scheduler_type, &scheduler_config),
     ) {
         Ok(i) => i,
         Err(e) => {
             error!(
-                "Failed to construct interpreter with given mount file and annotation file: {:?}",
+                "Failed to construct interpreter with given mount file: {:?}",
                 e
             );
             process::exit(exitcode::USAGE);
@@ -165,6 +211,25 @@ fn main() {
                 continue;
             }
         };
+        let words: Vec<&str> = cmd.split_whitespace().collect();
+        if let ["set", "scheduler", name] = words.as_slice() {
+            match set_scheduler(name, &mut interpreter, &scheduler_config) {
+                Ok(_) => {}
+                Err(e) => {
+                    error!("Failed to set scheduler: {:?}", e);
+                }
+            }
+            continue;
+        }
+        if let ["set", "progress", value] = words.as_slice() {
+            match set_progress(value, &mut interpreter) {
+                Ok(_) => {}
+                Err(e) => {
+                    error!("Failed to set progress: {:?}", e);
+                }
+            }
+            continue;
+        }
         let dag = match interpreter.parse_command_line(&cmd) {
             Ok(d) => match d {
                 Some(graph) => graph,
@@ -187,6 +252,30 @@ fn main() {
     }
 }
 
+/// Builtin to switch schedulers without restarting the shell: `set scheduler <name>`.
+fn set_scheduler(
+    name: &str,
+    interpreter: &mut interpreter::Interpreter,
+    scheduler_config: &SchedulerConfig,
+) -> Result<()> {
+    let scheduler_type: SchedulerType = name.parse()?;
+    interpreter.set_scheduler(new_scheduler(scheduler_type, scheduler_config));
+    println!("scheduler set to {}", scheduler_type);
+    Ok(())
+}
+
+/// Builtin to show or hide a live progress line while programs run: `set progress on|off`.
+fn set_progress(value: &str, interpreter: &mut interpreter::Interpreter) -> Result<()> {
+    let progress = match value {
+        "on" => true,
+        "off" => false,
+        x => bail!("expected on or off, got {:?}", x),
+    };
+    interpreter.set_progress(progress);
+    println!("progress {}", value);
+    Ok(())
+}
+
 fn readline() -> Result<String> {
     let mut input = String::new();
     match stdin().read_line(&mut input) {
@@ -203,8 +292,104 @@ fn run_program(
 ) -> Result<()> {
     // first, set the client's view of the current directory
     client.set_pwd(pwd.clone());
-    match client.run_command(prog) {
+    if !prog.get_progress() {
+        match client.run_command(prog) {
+            Ok(_) => return Ok(()),
+            Err(e) => bail!("Error running program: {:?}", e),
+        }
+    }
+    let labels = node_labels(&prog);
+    let progress = Progress::new();
+    let (done, done_receiver) = mpsc::channel::<()>();
+    let display = {
+        let progress = progress.clone();
+        thread::spawn(move || show_progress(&progress, &labels, done_receiver))
+    };
+    let result = client.run_command_with_progress(prog, &progress);
+    let _ = done.send(());
+    let _ = display.join();
+    match result {
         Ok(_) => Ok(()),
         Err(e) => bail!("Error running program: {:?}", e),
     }
 }
+
+/// Names nodes by their command, for the progress line.
+fn node_labels(prog: &program::Program) -> HashMap<NodeId, String> {
+    prog.get_nodes_iter()
+        .map(|(id, node)| {
+            let label = match node.get_elem() {
+                Elem::Cmd(cmdnode) => {
+                    let name = cmdnode.get_name();
+                    match Path::new(&name).file_name() {
+                        Some(file_name) => file_name.to_string_lossy().to_string(),
+                        None => name,
+                    }
+                }
+                Elem::Read(_) => String::from("read"),
+                Elem::Write(_) => String::from("write"),
+            };
+            (*id, label)
+        })
+        .collect()
+}
+
+/// Redraws the progress line on stderr every `REPORT_INTERVAL` until the program is done, then
+/// leaves the last one up.
+fn show_progress(progress: &Progress, labels: &HashMap<NodeId, String>, done: mpsc::Receiver<()>) {
+    let mut throughput = Throughput::default();
+    loop {
+        let finished = !matches!(
+            done.recv_timeout(REPORT_INTERVAL),
+            Err(RecvTimeoutError::Timeout)
+        );
+        let edges = progress.snapshot().unwrap_or_default();
+        let sample = throughput.sample(edges, Instant::now());
+        let mut err = stderr();
+        let _ = write!(err, "\r\x1b[K{}", progress_line(&sample, labels));
+        if finished {
+            let _ = writeln!(err);
+            return;
+        }
+        let _ = err.flush();
+    }
+}
+
+fn progress_line(sample: &Sample, labels: &HashMap<NodeId, String>) -> String {
+    let label = |id: NodeId| match labels.get(&id) {
+        Some(name) => format!("{}:{}", id, name),
+        None => id.to_string(),
+    };
+    let mut parts: Vec<String> = sample
+        .edges
+        .iter()
+        .map(|rate| {
+            format!(
+                "{}>{} {}/s ({}, {} lines)",
+                label(rate.edge.left),
+                label(rate.edge.right),
+                human_bytes(rate.bytes_per_sec),
+                human_bytes(rate.edge.bytes as f64),
+                rate.edge.lines
+            )
+        })
+        .collect();
+    if let Some(id) = sample.bottleneck {
+        parts.push(format!("bottleneck: {}", label(id)));
+    }
+    parts.join(" | ")
+}
+
+fn human_bytes(bytes: f64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{:.0}{}", value, units[unit]),
+        _ => format!("{:.1}{}", value, units[unit]),
+    }
+}
@@ -5,8 +5,10 @@ use dash::graph::program;
 use dash::runtime::new_client as client;
 use dash::util::Result;
 use failure::bail;
+use shell::annotations2::parser::Parser;
+use shell::config::scheduler::SchedulerConfig;
 use shell::interpreter::interpreter;
-use shell::scheduler::heuristic::HeuristicScheduler;
+use shell::scheduler::{new_scheduler, SchedulerType};
 use std::env::current_dir;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
@@ -91,6 +93,16 @@ struct Opt {
         default_value = "off"
     )]
     trace_level: TraceLevel,
+    #[structopt(
+        long = "scheduler",
+        help = "Scheduler to use (heuristic, dp, all-local, all-remote); overrides the mount file."
+    )]
+    scheduler: Option<SchedulerType>,
+    #[structopt(
+        long = "skip_bad_annotations",
+        help = "Print errors for annotations that don't parse and load the rest, instead of exiting."
+    )]
+    skip_bad_annotations: bool,
 }
 
 fn main() {
@@ -103,6 +115,8 @@ fn main() {
     let tmp_file = opt.tmp_file;
     let splitting_factor: u32 = opt.splitting_factor;
     let trace_level = opt.trace_level;
+    let scheduler_type = opt.scheduler;
+    let skip_bad_annotations = opt.skip_bad_annotations;
     let prep = opt.prep;
     let subscriber = match trace_level {
         TraceLevel::Debug => FmtSubscriber::builder()
@@ -142,18 +156,43 @@ fn main() {
         }
     };
 
-    let mut interpreter = match interpreter::Interpreter::new(
+    let scheduler_config = match SchedulerConfig::new(&mount_info) {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Failed to read scheduler config from mount file: {:?}", e);
+            exit(exitcode::USAGE);
+        }
+    };
+    let scheduler_type = scheduler_type
+        .or(scheduler_config.scheduler_type)
+        .unwrap_or_default();
+    let parser = if skip_bad_annotations {
+        Parser::new_skipping_errors(&annotation_file).map(|(parser, errors)| {
+            for e in errors.iter() {
+                eprintln!("{}: skipping annotation at {}", annotation_file, e);
+            }
+            parser
+        })
+    } else {
+        Parser::new(&annotation_file)
+    };
+    let parser = match parser {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(exitcode::USAGE);
+        }
+    };
+    let mut interpreter = match interpreter::Interpreter::with_parser(
         &mount_info,
-        &annotation_file,
-        Box::new(HeuristicScheduler {}),
+        parser,
+        new_scheduler(scheduler_type, &scheduler_config),
     ) {
         Ok(i) => i,
         Err(e) => {
             error!(
-                "Failed to construct interpreter with given mount file: {:?} and annotation file: {:?} -> {:?}",
-                mount_info,
-                annotation_file,
-                e
+                "Failed to construct interpreter with given mount file: {:?} -> {:?}",
+                mount_info, e
             );
             exit(exitcode::USAGE);
         }
@@ -0,0 +1,132 @@
+use super::discovery::proxy_is_running;
+use super::network::FileNetwork;
+use crate::annotations2::grammar::load_annotation_file;
+use dash::graph::Location;
+use dash::util::Result;
+use std::path::Path;
+use std::time::Duration;
+
+/// Problems with a mount config that don't stop it from loading, but break scheduling or
+/// execution: missing link speeds, overlapping mounts and unusable tmp directories.
+pub fn check_network(config: &FileNetwork) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    let locations = config.get_location_list();
+    for first in locations.iter() {
+        for second in locations.iter() {
+            if first != second && config.network_speed(first, second).is_none() {
+                problems.push(format!(
+                    "missing link speed from {} to {}",
+                    location_name(first),
+                    location_name(second)
+                ));
+            }
+        }
+    }
+
+    let mounts = config.get_mounts();
+    for (idx, (mount, location)) in mounts.iter().enumerate() {
+        if !mount.is_absolute() {
+            problems.push(format!("mount {:?} is not an absolute path", mount));
+        }
+        for (other_mount, other_location) in mounts[idx + 1..].iter() {
+            if other_mount.starts_with(mount) || mount.starts_with(other_mount) {
+                problems.push(format!(
+                    "mounts {:?} ({}) and {:?} ({}) overlap",
+                    mount,
+                    location_name(location),
+                    other_mount,
+                    location_name(other_location)
+                ));
+            }
+        }
+    }
+
+    for location in locations.iter() {
+        if *location == Location::Client {
+            continue;
+        }
+        match config.get_tmp_directory(location) {
+            None => problems.push(format!(
+                "server {} has no tmp directory",
+                location_name(location)
+            )),
+            Some(directory) => {
+                let directory_str = directory.to_string_lossy();
+                if !directory.is_absolute() || directory_str.contains('"') {
+                    problems.push(format!(
+                        "tmp directory {:?} of server {} should be an absolute path; check its quotes",
+                        directory_str,
+                        location_name(location)
+                    ));
+                }
+            }
+        }
+    }
+    problems
+}
+
+/// Servers in the config without a Posh proxy accepting connections on the given port.
+pub fn check_proxies(config: &FileNetwork, port: &str, timeout: Duration) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    for location in config.get_location_list().iter() {
+        if let Location::Server(ip) = location {
+            if !proxy_is_running(ip, port, timeout) {
+                problems.push(format!("no Posh proxy running at {}:{}", ip, port));
+            }
+        }
+    }
+    problems
+}
+
+/// Parses each annotation in the file, reporting every one that fails to parse or validate.
+pub fn check_annotations(annotations_file: &Path) -> Result<Vec<String>> {
+    let file = annotations_file.to_string_lossy();
+    Ok(load_annotation_file(&file)?.1)
+}
+
+fn location_name(location: &Location) -> String {
+    match location {
+        Location::Client => "client".to_string(),
+        Location::Server(ip) => ip.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_network() {
+        let config = FileNetwork::parse(
+            "mounts:
+  \"1.0.0.1\": \"/mnt/a\"
+  \"1.0.0.2\": \"/mnt/a/b\"
+links:
+  \"(1.0.0.1,client)\": 10
+  \"(client,1.0.0.1)\": 10
+  \"(1.0.0.1,1.0.0.2)\": 10
+  \"(1.0.0.2,1.0.0.1)\": 10
+  \"(1.0.0.2,client)\": 10
+tmp_directory:
+  \"1.0.0.1\": /tmp/posh\"
+",
+        )
+        .unwrap();
+        let problems = check_network(&config);
+        assert_eq!(problems.len(), 4);
+        assert!(problems.contains(&"missing link speed from client to 1.0.0.2".to_string()));
+        assert!(problems.iter().any(|p| p.contains("overlap")));
+        assert!(problems.contains(&"server 1.0.0.2 has no tmp directory".to_string()));
+        assert!(problems.iter().any(|p| p.contains("check its quotes")));
+    }
+
+    #[test]
+    fn test_bad_config_errors() {
+        assert!(FileNetwork::parse("mounts:\n  \"1.0.0.1\": 5\nlinks:\n").is_err());
+        assert!(FileNetwork::parse(
+            "mounts:\n  \"1.0.0.1\": \"/mnt\"\nlinks:\n  \"(1.0.0.1 client)\": 10\n"
+        )
+        .is_err());
+        assert!(FileNetwork::parse("").is_err());
+    }
+}
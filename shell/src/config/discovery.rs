@@ -0,0 +1,343 @@
+use super::network::FileNetwork;
+use dash::graph::Location;
+use dash::util::Result;
+use failure::bail;
+use std::fs::read_to_string;
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Filesystem types that expose a folder from another machine.
+const REMOTE_FS_TYPES: &[&str] = &["nfs", "nfs4", "cifs", "smb3", "fuse.sshfs"];
+
+/// A remote filesystem mounted at the client, as listed in `/proc/mounts`.
+#[derive(PartialEq, Debug, Clone, Eq)]
+pub struct DiscoveredMount {
+    /// Host as written in the mount source (hostname or IP).
+    pub host: String,
+    /// IP address of the host, if it could be resolved.
+    pub server: Option<String>,
+    /// Folder exported by the host.
+    pub remote_path: PathBuf,
+    /// Where the folder is mounted at the client.
+    pub mount_point: PathBuf,
+    /// Filesystem type, e.g. nfs4.
+    pub fs_type: String,
+}
+
+/// Reads a mounts file in the `/proc/mounts` format.
+pub fn read_mounts(mounts_file: &Path) -> Result<Vec<DiscoveredMount>> {
+    let contents = read_to_string(mounts_file)?;
+    Ok(parse_mounts(&contents))
+}
+
+/// Finds the NFS, CIFS and SSHFS mounts in the contents of a mounts file.
+/// Each line is `source mount_point fs_type options dump pass`; other filesystems are skipped.
+pub fn parse_mounts(contents: &str) -> Vec<DiscoveredMount> {
+    let mut mounts: Vec<DiscoveredMount> = Vec::new();
+    for line in contents.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 || !REMOTE_FS_TYPES.contains(&fields[2]) {
+            continue;
+        }
+        let (host, remote_path) = match split_source(&unescape(fields[0]), fields[2]) {
+            Some(source) => source,
+            None => continue,
+        };
+        mounts.push(DiscoveredMount {
+            server: resolve_host(&host),
+            host: host,
+            remote_path: remote_path,
+            mount_point: PathBuf::from(unescape(fields[1])),
+            fs_type: fields[2].to_string(),
+        });
+    }
+    mounts
+}
+
+/// Splits a mount source into host and remote path.
+/// NFS sources look like `host:/path` (IPv6 hosts in brackets), SSHFS sources like
+/// `user@host:/path` and CIFS sources like `//host/share`.
+fn split_source(source: &str, fs_type: &str) -> Option<(String, PathBuf)> {
+    if fs_type == "cifs" || fs_type == "smb3" {
+        let rest = source.strip_prefix("//")?;
+        let idx = rest.find('/').unwrap_or(rest.len());
+        let share = match &rest[idx..] {
+            "" => "/",
+            share => share,
+        };
+        return Some((rest[..idx].to_string(), PathBuf::from(share)));
+    }
+    let source = match source.rfind('@') {
+        Some(idx) => &source[idx + 1..],
+        None => source,
+    };
+    let (host, path) = if source.starts_with('[') {
+        let end = source.find(']')?;
+        (&source[1..end], source[end + 1..].strip_prefix(':')?)
+    } else {
+        let idx = source.find(':')?;
+        (&source[..idx], &source[idx + 1..])
+    };
+    if host.is_empty() {
+        return None;
+    }
+    let path = if path.is_empty() { "/" } else { path };
+    Some((host.to_string(), PathBuf::from(path)))
+}
+
+/// `/proc/mounts` escapes spaces, tabs, newlines and backslashes as octal.
+fn unescape(field: &str) -> String {
+    field
+        .replace("\\040", " ")
+        .replace("\\011", "\t")
+        .replace("\\012", "\n")
+        .replace("\\134", "\\")
+}
+
+/// The mount config identifies servers by IP, so hostnames are resolved, preferring IPv4.
+fn resolve_host(host: &str) -> Option<String> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Some(ip.to_string());
+    }
+    let addrs: Vec<SocketAddr> = (host, 0).to_socket_addrs().ok()?.collect();
+    addrs
+        .iter()
+        .find(|addr| addr.is_ipv4())
+        .or_else(|| addrs.first())
+        .map(|addr| addr.ip().to_string())
+}
+
+/// Generates a mount config for the discovered mounts, returning it along with a description of
+/// each mount left out of it. There is no config if every mount is left out.
+/// Every server gets the same link speed to and from the client and the same tmp directory, to
+/// be edited by hand afterwards. Mounts whose host could not be resolved are left out, and so
+/// are those served over IPv6, which the config can't name in its links. The config is parsed
+/// back before it's returned, so it loads as written.
+pub fn generate_config(
+    mounts: &[DiscoveredMount],
+    link_speed: u32,
+    tmp_directory: &str,
+) -> Result<(Option<String>, Vec<String>)> {
+    let mut left_out: Vec<String> = Vec::new();
+    // mount points of each server, in the order the servers first show up
+    let mut servers: Vec<(&String, Vec<String>)> = Vec::new();
+    for mount in mounts.iter() {
+        let server = match &mount.server {
+            Some(server) => server,
+            None => {
+                left_out.push(format!(
+                    "left out mount {:?}: could not resolve host {:?}",
+                    mount.mount_point, mount.host
+                ));
+                continue;
+            }
+        };
+        if let Ok(IpAddr::V6(_)) = server.parse::<IpAddr>() {
+            left_out.push(format!(
+                "left out mount {:?}: IPv6 server {} can't be named in the config",
+                mount.mount_point, server
+            ));
+            continue;
+        }
+        let mount_point = format!("{:?}", mount.mount_point.to_string_lossy());
+        match servers.iter_mut().find(|(s, _)| *s == server) {
+            Some((_, mount_points)) => mount_points.push(mount_point),
+            None => servers.push((server, vec![mount_point])),
+        }
+    }
+    if servers.is_empty() {
+        return Ok((None, left_out));
+    }
+    let mut config = "mounts:\n".to_string();
+    for (server, mount_points) in servers.iter() {
+        match mount_points.as_slice() {
+            [mount_point] => config.push_str(&format!("  \"{}\": {}\n", server, mount_point)),
+            _ => config.push_str(&format!(
+                "  \"{}\": [{}]\n",
+                server,
+                mount_points.join(", ")
+            )),
+        }
+    }
+    config.push_str("links:\n");
+    for (server, _) in servers.iter() {
+        config.push_str(&format!("  \"({},client)\": {}\n", server, link_speed));
+        config.push_str(&format!("  \"(client,{})\": {}\n", server, link_speed));
+    }
+    config.push_str("tmp_directory:\n");
+    for (server, _) in servers.iter() {
+        config.push_str(&format!("  \"{}\": {:?}\n", server, tmp_directory));
+    }
+    if let Err(e) = FileNetwork::parse(&config) {
+        bail!("Generated config does not load: {}\n{}", e, config);
+    }
+    Ok((Some(config), left_out))
+}
+
+/// Compares the discovered mounts with an existing mount config, returning a description of
+/// each mismatch.
+pub fn validate_config(mounts: &[DiscoveredMount], config: &FileNetwork) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    let configured = config.get_mounts();
+    for mount in mounts.iter() {
+        let server = match &mount.server {
+            Some(server) => server,
+            None => {
+                problems.push(format!(
+                    "could not resolve host {:?} of mount {:?}",
+                    mount.host, mount.mount_point
+                ));
+                continue;
+            }
+        };
+        match configured
+            .iter()
+            .find(|(path, _)| *path == mount.mount_point)
+        {
+            Some((_, Location::Server(ip))) if ip == server => {}
+            Some((_, location)) => problems.push(format!(
+                "mount {:?} is served by {:?}, but the config lists {:?}",
+                mount.mount_point, server, location
+            )),
+            None => problems.push(format!(
+                "mount {:?} served by {:?} is missing from the config",
+                mount.mount_point, server
+            )),
+        }
+    }
+    for (path, location) in configured.iter() {
+        if !mounts.iter().any(|mount| mount.mount_point == *path) {
+            problems.push(format!(
+                "config lists mount {:?} at {:?}, but nothing is mounted there",
+                path, location
+            ));
+        }
+    }
+    problems
+}
+
+/// Whether a Posh proxy is accepting connections on the server at the given port.
+pub fn proxy_is_running(server: &str, port: &str, timeout: Duration) -> bool {
+    let addrs = match (server, port.parse::<u16>().unwrap_or(0)).to_socket_addrs() {
+        Ok(addrs) => addrs,
+        Err(_) => {
+            return false;
+        }
+    };
+    for addr in addrs {
+        if TcpStream::connect_timeout(&addr, timeout).is_ok() {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTS: &str = "sysfs /sys sysfs rw,nosuid,nodev,noexec,relatime 0 0
+/dev/sda1 / ext4 rw,relatime 0 0
+10.0.0.1:/export/data /home/user/mnt/data nfs4 rw,relatime,vers=4.2 0 0
+[fe80::1]:/export /home/user/mnt/v6 nfs rw 0 0
+//10.0.0.2/share /home/user/mnt/my\\040share cifs rw 0 0
+user@10.0.0.3:/home/user /home/user/mnt/ssh fuse.sshfs rw,nosuid 0 0
+";
+
+    #[test]
+    fn test_parse_mounts() {
+        let mounts = parse_mounts(MOUNTS);
+        assert_eq!(mounts.len(), 4);
+        assert_eq!(mounts[0].host, "10.0.0.1");
+        assert_eq!(mounts[0].server, Some("10.0.0.1".to_string()));
+        assert_eq!(mounts[0].remote_path, Path::new("/export/data"));
+        assert_eq!(mounts[0].mount_point, Path::new("/home/user/mnt/data"));
+        assert_eq!(mounts[1].server, Some("fe80::1".to_string()));
+        assert_eq!(mounts[2].host, "10.0.0.2");
+        assert_eq!(mounts[2].remote_path, Path::new("/share"));
+        assert_eq!(mounts[2].mount_point, Path::new("/home/user/mnt/my share"));
+        assert_eq!(mounts[3].host, "10.0.0.3");
+        assert_eq!(mounts[3].fs_type, "fuse.sshfs");
+    }
+
+    #[test]
+    fn test_generate_config() {
+        let mounts = parse_mounts(MOUNTS);
+        let (config, left_out) = generate_config(&mounts[..1], 500, "/tmp/posh").unwrap();
+        assert!(left_out.is_empty());
+        let config = config.unwrap();
+        let network = FileNetwork::parse(&config).unwrap();
+        assert_eq!(
+            network.get_mounts(),
+            vec![(
+                PathBuf::from("/home/user/mnt/data"),
+                Location::Server("10.0.0.1".to_string())
+            )]
+        );
+        assert!(validate_config(&mounts[..1], &network).is_empty());
+    }
+
+    #[test]
+    fn test_generate_config_groups_and_skips() {
+        let mut mounts = parse_mounts(MOUNTS);
+        // a second export from the same server
+        let mut second = mounts[0].clone();
+        second.remote_path = PathBuf::from("/export/logs");
+        second.mount_point = PathBuf::from("/home/user/mnt/logs");
+        mounts.push(second);
+        let (config, left_out) = generate_config(&mounts, 500, "/tmp/posh").unwrap();
+        let config = config.unwrap();
+        assert_eq!(left_out.len(), 1);
+        assert!(left_out[0].contains("IPv6"));
+        let network = FileNetwork::parse(&config).unwrap();
+        let served_by_first: Vec<PathBuf> = network
+            .get_mounts()
+            .into_iter()
+            .filter(|(_, loc)| *loc == Location::Server("10.0.0.1".to_string()))
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(
+            served_by_first,
+            vec![
+                PathBuf::from("/home/user/mnt/data"),
+                PathBuf::from("/home/user/mnt/logs")
+            ]
+        );
+        assert_eq!(network.get_mounts().len(), 4);
+        assert!(!config.contains("fe80"));
+
+        // with every mount left out there's nothing to write
+        let ipv6: Vec<DiscoveredMount> = mounts
+            .into_iter()
+            .filter(|mount| mount.host.contains("fe80"))
+            .collect();
+        let (config, left_out) = generate_config(&ipv6, 500, "/tmp/posh").unwrap();
+        assert_eq!(config, None);
+        assert_eq!(left_out.len(), 1);
+        assert_eq!(
+            generate_config(&[], 500, "/tmp/posh").unwrap(),
+            (None, vec![])
+        );
+    }
+
+    #[test]
+    fn test_validate_config() {
+        let mounts = parse_mounts(MOUNTS);
+        let network = FileNetwork::parse(
+            "mounts:
+  \"10.0.0.9\": \"/home/user/mnt/data\"
+  \"10.0.0.2\": \"/home/user/mnt/gone\"
+links:
+  \"(10.0.0.9,client)\": 10
+tmp_directory:
+  \"10.0.0.9\": \"/tmp\"
+",
+        )
+        .unwrap();
+        let problems = validate_config(&mounts[..1], &network);
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("10.0.0.9"));
+        assert!(problems[1].contains("nothing is mounted"));
+    }
+}
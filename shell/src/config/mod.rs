@@ -10,9 +10,15 @@ macro_rules! named_complete {
         }
     )
 }
+/// Diagnostics for mount configs and annotation files.
+pub mod check;
+/// Finding remote mounts at the client to generate or validate a mount config.
+pub mod discovery;
 /// Caches full paths to save calls to stat.
 pub mod filecache;
 /// How do we query for file sizes?
 pub mod filesize;
 /// Information about where certain files are located and speeds between various machines.
 pub mod network;
+/// Which scheduler to use and how to tune it.
+pub mod scheduler;
@@ -1,5 +1,6 @@
 extern crate nom;
 extern crate yaml_rust;
+use dash::graph::compression::Compression;
 use dash::graph::filestream::FileStream;
 use dash::graph::Location;
 use dash::util::Result;
@@ -10,7 +11,7 @@ use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 use std::str::from_utf8;
-use yaml_rust::YamlLoader;
+use yaml_rust::{Yaml, YamlLoader};
 named_complete!(
     parse_client<Location>,
     map!(tag!("client"), |_| { Location::Client })
@@ -44,16 +45,20 @@ named_complete!(
     delimited!(tag!("("), parse_pair, tag!(")"))
 );
 
-#[derive(PartialEq, Debug, Clone, Eq, Default)]
+#[derive(PartialEq, Debug, Clone, Default)]
 pub struct FileNetwork {
     /// map of local mounted paths to IP addresses
     path_to_addr: HashMap<PathBuf, ServerKey>,
+    /// other servers that hold a replica of each mount's data, in their own proxy folder
+    replicas: HashMap<PathBuf, Vec<ServerKey>>,
     /// information about other servers (when they have NFS access)
     server_info: HashMap<ServerKey, ServerInfo>,
     /// Link speed information (topology information)
     links: HashMap<(Location, Location), u32>,
     /// list of servers
     locations: Vec<Location>,
+    /// which network streams to compress, if any
+    compression: Option<CompressionConfig>,
 }
 
 #[derive(PartialEq, Debug, Clone, Hash, Eq, Default)]
@@ -61,6 +66,34 @@ pub struct ServerKey {
     pub ip: String,
 }
 
+/// Which network streams are compressed, read from the optional `compression` section of the
+/// config:
+///
+/// compression:
+///   codec: zstd
+///   max_link_speed: 100
+///   ratio: 3
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct CompressionConfig {
+    /// Codec for streams over slow links.
+    pub codec: Compression,
+    /// Streams over links no faster than this (in the units of `links`) are compressed.
+    pub max_link_speed: u32,
+    /// How much the codec is expected to shrink the data, which the dp scheduler uses to
+    /// discount transfers over compressed links.
+    pub ratio: f64,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            codec: Compression::Zstd,
+            max_link_speed: 100,
+            ratio: 2.0,
+        }
+    }
+}
+
 /// Description of which servers can access which other mounts.
 #[derive(PartialEq, Debug, Clone, Hash, Eq, Default)]
 pub struct ServerInfo {
@@ -74,25 +107,40 @@ pub struct ServerInfo {
 
 impl FileNetwork {
     pub fn new(mount_file: &str) -> Result<Self> {
+        let file_str = read_to_string(Path::new(&mount_file))?;
+        FileNetwork::parse(&file_str)
+    }
+
+    /// Parses the contents of a mount config.
+    pub fn parse(file_str: &str) -> Result<Self> {
         let mut path_to_addr: HashMap<PathBuf, ServerKey> = HashMap::default();
         let mut server_info: HashMap<ServerKey, ServerInfo> = HashMap::default();
         let mut links: HashMap<(Location, Location), u32> = HashMap::default();
-        let file_str = read_to_string(Path::new(&mount_file))?;
         let yamls = match YamlLoader::load_from_str(&file_str) {
             Ok(docs) => docs,
             Err(e) => {
-                bail!("Could not parse yaml config: {:?}", e);
+                bail!("Could not parse yaml config: {}", e);
             }
         };
+        if yamls.is_empty() {
+            bail!("Config file is empty");
+        }
         let yaml = &yamls[0];
         match yaml["mounts"].as_hash() {
             Some(map) => {
+                // a server exporting several folders lists all their mount paths
                 for (key, value) in map.iter() {
-                    let mount = Path::new(&value.as_str().unwrap()).to_path_buf();
                     let ip = ServerKey {
-                        ip: key.as_str().unwrap().to_string(),
+                        ip: yaml_str(key, "Mount server")?,
                     };
-                    path_to_addr.insert(mount, ip);
+                    let paths = match value.as_vec() {
+                        Some(list) => list.clone(),
+                        None => vec![value.clone()],
+                    };
+                    for path in paths.iter() {
+                        let mount = Path::new(&yaml_str(path, "Mount path")?).to_path_buf();
+                        path_to_addr.insert(mount, ip.clone());
+                    }
                 }
             }
             None => {
@@ -103,11 +151,22 @@ impl FileNetwork {
         match yaml["links"].as_hash() {
             Some(map) => {
                 for (key, value) in map.iter() {
-                    let link_key =
-                        parse_link_key(CompleteByteSlice(key.as_str().unwrap().as_bytes()))
-                            .unwrap()
-                            .1;
-                    let speed: u32 = value.as_i64().unwrap() as u32;
+                    let link_str = yaml_str(key, "Link")?;
+                    let link_key = match parse_link_key(CompleteByteSlice(link_str.as_bytes())) {
+                        Ok((rest, link_key)) if rest.0.is_empty() => link_key,
+                        _ => bail!(
+                            "Link {:?} should look like \"(<ip or client>,<ip or client>)\"",
+                            link_str
+                        ),
+                    };
+                    let speed: u32 = match value.as_i64() {
+                        Some(speed) if speed >= 0 => speed as u32,
+                        _ => bail!(
+                            "Speed of link {:?} should be a non-negative integer, not {:?}",
+                            link_str,
+                            value
+                        ),
+                    };
                     links.insert(link_key, speed);
                 }
             }
@@ -116,14 +175,43 @@ impl FileNetwork {
             }
         }
 
-        // TODO: add in parsing options for servers accessing other machines via NFS
+        // replicas are optional: each mount maps to a list of servers holding the same data
+        let mut replicas: HashMap<PathBuf, Vec<ServerKey>> = HashMap::default();
+        if let Some(map) = yaml["replicas"].as_hash() {
+            for (key, value) in map.iter() {
+                let mount = match key.as_str() {
+                    Some(m) => Path::new(m).to_path_buf(),
+                    None => bail!("Replica key {:?} should be a mount path", key),
+                };
+                if !path_to_addr.contains_key(&mount) {
+                    bail!("Replicas listed for {:?}, which is not under mounts", mount);
+                }
+                let servers = match value.as_vec() {
+                    Some(list) => list.clone(),
+                    None => vec![value.clone()],
+                };
+                let mut keys: Vec<ServerKey> = Vec::new();
+                for server in servers.iter() {
+                    match server.as_str() {
+                        Some(ip) => keys.push(ServerKey { ip: ip.to_string() }),
+                        None => bail!(
+                            "Replica server {:?} for {:?} should be an IP",
+                            server,
+                            mount
+                        ),
+                    }
+                }
+                replicas.insert(mount, keys);
+            }
+        }
+
         match yaml["tmp_directory"].as_hash() {
             Some(map) => {
                 for (key, value) in map.iter() {
                     let ip = ServerKey {
-                        ip: key.as_str().unwrap().to_string(),
+                        ip: yaml_str(key, "Tmp directory server")?,
                     };
-                    let directory = Path::new(&value.as_str().unwrap()).to_path_buf();
+                    let directory = Path::new(&yaml_str(value, "Tmp directory")?).to_path_buf();
                     let info = ServerInfo {
                         tmp_directory: directory,
                         other_mounted_directories: Vec::new(),
@@ -136,17 +224,55 @@ impl FileNetwork {
             }
         }
 
-        let mut servers: Vec<Location> = path_to_addr
-            .iter()
-            .map(|(_mt, server)| Location::Server(server.ip.clone()))
-            .collect();
-        servers.push(Location::Client);
-        Ok(FileNetwork {
-            path_to_addr: path_to_addr,
-            server_info: server_info,
-            links: links,
-            locations: servers,
-        })
+        let mut network = FileNetwork::construct(path_to_addr, links, server_info);
+        for (mount, servers) in replicas.into_iter() {
+            for server in servers.into_iter() {
+                network.add_replica(mount.as_path(), server)?;
+            }
+        }
+
+        // compression is optional: without it, streams are sent as they are
+        let compression = &yaml["compression"];
+        if !compression.is_badvalue() && !compression.is_null() {
+            network.set_compression(parse_compression(compression)?);
+        }
+
+        // server mounts are optional: each proxy maps other servers to the local path where it
+        // can see their mount (e.g., over NFS)
+        if let Some(map) = yaml["server_mounts"].as_hash() {
+            for (key, value) in map.iter() {
+                let server = match key.as_str() {
+                    Some(ip) => ServerKey { ip: ip.to_string() },
+                    None => bail!("Server mount key {:?} should be an IP", key),
+                };
+                let mounts = match value.as_hash() {
+                    Some(mounts) => mounts,
+                    None => bail!(
+                        "Server mounts for {:?} should map server IPs to local paths",
+                        server
+                    ),
+                };
+                for (other, local) in mounts.iter() {
+                    let (other, local) = match (other.as_str(), local.as_str()) {
+                        (Some(other), Some(local)) => (other, local),
+                        _ => bail!(
+                            "Server mount {:?}: {:?} for {:?} should map an IP to a path",
+                            other,
+                            local,
+                            server
+                        ),
+                    };
+                    network.add_server_mount(
+                        server.clone(),
+                        Path::new(local),
+                        ServerKey {
+                            ip: other.to_string(),
+                        },
+                    )?;
+                }
+            }
+        }
+        Ok(network)
     }
 
     pub fn construct(
@@ -154,17 +280,95 @@ impl FileNetwork {
         links: HashMap<(Location, Location), u32>,
         server_info: HashMap<ServerKey, ServerInfo>,
     ) -> Self {
-        let mut servers: Vec<Location> = path_to_addr
-            .iter()
-            .map(|(_mt, server)| Location::Server(server.ip.clone()))
-            .collect();
+        // a server with several mounts is still one location
+        let mut servers: Vec<Location> = Vec::new();
+        for server in path_to_addr.values() {
+            let location = Location::Server(server.ip.clone());
+            if !servers.contains(&location) {
+                servers.push(location);
+            }
+        }
         servers.push(Location::Client);
         FileNetwork {
             path_to_addr: path_to_addr,
+            replicas: HashMap::default(),
             server_info: server_info,
             links: links,
             locations: servers,
+            compression: None,
+        }
+    }
+
+    /// Records that the given server also holds the data under this mount.
+    pub fn add_replica(&mut self, mount: &Path, server: ServerKey) -> Result<()> {
+        match self.path_to_addr.get(mount) {
+            Some(primary) => {
+                if *primary == server {
+                    bail!("Server {:?} is already the primary for {:?}", server, mount);
+                }
+            }
+            None => {
+                bail!("Cannot add replica for {:?}, which is not a mount", mount);
+            }
+        }
+        let location = Location::Server(server.ip.clone());
+        if !self.locations.contains(&location) {
+            // keep the client last
+            let client_idx = self.locations.len() - 1;
+            self.locations.insert(client_idx, location);
+        }
+        let replicas = self.replicas.entry(mount.to_path_buf()).or_default();
+        if !replicas.contains(&server) {
+            replicas.push(server);
+        }
+        Ok(())
+    }
+
+    /// Records that `server` can see the mount exposed by `other` at `local_path`.
+    pub fn add_server_mount(
+        &mut self,
+        server: ServerKey,
+        local_path: &Path,
+        other: ServerKey,
+    ) -> Result<()> {
+        if server == other {
+            bail!(
+                "Server {:?} cannot list its own mount as a server mount",
+                server
+            );
         }
+        let exposes_mount = self.path_to_addr.values().any(|key| *key == other)
+            || self.replicas.values().any(|keys| keys.contains(&other));
+        if !exposes_mount {
+            bail!(
+                "Server mount for {:?} refers to {:?}, which has no mount",
+                server,
+                other
+            );
+        }
+        let location = Location::Server(server.ip.clone());
+        if !self.locations.contains(&location) {
+            // keep the client last
+            let client_idx = self.locations.len() - 1;
+            self.locations.insert(client_idx, location);
+        }
+        let info = self.server_info.entry(server).or_default();
+        let entry = (local_path.to_path_buf(), other);
+        if !info.other_mounted_directories.contains(&entry) {
+            info.other_mounted_directories.push(entry);
+        }
+        Ok(())
+    }
+
+    /// Lists each mount with its primary location, sorted by path.
+    pub fn get_mounts(&self) -> Vec<(PathBuf, Location)> {
+        let mut mounts: Vec<(PathBuf, Location)> = self
+            .path_to_addr
+            .iter()
+            .map(|(mount, server)| (mount.clone(), Location::Server(server.ip.clone())))
+            .collect();
+        mounts.sort_by(|a, b| a.0.cmp(&b.0));
+        mounts
     }
 
     pub fn get_location_list(&self) -> Vec<Location> {
@@ -182,6 +386,40 @@ impl FileNetwork {
         }
     }
 
+    pub fn set_compression(&mut self, compression: CompressionConfig) {
+        self.compression = Some(compression);
+    }
+
+    /// Codec for a stream sent from machine1 to machine2: streams over links no faster than the
+    /// configured speed are compressed, and all others are sent as they are.
+    pub fn stream_compression(&self, machine1: &Location, machine2: &Location) -> Compression {
+        let compression = match &self.compression {
+            Some(compression) => compression,
+            None => return Compression::None,
+        };
+        if machine1 == machine2 {
+            return Compression::None;
+        }
+        match self.links.get(&(machine1.clone(), machine2.clone())) {
+            Some(speed) if *speed <= compression.max_link_speed => compression.codec,
+            _ => Compression::None,
+        }
+    }
+
+    /// Speed data effectively moves at from machine1 to machine2: the link speed, scaled up by
+    /// the expected compression ratio if streams over the link are compressed.
+    pub fn effective_network_speed(&self, machine1: &Location, machine2: &Location) -> Option<f64> {
+        let speed = self.network_speed(machine1, machine2)?;
+        match (
+            self.stream_compression(machine1, machine2),
+            &self.compression,
+        ) {
+            (Compression::None, _) | (_, None) => Some(speed),
+            (_, Some(compression)) => Some(speed * compression.ratio),
+        }
+    }
+
+    /// Queries for the primary location of a path: the server whose mount contains it.
     pub fn get_path_location(&self, path: PathBuf) -> Location {
         for (mount, serverkey) in self.path_to_addr.iter() {
             if path.starts_with(mount.as_path()) {
@@ -191,11 +429,142 @@ impl FileNetwork {
         return Location::Client;
     }
 
+    /// Queries for every location that can serve a path: the primary first, then replicas.
+    pub fn get_path_locations(&self, path: PathBuf) -> Vec<Location> {
+        for (mount, serverkey) in self.path_to_addr.iter() {
+            if path.starts_with(mount.as_path()) {
+                let mut ret = vec![Location::Server(serverkey.ip.clone())];
+                if let Some(replicas) = self.replicas.get(mount) {
+                    for replica in replicas.iter() {
+                        ret.push(Location::Server(replica.ip.clone()));
+                    }
+                }
+                return ret;
+            }
+        }
+        vec![Location::Client]
+    }
+
     /// Queries for where a certain file lives (origin filesystem).
     pub fn get_location(&self, filestream: &FileStream) -> Location {
         self.get_path_location(filestream.get_path())
     }
 
+    /// Queries for every location that can serve a certain file.
+    pub fn get_locations(&self, filestream: &FileStream) -> Vec<Location> {
+        self.get_path_locations(filestream.get_path())
+    }
+
+    /// Queries for every location that can access a path in place: the locations serving it,
+    /// then any server that mounts one of them.
+    /// Outputs must be written at the primary, so only it and servers mounting it qualify.
+    pub fn get_path_access_locations(&self, path: PathBuf, is_output: bool) -> Vec<Location> {
+        let mut ret = self.get_path_locations(path.clone());
+        if is_output {
+            ret.truncate(1);
+        }
+        if ret == vec![Location::Client] {
+            return ret;
+        }
+        for location in self.locations.iter() {
+            if ret.contains(location) {
+                continue;
+            }
+            if self.mounted_path(&path, location, is_output).is_some() {
+                ret.push(location.clone());
+            }
+        }
+        ret
+    }
+
+    /// Queries for every location that can access a certain file in place.
+    pub fn get_access_locations(&self, filestream: &FileStream, is_output: bool) -> Vec<Location> {
+        self.get_path_access_locations(filestream.get_path(), is_output)
+    }
+
+    /// Path at which the given server sees a client path through its server mounts, if it
+    /// mounts a server holding that path.
+    /// Mounts of the primary are preferred; with `primary_only`, they are the only option.
+    pub fn mounted_path(
+        &self,
+        path: &Path,
+        location: &Location,
+        primary_only: bool,
+    ) -> Option<PathBuf> {
+        let info = match location {
+            Location::Client => {
+                return None;
+            }
+            Location::Server(ip) => self.server_info.get(&ServerKey { ip: ip.clone() })?,
+        };
+        for (mount, serverkey) in self.path_to_addr.iter() {
+            if !path.starts_with(mount.as_path()) {
+                continue;
+            }
+            let relative = path.strip_prefix(mount.as_path()).ok()?;
+            let mut candidates = vec![serverkey];
+            if !primary_only {
+                if let Some(replicas) = self.replicas.get(mount) {
+                    candidates.extend(replicas.iter());
+                }
+            }
+            for candidate in candidates.into_iter() {
+                for (local_path, other) in info.other_mounted_directories.iter() {
+                    if other == candidate {
+                        return Some(local_path.join(relative));
+                    }
+                }
+            }
+            return None;
+        }
+        None
+    }
+
+    /// Whether the file can be read directly at this location.
+    pub fn is_available_at(&self, filestream: &FileStream, location: &Location) -> bool {
+        self.get_locations(filestream).contains(location)
+    }
+
+    /// Of the locations that can serve a file, the one with the fastest link to the given
+    /// location.
+    /// Ties go to the earlier candidate, so the primary is preferred.
+    pub fn closest_location(&self, filestream: &FileStream, location: &Location) -> Location {
+        let candidates = self.get_locations(filestream);
+        let mut best = candidates[0].clone();
+        let mut best_speed = -1.0;
+        for candidate in candidates.into_iter() {
+            let speed = self.network_speed(&candidate, location).unwrap_or(0.0);
+            if speed > best_speed {
+                best_speed = speed;
+                best = candidate;
+            }
+        }
+        best
+    }
+
+    /// Chooses among replicas by their link speed to the location consuming the data,
+    /// discounted by how many nodes are already assigned to each replica.
+    /// Ties go to the earlier candidate, so the primary is preferred.
+    pub fn choose_replica(
+        &self,
+        candidates: &[Location],
+        consumer: &Location,
+        load: &HashMap<Location, u32>,
+    ) -> Location {
+        let mut best = candidates[0].clone();
+        let mut best_score = -1.0;
+        for candidate in candidates.iter() {
+            let speed = self.network_speed(candidate, consumer).unwrap_or(0.0);
+            let current_load = *load.get(candidate).unwrap_or(&0) as f64;
+            let score = speed / (1.0 + current_load);
+            if score > best_score {
+                best_score = score;
+                best = candidate.clone();
+            }
+        }
+        best
+    }
+
     pub fn stripped_path(
         &self,
         path: &Path,
@@ -222,7 +591,19 @@ impl FileNetwork {
                         match new_location {
                             Location::Client => unreachable!(),
                             Location::Server(ip) => {
-                                if *ip != serverkey.ip {
+                                let is_replica = match self.replicas.get(mount) {
+                                    Some(replicas) => replicas.iter().any(|r| r.ip == *ip),
+                                    None => false,
+                                };
+                                if *ip != serverkey.ip && !is_replica {
+                                    // the new location may see the file through a server mount
+                                    let path = filestream.get_path();
+                                    if let Some(local_path) =
+                                        self.mounted_path(&path, new_location, false)
+                                    {
+                                        filestream.set_path(&local_path);
+                                        return Ok(());
+                                    }
                                     bail!("New location {:?} of file, passed in, is not the prefix mount location {:?}", new_location, ip);
                                 }
                                 filestream.strip_prefix(mount.as_path())?;
@@ -269,15 +650,40 @@ impl FileNetwork {
         }
     }
 
-    /// Gets a new tmp file in the desired location with that filestem.
-    /// TODO: better naming scheme
-    pub fn get_tmp(&self, stem: &Path, location: &Location) -> Result<PathBuf> {
+    /// The tmp directory configured for a server, if any.
+    pub fn get_tmp_directory(&self, location: &Location) -> Option<PathBuf> {
+        match location {
+            Location::Client => None,
+            Location::Server(ip) => {
+                let info = self.server_info.get(&ServerKey { ip: ip.clone() })?;
+                if info.tmp_directory.as_os_str().is_empty() {
+                    return None;
+                }
+                Some(info.tmp_directory.clone())
+            }
+        }
+    }
+
+    /// Gets a new tmp file in the desired location with that filestem, inside the program's
+    /// namespace (see `Program::get_tmp_namespace`) so programs never share tmp files.
+    /// Only the stem's file name is used, so paths don't escape the tmp directory; the client
+    /// uses the system's tmp directory.
+    pub fn get_tmp(&self, stem: &Path, location: &Location, namespace: &Path) -> Result<PathBuf> {
+        let name = match stem.file_name() {
+            Some(name) => name,
+            None => bail!("Tmp file stem {:?} has no file name", stem),
+        };
         match location {
-            Location::Client => unreachable!(),
-            Location::Server(ip) => match self.server_info.get(&ServerKey { ip: ip.clone() }) {
-                Some(info) => {
-                    let mut pathbuf = info.tmp_directory.clone();
-                    pathbuf.push(stem);
+            Location::Client => {
+                let mut pathbuf = std::env::temp_dir();
+                pathbuf.push(namespace);
+                pathbuf.push(name);
+                Ok(pathbuf)
+            }
+            Location::Server(ip) => match self.get_tmp_directory(location) {
+                Some(mut pathbuf) => {
+                    pathbuf.push(namespace);
+                    pathbuf.push(name);
                     return Ok(pathbuf);
                 }
                 None => {
@@ -287,3 +693,337 @@ impl FileNetwork {
         }
     }
 }
+
+/// Reads a string out of the config, describing the field if it isn't one.
+fn yaml_str(yaml: &Yaml, field: &str) -> Result<String> {
+    match yaml.as_str() {
+        Some(s) => Ok(s.to_string()),
+        None => bail!("{} should be a string, not {:?}", field, yaml),
+    }
+}
+
+/// Parses the compression section; each field falls back to its default when left out.
+fn parse_compression(yaml: &Yaml) -> Result<CompressionConfig> {
+    if yaml.as_hash().is_none() {
+        bail!("Compression section of config should be a map: {:?}", yaml);
+    }
+    let mut compression = CompressionConfig::default();
+    match &yaml["codec"] {
+        Yaml::BadValue => {}
+        codec => {
+            compression.codec = yaml_str(codec, "Compression codec")?.parse::<Compression>()?;
+        }
+    }
+    match &yaml["max_link_speed"] {
+        Yaml::BadValue => {}
+        speed => match speed.as_i64() {
+            Some(speed) if speed >= 0 => compression.max_link_speed = speed as u32,
+            _ => bail!(
+                "Compression max_link_speed should be a non-negative integer, not {:?}",
+                speed
+            ),
+        },
+    }
+    match &yaml["ratio"] {
+        Yaml::BadValue => {}
+        ratio => {
+            let ratio = match ratio {
+                Yaml::Integer(i) => *i as f64,
+                Yaml::Real(_) => ratio.as_f64().unwrap(),
+                other => bail!("Compression ratio should be a number, not {:?}", other),
+            };
+            if ratio < 1.0 {
+                bail!("Compression ratio {:?} should be at least 1", ratio);
+            }
+            compression.ratio = ratio;
+        }
+    }
+    Ok(compression)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replica_config() -> FileNetwork {
+        FileNetwork::parse(
+            "mounts:
+  \"1.0.0.1\": \"/mnt/a\"
+  \"1.0.0.2\": \"/mnt/b\"
+replicas:
+  \"/mnt/a\": [\"1.0.0.2\", \"1.0.0.3\"]
+links:
+  \"(1.0.0.1,client)\": 10
+  \"(client,1.0.0.1)\": 10
+  \"(1.0.0.2,client)\": 100
+  \"(client,1.0.0.2)\": 100
+  \"(1.0.0.3,client)\": 100
+  \"(client,1.0.0.3)\": 100
+tmp_directory:
+  \"1.0.0.1\": \"/tmp\"
+",
+        )
+        .unwrap()
+    }
+
+    fn server(ip: &str) -> Location {
+        Location::Server(ip.to_string())
+    }
+
+    #[test]
+    fn test_replica_locations() {
+        let config = replica_config();
+        let fs = FileStream::new(Path::new("/mnt/a/foo.txt"), Location::Client);
+        assert_eq!(config.get_location(&fs), server("1.0.0.1"));
+        assert_eq!(
+            config.get_locations(&fs),
+            vec![server("1.0.0.1"), server("1.0.0.2"), server("1.0.0.3")]
+        );
+        assert!(config.get_location_list().contains(&server("1.0.0.3")));
+        assert_eq!(config.get_location_list().last(), Some(&Location::Client));
+        // the replicas have the faster link to the client
+        assert_eq!(
+            config.closest_location(&fs, &Location::Client),
+            server("1.0.0.2")
+        );
+        let mut load: HashMap<Location, u32> = HashMap::default();
+        load.insert(server("1.0.0.2"), 1);
+        assert_eq!(
+            config.choose_replica(&config.get_locations(&fs), &Location::Client, &load),
+            server("1.0.0.3")
+        );
+        // data read by a node on the primary stays there
+        assert_eq!(
+            config.choose_replica(&config.get_locations(&fs), &server("1.0.0.1"), &load),
+            server("1.0.0.1")
+        );
+    }
+
+    #[test]
+    fn test_get_tmp() {
+        let config = replica_config();
+        assert_eq!(
+            config
+                .get_tmp(
+                    Path::new("/mnt/b/out.tar"),
+                    &server("1.0.0.1"),
+                    Path::new("123-456/2")
+                )
+                .unwrap(),
+            Path::new("/tmp/123-456/2/out.tar")
+        );
+        assert!(config
+            .get_tmp(
+                Path::new("out.tar"),
+                &server("1.0.0.2"),
+                Path::new("123-456/2")
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_strip_replica_path() {
+        let config = replica_config();
+        let stripped = config
+            .stripped_path(
+                Path::new("/mnt/a/foo.txt"),
+                &Location::Client,
+                &server("1.0.0.3"),
+            )
+            .unwrap();
+        assert_eq!(stripped, Path::new("foo.txt"));
+        assert!(config
+            .stripped_path(
+                Path::new("/mnt/b/foo.txt"),
+                &Location::Client,
+                &server("1.0.0.3"),
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_replica_of_unknown_mount() {
+        let config = FileNetwork::parse(
+            "mounts:
+  \"1.0.0.1\": \"/mnt/a\"
+replicas:
+  \"/mnt/c\": \"1.0.0.2\"
+links:
+  \"(1.0.0.1,client)\": 10
+tmp_directory:
+  \"1.0.0.1\": \"/tmp\"
+",
+        );
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn test_several_mounts_on_one_server() {
+        let config = FileNetwork::parse(
+            "mounts:
+  \"1.0.0.1\": [\"/mnt/a\", \"/mnt/b\"]
+links:
+  \"(1.0.0.1,client)\": 10
+tmp_directory:
+  \"1.0.0.1\": \"/tmp\"
+",
+        )
+        .unwrap();
+        assert_eq!(
+            config.get_location_list(),
+            vec![server("1.0.0.1"), Location::Client]
+        );
+        let fs = FileStream::new(Path::new("/mnt/b/foo.txt"), Location::Client);
+        assert_eq!(config.get_location(&fs), server("1.0.0.1"));
+    }
+
+    fn server_mount_config() -> FileNetwork {
+        FileNetwork::parse(
+            "mounts:
+  \"1.0.0.1\": \"/mnt/a\"
+  \"1.0.0.2\": \"/mnt/b\"
+server_mounts:
+  \"1.0.0.1\":
+    \"1.0.0.2\": \"/nfs/b\"
+  \"1.0.0.3\":
+    \"1.0.0.1\": \"/nfs/a\"
+links:
+  \"(1.0.0.1,client)\": 10
+  \"(client,1.0.0.1)\": 10
+tmp_directory:
+  \"1.0.0.1\": \"/tmp\"
+",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_server_mount_locations() {
+        let config = server_mount_config();
+        let fs = FileStream::new(Path::new("/mnt/b/foo.txt"), Location::Client);
+        assert_eq!(
+            config.get_access_locations(&fs, false),
+            vec![server("1.0.0.2"), server("1.0.0.1")]
+        );
+        assert_eq!(
+            config.mounted_path(&fs.get_path(), &server("1.0.0.1"), true),
+            Some(PathBuf::from("/nfs/b/foo.txt"))
+        );
+        assert_eq!(
+            config.mounted_path(&fs.get_path(), &server("1.0.0.3"), false),
+            None
+        );
+        // a proxy with no mount of its own is still a location
+        assert!(config.get_location_list().contains(&server("1.0.0.3")));
+        assert_eq!(config.get_location_list().last(), Some(&Location::Client));
+        // but it has no tmp directory to put files in
+        assert!(config
+            .get_tmp(Path::new("out.tar"), &server("1.0.0.3"), Path::new("ns"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_strip_server_mount_path() {
+        let config = server_mount_config();
+        let stripped = config
+            .stripped_path(
+                Path::new("/mnt/b/dir/foo.txt"),
+                &Location::Client,
+                &server("1.0.0.1"),
+            )
+            .unwrap();
+        assert_eq!(stripped, Path::new("/nfs/b/dir/foo.txt"));
+        let stripped = config
+            .stripped_path(
+                Path::new("/nfs/a/foo.txt"),
+                &server("1.0.0.3"),
+                &server("1.0.0.1"),
+            )
+            .unwrap();
+        assert_eq!(stripped, Path::new("foo.txt"));
+    }
+
+    #[test]
+    fn test_server_mount_of_unknown_server() {
+        let config = FileNetwork::parse(
+            "mounts:
+  \"1.0.0.1\": \"/mnt/a\"
+server_mounts:
+  \"1.0.0.1\":
+    \"1.0.0.9\": \"/nfs/x\"
+links:
+  \"(1.0.0.1,client)\": 10
+tmp_directory:
+  \"1.0.0.1\": \"/tmp\"
+",
+        );
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn test_compression() {
+        let config = |compression: &str| {
+            FileNetwork::parse(&format!(
+                "mounts:
+  \"1.0.0.1\": \"/mnt/a\"
+  \"1.0.0.2\": \"/mnt/b\"
+links:
+  \"(1.0.0.1,client)\": 10
+  \"(1.0.0.2,client)\": 100
+tmp_directory:
+  \"1.0.0.1\": \"/tmp\"
+{}",
+                compression
+            ))
+        };
+        // without the section, nothing is compressed
+        let uncompressed = config("").unwrap();
+        assert_eq!(
+            uncompressed.stream_compression(&server("1.0.0.1"), &Location::Client),
+            Compression::None
+        );
+        assert_eq!(
+            uncompressed.effective_network_speed(&server("1.0.0.1"), &Location::Client),
+            Some(10.0)
+        );
+
+        let compressed =
+            config("compression:\n  codec: lz4\n  max_link_speed: 50\n  ratio: 3\n").unwrap();
+        assert_eq!(
+            compressed.stream_compression(&server("1.0.0.1"), &Location::Client),
+            Compression::Lz4
+        );
+        assert_eq!(
+            compressed.effective_network_speed(&server("1.0.0.1"), &Location::Client),
+            Some(30.0)
+        );
+        // fast links, unknown links and local streams are left alone
+        assert_eq!(
+            compressed.stream_compression(&server("1.0.0.2"), &Location::Client),
+            Compression::None
+        );
+        assert_eq!(
+            compressed.effective_network_speed(&server("1.0.0.2"), &Location::Client),
+            Some(100.0)
+        );
+        assert_eq!(
+            compressed.stream_compression(&Location::Client, &server("1.0.0.1")),
+            Compression::None
+        );
+        assert_eq!(
+            compressed.stream_compression(&server("1.0.0.1"), &server("1.0.0.1")),
+            Compression::None
+        );
+
+        let defaults = config("compression: {}\n").unwrap();
+        assert_eq!(
+            defaults.stream_compression(&server("1.0.0.2"), &Location::Client),
+            Compression::Zstd
+        );
+
+        assert!(config("compression:\n  codec: gzip\n").is_err());
+        assert!(config("compression:\n  ratio: 0.5\n").is_err());
+        assert!(config("compression:\n  max_link_speed: fast\n").is_err());
+        assert!(config("compression: zstd\n").is_err());
+    }
+}
@@ -0,0 +1,154 @@
+extern crate yaml_rust;
+use crate::scheduler::SchedulerType;
+use dash::graph::Location;
+use dash::util::Result;
+use failure::bail;
+use std::fs::read_to_string;
+use std::path::Path;
+use yaml_rust::{Yaml, YamlLoader};
+
+/// Scheduler selection and per-scheduler parameters.
+/// Read from the optional `scheduler` section of the mount config:
+///
+/// scheduler:
+///   type: dp
+///   heuristic:
+///     reduction_factor: 2
+///   dp:
+///     reduction_factor: 4
+///   all_remote:
+///     server: "34.82.47.137"
+#[derive(PartialEq, Debug, Clone)]
+pub struct SchedulerConfig {
+    /// Scheduler to use when none is passed in on the command line.
+    pub scheduler_type: Option<SchedulerType>,
+    /// Factor by which the heuristic scheduler shrinks an edge after a node that reduces input.
+    pub heuristic_reduction_factor: f64,
+    /// Factor by which the DP scheduler shrinks the output of a node that reduces input.
+    pub dp_reduction_factor: f64,
+    /// Server the all-remote scheduler uses for nodes with no file dependencies.
+    pub all_remote_server: Option<Location>,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        SchedulerConfig {
+            scheduler_type: None,
+            heuristic_reduction_factor: 2.0,
+            dp_reduction_factor: 2.0,
+            all_remote_server: None,
+        }
+    }
+}
+
+impl SchedulerConfig {
+    /// Reads the scheduler section of the mount config; all fields are optional.
+    pub fn new(mount_file: &str) -> Result<Self> {
+        let file_str = read_to_string(Path::new(&mount_file))?;
+        let yamls = match YamlLoader::load_from_str(&file_str) {
+            Ok(docs) => docs,
+            Err(e) => {
+                bail!("Could not parse yaml config: {:?}", e);
+            }
+        };
+        if yamls.is_empty() {
+            return Ok(SchedulerConfig::default());
+        }
+        SchedulerConfig::from_yaml(&yamls[0]["scheduler"])
+    }
+
+    /// Parses the scheduler section itself.
+    pub fn from_yaml(yaml: &Yaml) -> Result<Self> {
+        let mut config = SchedulerConfig::default();
+        if yaml.is_badvalue() || yaml.is_null() {
+            return Ok(config);
+        }
+        if yaml.as_hash().is_none() {
+            bail!("Scheduler section of config should be a map: {:?}", yaml);
+        }
+
+        match &yaml["type"] {
+            Yaml::BadValue => {}
+            Yaml::String(s) => {
+                config.scheduler_type = Some(s.parse::<SchedulerType>()?);
+            }
+            other => {
+                bail!("Scheduler type should be a string, not {:?}", other);
+            }
+        }
+
+        if let Some(factor) = parse_factor(&yaml["heuristic"]["reduction_factor"])? {
+            config.heuristic_reduction_factor = factor;
+        }
+        if let Some(factor) = parse_factor(&yaml["dp"]["reduction_factor"])? {
+            config.dp_reduction_factor = factor;
+        }
+
+        match &yaml["all_remote"]["server"] {
+            Yaml::BadValue => {}
+            Yaml::String(s) => {
+                config.all_remote_server = Some(Location::Server(s.clone()));
+            }
+            other => {
+                bail!("All remote server should be a string, not {:?}", other);
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Reduction factors can be written as integers or floats, but must be at least 1.
+fn parse_factor(yaml: &Yaml) -> Result<Option<f64>> {
+    let factor = match yaml {
+        Yaml::BadValue => {
+            return Ok(None);
+        }
+        Yaml::Integer(i) => *i as f64,
+        Yaml::Real(_) => yaml.as_f64().unwrap(),
+        other => {
+            bail!("Reduction factor should be a number, not {:?}", other);
+        }
+    };
+    if factor < 1.0 {
+        bail!("Reduction factor {:?} should be at least 1", factor);
+    }
+    Ok(Some(factor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(config: &str) -> Result<SchedulerConfig> {
+        let yamls = YamlLoader::load_from_str(config).unwrap();
+        SchedulerConfig::from_yaml(&yamls[0]["scheduler"])
+    }
+
+    #[test]
+    fn test_missing_section() {
+        let config = parse("mounts:\n  \"1.1.1.1\": \"/mnt\"\n").unwrap();
+        assert_eq!(config, SchedulerConfig::default());
+    }
+
+    #[test]
+    fn test_full_section() {
+        let config = parse(
+            "scheduler:\n  type: dp\n  heuristic:\n    reduction_factor: 3\n  dp:\n    reduction_factor: 1.5\n  all_remote:\n    server: \"1.1.1.1\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.scheduler_type, Some(SchedulerType::DP));
+        assert_eq!(config.heuristic_reduction_factor, 3.0);
+        assert_eq!(config.dp_reduction_factor, 1.5);
+        assert_eq!(
+            config.all_remote_server,
+            Some(Location::Server("1.1.1.1".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        assert!(parse("scheduler:\n  type: fastest\n").is_err());
+        assert!(parse("scheduler:\n  dp:\n    reduction_factor: 0\n").is_err());
+        assert!(parse("scheduler:\n  dp:\n    reduction_factor: fast\n").is_err());
+    }
+}
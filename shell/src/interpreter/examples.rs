@@ -350,7 +350,7 @@ fn get_test_network_config() -> FileNetwork {
 
 pub fn get_test_interpreter() -> Interpreter {
     // TODO: actually choose with scheduler to use
-    let scheduler = Box::new(DPScheduler {});
+    let scheduler = Box::new(DPScheduler::default());
     let filesizemod = Box::new(TestFileSize {});
     Interpreter::construct(
         get_test_network_config(),
@@ -4,14 +4,16 @@ use argument_matcher::{ArgMatch, RemoteAccessInfo};
 use config::filecache::FileCache;
 use config::filesize::{FileSize, OffloadQueryFileSize};
 use config::network::FileNetwork;
-use dash::graph::filestream::{FifoMode, FifoStream, FileStream};
+use dash::graph::command::CommandNode;
+use dash::graph::filestream::{FifoMode, FifoStream, FileMode, FileStream};
 use dash::graph::info::Info;
-use dash::graph::program::{Elem, NodeId, Program};
+use dash::graph::program::{Elem, NodeId, ProgId, Program};
 use dash::graph::stream::{DashStream, IOType, PipeStream};
+use dash::graph::tmpdir;
 use dash::graph::Location;
 use dash::runtime::new_client::ShellClient;
 use failure::bail;
-use grammar::{AccessType, ArgType};
+use grammar::{AccessType, ArgType, Combiner};
 use parser::Parser;
 use scheduler::Scheduler;
 use shellparser::shellparser::{parse_command, Command};
@@ -20,6 +22,9 @@ use std::env;
 use std::path::PathBuf;
 use tracing::debug;
 
+/// Awk program for the `sum` combiner: adds up each column across all lines.
+const SUM_COLUMNS: &str = "{ for (i = 1; i <= NF; i++) sum[i] += $i; if (NF > n) n = NF } END { for (i = 1; i <= n; i++) printf \"%s%s\", sum[i], (i < n ? OFS : ORS) }";
+
 pub struct Interpreter {
     /// Where interpreter keeps track of filesystem and link information for scheduling.
     config: FileNetwork,
@@ -35,6 +40,10 @@ pub struct Interpreter {
     pwd: PathBuf,
     /// Environment values.
     env: HashMap<String, String>,
+    /// Programs parsed so far, which numbers the next one.
+    programs: ProgId,
+    /// Whether programs report how much moves along each edge while they run.
+    progress: bool,
 }
 
 impl Interpreter {
@@ -46,6 +55,16 @@ impl Interpreter {
         scheduler: Box<dyn Scheduler>,
     ) -> Result<Self> {
         let parser = Parser::new(annotations_file)?;
+        Interpreter::with_parser(config_file, parser, scheduler)
+    }
+
+    /// Constructs a new interpreter given a file with config information and an annotation
+    /// parser.
+    pub fn with_parser(
+        config_file: &str,
+        parser: Parser,
+        scheduler: Box<dyn Scheduler>,
+    ) -> Result<Self> {
         let config = FileNetwork::new(config_file)?;
         Ok(Interpreter {
             config: config,
@@ -55,6 +74,8 @@ impl Interpreter {
             splitting_factor: 1,
             pwd: Default::default(),
             env: Default::default(),
+            programs: 0,
+            progress: false,
         })
     }
 
@@ -79,6 +100,8 @@ impl Interpreter {
             splitting_factor: 1,
             pwd: pwd,
             env: Default::default(),
+            programs: 0,
+            progress: false,
         }
     }
     pub fn set_splitting_factor(&mut self, factor: u32) {
@@ -89,6 +112,21 @@ impl Interpreter {
         self.pwd = pwd;
     }
 
+    /// Swaps in different annotations for subsequent command lines.
+    pub fn set_parser(&mut self, parser: Parser) {
+        self.parser = parser;
+    }
+
+    /// Swaps in a different scheduler for subsequent command lines.
+    pub fn set_scheduler(&mut self, scheduler: Box<dyn Scheduler>) {
+        self.scheduler = scheduler;
+    }
+
+    /// Turns progress reporting on or off for subsequent command lines.
+    pub fn set_progress(&mut self, progress: bool) {
+        self.progress = progress;
+    }
+
     /// Takes a command line and returns a program, ready for execution.
     /// Handles parsing, scheduling, and implicit parallelization.
     pub fn parse_command_line(&mut self, command: &str) -> Result<Option<Program>> {
@@ -110,6 +148,11 @@ impl Interpreter {
 
     /// Runs parsing pipeline, which parses, parallelizes, and schedules programs.
     fn parse_program(&mut self, program: &mut Program) -> Result<()> {
+        // name the program so its tmp files don't clash with any other program's
+        self.programs += 1;
+        program.set_id(self.programs);
+        program.set_session(&tmpdir::session_id());
+
         // run parser to produce arg matches for command nodes
         let mut match_map = self.run_parser(program)?;
 
@@ -158,6 +201,9 @@ impl Interpreter {
             }
         }
         for (id, (elems, mut argmatches)) in replacement_map.into_iter() {
+            if elems.len() > 1 {
+                self.add_combiner(program, match_map, id)?;
+            }
             let new_ids = program.replace_node_parallel(id, elems, false)?;
             assert!(new_ids.len() == argmatches.len());
             let _ = match_map.remove(&id);
@@ -192,6 +238,9 @@ impl Interpreter {
             }
         }
         for id in nodes_to_split.iter() {
+            if program.get_node(*id).unwrap().get_stdin_len() > 1 {
+                self.add_combiner(program, match_map, *id)?;
+            }
             let new_node_ids = program.split_across_input(*id)?;
             if new_node_ids.len() <= 1 {
                 continue;
@@ -205,6 +254,51 @@ impl Interpreter {
         }
         Ok(())
     }
+    /// Adds a node after a command that is about to be split into parallel parts, which merges
+    /// the parts' outputs as the command's annotation says.
+    /// Parts are concatenated in order by default, which needs no extra node.
+    fn add_combiner(
+        &self,
+        program: &mut Program,
+        match_map: &mut HashMap<NodeId, ArgMatch>,
+        id: NodeId,
+    ) -> Result<()> {
+        let argmatch = match_map.get(&id).unwrap();
+        let (cmdnode, combiner_match) = match argmatch.get_combiner() {
+            Combiner::Concat => return Ok(()),
+            Combiner::Sum => {
+                let args = vec![SUM_COLUMNS.to_string()];
+                (
+                    CommandNode::new("awk", Location::default())?,
+                    ArgMatch::new_default("awk", &args),
+                )
+            }
+            Combiner::Rerun => {
+                let mut cmdnode = match program.get_node(id).unwrap().get_elem() {
+                    Elem::Cmd(cmdnode) => cmdnode,
+                    _ => bail!("Only command nodes have combiners"),
+                };
+                cmdnode.clear_stdin();
+                cmdnode.clear_stdout();
+                cmdnode.clear_stderr();
+                (cmdnode, argmatch.rerun_match())
+            }
+            Combiner::Custom(command) => {
+                let words = match shellwords::split(&command) {
+                    Ok(words) if !words.is_empty() => words,
+                    _ => bail!("Combiner {:?} isn't a command line", command),
+                };
+                (
+                    CommandNode::new(&words[0], Location::default())?,
+                    ArgMatch::new_default(&words[0], &words[1..].to_vec()),
+                )
+            }
+        };
+        let combiner_id = program.insert_after(id, Elem::Cmd(cmdnode))?;
+        match_map.insert(combiner_id, combiner_match);
+        Ok(())
+    }
+
     /// Finds annotation matches (if any) and resolves Strings in each node of program.
     fn run_parser(&mut self, program: &mut Program) -> Result<HashMap<NodeId, ArgMatch>> {
         let mut match_map: HashMap<NodeId, ArgMatch> = HashMap::default();
@@ -285,7 +379,7 @@ impl Interpreter {
                         let argmatch = matches.get_mut(id).unwrap();
 
                         // if the node requires pwd, assign pwd to the cmdnode itself
-                        if argmatch.get_needs_current_dir() {
+                        if argmatch.uses_current_dir() {
                             let mut fs = FileStream::new(&self.pwd.as_path(), Location::Client);
                             self.config.strip_file_path(
                                 &mut fs,
@@ -321,6 +415,8 @@ impl Interpreter {
                             _ => {}
                         }
                     }
+                    // fifos of output process substitutions are placed below
+                    Elem::Read(ref readnode) if readnode.is_input_fifo() => {}
                     Elem::Read(ref mut readnode) => {
                         let loc = readnode.get_loc();
                         let mut input = readnode.get_stdin_mut();
@@ -332,10 +428,21 @@ impl Interpreter {
             }
         }
 
+        // commands write into the fifos of output process substitutions where they run
+        let fifos = self.setup_output_fifos(prog, matches)?;
+        for (_id, remote_access_vec) in remote_access_map.iter_mut() {
+            remote_access_vec.retain(|info| !fifos.contains(&info.filestream.get_path()));
+        }
+
         for (id, remote_access_vec) in remote_access_map.iter() {
             let mut argmatch = matches.get_mut(id).unwrap();
             for remote_access_info in remote_access_vec.iter() {
-                self.setup_remote_access(prog, &mut argmatch, &mut remote_access_info.clone())?;
+                self.setup_remote_access(
+                    prog,
+                    *id,
+                    &mut argmatch,
+                    &mut remote_access_info.clone(),
+                )?;
             }
         }
 
@@ -345,8 +452,10 @@ impl Interpreter {
         let mut readmap: HashMap<PipeStream, FileStream> = HashMap::new();
         for (_id, node) in prog.get_nodes_iter() {
             match node.get_elem() {
+                // fifos have to be created, so their read nodes stay
+                Elem::Read(readnode) if readnode.is_input_fifo() => {}
                 Elem::Read(readnode) => {
-                    if let Some(output) = readnode.get_stdout() {
+                    if let Some(output) = readnode.get_stdout().first() {
                         match output {
                             DashStream::Pipe(ps) => {
                                 assert_eq!(
@@ -383,15 +492,71 @@ impl Interpreter {
             }
         }
         self.mark_pipes_bufferable(prog)?;
+        if self.progress {
+            prog.set_progress()?;
+        }
+        self.set_stream_compression(prog)?;
+        Ok(())
+    }
+
+    /// Chooses the codec for each tcp stream from the link it's sent over. Both ends of a stream
+    /// see the same locations, so they agree on the codec.
+    fn set_stream_compression(&self, prog: &mut Program) -> Result<()> {
+        let config = &self.config;
+        let set = |stream: &mut DashStream| -> Result<()> {
+            if let DashStream::Tcp(netstream) = stream {
+                let compression = config.stream_compression(
+                    &netstream.get_left_location(),
+                    &netstream.get_right_location(),
+                );
+                stream.set_compression(compression)?;
+            }
+            Ok(())
+        };
+        for (_id, node) in prog.get_mut_nodes_iter() {
+            match node.get_mut_elem() {
+                Elem::Cmd(ref mut cmdnode) => {
+                    for stream in cmdnode.get_stdin_iter_mut() {
+                        set(stream)?;
+                    }
+                    for stream in cmdnode.get_stdout_iter_mut() {
+                        set(stream)?;
+                    }
+                    if let Some(mut stream) = cmdnode.get_stderr() {
+                        set(&mut stream)?;
+                        cmdnode.set_stderr(stream)?;
+                    }
+                }
+                Elem::Write(ref mut writenode) => {
+                    for stream in writenode.get_stdin_iter_mut() {
+                        set(stream)?;
+                    }
+                }
+                Elem::Read(ref mut readnode) => {
+                    set(readnode.get_stdout_mut())?;
+                }
+            }
+        }
         Ok(())
     }
 
     /// Mark any pipes where buffering is necessary as bufferable.
-    /// This is all tcp streams and pipes where the pipe feeds into stdin and it is 2nd or later in
-    /// the list.
+    /// This is all tcp streams, pipes where the pipe feeds into stdin and it is 2nd or later in
+    /// the list, pipes out of a node whose stdout fans out to several nodes, and pipes out of
+    /// read nodes, which have no process to pipe from.
     fn mark_pipes_bufferable(&self, prog: &mut Program) -> Result<()> {
         let mut bufferable_map: HashMap<DashStream, (NodeId, NodeId)> = HashMap::default();
         for (_id, node) in prog.get_nodes_iter() {
+            let stdout = node.get_stdout();
+            let from_read = matches!(node.get_elem(), Elem::Read(_));
+            if stdout.len() > 1 || from_read {
+                for stream in stdout.into_iter() {
+                    if let DashStream::Pipe(pipestream) = &stream {
+                        let link = (pipestream.get_left(), pipestream.get_right());
+                        bufferable_map.insert(stream, link);
+                    }
+                }
+            }
             let stdin = node.get_stdin();
             let mut count = 0;
             for stream in stdin.iter() {
@@ -425,15 +590,10 @@ impl Interpreter {
                             stream.set_bufferable()?;
                         }
                     }
-                    match cmdnode.get_stdout() {
-                        Some(stream) => {
-                            let mut copy = stream.clone();
-                            if bufferable_map.contains_key(&stream) {
-                                copy.set_bufferable()?;
-                            }
-                            cmdnode.set_stdout(copy.clone())?;
+                    for stream in cmdnode.get_stdout_iter_mut() {
+                        if bufferable_map.contains_key(&stream) {
+                            stream.set_bufferable()?;
                         }
-                        None => {}
                     }
                     match cmdnode.get_stderr() {
                         Some(stream) => {
@@ -464,20 +624,146 @@ impl Interpreter {
         Ok(())
     }
 
-    /// Modifies the program to reflect that this filestream is read remotely.
+    /// Places the fifo of each output process substitution in a tmp file where the command
+    /// writing into it runs, along with the read node streaming it on to the subcommand, which
+    /// may run elsewhere. Returns the placeholder paths the fifos replaced.
+    fn setup_output_fifos(
+        &self,
+        prog: &mut Program,
+        matches: &mut HashMap<NodeId, ArgMatch>,
+    ) -> Result<Vec<PathBuf>> {
+        let fifos: Vec<(NodeId, PathBuf)> = prog
+            .get_nodes_iter()
+            .filter_map(|(id, node)| match node.get_elem() {
+                Elem::Read(readnode) if readnode.is_input_fifo() => {
+                    Some((*id, readnode.get_input_ref().get_path()))
+                }
+                _ => None,
+            })
+            .collect();
+        for (read_id, placeholder) in fifos.iter() {
+            let writers: Vec<NodeId> = matches
+                .iter()
+                .filter(|(_, argmatch)| argmatch.names_path(placeholder))
+                .map(|(id, _)| *id)
+                .collect();
+            let writer = match writers.as_slice() {
+                [writer] => *writer,
+                _ => bail!(
+                    "Output fifo {:?} must be written by one command, not {:?}",
+                    placeholder,
+                    writers
+                ),
+            };
+            let location = prog.get_node(writer).unwrap().get_loc();
+            let tmp_path =
+                self.config
+                    .get_tmp(placeholder, &location, &prog.get_tmp_namespace())?;
+            let fifo = FileStream::new(tmp_path.as_path(), location);
+            matches
+                .get_mut(&writer)
+                .unwrap()
+                .replace_path_arg(placeholder, &fifo);
+            prog.set_output_fifo(*read_id, &fifo)?;
+        }
+        Ok(fifos
+            .into_iter()
+            .map(|(_, placeholder)| placeholder)
+            .collect())
+    }
+
+    /// Modifies the program to reflect that this filestream of node `id` is accessed remotely.
     pub fn setup_remote_access(
         &self,
         prog: &mut Program,
+        id: NodeId,
         argmatch: &mut ArgMatch,
         remote_access_info: &mut RemoteAccessInfo,
     ) -> Result<()> {
-        // transfer output file to correct location after job is done
-        if remote_access_info.argtype == ArgType::OutputFile {
-            unimplemented!()
+        // if the access location mounts the file's server, the command can use it in place
+        let is_output = matches!(
+            remote_access_info.argtype,
+            ArgType::OutputFile | ArgType::OutputFileList
+        );
+        if let Some(mounted_path) = self.config.mounted_path(
+            &remote_access_info.filestream.get_path(),
+            &remote_access_info.access_location,
+            is_output,
+        ) {
+            remote_access_info.set_tmp_name(FileStream::new(
+                mounted_path.as_path(),
+                remote_access_info.access_location.clone(),
+            ));
+            argmatch.change_arg(remote_access_info)?;
+            return Ok(());
+        }
+
+        // outputs are written into a fifo at the access location, which is streamed back to the
+        // file, appending to it or replacing it like the command would
+        if is_output {
+            let origin_location = remote_access_info.origin_location.clone();
+            let access_location = remote_access_info.access_location.clone();
+            self.config.strip_file_path(
+                &mut remote_access_info.filestream,
+                &Location::Client,
+                &origin_location,
+            )?;
+            remote_access_info
+                .filestream
+                .set_location(origin_location.clone());
+            remote_access_info
+                .filestream
+                .set_mode(match remote_access_info.appends {
+                    true => FileMode::APPEND,
+                    false => FileMode::CREATE,
+                });
+            let tmp_path = self.config.get_tmp(
+                remote_access_info.filestream.get_path().as_path(),
+                &access_location,
+                &prog.get_tmp_namespace(),
+            )?;
+            remote_access_info
+                .set_tmp_name(FileStream::new(tmp_path.as_path(), access_location.clone()));
+            argmatch.change_arg(remote_access_info)?;
+            let fifostream =
+                FifoStream::new(tmp_path.as_path(), access_location.clone(), FifoMode::READ);
+            prog.add_remote_fifo_write(
+                &origin_location,
+                &access_location,
+                &remote_access_info.filestream,
+                &fifostream,
+            )?;
+            return Ok(());
         }
-        // transfer input file to correct location before job starts
-        if argmatch.get_access_type() != AccessType::Sequential {
-            unimplemented!();
+        // random-access inputs can't be replaced by a fifo, so are copied into a tmp file before
+        // the command starts
+        if argmatch.get_access_type() == AccessType::Random {
+            let origin_location = remote_access_info.origin_location.clone();
+            let access_location = remote_access_info.access_location.clone();
+            self.config.strip_file_path(
+                &mut remote_access_info.filestream,
+                &Location::Client,
+                &origin_location,
+            )?;
+            remote_access_info
+                .filestream
+                .set_location(origin_location.clone());
+            let tmp_path = self.config.get_tmp(
+                remote_access_info.filestream.get_path().as_path(),
+                &access_location,
+                &prog.get_tmp_namespace(),
+            )?;
+            let tmp_filestream = FileStream::new(tmp_path.as_path(), access_location.clone());
+            remote_access_info.set_tmp_name(tmp_filestream.clone());
+            argmatch.change_arg(remote_access_info)?;
+            prog.add_remote_stage_in(
+                &origin_location,
+                &access_location,
+                &remote_access_info.filestream,
+                &tmp_filestream,
+                id,
+            )?;
+            return Ok(());
         }
 
         // add in a remote fifo read
@@ -493,6 +779,7 @@ impl Interpreter {
         let tmp_path = self.config.get_tmp(
             &remote_access_info.filestream.get_path().as_path(),
             &remote_access_info.access_location,
+            &prog.get_tmp_namespace(),
         )?;
 
         remote_access_info.set_tmp_name(FileStream::new(
@@ -518,3 +805,373 @@ impl Interpreter {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::examples::get_test_interpreter;
+    use super::*;
+    use annotations2::grammar::parse_annotation_str;
+    use config::network::CompressionConfig;
+    use dash::graph::command::NodeArg;
+    use dash::graph::compression::Compression;
+    use dash::graph::stream::NetStream;
+    use std::path::Path;
+
+    /// Names and arguments of the command nodes in the program.
+    fn commands(program: &Program) -> Vec<(String, Vec<String>)> {
+        let mut ret: Vec<(String, Vec<String>)> = program
+            .get_nodes_iter()
+            .filter_map(|(_, node)| match node.get_elem() {
+                Elem::Cmd(cmdnode) => Some((
+                    cmdnode.get_name().rsplit('/').next().unwrap().to_string(),
+                    cmdnode.get_string_args(),
+                )),
+                _ => None,
+            })
+            .collect();
+        ret.sort();
+        ret
+    }
+
+    #[test]
+    fn test_combiners() {
+        let mut interpreter = get_test_interpreter();
+        interpreter.set_parser(
+            Parser::from_annotations(
+                parse_annotation_str(
+                    "cat: PARAMS:[(type:input_file,splittable,size:list(list_separator:( )))]
+wc[splittable_across_input,combiner:(sum)]: FLAGS:[(short:l)]
+sort[splittable_across_input,combiner:(rerun)]: OPTPARAMS:[(short:k,type:str,size:1)] PARAMS:[(type:input_file,size:list(list_separator:( )))]",
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+        );
+        // cat is split by where its files live, then wc across cat's parts
+        let program = interpreter
+            .parse_command_line("cat /b/a/x /c/b/y | wc -l")
+            .unwrap()
+            .unwrap();
+        let cmds = commands(&program);
+        assert_eq!(cmds.iter().filter(|(name, _)| name == "wc").count(), 2);
+        assert!(cmds.contains(&("awk".to_string(), vec![SUM_COLUMNS.to_string()])));
+
+        let program = interpreter
+            .parse_command_line("cat /b/a/x /c/b/y | sort -k 2")
+            .unwrap()
+            .unwrap();
+        let sorts: Vec<Vec<String>> = commands(&program)
+            .into_iter()
+            .filter(|(name, _)| name == "sort")
+            .map(|(_, args)| args)
+            .collect();
+        assert_eq!(sorts.len(), 3);
+        assert!(sorts.iter().all(|args| args == &vec!["-k", "2"]));
+    }
+
+    /// Where the test config's servers put a program's tmp file.
+    fn tmp_path(program: &Program, name: &str) -> PathBuf {
+        Path::new("/dash/tmp")
+            .join(program.get_tmp_namespace())
+            .join(name)
+    }
+
+    /// Read nodes streaming into write nodes that write files: where and what each reads, and
+    /// where, what and how each writes.
+    fn remote_copies(program: &Program) -> Vec<(Location, PathBuf, Location, PathBuf, FileMode)> {
+        let mut ret = Vec::new();
+        for (_, node) in program.get_nodes_iter() {
+            if let Elem::Read(readnode) = node.get_elem() {
+                let writenode = match readnode.get_stdout_id() {
+                    Some(id) => program.get_node(id).unwrap().get_elem(),
+                    None => continue,
+                };
+                if let Elem::Write(writenode) = writenode {
+                    if let DashStream::File(fs) = writenode.get_output_ref() {
+                        ret.push((
+                            readnode.get_loc(),
+                            readnode.get_input_ref().get_path(),
+                            writenode.get_loc(),
+                            fs.get_path(),
+                            fs.get_mode(),
+                        ));
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_home_implicit_file_on_client() {
+        let mut interpreter = get_test_interpreter();
+        interpreter.set_parser(
+            Parser::from_annotations(
+                parse_annotation_str(
+                    "tool[implicit_output:(~/tool.log)]: PARAMS:[(type:input_file,size:1)]",
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+        );
+        // the client's home is on the same mount as the input, but a proxy would write the log
+        // under its own home, so the command stays on the client
+        let home = std::env::var_os("HOME");
+        std::env::set_var("HOME", "/b/a");
+        let program = interpreter
+            .parse_command_line("tool /b/a/x")
+            .unwrap()
+            .unwrap();
+        match home {
+            Some(home) => std::env::set_var("HOME", home),
+            None => std::env::remove_var("HOME"),
+        }
+        let locations: Vec<Location> = program
+            .get_nodes_iter()
+            .filter_map(|(_, node)| match node.get_elem() {
+                Elem::Cmd(_) => Some(node.get_loc()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(locations, vec![Location::Client]);
+    }
+
+    #[test]
+    fn test_remote_output() {
+        let mut interpreter = get_test_interpreter();
+        interpreter.set_parser(
+            Parser::from_annotations(
+                parse_annotation_str(
+                    "tar: FLAGS:[(short:c)] OPTPARAMS:[(short:f,type:output_file,size:1)] PARAMS:[(type:input_file,size:list(list_separator:( )))]
+cat: PARAMS:[(type:input_file,size:list(list_separator:( )))]
+tee: FLAGS:[(short:a)] PARAMS:[(type:output_file,size:1,appends)]",
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+        );
+        // tar runs by its input, writing the archive into a fifo that's streamed back
+        let program = interpreter
+            .parse_command_line("tar -c -f /c/b/out.tar /b/a/x")
+            .unwrap()
+            .unwrap();
+        let server = |ip: &str| Location::Server(ip.to_string());
+        let tar_files: Vec<PathBuf> = program
+            .get_nodes_iter()
+            .filter_map(|(_, node)| match node.get_elem() {
+                Elem::Cmd(cmdnode) => Some(cmdnode.get_args()),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|arg| match arg {
+                NodeArg::Stream(fs) => Some(fs.get_path()),
+                NodeArg::Str(_) => None,
+            })
+            .collect();
+        assert!(tar_files.contains(&tmp_path(&program, "out.tar")));
+        assert_eq!(
+            remote_copies(&program),
+            vec![(
+                server("125.0.0.1"),
+                tmp_path(&program, "out.tar"),
+                server("126.0.0.1"),
+                PathBuf::from("out.tar"),
+                FileMode::CREATE
+            )]
+        );
+
+        let program = interpreter
+            .parse_command_line("cat /b/a/x | tee -a /c/b/log")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            remote_copies(&program),
+            vec![(
+                server("125.0.0.1"),
+                tmp_path(&program, "log"),
+                server("126.0.0.1"),
+                PathBuf::from("log"),
+                FileMode::APPEND
+            )]
+        );
+    }
+
+    #[test]
+    fn test_remote_stage_in() {
+        let mut interpreter = get_test_interpreter();
+        interpreter.set_parser(
+            Parser::from_annotations(
+                parse_annotation_str(
+                    "comm[random_access]: PARAMS:[(type:input_file,size:list(list_separator:( )))]",
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+        );
+        // comm runs by one of its inputs, and the other is copied over before it starts
+        let program = interpreter
+            .parse_command_line("comm /e/d/x /f/e/y > /f/e/out")
+            .unwrap()
+            .unwrap();
+        let server = |ip: &str| Location::Server(ip.to_string());
+        assert_eq!(
+            remote_copies(&program),
+            vec![(
+                server("128.0.0.1"),
+                PathBuf::from("x"),
+                server("129.0.0.1"),
+                tmp_path(&program, "x"),
+                FileMode::CREATE
+            )]
+        );
+        let comm_args = program
+            .get_nodes_iter()
+            .find_map(|(_, node)| match node.get_elem() {
+                Elem::Cmd(cmdnode) => Some(cmdnode.get_args()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            comm_args,
+            vec![
+                NodeArg::Stream(FileStream::new(
+                    tmp_path(&program, "x").as_path(),
+                    server("129.0.0.1")
+                )),
+                NodeArg::Stream(FileStream::new(Path::new("y"), server("129.0.0.1"))),
+            ]
+        );
+        // the copy is staged before comm is spawned
+        let staging: Vec<(NodeId, NodeId)> = program
+            .get_staging_iter()
+            .map(|link| (link.get_left(), link.get_right()))
+            .collect();
+        assert_eq!(staging.len(), 1);
+        assert!(matches!(
+            program.get_node(staging[0].1).unwrap().get_elem(),
+            Elem::Cmd(_)
+        ));
+    }
+
+    #[test]
+    fn test_output_fifo() {
+        let mut interpreter = get_test_interpreter();
+        interpreter.set_parser(
+            Parser::from_annotations(
+                parse_annotation_str(
+                    "cat: PARAMS:[(type:input_file,size:list(list_separator:( )))]
+tee: FLAGS:[(short:a)] PARAMS:[(type:output_file,size:list(list_separator:( )))]",
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+        );
+        let program = interpreter
+            .parse_command_line("cat /b/a/x | tee >( wc -l > /c/b/count ) > /b/a/copy")
+            .unwrap()
+            .unwrap();
+        let tee = program
+            .get_nodes_iter()
+            .find_map(|(_, node)| match node.get_elem() {
+                Elem::Cmd(cmdnode) if cmdnode.get_name().ends_with("tee") => Some(node),
+                _ => None,
+            })
+            .unwrap();
+        let fifo = match tee.get_elem() {
+            Elem::Cmd(cmdnode) => match &cmdnode.get_args()[0] {
+                NodeArg::Stream(fs) => fs.get_path(),
+                NodeArg::Str(s) => panic!("Fifo argument {:?} not a file", s),
+            },
+            _ => unreachable!(),
+        };
+        assert_eq!(fifo.parent(), tmp_path(&program, "fifo").parent());
+        // the fifo is read where tee writes it, and streamed to wc
+        let (_, reader) = program
+            .get_nodes_iter()
+            .find(|(_, node)| match node.get_elem() {
+                Elem::Read(readnode) => readnode.is_input_fifo(),
+                _ => false,
+            })
+            .unwrap();
+        assert_eq!(reader.get_loc(), tee.get_loc());
+        match reader.get_elem() {
+            Elem::Read(readnode) => assert_eq!(readnode.get_input_ref().get_path(), fifo),
+            _ => unreachable!(),
+        }
+        let wc = program.get_node(reader.get_stdout_id().unwrap()).unwrap();
+        assert!(matches!(wc.get_elem(), Elem::Cmd(cmdnode) if cmdnode.get_name().ends_with("wc")));
+        assert!(program
+            .get_tmp_files()
+            .contains(&(tee.get_loc(), fifo.clone())));
+    }
+
+    #[test]
+    fn test_stream_compression() {
+        let mut interpreter = get_test_interpreter();
+        // compress streams over the slow links to the client, but not between servers
+        interpreter.config.set_compression(CompressionConfig {
+            codec: Compression::Zstd,
+            max_link_speed: 100,
+            ratio: 2.0,
+        });
+        let program = interpreter
+            .parse_command_line("cat /e/d/x > /f/e/out")
+            .unwrap()
+            .unwrap();
+        let mut netstreams = Vec::new();
+        for (_, node) in program.get_nodes_iter() {
+            let streams = node
+                .get_stdin()
+                .into_iter()
+                .chain(node.get_stdout())
+                .chain(node.get_stderr());
+            for stream in streams {
+                if let DashStream::Tcp(netstream) = stream {
+                    netstreams.push(netstream);
+                }
+            }
+        }
+        // each stream is on both of its ends
+        assert!(netstreams.len() >= 4);
+        let involves_client = |netstream: &NetStream| {
+            netstream.get_left_location() == Location::Client
+                || netstream.get_right_location() == Location::Client
+        };
+        assert!(netstreams.iter().any(involves_client));
+        assert!(netstreams.iter().any(|ns| !involves_client(ns)));
+        for netstream in netstreams.iter() {
+            let expected = match involves_client(netstream) {
+                true => Compression::Zstd,
+                false => Compression::None,
+            };
+            assert_eq!(netstream.get_compression(), expected, "{:?}", netstream);
+        }
+    }
+
+    #[test]
+    fn test_progress_buffers_pipes() {
+        let mut interpreter = get_test_interpreter();
+        let command = "cat /b/a/x /c/b/y | sort -k 2 | wc -l";
+        let program = interpreter.parse_command_line(command).unwrap().unwrap();
+        assert!(!program.get_progress());
+        interpreter.set_progress(true);
+        let program = interpreter.parse_command_line(command).unwrap().unwrap();
+        assert!(program.get_progress());
+        let mut pipes = 0;
+        for (_, node) in program.get_nodes_iter() {
+            let streams = node
+                .get_stdin()
+                .into_iter()
+                .chain(node.get_stdout())
+                .chain(node.get_stderr());
+            for stream in streams {
+                if let DashStream::Pipe(pipestream) = stream {
+                    // every pipe is copied through a buffer, where it's metered
+                    assert!(pipestream.get_bufferable(), "{:?}", pipestream);
+                    pipes += 1;
+                }
+            }
+        }
+        assert!(pipes >= 4);
+    }
+}
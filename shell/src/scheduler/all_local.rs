@@ -0,0 +1,33 @@
+use super::annotations2::argument_matcher::ArgMatch;
+use super::config::filecache::FileCache;
+use super::config::network::FileNetwork;
+use super::{mandatory_assignments, Scheduler};
+use dash::graph::program::{Elem, NodeId, Program};
+use dash::graph::Location;
+use dash::util::Result;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Baseline scheduler that runs every command on the client.
+/// Read and write nodes still run where their files live, so remote files are streamed to the
+/// client.
+pub struct AllLocalScheduler;
+
+impl Scheduler for AllLocalScheduler {
+    fn schedule(
+        &mut self,
+        prog: &Program,
+        match_map: &mut HashMap<NodeId, ArgMatch>,
+        config: &FileNetwork,
+        _filecache: &mut FileCache,
+        pwd: &Path,
+    ) -> Result<HashMap<NodeId, Location>> {
+        let mut assigned = mandatory_assignments(prog, match_map, config, pwd)?;
+        for (id, node) in prog.get_nodes_iter() {
+            if let Elem::Cmd(_cmdnode) = node.get_elem() {
+                assigned.insert(*id, Location::Client);
+            }
+        }
+        Ok(assigned)
+    }
+}
@@ -0,0 +1,69 @@
+use super::annotations2::argument_matcher::ArgMatch;
+use super::config::filecache::FileCache;
+use super::config::network::FileNetwork;
+use super::{mandatory_assignments, Scheduler};
+use dash::graph::program::{NodeId, Program};
+use dash::graph::Location;
+use dash::util::Result;
+use failure::bail;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Baseline scheduler that offloads every command it can.
+/// Commands pinned by their file dependencies run where those files live.
+/// Any other command runs next to its first upstream node if that node is on a server, and
+/// otherwise on the configured server (or the first server in the config).
+pub struct AllRemoteScheduler {
+    /// Server to run commands that have no remote dependencies.
+    pub server: Option<Location>,
+}
+
+impl AllRemoteScheduler {
+    fn default_server(&self, config: &FileNetwork) -> Result<Location> {
+        if let Some(server) = &self.server {
+            return Ok(server.clone());
+        }
+        let mut servers: Vec<Location> = config
+            .get_location_list()
+            .into_iter()
+            .filter(|loc| *loc != Location::Client)
+            .collect();
+        servers.sort_by_key(|loc| match loc {
+            Location::Server(ip) => ip.clone(),
+            Location::Client => unreachable!(),
+        });
+        match servers.into_iter().next() {
+            Some(server) => Ok(server),
+            None => bail!("All remote scheduler needs at least one server in the config"),
+        }
+    }
+}
+
+impl Scheduler for AllRemoteScheduler {
+    fn schedule(
+        &mut self,
+        prog: &Program,
+        match_map: &mut HashMap<NodeId, ArgMatch>,
+        config: &FileNetwork,
+        _filecache: &mut FileCache,
+        pwd: &Path,
+    ) -> Result<HashMap<NodeId, Location>> {
+        let mut assigned = mandatory_assignments(prog, match_map, config, pwd)?;
+        let default_server = self.default_server(config)?;
+        // topological order ensures upstream nodes are assigned first
+        for id in prog.execution_order() {
+            if assigned.contains_key(&id) {
+                continue;
+            }
+            let upstream_server = prog
+                .get_dependent_nodes(id)
+                .iter()
+                .filter_map(|dep| assigned.get(dep))
+                .find(|loc| **loc != Location::Client)
+                .cloned();
+            let location = upstream_server.unwrap_or(default_server.clone());
+            assigned.insert(id, location);
+        }
+        Ok(assigned)
+    }
+}
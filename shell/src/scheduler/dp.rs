@@ -12,9 +12,15 @@ use dash::util::Result;
 use failure::bail;
 use std::collections::HashMap;
 use std::f64::INFINITY;
+use std::fs::read_dir;
 use std::path::{Path, PathBuf};
 use std::time::Instant;
 
+/// Estimated output size, in bytes, of commands whose output doesn't depend on their input.
+const CONSTANT_OUTPUT_SIZE: f64 = 1024.0;
+/// Estimated output size, in bytes, for each input file of commands that output a line per file.
+const OUTPUT_SIZE_PER_FILE: f64 = 100.0;
+
 type NodeAssignment = (NodeId, Location);
 #[derive(PartialEq, Debug, Clone, Default)]
 struct DP {
@@ -65,6 +71,10 @@ impl DP {
         }
     }
 
+    pub fn has_min_loc(&self, id: &NodeId) -> bool {
+        self.min_loc.contains_key(id)
+    }
+
     pub fn set_min_loc(&mut self, id: NodeId, location: Location, val: f64) -> Result<()> {
         match self.min_loc.get(&id) {
             Some((loc, old_val)) => {
@@ -88,7 +98,18 @@ impl DP {
         }
     }
 }
-pub struct DPScheduler;
+pub struct DPScheduler {
+    /// How much a node that reduces its input shrinks the data it outputs.
+    pub reduction_factor: f64,
+}
+
+impl Default for DPScheduler {
+    fn default() -> Self {
+        DPScheduler {
+            reduction_factor: 2.0,
+        }
+    }
+}
 
 impl Scheduler for DPScheduler {
     fn schedule(
@@ -127,7 +148,8 @@ impl Scheduler for DPScheduler {
             start.elapsed().as_secs()
         );
         // estimate weights of each edge
-        let edge_weights = calculate_edge_weights(prog, match_map, filecache)?;
+        let edge_weights =
+            calculate_edge_weights(prog, match_map, filecache, self.reduction_factor)?;
 
         // define a new DP to fill in
         let mut dp = DP::default();
@@ -196,7 +218,7 @@ fn backtrack(prog: &Program, config: &FileNetwork, dp: &mut DP) -> Result<()> {
         // now do the backtracking
         // if sink is for stderr, ignore while backtracking
         match prog.get_node(*sink_id).unwrap().get_elem() {
-            Elem::Write(writenode) => match writenode.get_stdout().unwrap() {
+            Elem::Write(writenode) => match writenode.get_output_ref() {
                 DashStream::Stderr => {
                     continue;
                 }
@@ -213,6 +235,11 @@ fn backtrack(prog: &Program, config: &FileNetwork, dp: &mut DP) -> Result<()> {
             if prog.get_dependent_nodes(node).len() > 0 {
                 let predecessors = dp.get_pred_loc(&(node, current_min)).unwrap();
                 for (id, (loc, val)) in predecessors.iter() {
+                    // a node whose stdout fans out stays where the first consumer
+                    // backtracked through it wanted it
+                    if dp.has_min_loc(id) {
+                        continue;
+                    }
                     tracing::debug!(
                         "Setting for {:?} loc {:?} with val {:?} as min",
                         id,
@@ -232,6 +259,7 @@ fn calculate_edge_weights(
     prog: &Program,
     match_map: &mut HashMap<NodeId, ArgMatch>,
     filecache: &mut FileCache,
+    reduction_factor: f64,
 ) -> Result<HashMap<Link, f64>> {
     let execution_order = prog.execution_order();
     let mut edge_weights: HashMap<Link, f64> = HashMap::new();
@@ -251,13 +279,9 @@ fn calculate_edge_weights(
                 }
             }
         }
-        let mut is_filter_node = false;
         match prog.get_node(*id).unwrap().get_elem() {
             Elem::Cmd(_cmdnode) => {
                 let argmatch = match_map.get(id).unwrap();
-                if argmatch.get_reduces_input() {
-                    is_filter_node = true;
-                }
                 for (argtype, fs) in argmatch.file_dependencies().iter() {
                     match argtype {
                         ArgType::InputFile => {
@@ -271,6 +295,8 @@ fn calculate_edge_weights(
             Elem::Write(_writenode) => {
                 // no input dependencies
             }
+            // nothing is known about what a command writes into an output fifo until it runs
+            Elem::Read(readnode) if readnode.is_input_fifo() => {}
             Elem::Read(readnode) => {
                 let fs = readnode.get_input_ref();
                 let size = filecache.get_size(fs.get_path())?;
@@ -278,9 +304,11 @@ fn calculate_edge_weights(
             }
         }
 
-        let mut output_size = match is_filter_node {
-            true => input_size / 2.0,
-            false => input_size as f64,
+        let mut output_size = match prog.get_node(*id).unwrap().get_elem() {
+            Elem::Cmd(_cmdnode) => {
+                estimate_output_size(match_map.get(id).unwrap(), input_size, reduction_factor)
+            }
+            _ => input_size,
         };
 
         // if this node is a cmdnode, and writes to output files, output edge size is 0
@@ -301,11 +329,9 @@ fn calculate_edge_weights(
         }
 
         let outgoing_edges = prog.get_outgoing_edges(*id);
-        // right now, nodes can only have up to 2 outgoing edges:
-        // 1 for stdout, 1 for stderr
-        assert!(outgoing_edges.len() <= 2);
         for (iotype, outgoing_edge) in outgoing_edges.into_iter() {
             match iotype {
+                // stdout that fans out sends a full copy down each of its edges
                 IOType::Stdout => {
                     edge_weights.insert(outgoing_edge, output_size);
                 }
@@ -323,6 +349,33 @@ fn calculate_edge_weights(
     Ok(edge_weights)
 }
 
+/// Estimates the size of a command's output from its annotation's size hint, or else by
+/// dividing its input by the reduction factor if it reduces its input.
+fn estimate_output_size(argmatch: &ArgMatch, input_size: f64, reduction_factor: f64) -> f64 {
+    match argmatch.get_output_size() {
+        Some(OutputSize::Ratio(ratio)) => input_size * ratio,
+        Some(OutputSize::ExpandsInput) => input_size * reduction_factor,
+        Some(OutputSize::Constant) => CONSTANT_OUTPUT_SIZE,
+        Some(OutputSize::PerInputFile) => {
+            // a directory argument (e.g., `ls dir`) lists each of its entries
+            let files: usize = argmatch
+                .file_dependencies()
+                .iter()
+                .filter(|(argtype, _)| *argtype == ArgType::InputFile)
+                .map(|(_, fs)| match read_dir(fs.get_path()) {
+                    Ok(entries) => entries.count(),
+                    Err(_) => 1,
+                })
+                .sum();
+            files as f64 * OUTPUT_SIZE_PER_FILE
+        }
+        None => match argmatch.get_reduces_input() {
+            true => input_size / reduction_factor,
+            false => input_size,
+        },
+    }
+}
+
 /// Represent constraints by returning 0 if the assigned location is not the potential location
 fn constraint(assigned_loc: &Location, potential_loc: &Location) -> Result<f64> {
     if assigned_loc != potential_loc {
@@ -332,6 +385,15 @@ fn constraint(assigned_loc: &Location, potential_loc: &Location) -> Result<f64>
     }
 }
 
+/// Like `constraint`, but any of the given locations (e.g., replicas) satisfies it.
+fn constraint_any(assigned_loc: &Location, potential_locs: &[Location]) -> Result<f64> {
+    if potential_locs.contains(assigned_loc) {
+        Ok(0.0)
+    } else {
+        Ok(INFINITY)
+    }
+}
+
 fn calculate_dp(
     entry: &NodeAssignment,
     prog: &Program,
@@ -356,8 +418,8 @@ fn calculate_dp(
                         bail!("No edge between {:?} and {:?}");
                     }
                 };
-                // if no link between two machines, dp is infinite
-                let bw = config.network_speed(&loc, location).unwrap_or(0.0);
+                // if no link between two machines, dp is infinite; compressed links move data faster
+                let bw = config.effective_network_speed(loc, location).unwrap_or(0.0);
                 let dp_val = dp.get(&(prev_id, loc.clone()))?;
                 tracing::debug!("prev node: {:?}, curr node: {:?}, prev node loc: {:?}, edge size: {:?}, bw: {:?}, prev dp: {:?}, res: {:?}", prev_id, id, loc.clone(), edge_size, bw, dp_val, dp_val + edge_size/bw);
                 if bw != 0.0 {
@@ -403,9 +465,10 @@ fn calculate_dp(
             for (argtype, fs) in argmatch.file_dependencies().iter() {
                 match argtype {
                     ArgType::InputFile => {
-                        let file_location = config.get_location(fs);
+                        // read from whichever replica has the fastest link here
+                        let file_location = config.closest_location(fs, location);
                         let speed = config
-                            .network_speed(location, &file_location)
+                            .effective_network_speed(location, &file_location)
                             .unwrap_or(0.0);
                         let file_size = filecache.get_size(fs.get_path())?;
                         if speed == 0.0 {
@@ -418,6 +481,17 @@ fn calculate_dp(
                 }
             }
 
+            // implicit files aren't arguments, so can't be replaced with a stream
+            if argmatch.needs_client() {
+                input_time += constraint_any(location, &[Location::Client])?;
+            }
+            for (argtype, fs) in argmatch.implicit_dependencies().iter() {
+                let is_output = *argtype == ArgType::OutputFile;
+                let mut access_locations = config.get_access_locations(fs, is_output);
+                access_locations.push(Location::Client);
+                input_time += constraint_any(location, &access_locations)?;
+            }
+
             if argmatch.get_needs_current_dir() {
                 // Querying for filesize can be extremely expensive
                 // Especially for large nested repos (e.g., git)
@@ -430,8 +504,8 @@ fn calculate_dp(
                 } else {
                     input_time += dir_size / speed;
                 }*/
-                let pwd_location = config.get_path_location(pwd.to_path_buf());
-                let time = constraint(location, &pwd_location)?;
+                let pwd_locations = config.get_path_access_locations(pwd.to_path_buf(), false);
+                let time = constraint_any(location, &pwd_locations)?;
                 input_time += time;
             }
 
@@ -446,7 +520,12 @@ fn calculate_dp(
         }
         Elem::Read(readnode) => {
             assert!(prog.get_dependent_nodes(id).len() == 0);
-            return constraint(location, &config.get_location(readnode.get_input_ref()));
+            // output fifos are moved next to the command writing into them after scheduling,
+            // so they don't hold back where their reader runs
+            if readnode.is_input_fifo() {
+                return Ok(0.0);
+            }
+            return constraint_any(location, &config.get_locations(readnode.get_input_ref()));
         }
         Elem::Write(writenode) => {
             // calculate the preceeding min execution times
@@ -455,17 +534,10 @@ fn calculate_dp(
                 let transfer_term = min_term(*preceeding_id, dp)?;
                 dp_val += transfer_term;
             }
-            let writecost = match writenode.get_stdout() {
-                Some(stdout) => match stdout {
-                    DashStream::File(fs) => constraint(location, &config.get_location(&fs)),
-                    DashStream::Stdout | DashStream::Stderr => {
-                        constraint(location, &Location::Client)
-                    }
-                    _ => {
-                        unreachable!();
-                    }
-                },
-                None => {
+            let writecost = match writenode.get_output_ref() {
+                DashStream::File(fs) => constraint(location, &config.get_location(fs)),
+                DashStream::Stdout | DashStream::Stderr => constraint(location, &Location::Client),
+                _ => {
                     unreachable!();
                 }
             }?;
@@ -473,3 +545,41 @@ fn calculate_dp(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::annotations2::grammar::parse_annotation_str;
+    use super::super::annotations2::parser::Parser;
+    use super::*;
+
+    fn output_size(annotation: &str, input_size: f64) -> f64 {
+        let parser = Parser::from_annotations(parse_annotation_str(annotation).unwrap()).unwrap();
+        let argmatch = parser.match_invocation("cmd", vec![]).unwrap();
+        estimate_output_size(&argmatch, input_size, 2.0)
+    }
+
+    #[test]
+    fn test_estimate_output_size() {
+        assert_eq!(output_size("cmd: FLAGS:[(short:a)]", 100.0), 100.0);
+        assert_eq!(
+            output_size("cmd[reduces_input]: FLAGS:[(short:a)]", 100.0),
+            50.0
+        );
+        // size hints override reduces_input
+        assert_eq!(
+            output_size(
+                "cmd[reduces_input,selectivity:(0.1)]: FLAGS:[(short:a)]",
+                100.0
+            ),
+            10.0
+        );
+        assert_eq!(
+            output_size("cmd[expands_input]: FLAGS:[(short:a)]", 100.0),
+            200.0
+        );
+        assert_eq!(
+            output_size("cmd[constant_output]: FLAGS:[(short:a)]", 1e9),
+            CONSTANT_OUTPUT_SIZE
+        );
+    }
+}
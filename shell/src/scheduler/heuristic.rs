@@ -1,19 +1,26 @@
 use super::annotations2::argument_matcher::ArgMatch;
 use super::config::filecache::FileCache;
 use super::config::network::FileNetwork;
-use super::Scheduler;
-use dash::graph::filestream::FileStream;
+use super::{mandatory_assignments, Scheduler};
 use dash::graph::program::{Elem, NodeId, Program};
-use dash::graph::stream::DashStream;
 use dash::graph::Location;
 use dash::util::Result;
 use failure::bail;
-use std::collections::{HashMap, HashSet};
-use std::convert::Into;
+use std::collections::HashMap;
 use std::f64;
-use std::iter::FromIterator;
 use std::path::Path;
-pub struct HeuristicScheduler;
+pub struct HeuristicScheduler {
+    /// How much an edge shrinks after a node that reduces its input.
+    pub reduction_factor: f64,
+}
+
+impl Default for HeuristicScheduler {
+    fn default() -> Self {
+        HeuristicScheduler {
+            reduction_factor: 2.0,
+        }
+    }
+}
 
 /// Custom scheduling algorithm (DP/Max Flow based) to assign locations for nodes that haven't
 /// previously been assigned.
@@ -38,6 +45,7 @@ fn optimize_node_schedule(
     prog: &Program,
     assigned: &mut HashMap<NodeId, Location>,
     match_map: &HashMap<NodeId, ArgMatch>,
+    reduction_factor: f64,
 ) -> Result<()> {
     let mut possible_assignments: HashMap<NodeId, HashMap<Location, u32>> = HashMap::default();
     // closure to insert new assignments
@@ -123,7 +131,7 @@ fn optimize_node_schedule(
             };
 
             if reduces_input {
-                current_weight = current_weight / 2 as f64;
+                current_weight = current_weight / reduction_factor;
             }
 
             // insert the weight of the *previous edge*;
@@ -205,61 +213,9 @@ impl Scheduler for HeuristicScheduler {
         _filecache: &mut FileCache,
         pwd: &Path,
     ) -> Result<HashMap<NodeId, Location>> {
-        let mut assigned: HashMap<NodeId, Location> = HashMap::default();
-
-        // constraints/mandatory assigments
-        for (id, node) in prog.get_nodes_iter() {
-            match node.get_elem() {
-                Elem::Read(readnode) => {
-                    let location = config.get_location(readnode.get_input_ref());
-                    assigned.insert(*id, location);
-                }
-                Elem::Write(writenode) => {
-                    let location = match writenode.get_output_ref() {
-                        DashStream::File(fs) => config.get_location(fs),
-                        DashStream::Stdout => Location::Client,
-                        DashStream::Stderr => Location::Client,
-                        _ => {
-                            bail!("During scheduling stage, writenode cannot have TCP, Pipe or Fifo as output");
-                        }
-                    };
-                    assigned.insert(*id, location);
-                }
-                Elem::Cmd(_cmdnode) => {
-                    // if the node depends on the current directory, need to set it to run where
-                    // that directory is located
-                    let argmatch = match_map.get(id).unwrap();
-                    let file_locations: Vec<Location> = argmatch
-                        .file_dependencies()
-                        .into_iter()
-                        .map(|(_t, arg)| {
-                            let file_option: Option<FileStream> = arg.into();
-                            config.get_location(&file_option.unwrap())
-                        })
-                        .collect();
-                    let mut dependent_locations: HashSet<Location> =
-                        HashSet::from_iter(file_locations);
-                    if argmatch.get_needs_current_dir() {
-                        let pwd_location =
-                            config.get_location(&FileStream::new(pwd, Location::Client));
-                        dependent_locations.insert(pwd_location);
-                    }
-                    match dependent_locations.len() {
-                        0 => {}
-                        1 => {
-                            for loc in dependent_locations.drain() {
-                                assigned.insert(*id, loc.clone());
-                            }
-                        }
-                        _ => {
-                            assigned.insert(*id, Location::Client);
-                        }
-                    }
-                }
-            }
-        }
+        let mut assigned = mandatory_assignments(prog, match_map, config, pwd)?;
         tracing::debug!("Assigned so far: {:?}", assigned);
-        optimize_node_schedule(prog, &mut assigned, match_map)?;
+        optimize_node_schedule(prog, &mut assigned, match_map, self.reduction_factor)?;
         Ok(assigned)
     }
 }
@@ -1,12 +1,18 @@
 use super::config::filecache::FileCache;
 use super::config::network::FileNetwork;
+use super::config::scheduler::SchedulerConfig;
 use super::{annotations2, config};
 use annotations2::argument_matcher::ArgMatch;
-use dash::graph::program::{NodeId, Program};
+use annotations2::grammar::ArgType;
+use dash::graph::program::{Elem, NodeId, Program};
+use dash::graph::stream::DashStream;
 use dash::graph::Location;
 use dash::util::Result;
+use failure::bail;
 use std::collections::HashMap;
+use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
 pub trait Scheduler {
     fn schedule(
         &mut self,
@@ -18,5 +24,164 @@ pub trait Scheduler {
     ) -> Result<HashMap<NodeId, Location>>;
 }
 
+pub mod all_local;
+pub mod all_remote;
 pub mod dp;
 pub mod heuristic;
+
+/// Available scheduling algorithms.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum SchedulerType {
+    Heuristic,
+    DP,
+    AllLocal,
+    AllRemote,
+}
+
+impl Default for SchedulerType {
+    fn default() -> Self {
+        SchedulerType::Heuristic
+    }
+}
+
+impl FromStr for SchedulerType {
+    type Err = failure::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "heuristic" => SchedulerType::Heuristic,
+            "dp" => SchedulerType::DP,
+            "all-local" => SchedulerType::AllLocal,
+            "all-remote" => SchedulerType::AllRemote,
+            x => bail!(
+                "unknown scheduler {:?}, options are heuristic, dp, all-local, all-remote",
+                x
+            ),
+        })
+    }
+}
+
+impl fmt::Display for SchedulerType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SchedulerType::Heuristic => "heuristic",
+            SchedulerType::DP => "dp",
+            SchedulerType::AllLocal => "all-local",
+            SchedulerType::AllRemote => "all-remote",
+        };
+        f.pad(name)
+    }
+}
+
+/// Constructs the requested scheduler, tuned with any parameters from the config.
+pub fn new_scheduler(
+    scheduler_type: SchedulerType,
+    scheduler_config: &SchedulerConfig,
+) -> Box<dyn Scheduler> {
+    match scheduler_type {
+        SchedulerType::Heuristic => Box::new(heuristic::HeuristicScheduler {
+            reduction_factor: scheduler_config.heuristic_reduction_factor,
+        }),
+        SchedulerType::DP => Box::new(dp::DPScheduler {
+            reduction_factor: scheduler_config.dp_reduction_factor,
+        }),
+        SchedulerType::AllLocal => Box::new(all_local::AllLocalScheduler {}),
+        SchedulerType::AllRemote => Box::new(all_remote::AllRemoteScheduler {
+            server: scheduler_config.all_remote_server.clone(),
+        }),
+    }
+}
+
+/// Assignments forced by the program: read and write nodes run where their files live, and
+/// command nodes run where their file dependencies (and current directory, if needed) live.
+/// Inputs served by several replicas can be read at any of them, so nodes are placed on a
+/// replica common to all their dependencies, chosen by link speed to the nodes reading their
+/// output and current load.
+/// Outputs must be written at the primary location.
+/// Command nodes may also run on a server that mounts their files' servers.
+/// Command nodes with implicit files outside the current directory run on the client, since they
+/// find those files with the client's home and environment.
+/// Command nodes with no common location are assigned to the client; command nodes with no
+/// dependencies are left unassigned.
+pub fn mandatory_assignments(
+    prog: &Program,
+    match_map: &HashMap<NodeId, ArgMatch>,
+    config: &FileNetwork,
+    pwd: &Path,
+) -> Result<HashMap<NodeId, Location>> {
+    let mut assigned: HashMap<NodeId, Location> = HashMap::default();
+    let mut load: HashMap<Location, u32> = HashMap::default();
+    // a node's data goes to the nodes it feeds, or to the client if they're unassigned
+    let mut assign = |id: NodeId, consumers: Vec<NodeId>, candidates: Vec<Location>| {
+        let consumer = consumers
+            .iter()
+            .find_map(|consumer| assigned.get(consumer))
+            .cloned()
+            .unwrap_or(Location::Client);
+        let location = config.choose_replica(&candidates, &consumer, &load);
+        *load.entry(location.clone()).or_insert(0) += 1;
+        assigned.insert(id, location);
+    };
+    // visit nodes in a fixed order so replica choices are deterministic, and consumers before
+    // the nodes feeding them
+    for id in prog.execution_order().into_iter().rev() {
+        let node = prog.get_node(id).unwrap();
+        let consumers = prog.get_outgoing_nodes(id);
+        match node.get_elem() {
+            Elem::Read(readnode) => {
+                assign(
+                    id,
+                    consumers,
+                    config.get_locations(readnode.get_input_ref()),
+                );
+            }
+            Elem::Write(writenode) => {
+                let location = match writenode.get_output_ref() {
+                    DashStream::File(fs) => config.get_location(fs),
+                    DashStream::Stdout => Location::Client,
+                    DashStream::Stderr => Location::Client,
+                    _ => {
+                        bail!("During scheduling stage, writenode cannot have TCP, Pipe or Fifo as output");
+                    }
+                };
+                assign(id, consumers, vec![location]);
+            }
+            Elem::Cmd(_cmdnode) => {
+                // if the node depends on the current directory, need to set it to run where
+                // that directory is located
+                let argmatch = match_map.get(&id).unwrap();
+                if argmatch.needs_client() {
+                    assign(id, consumers, vec![Location::Client]);
+                    continue;
+                }
+                let mut dependencies: Vec<Vec<Location>> = argmatch
+                    .file_dependencies()
+                    .into_iter()
+                    .map(|(argtype, fs)| match argtype {
+                        ArgType::OutputFile | ArgType::OutputFileList => {
+                            config.get_access_locations(&fs, true)
+                        }
+                        _ => config.get_access_locations(&fs, false),
+                    })
+                    .collect();
+                if argmatch.get_needs_current_dir() {
+                    dependencies.push(config.get_path_access_locations(pwd.to_path_buf(), false));
+                }
+                if dependencies.is_empty() {
+                    continue;
+                }
+                // locations that can serve every dependency, in the first dependency's order
+                let common: Vec<Location> = dependencies[0]
+                    .iter()
+                    .filter(|loc| dependencies.iter().all(|dep| dep.contains(loc)))
+                    .cloned()
+                    .collect();
+                if common.is_empty() {
+                    assign(id, consumers, vec![Location::Client]);
+                } else {
+                    assign(id, consumers, common);
+                }
+            }
+        }
+    }
+    Ok(assigned)
+}
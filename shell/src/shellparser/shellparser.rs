@@ -21,10 +21,16 @@ use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use stream::{DashStream, IOType, PipeStream};
 use write::WriteNode;
 
+/// Placeholder path for the fifo that the `idx`th output process substitution of shell graph
+/// node `id` writes into. The interpreter replaces it with a tmp file wherever the node runs.
+pub fn output_fifo_placeholder(id: NodeId, idx: usize) -> PathBuf {
+    PathBuf::from(format!("/dash_fifo_{}_{}", id, idx))
+}
+
 // General types
 pub enum Command {
     /// just an export command.
@@ -96,6 +102,7 @@ impl ShellGraphNode {
         let mut stdin_nodes: Vec<ReadNode> = Vec::new();
         let mut stdout_nodes: Vec<WriteNode> = Vec::new();
         let mut stderr_nodes: Vec<WriteNode> = Vec::new();
+        let mut fifo_nodes: Vec<ReadNode> = Vec::new();
 
         while let Some(elt) = iter.next() {
             match elt {
@@ -193,10 +200,27 @@ impl ShellGraphNode {
                 RawShellElement::Subcmd(subcmd) => {
                     bail!("Should not encounter subcommand when generating a subprogram from a shell graph node: {:?}", subcmd);
                 }
+                RawShellElement::OutputSubcmd(_) => {
+                    // the command writes into a fifo named by this argument, which a read node
+                    // streams to the subcommand; the subcommand is linked to the read node when
+                    // the subprograms are merged
+                    let fifo = output_fifo_placeholder(self.id, fifo_nodes.len());
+                    cmd_node.add_arg(NodeArg::Str(fifo.to_string_lossy().to_string()));
+                    let mut readnode = ReadNode::default();
+                    readnode.add_stdin(DashStream::File(FileStream::new(
+                        fifo.as_path(),
+                        Location::Client,
+                    )))?;
+                    readnode.set_input_fifo(true);
+                    fifo_nodes.push(readnode);
+                }
             }
         }
 
         let cmd_node_id = new_program.add_elem(Elem::Cmd(cmd_node));
+        for fifo in fifo_nodes.into_iter() {
+            new_program.add_elem(Elem::Read(fifo));
+        }
         for stdin in stdin_nodes.into_iter() {
             // insert both nodes into the graph, and add an edge
             let stdin_node_id = new_program.add_elem(Elem::Read(stdin));
@@ -257,6 +281,19 @@ impl ShellGraphNode {
 pub struct ShellLink {
     pub left: NodeId,
     pub right: NodeId,
+    /// Which of the left node's output process substitutions the right node reads, if it reads
+    /// one instead of the left node's stdout.
+    pub fifo: Option<usize>,
+}
+
+impl ShellLink {
+    pub fn new(left: NodeId, right: NodeId) -> Self {
+        ShellLink {
+            left: left,
+            right: right,
+            fifo: None,
+        }
+    }
 }
 
 /// Representation of ShellGraph as a connection of piped processes.
@@ -322,17 +359,15 @@ impl ShellGraph {
         self.nodes.get_mut(&id)
     }
 
-    fn add_link(&mut self, left: NodeId, right: NodeId) {
-        if self.sinks.contains(&left) {
-            self.sinks.retain(|&x| x != left);
+    fn add_link(&mut self, link: ShellLink) {
+        // a node's stdout still goes on after it writes into a process substitution
+        if link.fifo.is_none() && self.sinks.contains(&link.left) {
+            self.sinks.retain(|&x| x != link.left);
         }
-        if self.front.contains(&right) {
-            self.front.retain(|&x| x != right);
+        if self.front.contains(&link.right) {
+            self.front.retain(|&x| x != link.right);
         }
-        self.edges.push(ShellLink {
-            left: left,
-            right: right,
-        });
+        self.edges.push(link);
     }
 
     // finds "right most node"
@@ -354,11 +389,43 @@ impl ShellGraph {
         Ok(subgraph_map)
     }
 
+    /// Links between the subgraphs for each edge. Pipes connect node 1 of each subgraph, the
+    /// command; process substitutions connect the read node for the fifo the left command writes
+    /// into instead.
+    fn get_subgraph_links(
+        &self,
+        subgraph_map: &HashMap<NodeId, Program>,
+    ) -> Result<Vec<((NodeId, NodeId), (NodeId, NodeId))>> {
+        let mut links: Vec<((NodeId, NodeId), (NodeId, NodeId))> = Vec::new();
+        for edge in self.edges.iter() {
+            let left = match edge.fifo {
+                None => 1,
+                Some(idx) => {
+                    let fifo = output_fifo_placeholder(edge.left, idx);
+                    let subgraph = match subgraph_map.get(&edge.left) {
+                        Some(subgraph) => subgraph,
+                        None => bail!("No subgraph for shell graph node {:?}", edge.left),
+                    };
+                    match subgraph
+                        .get_nodes_iter()
+                        .find(|(_, node)| match node.get_elem() {
+                            Elem::Read(readnode) => readnode.get_input_ref().get_path() == fifo,
+                            _ => false,
+                        }) {
+                        Some((id, _)) => *id,
+                        None => bail!("No read node for process substitution {:?}", fifo),
+                    }
+                }
+            };
+            links.push(((edge.left, left), (edge.right, 1)));
+        }
+        Ok(links)
+    }
+
     pub fn get_program_without_output_nodes(&self) -> Result<Program> {
         // generate subgraphs for each part
         let subgraph_map = self.get_subgraph_map()?;
 
-        let mut links: Vec<((NodeId, NodeId), (NodeId, NodeId))> = Vec::new();
         let mut old_links: Vec<((NodeId, NodeId), (NodeId, NodeId))> = Vec::new();
         // any links present in subgraphs
         for (id, subgraph) in subgraph_map.iter() {
@@ -368,10 +435,7 @@ impl ShellGraph {
         }
 
         // connect subgraphs by pipe via adding a new edge.
-        for edge in self.edges.iter() {
-            // connect node 0 of each new subgraph
-            links.push(((edge.left, 1), (edge.right, 1)));
-        }
+        let links = self.get_subgraph_links(&subgraph_map)?;
         // merge all subgraphs into 1 program
         let program = Program::merge_subgraphs(subgraph_map, old_links, links)?;
         Ok(program)
@@ -385,7 +449,6 @@ impl ShellGraph {
         // generate subgraphs for each part
         let subgraph_map = self.get_subgraph_map()?;
 
-        let mut links: Vec<((NodeId, NodeId), (NodeId, NodeId))> = Vec::new();
         let mut old_links: Vec<((NodeId, NodeId), (NodeId, NodeId))> = Vec::new();
         // any links present in subgraphs
         for (id, subgraph) in subgraph_map.iter() {
@@ -395,10 +458,7 @@ impl ShellGraph {
         }
 
         // connect subgraphs by pipe via adding a new edge.
-        for edge in self.edges.iter() {
-            // connect node 0 of each new subgraph
-            links.push(((edge.left, 1), (edge.right, 1)));
-        }
+        let links = self.get_subgraph_links(&subgraph_map)?;
         // merge all subgraphs into 1 program
         let mut program = Program::merge_subgraphs(subgraph_map, old_links, links)?;
         // now, go through and add in stdout and stderr redirections for any nodes that do not
@@ -473,17 +533,21 @@ impl ShellGraph {
     ) -> Result<()> {
         // Check if both graphs contain the nodes on the given link.
         let mut id_map: HashMap<NodeId, NodeId> = HashMap::default();
-        for (old_id, node) in other.nodes.iter() {
-            let new_id = self.add_node(node.cmd.clone());
-            id_map.insert(old_id.clone(), new_id);
+        // add nodes in order, so the first sink and front of the other graph stay first
+        let mut old_ids: Vec<NodeId> = other.nodes.keys().cloned().collect();
+        old_ids.sort();
+        for old_id in old_ids.into_iter() {
+            let new_id = self.add_node(other.nodes[&old_id].cmd.clone());
+            id_map.insert(old_id, new_id);
         }
 
         // add all the old links
         for link in other.edges.iter() {
-            self.add_link(
-                id_map.get(&link.left).unwrap().clone(),
-                id_map.get(&link.right).unwrap().clone(),
-            );
+            self.add_link(ShellLink {
+                left: id_map.get(&link.left).unwrap().clone(),
+                right: id_map.get(&link.right).unwrap().clone(),
+                fifo: link.fifo,
+            });
         }
 
         // add in the connection
@@ -498,7 +562,11 @@ impl ShellGraph {
                             self.nodes.keys()
                         );
                     }
-                    self.add_link(id_map.get(&link.left).unwrap().clone(), link.right.clone());
+                    self.add_link(ShellLink {
+                        left: id_map.get(&link.left).unwrap().clone(),
+                        right: link.right,
+                        fifo: link.fifo,
+                    });
                 } else {
                     if !self.contains(link.left) || !other.contains(link.right) {
                         bail!(
@@ -508,7 +576,11 @@ impl ShellGraph {
                             other.nodes.keys(),
                         );
                     }
-                    self.add_link(link.left.clone(), id_map.get(&link.right).unwrap().clone());
+                    self.add_link(ShellLink {
+                        left: link.left,
+                        right: id_map.get(&link.right).unwrap().clone(),
+                        fifo: link.fifo,
+                    });
                 }
             }
             None => {}
@@ -527,6 +599,8 @@ pub enum RawShellElement {
     Pipe,
     StdoutAppend,
     Subcmd(SubCommand),
+    /// Output process substitution, `>( ... )`, given as an argument.
+    OutputSubcmd(SubCommand),
 }
 
 impl RawShellElement {
@@ -539,6 +613,7 @@ impl RawShellElement {
             RawShellElement::Pipe => "|".to_string(),
             RawShellElement::StdoutAppend => ">>".to_string(),
             RawShellElement::Subcmd(cmd) => cmd.to_string(),
+            RawShellElement::OutputSubcmd(cmd) => format!(">( {} )", cmd.to_string()),
         }
     }
 }
@@ -565,44 +640,11 @@ impl ShellSplit {
             // first look for a subcommand
             match elt.as_ref() {
                 "<(" => {
-                    let mut found_close_parens = false;
-                    let mut subcommand: Vec<RawShellElement> = Vec::new();
-                    while let Some(inner_elt) = it.next() {
-                        match inner_elt.as_ref() {
-                            ")" => {
-                                found_close_parens = true;
-                            }
-                            _ => {}
-                        }
-                        if found_close_parens {
-                            break;
-                        }
-                        match inner_elt.as_ref() {
-                            ">" => {
-                                subcommand.push(RawShellElement::Stdout);
-                            }
-                            ">>" => {
-                                subcommand.push(RawShellElement::StdoutAppend);
-                            }
-                            "<" => {
-                                subcommand.push(RawShellElement::Stdin);
-                            }
-                            "2>" => {
-                                subcommand.push(RawShellElement::Stderr);
-                            }
-                            "|" => {
-                                subcommand.push(RawShellElement::Pipe);
-                            }
-                            _ => {
-                                subcommand.push(RawShellElement::Str(inner_elt.clone()));
-                            }
-                        }
-                    }
-                    if !found_close_parens {
-                        bail!("Unclosed parens!");
-                    }
                     elements.push(RawShellElement::Stdin);
-                    elements.push(RawShellElement::Subcmd(SubCommand::new(subcommand)));
+                    elements.push(RawShellElement::Subcmd(parse_subcommand(&mut it)?));
+                }
+                ">(" => {
+                    elements.push(RawShellElement::OutputSubcmd(parse_subcommand(&mut it)?));
                 }
                 "<" => {
                     elements.push(RawShellElement::Stdin);
@@ -665,13 +707,7 @@ impl ShellSplit {
                 );*/
                 graph.merge(
                     new_subgraph,
-                    Some((
-                        ShellLink {
-                            left: graph_end,
-                            right: subgraph_front,
-                        },
-                        false,
-                    )),
+                    Some((ShellLink::new(graph_end, subgraph_front), false)),
                 )?;
                 //tracing::debug!("new graph nodes: {:?}", graph.nodes.keys());
             }
@@ -680,11 +716,43 @@ impl ShellSplit {
     }
 }
 
+/// Parses the words of a process substitution up to its closing parens.
+fn parse_subcommand<'a, I: Iterator<Item = &'a String>>(it: &mut I) -> Result<SubCommand> {
+    let mut subcommand: Vec<RawShellElement> = Vec::new();
+    while let Some(inner_elt) = it.next() {
+        match inner_elt.as_ref() {
+            ")" => {
+                return Ok(SubCommand::new(subcommand));
+            }
+            ">" => {
+                subcommand.push(RawShellElement::Stdout);
+            }
+            ">>" => {
+                subcommand.push(RawShellElement::StdoutAppend);
+            }
+            "<" => {
+                subcommand.push(RawShellElement::Stdin);
+            }
+            "2>" => {
+                subcommand.push(RawShellElement::Stderr);
+            }
+            "|" => {
+                subcommand.push(RawShellElement::Pipe);
+            }
+            _ => {
+                subcommand.push(RawShellElement::Str(inner_elt.clone()));
+            }
+        }
+    }
+    bail!("Unclosed parens!");
+}
+
 fn get_subgraph(subcmd: &[RawShellElement]) -> Result<ShellGraph> {
     // Takes out any internal pipes stdout directives
     let mut graph = ShellGraph::default();
     let mut it = subcmd.iter();
     let id = graph.add_node(SubCommand::new(Vec::<RawShellElement>::new()));
+    let mut num_fifos = 0;
     while let Some(elt) = it.next() {
         match elt.clone() {
             RawShellElement::Str(cmd) => {
@@ -706,16 +774,7 @@ fn get_subgraph(subcmd: &[RawShellElement]) -> Result<ShellGraph> {
                             let new_shell_split = ShellSplit::from_vec(subcmd.elts);
                             let new_subgraph = new_shell_split.convert_into_shell_graph()?;
                             let sink_id = new_subgraph.get_end()[0];
-                            graph.merge(
-                                new_subgraph,
-                                Some((
-                                    ShellLink {
-                                        left: sink_id,
-                                        right: id,
-                                    },
-                                    true,
-                                )),
-                            )?;
+                            graph.merge(new_subgraph, Some((ShellLink::new(sink_id, id), true)))?;
                         }
                         _ => {
                             bail!("Found stdin symbol followed by stdin, stdout, or stderr symbol");
@@ -743,6 +802,27 @@ fn get_subgraph(subcmd: &[RawShellElement]) -> Result<ShellGraph> {
                     subcmd
                 );
             }
+            RawShellElement::OutputSubcmd(subcmd) => {
+                // the node keeps the subcommand in place of the fifo argument, and its subgraph
+                // reads from the fifo
+                let current_node = graph.get_node(id).unwrap();
+                current_node.push(RawShellElement::OutputSubcmd(subcmd.clone()));
+                let new_shell_split = ShellSplit::from_vec(subcmd.elts);
+                let new_subgraph = new_shell_split.convert_into_shell_graph()?;
+                let front_id = new_subgraph.get_front()[0];
+                graph.merge(
+                    new_subgraph,
+                    Some((
+                        ShellLink {
+                            left: id,
+                            right: front_id,
+                            fifo: Some(num_fifos),
+                        },
+                        false,
+                    )),
+                )?;
+                num_fifos += 1;
+            }
             RawShellElement::Pipe => {
                 bail!("Shouldn't have nested pipes");
             }
@@ -800,6 +880,52 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_output_process_substitution() {
+        let cmd = "tee >( grep foo > foo.txt ) >( wc -l ) | sort";
+        let program = ShellSplit::new(cmd)
+            .unwrap()
+            .convert_into_shell_graph()
+            .unwrap()
+            .convert_into_program()
+            .unwrap();
+        let name = |id: NodeId| match program.get_node(id).unwrap().get_elem() {
+            Elem::Cmd(cmdnode) => cmdnode.get_name(),
+            _ => String::new(),
+        };
+        let (tee_id, tee) = program
+            .get_nodes_iter()
+            .find_map(|(id, node)| match node.get_elem() {
+                Elem::Cmd(cmdnode) if cmdnode.get_name() == "tee" => Some((*id, cmdnode)),
+                _ => None,
+            })
+            .unwrap();
+        // tee's stdout still goes down the pipe
+        assert_eq!(
+            program.get_node(tee_id).unwrap().get_stdout_ids(),
+            vec![program
+                .get_nodes_iter()
+                .find(|(id, _)| name(**id) == "sort")
+                .map(|(id, _)| *id)
+                .unwrap()]
+        );
+        // each fifo argument is read by a read node piping into its subcommand
+        let fifos = tee.get_string_args();
+        assert_eq!(fifos.len(), 2);
+        let mut readers: Vec<String> = Vec::new();
+        for fifo in fifos.iter() {
+            let (_, readnode) = program
+                .get_nodes_iter()
+                .find(|(_, node)| match node.get_elem() {
+                    Elem::Read(readnode) => readnode.get_input_ref().get_path() == Path::new(fifo),
+                    _ => false,
+                })
+                .unwrap();
+            readers.push(name(readnode.get_stdout_id().unwrap()));
+        }
+        assert_eq!(readers, vec!["grep", "wc"]);
+    }
+
     #[test]
     fn test_scan_command() {
         let cmd = "pr -mts, <( cat annotated | jq \".ip\" | tr -d '\"' ) <( cat annotated | jq -c \".zannotate.routing.asn\" ) | awk -F',' '{ a[$2]++; } END { for (n in a) print n \",\" a[n] } ' | sort -k2 -n -t',' -r > as_popularity";